@@ -0,0 +1,271 @@
+//! Integration with the [actix-web](https://crates.io/crates/actix-web) web framework
+//!
+//! The [CognitoAuth] middleware verifies the bearer token of each request with
+//! a KeySet and Verifier (for either ID or access tokens), rejecting requests
+//! that don't have a valid token. The verified claims are added to the request
+//! extensions (as [VerifiedClaims]) and can be extracted in handlers with
+//! [CognitoClaims].
+//!
+//! ```no_run
+//! # use jsonwebtokens_cognito::{AccessTokenClaims, KeySet};
+//! # use jsonwebtokens_cognito::actix::{CognitoAuth, CognitoClaims};
+//! # use actix_web::{web, App};
+//! async fn handler(claims: CognitoClaims<AccessTokenClaims>) -> String {
+//!     format!("Hello {}", claims.0.sub)
+//! }
+//!
+//! # fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! let keyset = KeySet::new("eu-west-1", "eu-west-1_AbCd1234")?;
+//! let verifier = keyset.new_access_token_verifier(&["client-id-0"]).build()?;
+//! let auth = CognitoAuth::new(keyset, verifier);
+//!
+//! let app = App::new()
+//!     .service(web::scope("/read")
+//!         .wrap(auth.clone().require_scope("api/read"))
+//!         .route("", web::get().to(handler)))
+//!     .service(web::scope("/write")
+//!         .wrap(auth.require_scope("api/write"))
+//!         .route("", web::post().to(handler)));
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! _Requires the `actix` feature_
+
+use std::future::{ready, Future, Ready};
+use std::pin::Pin;
+use std::rc::Rc;
+use std::sync::Arc;
+
+use actix_web::body::EitherBody;
+use actix_web::dev::{forward_ready, Payload, Service, ServiceRequest, ServiceResponse, Transform};
+use actix_web::guard::{Guard, GuardContext};
+use actix_web::http::StatusCode;
+use actix_web::http::header::{AUTHORIZATION, WWW_AUTHENTICATE};
+use actix_web::{FromRequest, HttpMessage, HttpRequest, HttpResponse, ResponseError};
+use serde::de::DeserializeOwned;
+use serde_json::value::Value;
+
+use jsonwebtokens::Verifier;
+
+use crate::{Denial, Error, KeySet, Policy};
+use crate::http_util::{has_scope, parse_bearer};
+
+/// The verified claims of a request's token, as added to the request
+/// extensions by the [CognitoAuth] middleware
+#[derive(Debug, Clone)]
+pub struct VerifiedClaims(pub Value);
+
+/// Extracts the claims that were verified by the [CognitoAuth] middleware
+///
+/// The claims are deserialized into `T`, which can be `serde_json::Value`,
+/// `IdTokenClaims`, `AccessTokenClaims` or any other type that implements
+/// `Deserialize`.
+#[derive(Debug, Clone)]
+pub struct CognitoClaims<T = Value>(pub T);
+
+impl<T: DeserializeOwned> FromRequest for CognitoClaims<T> {
+    type Error = AuthError;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        let result = match req.extensions().get::<VerifiedClaims>() {
+            Some(VerifiedClaims(claims)) => crate::deserialize_claims(claims.clone())
+                .map(CognitoClaims)
+                .map_err(AuthError::InvalidToken),
+            None => Err(AuthError::MissingMiddleware),
+        };
+        ready(result)
+    }
+}
+
+/// Why a request was rejected by the [CognitoAuth] middleware or the
+/// [CognitoClaims] extractor
+#[derive(Debug)]
+pub enum AuthError {
+    /// The request had no `Authorization: Bearer` token
+    MissingToken,
+
+    /// The token couldn't be verified
+    InvalidToken(Error),
+
+    /// The token was valid but didn't have a required scope
+    MissingScope(String),
+
+    /// The token was valid but its claims were denied by the middleware's
+    /// [Policy]
+    PolicyDenied(Denial),
+
+    /// No verified claims were found, because there's no [CognitoAuth]
+    /// middleware for the route
+    MissingMiddleware,
+}
+
+impl std::fmt::Display for AuthError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            AuthError::MissingToken => write!(f, "Missing bearer token"),
+            AuthError::InvalidToken(e) => e.fmt(f),
+            AuthError::MissingScope(scope) => write!(f, "Token is missing scope \"{}\"", scope),
+            AuthError::PolicyDenied(denial) => denial.fmt(f),
+            AuthError::MissingMiddleware => write!(f, "No CognitoAuth middleware for route"),
+        }
+    }
+}
+
+impl ResponseError for AuthError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            AuthError::MissingToken => StatusCode::UNAUTHORIZED,
+            AuthError::InvalidToken(e) => e.http_status(),
+            AuthError::MissingScope(_) | AuthError::PolicyDenied(_) => StatusCode::FORBIDDEN,
+            AuthError::MissingMiddleware => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        let mut response = HttpResponse::build(self.status_code());
+        if self.status_code() == StatusCode::UNAUTHORIZED {
+            response.insert_header((WWW_AUTHENTICATE, "Bearer"));
+        }
+        response.body(self.to_string())
+    }
+}
+
+/// A route guard that only matches requests whose verified claims include the
+/// given scope
+///
+/// This depends on the [CognitoAuth] middleware having already verified the
+/// request's token. Unlike `CognitoAuth::require_scope()`, requests without
+/// the scope won't match the route instead of being rejected.
+#[derive(Debug, Clone)]
+pub struct ScopeGuard(String);
+
+impl ScopeGuard {
+    pub fn new(scope: impl Into<String>) -> Self {
+        ScopeGuard(scope.into())
+    }
+}
+
+impl Guard for ScopeGuard {
+    fn check(&self, ctx: &GuardContext<'_>) -> bool {
+        match ctx.req_data().get::<VerifiedClaims>() {
+            Some(VerifiedClaims(claims)) => has_scope(claims, &self.0),
+            None => false,
+        }
+    }
+}
+
+/// Middleware that verifies bearer tokens with a KeySet and Verifier (see the
+/// [module documentation](self))
+#[derive(Clone)]
+pub struct CognitoAuth {
+    keyset: KeySet,
+    verifier: Arc<Verifier>,
+    required_scopes: Arc<Vec<String>>,
+    policy: Arc<Policy>,
+}
+
+impl CognitoAuth {
+    /// Constructs middleware that verifies tokens with the given KeySet and Verifier
+    pub fn new(keyset: KeySet, verifier: impl Into<Arc<Verifier>>) -> Self {
+        CognitoAuth {
+            keyset,
+            verifier: verifier.into(),
+            required_scopes: Arc::new(Vec::new()),
+            policy: Arc::new(Policy::new()),
+        }
+    }
+
+    /// Requires that the token's `scope` claim includes the given scope,
+    /// otherwise requests are rejected with a 403 status
+    pub fn require_scope(mut self, scope: impl Into<String>) -> Self {
+        Arc::make_mut(&mut self.required_scopes).push(scope.into());
+        self
+    }
+
+    /// Requires that the token's claims are allowed by the given policy,
+    /// otherwise requests are rejected with a 403 status
+    pub fn policy(mut self, policy: Policy) -> Self {
+        self.policy = Arc::new(policy);
+        self
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for CognitoAuth
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = actix_web::Error;
+    type Transform = CognitoAuthMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(CognitoAuthMiddleware {
+            service: Rc::new(service),
+            auth: self.clone(),
+        }))
+    }
+}
+
+/// The service created by the [CognitoAuth] middleware
+pub struct CognitoAuthMiddleware<S> {
+    service: Rc<S>,
+    auth: CognitoAuth,
+}
+
+impl<S, B> Service<ServiceRequest> for CognitoAuthMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = actix_web::Error;
+    #[allow(clippy::type_complexity)]
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>>>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let service = self.service.clone();
+        let auth = self.auth.clone();
+
+        Box::pin(async move {
+            let token = req.headers().get(AUTHORIZATION)
+                .and_then(|value| value.to_str().ok())
+                .and_then(parse_bearer)
+                .map(String::from);
+
+            let result = match token {
+                Some(token) => auth.keyset.verify(&token, &auth.verifier).await
+                    .map_err(AuthError::InvalidToken),
+                None => Err(AuthError::MissingToken),
+            };
+            let result = result.and_then(|claims| {
+                match auth.required_scopes.iter().find(|scope| !has_scope(&claims, scope)) {
+                    Some(scope) => Err(AuthError::MissingScope(scope.clone())),
+                    None => Ok(claims),
+                }
+            });
+            let result = result.and_then(|claims| {
+                auth.policy.check(&claims).map_err(AuthError::PolicyDenied)?;
+                Ok(claims)
+            });
+
+            match result {
+                Ok(claims) => {
+                    req.extensions_mut().insert(VerifiedClaims(claims));
+                    let res = service.call(req).await?;
+                    Ok(res.map_into_left_body())
+                }
+                Err(e) => {
+                    let response = e.error_response();
+                    Ok(req.into_response(response).map_into_right_body())
+                }
+            }
+        })
+    }
+}
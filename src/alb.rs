@@ -0,0 +1,397 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Duration;
+
+use reqwest::StatusCode;
+use serde_json::value::Value;
+use web_time::Instant;
+
+use jsonwebtokens as jwt;
+use jwt::{raw, Algorithm, AlgorithmID, Verifier};
+
+use crate::{Clock, Error, ErrorDetails, JwksFetcher, JwksRequest, Partition, ReqwestFetcher, SystemClock};
+use crate::{DEFAULT_MIN_JWKS_FETCH_INTERVAL, DEFAULT_UNKNOWN_KID_TTL, MAX_UNKNOWN_KIDS};
+use crate::validate::is_valid_region;
+
+/// Verifies the `x-amzn-oidc-data` headers that an Application Load Balancer
+/// adds to requests after authenticating users with Cognito
+///
+/// These tokens are signed with ES256 keys that aren't published as a JWKS key
+/// set. Instead, each key is fetched as a PEM from a regional ELB endpoint
+/// according to the token's `kid`. Since a key never changes for a given
+/// `kid`, fetched keys are cached indefinitely.
+///
+/// Since any token can name a new `kid`, fetches are limited like a
+/// [KeySet](crate::KeySet)'s: a `kid` the endpoint has no key for is
+/// remembered for the unknown `kid` TTL and rejected with `UnknownKeyId`,
+/// and after any failed fetch, further fetches are throttled for the minimum
+/// key fetch interval. Keys are fetched one at a time, so concurrent requests
+/// for a new `kid` share a single fetch.
+///
+/// ALB tokens are also non-standard in that their base64 encoding may include
+/// `=` padding, which is accepted here.
+///
+/// ```no_run
+/// # use jsonwebtokens_cognito::{AlbKeySet, Error};
+/// # use jsonwebtokens::Verifier;
+/// # #[async_std::main]
+/// # async fn main() -> Result<(), Error> {
+/// let mut keyset = AlbKeySet::new("eu-west-1")?;
+/// keyset.set_expected_signer(
+///     "arn:aws:elasticloadbalancing:eu-west-1:123456789012:loadbalancer/app/my-alb/0123456789abcdef");
+/// let verifier = Verifier::create()
+///     .issuer("https://cognito-idp.eu-west-1.amazonaws.com/eu-west-1_AbCd1234")
+///     .build()?;
+///
+/// # let header_value = "";
+/// let claims = keyset.verify(header_value, &verifier).await?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct AlbKeySet {
+    region: String,
+    keys_url: String,
+    signer: Option<String>,
+    keys: Arc<RwLock<HashMap<String, Arc<Algorithm>>>>,
+    fetcher: Arc<dyn JwksFetcher>,
+    fetch_state: Arc<Mutex<FetchState>>,
+    fetch_lock: Arc<futures_util::lock::Mutex<()>>,
+    clock: Arc<dyn Clock>,
+    min_key_fetch_interval: Duration,
+    unknown_kid_ttl: Duration,
+}
+
+#[derive(Debug, Default)]
+struct FetchState {
+    // When each kid that the endpoint had no key for was looked up
+    unknown_kids: HashMap<String, Instant>,
+    last_failure: Option<Instant>,
+}
+
+impl AlbKeySet {
+    /// Constructs a key set for verifying tokens from load balancers in the
+    /// given region
+    pub fn new(region: impl Into<String>) -> Result<Self, Error> {
+        let region = region.into();
+        if !is_valid_region(&region) {
+            return Err(Error::InvalidConfiguration(ErrorDetails::new(
+                format!("Invalid AWS region \"{}\"", region))));
+        }
+
+        let partition = Partition::for_region(&region);
+        let keys_url = if partition == Partition::AwsUsGov {
+            format!("https://s3-{}.{}/aws-elb-public-keys-prod-{}", region, partition.dns_suffix(), region)
+        } else {
+            format!("https://public-keys.auth.elb.{}.{}", region, partition.dns_suffix())
+        };
+
+        Ok(AlbKeySet {
+            region,
+            keys_url,
+            signer: None,
+            keys: Arc::new(RwLock::new(HashMap::new())),
+            fetcher: Arc::new(ReqwestFetcher::new()),
+            fetch_state: Default::default(),
+            fetch_lock: Default::default(),
+            clock: Arc::new(SystemClock),
+            min_key_fetch_interval: DEFAULT_MIN_JWKS_FETCH_INTERVAL,
+            unknown_kid_ttl: DEFAULT_UNKNOWN_KID_TTL,
+        })
+    }
+
+    /// The AWS region of the load balancers
+    pub fn region(&self) -> &str {
+        &self.region
+    }
+
+    /// Requires the `signer` header of tokens to match the ARN of a specific
+    /// load balancer
+    ///
+    /// This is recommended, to ensure that tokens can't be signed by another
+    /// load balancer in the same region.
+    pub fn set_expected_signer(&mut self, alb_arn: impl Into<String>) {
+        self.signer = Some(alb_arn.into());
+    }
+
+    /// Sets the fetcher used to get the public keys
+    ///
+    /// The fetcher is sent requests for PEM keys instead of JWKS key sets, but
+    /// the requests are otherwise the same.
+    pub fn set_key_fetcher(&mut self, fetcher: impl JwksFetcher + 'static) {
+        self.fetcher = Arc::new(fetcher);
+    }
+
+    /// Sets how long key fetches are throttled for after a failed fetch
+    /// (default 60 seconds)
+    pub fn set_min_key_fetch_interval(&mut self, interval: Duration) {
+        self.min_key_fetch_interval = interval;
+    }
+
+    /// Sets how long a `kid` that the endpoint had no key for is rejected
+    /// without fetching it again (default 5 minutes)
+    ///
+    /// A TTL of zero disables this, though fetches are still throttled after
+    /// the endpoint fails to find a key.
+    pub fn set_unknown_kid_ttl(&mut self, ttl: Duration) {
+        self.unknown_kid_ttl = ttl;
+    }
+
+    /// Sets the clock used for throttling key fetches (see [Clock])
+    pub fn set_clock(&mut self, clock: impl Clock + 'static) {
+        self.clock = Arc::new(clock);
+    }
+
+    fn cached_key(&self, kid: &str) -> Option<Arc<Algorithm>> {
+        let readable_keys = self.keys.read().unwrap();
+        readable_keys.get(kid).cloned()
+    }
+
+    // Checks whether a key may be fetched for the given kid
+    fn check_fetch(&self, kid: &str) -> Result<(), Error> {
+        let fetch_state = self.fetch_state.lock().unwrap();
+        let now = self.clock.now();
+        if let Some(last_looked_for) = fetch_state.unknown_kids.get(kid) {
+            if now.duration_since(*last_looked_for) < self.unknown_kid_ttl {
+                return Err(Error::UnknownKeyId(kid.to_string()));
+            }
+        }
+        if let Some(last_failure) = fetch_state.last_failure {
+            let elapsed = now.duration_since(last_failure);
+            if elapsed < self.min_key_fetch_interval {
+                return Err(Error::Throttled { retry_after: self.min_key_fetch_interval - elapsed });
+            }
+        }
+        Ok(())
+    }
+
+    fn record_failure(&self, kid: &str, error: &Error) {
+        let mut fetch_state = self.fetch_state.lock().unwrap();
+        let now = self.clock.now();
+        fetch_state.last_failure = Some(now);
+        if matches!(error, Error::UnknownKeyId(_)) && self.unknown_kid_ttl > Duration::from_secs(0) {
+            let ttl = self.unknown_kid_ttl;
+            fetch_state.unknown_kids.retain(|_, last_looked_for| now.duration_since(*last_looked_for) < ttl);
+            if fetch_state.unknown_kids.len() >= MAX_UNKNOWN_KIDS {
+                fetch_state.unknown_kids.clear();
+            }
+            fetch_state.unknown_kids.insert(kid.to_string(), now);
+        }
+    }
+
+    async fn fetch_key(&self, kid: &str) -> Result<Arc<Algorithm>, Error> {
+        self.check_fetch(kid)?;
+        let _fetching = self.fetch_lock.lock().await;
+        // The key may have been fetched, or the fetch failed, while waiting
+        if let Some(algorithm) = self.cached_key(kid) {
+            return Ok(algorithm);
+        }
+        self.check_fetch(kid)?;
+
+        let result = self.request_key(kid).await;
+        if let Err(e) = &result {
+            self.record_failure(kid, e);
+        }
+        result
+    }
+
+    async fn request_key(&self, kid: &str) -> Result<Arc<Algorithm>, Error> {
+        let url = format!("{}/{}", self.keys_url, kid);
+        let response = self.fetcher.fetch_jwks(&JwksRequest { url: &url, if_none_match: None }).await?;
+        // The S3 endpoints in GovCloud respond with 403 for missing keys
+        if response.status == StatusCode::NOT_FOUND || response.status == StatusCode::FORBIDDEN {
+            return Err(Error::UnknownKeyId(kid.to_string()));
+        }
+        if !response.status.is_success() {
+            return Err(Error::JwksHttpStatus(response.status, crate::body_snippet(&response.body)));
+        }
+
+        let mut algorithm = Algorithm::new_ecdsa_pem_verifier(AlgorithmID::ES256, &response.body)?;
+        algorithm.set_kid(kid);
+        let algorithm = Arc::new(algorithm);
+
+        let mut writeable_keys = self.keys.write().unwrap();
+        writeable_keys.insert(kid.to_string(), algorithm.clone());
+        Ok(algorithm)
+    }
+
+    /// Verifies an `x-amzn-oidc-data` header value, fetching its key if it
+    /// isn't already cached
+    pub async fn verify(
+        &self,
+        token: &str,
+        verifier: &Verifier
+    ) -> Result<Value, Error> {
+        let kid = self.header_kid(token)?;
+        let algorithm = match self.cached_key(&kid) {
+            Some(algorithm) => algorithm,
+            None => self.fetch_key(&kid).await?,
+        };
+
+        self.verify_with_algorithm(token, &algorithm, verifier)
+    }
+
+    /// Verifies an `x-amzn-oidc-data` header value, without fetching keys
+    ///
+    /// Returns `Error::CacheMiss` if the token's key hasn't been fetched yet.
+    pub fn try_verify(
+        &self,
+        token: &str,
+        verifier: &Verifier
+    ) -> Result<Value, Error> {
+        let kid = self.header_kid(token)?;
+        let algorithm = match self.cached_key(&kid) {
+            Some(algorithm) => algorithm,
+            None => {
+                let mut cached_kids: Vec<String> = self.keys.read().unwrap().keys().cloned().collect();
+                cached_kids.sort();
+                return Err(Error::CacheMiss { kid: Some(kid), last_updated: None, cached_kids, retry_after: None });
+            }
+        };
+
+        self.verify_with_algorithm(token, &algorithm, verifier)
+    }
+
+    // Checks the token's signer and returns its kid
+    fn header_kid(&self, token: &str) -> Result<String, Error> {
+        let raw::TokenSlices { header, .. } = raw::split_token(token)?;
+        let header = raw::decode_json_token_slice(header.trim_end_matches('='))?;
+
+        if let Some(expected) = &self.signer {
+            if header.get("signer").and_then(Value::as_str) != Some(expected.as_str()) {
+                return Err(Error::MalformedToken(ErrorDetails::new("Unexpected 'signer' for ALB token")));
+            }
+        }
+
+        // The kid is used to build a URL so we only allow the characters of the
+        // UUIDs used by ALB
+        match header.get("kid").and_then(Value::as_str) {
+            Some(kid) if !kid.is_empty() && kid.chars().all(|c| c.is_ascii_alphanumeric() || c == '-') => {
+                Ok(kid.to_string())
+            }
+            Some(_) => Err(Error::MalformedToken(ErrorDetails::new("Invalid 'kid' for ALB token"))),
+            None => Err(Error::NoKeyID()),
+        }
+    }
+
+    fn verify_with_algorithm(
+        &self,
+        token: &str,
+        algorithm: &Algorithm,
+        verifier: &Verifier
+    ) -> Result<Value, Error> {
+        // The signature covers the message as sent, including any padding
+        let raw::TokenSlices { message, signature, header, claims } = raw::split_token(token)?;
+        let header = raw::decode_json_token_slice(header.trim_end_matches('='))?;
+        raw::verify_signature_only(&header, message, signature.trim_end_matches('='), algorithm)?;
+
+        let claims = raw::decode_json_token_slice(claims.trim_end_matches('='))?;
+        crate::verify_claims(verifier, &claims, crate::unix_time_now()?)?;
+        Ok(claims)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use async_trait::async_trait;
+    use serde_json::json;
+
+    use super::*;
+    use crate::{JwksResponse, ManualClock, unix_time_now};
+    use crate::test_util::*;
+
+    #[test]
+    fn alb_oidc_data() {
+        #[derive(Debug)]
+        struct PemFetcher;
+
+        #[async_trait]
+        impl JwksFetcher for PemFetcher {
+            async fn fetch_jwks(&self, request: &JwksRequest<'_>) -> Result<JwksResponse, Error> {
+                assert_eq!(request.url, "https://public-keys.auth.elb.eu-west-1.amazonaws.com/alb-key-0");
+                Ok(JwksResponse::new(StatusCode::OK, &include_bytes!("../testdata/ec-public.pem")[..]))
+            }
+        }
+
+        let signer_arn = "arn:aws:elasticloadbalancing:eu-west-1:123456789012:loadbalancer/app/my-alb/0123456789abcdef";
+        let mut keyset = AlbKeySet::new("eu-west-1").unwrap();
+        keyset.set_expected_signer(signer_arn);
+        keyset.set_key_fetcher(PemFetcher);
+        let verifier = Verifier::create().build().unwrap();
+
+        // ALB pads the base64 encoded segments
+        let encode = |value: Value| base64::Engine::encode(&base64::engine::general_purpose::URL_SAFE,
+                                                           value.to_string());
+        let header = encode(json!({ "alg": "ES256", "kid": "alb-key-0", "signer": signer_arn }));
+        let claims = encode(json!({ "sub": "user", "exp": unix_time_now().unwrap() + 60 }));
+        let message = format!("{}.{}", header, claims);
+        let signer = Algorithm::new_ecdsa_pem_signer(AlgorithmID::ES256,
+                                                     include_bytes!("../testdata/ec-private.pem")).unwrap();
+        let token = format!("{}.{}", message, signer.sign(&message).unwrap());
+
+        assert!(matches!(keyset.try_verify(&token, &verifier), Err(Error::CacheMiss { .. })));
+        let claims = async_std::task::block_on(keyset.verify(&token, &verifier)).unwrap();
+        assert_eq!(claims["sub"], "user");
+        assert!(keyset.try_verify(&token, &verifier).is_ok());
+
+        keyset.set_expected_signer("arn:aws:elasticloadbalancing:eu-west-1:123456789012:loadbalancer/app/other/0");
+        assert!(keyset.try_verify(&token, &verifier).is_err());
+    }
+
+    #[test]
+    fn alb_key_fetch_throttling() {
+        // Yields before responding, so concurrent fetches overlap
+        #[derive(Debug)]
+        struct SlowFetcher(TestFetcher);
+
+        #[async_trait]
+        impl JwksFetcher for SlowFetcher {
+            async fn fetch_jwks(&self, request: &JwksRequest<'_>) -> Result<JwksResponse, Error> {
+                futures_timer::Delay::new(Duration::from_millis(10)).await;
+                self.0.fetch_jwks(request).await
+            }
+        }
+
+        let fetcher = TestFetcher::new(vec![
+            Ok(JwksResponse::new(StatusCode::OK, &include_bytes!("../testdata/ec-public.pem")[..])),
+            Ok(JwksResponse::new(StatusCode::NOT_FOUND, "")),
+            Ok(JwksResponse::new(StatusCode::OK, &include_bytes!("../testdata/ec-public.pem")[..])),
+        ]);
+        let clock = ManualClock::new();
+        let mut keyset = AlbKeySet::new("eu-west-1").unwrap();
+        keyset.set_key_fetcher(SlowFetcher(fetcher.clone()));
+        keyset.set_clock(clock.clone());
+        let verifier = Verifier::create().build().unwrap();
+
+        let signer = Algorithm::new_ecdsa_pem_signer(AlgorithmID::ES256,
+                                                     include_bytes!("../testdata/ec-private.pem")).unwrap();
+        let token = |kid: &str| {
+            let encode = |value: Value| base64::Engine::encode(&base64::engine::general_purpose::URL_SAFE,
+                                                               value.to_string());
+            let message = format!("{}.{}", encode(json!({ "alg": "ES256", "kid": kid })),
+                                  encode(json!({ "sub": "user", "exp": unix_time_now().unwrap() + 60 })));
+            format!("{}.{}", message, signer.sign(&message).unwrap())
+        };
+
+        // Concurrent requests for a new kid share one fetch
+        let (token_0, token_1) = (token("alb-key-0"), token("alb-key-1"));
+        let (first, second) = async_std::task::block_on(futures_util::future::join(
+            keyset.verify(&token_0, &verifier), keyset.verify(&token_0, &verifier)));
+        assert!(first.is_ok() && second.is_ok());
+        assert_eq!(fetcher.fetch_count(), 1);
+
+        // A kid without a key is remembered, and other fetches are throttled
+        let verify = |token: &str| async_std::task::block_on(keyset.verify(token, &verifier));
+        assert!(matches!(verify(&token_1), Err(Error::UnknownKeyId(kid)) if kid == "alb-key-1"));
+        assert!(matches!(verify(&token_1), Err(Error::UnknownKeyId(_))));
+        assert!(matches!(verify(&token("alb-key-2")), Err(Error::Throttled { .. })));
+        assert_eq!(fetcher.fetch_count(), 2);
+
+        clock.advance(DEFAULT_MIN_JWKS_FETCH_INTERVAL);
+        assert!(matches!(verify(&token_1), Err(Error::UnknownKeyId(_))));
+        assert!(verify(&token("alb-key-2")).is_ok());
+        assert_eq!(fetcher.fetch_count(), 3);
+        assert!(verify(&token_0).is_ok());
+        assert_eq!(fetcher.fetch_count(), 3);
+    }
+}
@@ -0,0 +1,233 @@
+use std::fmt::Debug;
+
+use serde::Serialize;
+use serde_json::value::Value;
+
+use crate::{decode_unverified, unix_time_now, Error, VerifiedTokenData};
+
+/// Records an [AuditEvent] for each token verification attempt
+///
+/// An `AuditSink` set via `KeySet::set_audit_sink()` is called after every
+/// `verify()` and `try_verify()` (and the APIs based on them, like
+/// `verify_into()`), for keeping a record of verification outcomes separately
+/// from debug logging. By default nothing is recorded.
+///
+/// This is called while verifying tokens so it shouldn't block, e.g. it should
+/// queue events to be written elsewhere. With the `tracing` feature,
+/// [TracingAuditSink] emits each event as a `tracing` event.
+pub trait AuditSink: Debug + Send + Sync {
+    /// Records the outcome of a verification attempt
+    fn record(&self, event: &AuditEvent);
+}
+
+impl<T: AuditSink + ?Sized> AuditSink for std::sync::Arc<T> {
+    fn record(&self, event: &AuditEvent) {
+        (**self).record(event)
+    }
+}
+
+/// Whether a token was verified or rejected
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditOutcome {
+    Verified,
+    Rejected,
+}
+
+/// A token verification attempt, as recorded by an [AuditSink]
+///
+/// The token itself isn't included. For rejected tokens the `sub`, `client_id`
+/// and `iss` are read from the token without verifying it, so they may have
+/// been forged.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[non_exhaustive]
+pub struct AuditEvent {
+    /// When the token was verified (unix epoch timestamp)
+    pub timestamp: u64,
+
+    pub outcome: AuditOutcome,
+
+    /// The error code of a rejected token (see `Error::code()`)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error_code: Option<&'static str>,
+
+    /// Why a token was rejected
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+
+    /// The key ID (`kid`) of the key that verified the token, or from the token
+    /// header of a rejected token
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub kid: Option<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sub: Option<String>,
+
+    /// The access token's `client_id`, or the ID token's `aud`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub client_id: Option<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub iss: Option<String>,
+
+    /// The token's unique identifier
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub jti: Option<String>,
+}
+
+impl AuditEvent {
+    pub(crate) fn new(token: &str, result: &Result<VerifiedTokenData, Error>) -> Self {
+        let timestamp = unix_time_now().unwrap_or(0);
+        match result {
+            Ok(data) => AuditEvent {
+                timestamp,
+                outcome: AuditOutcome::Verified,
+                error_code: None,
+                reason: None,
+                kid: Some(data.kid.clone()),
+                ..AuditEvent::from_claims(timestamp, &data.claims)
+            },
+            Err(e) => {
+                // An oversized token isn't decoded, as for verification
+                let unverified = match e {
+                    Error::TokenTooLarge { .. } => None,
+                    _ => decode_unverified(token).ok(),
+                };
+                let claims = unverified.as_ref().map(|data| &data.claims).unwrap_or(&Value::Null);
+                let kid = unverified.as_ref().and_then(|data| data.header.get("kid")).and_then(Value::as_str);
+                AuditEvent {
+                    outcome: AuditOutcome::Rejected,
+                    error_code: Some(e.code()),
+                    reason: Some(e.to_string()),
+                    kid: kid.map(String::from),
+                    ..AuditEvent::from_claims(timestamp, claims)
+                }
+            }
+        }
+    }
+
+    fn from_claims(timestamp: u64, claims: &Value) -> Self {
+        let string_claim = |claim: &str| claims.get(claim).and_then(Value::as_str).map(String::from);
+        AuditEvent {
+            timestamp,
+            outcome: AuditOutcome::Verified,
+            error_code: None,
+            reason: None,
+            kid: None,
+            sub: string_claim("sub"),
+            client_id: string_claim("client_id").or_else(|| string_claim("aud")),
+            iss: string_claim("iss"),
+            jti: string_claim("jti"),
+        }
+    }
+}
+
+/// An [AuditSink] that emits each event as a `tracing` event, with the
+/// `jsonwebtokens_cognito::audit` target
+///
+/// Verified tokens are recorded at the info level and rejected tokens at the
+/// warn level.
+///
+/// _Requires the `tracing` feature_
+#[cfg(feature = "tracing")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TracingAuditSink;
+
+#[cfg(feature = "tracing")]
+impl AuditSink for TracingAuditSink {
+    fn record(&self, event: &AuditEvent) {
+        match event.outcome {
+            AuditOutcome::Verified => tracing::info!(
+                target: "jsonwebtokens_cognito::audit",
+                kid = event.kid.as_deref(),
+                sub = event.sub.as_deref(),
+                client_id = event.client_id.as_deref(),
+                iss = event.iss.as_deref(),
+                jti = event.jti.as_deref(),
+                "Token verified"
+            ),
+            AuditOutcome::Rejected => tracing::warn!(
+                target: "jsonwebtokens_cognito::audit",
+                error_code = event.error_code,
+                reason = event.reason.as_deref(),
+                kid = event.kid.as_deref(),
+                sub = event.sub.as_deref(),
+                client_id = event.client_id.as_deref(),
+                iss = event.iss.as_deref(),
+                jti = event.jti.as_deref(),
+                "Token rejected"
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    use serde_json::json;
+
+    use super::*;
+    use crate::{KeySet, DEFAULT_MAX_TOKEN_LEN};
+    use crate::test_util::*;
+
+    #[test]
+    fn audit_sink() {
+        #[derive(Debug, Default)]
+        struct RecordingSink(Mutex<Vec<AuditEvent>>);
+
+        impl AuditSink for RecordingSink {
+            fn record(&self, event: &AuditEvent) {
+                self.0.lock().unwrap().push(event.clone());
+            }
+        }
+
+        let sink = Arc::new(RecordingSink::default());
+        let mut keyset = KeySet::new("eu-west-1", "eu-west-1_AbCd1234").unwrap();
+        keyset.set_audit_sink(sink.clone());
+        insert_test_key(&keyset, "test-key", "secret");
+        let verifier = keyset.new_issuer_verifier().build().unwrap();
+        let iss = "https://cognito-idp.eu-west-1.amazonaws.com/eu-west-1_AbCd1234";
+
+        let token = test_token(json!({ "alg": "HS256", "kid": "test-key" }), "secret");
+        async_std::task::block_on(keyset.verify(&token, &verifier)).unwrap();
+        let forged = test_token(json!({ "alg": "HS256", "kid": "test-key" }), "wrong-secret");
+        assert!(keyset.try_verify(&forged, &verifier).is_err());
+
+        // Cache misses aren't an outcome
+        let unknown_kid = test_token(json!({ "alg": "HS256", "kid": "unknown-key" }), "secret");
+        assert!(keyset.try_verify(&unknown_kid, &verifier).is_err());
+
+        // Oversized tokens aren't decoded for the event
+        keyset.set_max_token_len(token.len() - 1);
+        assert!(keyset.try_verify(&token, &verifier).is_err());
+        keyset.set_max_token_len(DEFAULT_MAX_TOKEN_LEN);
+
+        // verify_with_grace() only records its final outcome
+        let now = unix_time_now().unwrap();
+        let grace = Duration::from_secs(60);
+        let expired = hs256_token(&json!({ "iss": iss, "exp": now - 10 }));
+        assert!(async_std::task::block_on(keyset.verify_with_grace(&expired, &verifier, grace)).is_ok());
+        let expired = hs256_token(&json!({ "iss": iss, "exp": now - 120 }));
+        assert!(async_std::task::block_on(keyset.verify_with_grace(&expired, &verifier, grace)).is_err());
+
+        let events = sink.0.lock().unwrap();
+        assert_eq!(events.len(), 5);
+        assert_eq!(events[0].outcome, AuditOutcome::Verified);
+        assert_eq!(events[0].kid.as_deref(), Some("test-key"));
+        assert_eq!(events[0].iss.as_deref(), Some(iss));
+        assert_eq!(events[0].error_code, None);
+        assert_eq!(events[1].outcome, AuditOutcome::Rejected);
+        assert_eq!(events[1].error_code, Some("invalid_signature"));
+        assert_eq!(events[1].kid.as_deref(), Some("test-key"));
+        assert_eq!(events[1].iss.as_deref(), Some(iss));
+        assert_eq!(events[2].error_code, Some("token_too_large"));
+        assert_eq!(events[2].kid, None);
+        assert_eq!(events[2].iss, None);
+        assert_eq!(events[3].outcome, AuditOutcome::Verified);
+        assert_eq!(events[3].kid.as_deref(), Some("test-key"));
+        assert_eq!(events[4].outcome, AuditOutcome::Rejected);
+        assert_eq!(events[4].error_code, Some("token_expired"));
+    }
+}
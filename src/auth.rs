@@ -0,0 +1,460 @@
+//! A client for authenticating with a username and password via Cognito's
+//! `USER_SRP_AUTH` flow
+//!
+//! [SrpClient] performs the Secure Remote Password handshake with the user
+//! pool's `InitiateAuth` and `RespondToAuthChallenge` APIs, so the password is
+//! never sent to Cognito. This is useful for CLIs and backend service accounts
+//! that can't use the hosted UI. The returned [AuthTokens] can be verified with
+//! a KeySet like any other token:
+//!
+//! ```no_run
+//! # use jsonwebtokens_cognito::KeySet;
+//! # use jsonwebtokens_cognito::auth::SrpClient;
+//! # #[async_std::main]
+//! # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! let client = SrpClient::new("eu-west-1_AbCd1234", "client-id-0")?;
+//! let tokens = client.authenticate("alice", "correct horse battery staple").await?;
+//!
+//! let keyset = KeySet::new("eu-west-1", "eu-west-1_AbCd1234")?;
+//! let verifier = keyset.new_access_token_verifier(&["client-id-0"]).build()?;
+//! let claims = keyset.verify(&tokens.access_token, &verifier).await?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Challenges other than the password verifier (such as `NEW_PASSWORD_REQUIRED`
+//! or MFA challenges) aren't supported and fail with an
+//! `Error::UnsupportedChallenge`.
+//!
+//! _Requires the `auth` feature_
+
+use std::collections::HashMap;
+use std::fmt;
+
+use num_bigint::BigUint;
+use ring::rand::{SecureRandom, SystemRandom};
+use ring::{digest, hkdf, hmac};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use serde_json::value::Value;
+use web_time::SystemTime;
+
+use crate::{Error, ErrorDetails, Partition};
+
+pub use crate::secret_hash::secret_hash;
+
+// The 3072 bit SRP group from RFC 5054 (RFC 3526's 3072 bit MODP prime), with
+// a generator of 2
+const N_HEX: &str = "\
+    FFFFFFFFFFFFFFFFC90FDAA22168C234C4C6628B80DC1CD129024E088A67CC74\
+    020BBEA63B139B22514A08798E3404DDEF9519B3CD3A431B302B0A6DF25F1437\
+    4FE1356D6D51C245E485B576625E7EC6F44C42E9A637ED6B0BFF5CB6F406B7ED\
+    EE386BFB5A899FA5AE9F24117C4B1FE649286651ECE45B3DC2007CB8A163BF05\
+    98DA48361C55D39A69163FA8FD24CF5F83655D23DCA3AD961C62F356208552BB\
+    9ED529077096966D670C354E4ABC9804F1746C08CA18217C32905E462E36CE3B\
+    E39E772C180E86039B2783A2EC07A28FB5C55DF06F4C52C9DE2BCBF695581718\
+    3995497CEA956AE515D2261898FA051015728E5A8AAAC42DAD33170D04507A33\
+    A85521ABDF1CBA64ECFB850458DBEF0A8AEA71575D060C7DB3970F85A6E1E4C7\
+    ABF5AE8CDB0933D71E8C94E04A25619DCEE3D2261AD2EE6BF12FFA06D98A0864\
+    D87602733EC86A64521F2B18177B200CBBE117577A615D6C770988C0BAD946E2\
+    08E24FA074E5AB3143DB5BFCE0FD108E4B82D120A93AD2CAFFFFFFFFFFFFFFFF";
+const G: u32 = 2;
+const DERIVED_KEY_INFO: &[u8] = b"Caldera Derived Key";
+
+/// The tokens returned by a successful authentication
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct AuthTokens {
+    pub access_token: String,
+    pub id_token: String,
+
+    #[serde(default)]
+    pub refresh_token: Option<String>,
+
+    /// Always "Bearer"
+    pub token_type: String,
+
+    /// How many seconds until the access and ID tokens expire
+    pub expires_in: u64,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct AuthResponse {
+    #[serde(default)]
+    challenge_name: Option<String>,
+    #[serde(default)]
+    challenge_parameters: HashMap<String, String>,
+    #[serde(default)]
+    session: Option<String>,
+    #[serde(default)]
+    authentication_result: Option<AuthTokens>,
+}
+
+/// A client for authenticating users of a Cognito user pool with the
+/// `USER_SRP_AUTH` flow
+///
+/// The app client must allow the `ALLOW_USER_SRP_AUTH` authentication flow.
+#[derive(Clone)]
+pub struct SrpClient {
+    pool_id: String,
+    client_id: String,
+    client_secret: Option<String>,
+    endpoint: String,
+    client: reqwest::Client,
+}
+
+impl fmt::Debug for SrpClient {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SrpClient")
+            .field("pool_id", &self.pool_id)
+            .field("client_id", &self.client_id)
+            .field("client_secret", &self.client_secret.as_ref().map(|_| "<redacted>"))
+            .finish()
+    }
+}
+
+impl SrpClient {
+    /// Constructs a client for the given user pool (like `eu-west-1_AbCd1234`)
+    /// and app client
+    ///
+    /// The region is taken from the user pool ID.
+    pub fn new(pool_id: impl Into<String>, client_id: impl Into<String>) -> Result<Self, Error> {
+        let pool_id = pool_id.into();
+        let region = match pool_id.split_once('_') {
+            Some((region, name)) if !region.is_empty() && !name.is_empty() => region,
+            _ => {
+                return Err(Error::InvalidConfiguration(ErrorDetails::new(
+                    format!("User pool ID \"{}\" should be like <region>_<id>", pool_id))));
+            }
+        };
+        let endpoint = format!("https://{}/", Partition::for_region(region).cognito_idp_host(region, false));
+
+        Ok(SrpClient {
+            pool_id,
+            client_id: client_id.into(),
+            client_secret: None,
+            endpoint,
+            client: crate::fetcher::default_client_builder().build().expect("Failed to build default HTTP client"),
+        })
+    }
+
+    /// Sets the app client's secret, for confidential clients, which is used
+    /// to compute each request's `SECRET_HASH`
+    pub fn with_client_secret(mut self, client_secret: impl Into<String>) -> Self {
+        self.client_secret = Some(client_secret.into());
+        self
+    }
+
+    /// Uses an externally configured `reqwest::Client` for requests
+    pub fn with_http_client(mut self, client: reqwest::Client) -> Self {
+        self.client = client;
+        self
+    }
+
+    /// Authenticates the user with their username (or an alias, such as their
+    /// email) and password, returning their tokens
+    ///
+    /// An incorrect username or password fails with an `Error::UserPoolApiError`
+    /// whose `error_type` is `NotAuthorizedException` (or
+    /// `UserNotFoundException`, if the user pool reveals that users don't exist).
+    pub async fn authenticate(&self, username: &str, password: &str) -> Result<AuthTokens, Error> {
+        let srp = SrpSession::new();
+
+        let mut params = json!({ "USERNAME": username, "SRP_A": srp.big_a.to_str_radix(16) });
+        if let Some(client_secret) = &self.client_secret {
+            params["SECRET_HASH"] = json!(secret_hash(username, &self.client_id, client_secret));
+        }
+        let resp = self.call("InitiateAuth", json!({
+            "AuthFlow": "USER_SRP_AUTH",
+            "ClientId": self.client_id,
+            "AuthParameters": params,
+        })).await?;
+
+        match resp.challenge_name.as_deref() {
+            Some("PASSWORD_VERIFIER") => {}
+            _ => return auth_result(resp),
+        }
+
+        let challenge = &resp.challenge_parameters;
+        let param = |name: &str| challenge.get(name).map(String::as_str).ok_or_else(|| {
+            Error::InvalidUserPoolResponse(ErrorDetails::new(format!("Password verifier challenge had no {}", name)))
+        });
+        let user_id = param("USER_ID_FOR_SRP")?;
+        let username = param("USERNAME").unwrap_or(user_id);
+        let secret_block = param("SECRET_BLOCK")?;
+        let salt = parse_hex(param("SALT")?)?;
+        let big_b = parse_hex(param("SRP_B")?)?;
+
+        let pool_name = self.pool_id.split_once('_').map(|(_, name)| name).unwrap_or_default();
+        let key = srp.password_key(pool_name, user_id, password, &salt, &big_b)?;
+        let timestamp = format_timestamp(unix_time_now());
+        let signature = password_claim_signature(&key, pool_name, user_id, secret_block, &timestamp)?;
+
+        let mut responses = json!({
+            "USERNAME": username,
+            "PASSWORD_CLAIM_SECRET_BLOCK": secret_block,
+            "PASSWORD_CLAIM_SIGNATURE": signature,
+            "TIMESTAMP": timestamp,
+        });
+        if let Some(client_secret) = &self.client_secret {
+            responses["SECRET_HASH"] = json!(secret_hash(username, &self.client_id, client_secret));
+        }
+        let mut request = json!({
+            "ChallengeName": "PASSWORD_VERIFIER",
+            "ClientId": self.client_id,
+            "ChallengeResponses": responses,
+        });
+        if let Some(session) = &resp.session {
+            request["Session"] = json!(session);
+        }
+        auth_result(self.call("RespondToAuthChallenge", request).await?)
+    }
+
+    async fn call(&self, action: &str, body: Value) -> Result<AuthResponse, Error> {
+        let resp = self.client.post(&self.endpoint)
+            .header("X-Amz-Target", format!("AWSCognitoIdentityProviderService.{}", action))
+            .header(reqwest::header::CONTENT_TYPE, "application/x-amz-json-1.1")
+            .body(body.to_string())
+            .send().await?;
+        let status = resp.status();
+        let body = resp.bytes().await?;
+        parse_response(status, &body)
+    }
+}
+
+fn auth_result(resp: AuthResponse) -> Result<AuthTokens, Error> {
+    match (resp.authentication_result, resp.challenge_name) {
+        (Some(tokens), _) => Ok(tokens),
+        (None, Some(challenge)) => Err(Error::UnsupportedChallenge { challenge, session: resp.session }),
+        (None, None) => Err(Error::InvalidUserPoolResponse(ErrorDetails::new("Authentication response had no tokens"))),
+    }
+}
+
+// Parses a user pool API response, or its error response
+fn parse_response(status: reqwest::StatusCode, body: &[u8]) -> Result<AuthResponse, Error> {
+    if status.is_success() {
+        return serde_json::from_slice(body).map_err(|e| {
+            Error::InvalidUserPoolResponse(ErrorDetails::map("Failed to parse authentication response", e))
+        });
+    }
+
+    let resp: Value = serde_json::from_slice(body).unwrap_or_default();
+    // The type may be qualified, like "com.amazonaws...#NotAuthorizedException"
+    let error_type = resp.get("__type").and_then(Value::as_str).unwrap_or_default();
+    let error_type = error_type.rsplit('#').next().unwrap_or_default();
+    let message = resp.get("message").or_else(|| resp.get("Message")).and_then(Value::as_str);
+    Err(Error::UserPoolApiError {
+        status,
+        error_type: error_type.to_string(),
+        message: message.map(String::from),
+    })
+}
+
+// The client's side of an SRP handshake
+pub(crate) struct SrpSession {
+    a: BigUint,
+    // A = g^a % N
+    pub(crate) big_a: BigUint,
+}
+
+impl SrpSession {
+    fn new() -> Self {
+        // Like Cognito's SDKs, 128 random bytes
+        let mut bytes = [0u8; 128];
+        SystemRandom::new().fill(&mut bytes).expect("Failed to generate random bytes");
+        SrpSession::with_secret(&bytes)
+    }
+
+    pub(crate) fn with_secret(bytes: &[u8]) -> Self {
+        let a = BigUint::from_bytes_be(bytes) % n();
+        let big_a = BigUint::from(G).modpow(&a, &n());
+        SrpSession { a, big_a }
+    }
+
+    // The key that proves the client knows the password, derived from the
+    // shared secret
+    pub(crate) fn password_key(
+        &self,
+        pool_name: &str,
+        user_id: &str,
+        password: &str,
+        salt: &BigUint,
+        big_b: &BigUint
+    ) -> Result<[u8; 16], Error> {
+        let n = n();
+        if (big_b % &n) == BigUint::default() {
+            return Err(Error::InvalidUserPoolResponse(ErrorDetails::new("Invalid SRP_B value")));
+        }
+
+        let u = hash_to_int(&[&pad(&self.big_a), &pad(big_b)]);
+        if u == BigUint::default() {
+            return Err(Error::InvalidUserPoolResponse(ErrorDetails::new("Invalid SRP_B value")));
+        }
+
+        let credentials = format!("{}{}:{}", pool_name, user_id, password);
+        let credentials_hash = digest::digest(&digest::SHA256, credentials.as_bytes());
+        let x = hash_to_int(&[&pad(salt), credentials_hash.as_ref()]);
+
+        // S = (B - k * g^x) ^ (a + u * x) % N
+        let g = BigUint::from(G);
+        let k = hash_to_int(&[&pad(&n), &pad(&g)]);
+        let kgx = (k * g.modpow(&x, &n)) % &n;
+        let base = ((big_b % &n) + &n - kgx) % &n;
+        let s = base.modpow(&(&self.a + &u * &x), &n);
+
+        derive_key(&s, &u)
+    }
+}
+
+// Derives the password key from the shared secret S and the scrambling
+// parameter u
+pub(crate) fn derive_key(s: &BigUint, u: &BigUint) -> Result<[u8; 16], Error> {
+    let mut key = [0u8; 16];
+    hkdf::Salt::new(hkdf::HKDF_SHA256, &pad(u))
+        .extract(&pad(s))
+        .expand(&[DERIVED_KEY_INFO], KeyLength)
+        .and_then(|okm| okm.fill(&mut key))
+        .map_err(|_| Error::InvalidUserPoolResponse(ErrorDetails::new("Failed to derive SRP key")))?;
+    Ok(key)
+}
+
+struct KeyLength;
+
+impl hkdf::KeyType for KeyLength {
+    fn len(&self) -> usize {
+        16
+    }
+}
+
+pub(crate) fn password_claim_signature(
+    key: &[u8],
+    pool_name: &str,
+    user_id: &str,
+    secret_block: &str,
+    timestamp: &str
+) -> Result<String, Error> {
+    let secret_block = base64::Engine::decode(&base64::engine::general_purpose::STANDARD, secret_block)
+        .map_err(|e| Error::InvalidUserPoolResponse(ErrorDetails::map("Invalid SECRET_BLOCK", e)))?;
+
+    let key = hmac::Key::new(hmac::HMAC_SHA256, key);
+    let mut ctx = hmac::Context::with_key(&key);
+    ctx.update(pool_name.as_bytes());
+    ctx.update(user_id.as_bytes());
+    ctx.update(&secret_block);
+    ctx.update(timestamp.as_bytes());
+    Ok(base64::Engine::encode(&base64::engine::general_purpose::STANDARD, ctx.sign()))
+}
+
+pub(crate) fn n() -> BigUint {
+    BigUint::parse_bytes(N_HEX.as_bytes(), 16).expect("Invalid SRP group")
+}
+
+fn parse_hex(hex: &str) -> Result<BigUint, Error> {
+    BigUint::parse_bytes(hex.as_bytes(), 16).ok_or_else(|| {
+        Error::InvalidUserPoolResponse(ErrorDetails::new(format!("Invalid SRP hex value \"{}\"", hex)))
+    })
+}
+
+// The big-endian bytes of a value, with a leading zero if the high bit is set
+// (so the value would be positive as a signed integer)
+pub(crate) fn pad(value: &BigUint) -> Vec<u8> {
+    let bytes = value.to_bytes_be();
+    if bytes[0] & 0x80 != 0 {
+        [&[0u8][..], &bytes].concat()
+    } else {
+        bytes
+    }
+}
+
+pub(crate) fn hash_to_int(parts: &[&[u8]]) -> BigUint {
+    let mut ctx = digest::Context::new(&digest::SHA256);
+    for part in parts {
+        ctx.update(part);
+    }
+    BigUint::from_bytes_be(ctx.finish().as_ref())
+}
+
+fn unix_time_now() -> u64 {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map(|since_epoch| since_epoch.as_secs())
+        .unwrap_or(0)
+}
+
+// Formats a unix time like "Tue Jan 5 08:03:09 UTC 2021", as Cognito expects
+pub(crate) fn format_timestamp(unix_time: u64) -> String {
+    const WEEKDAYS: [&str; 7] = ["Thu", "Fri", "Sat", "Sun", "Mon", "Tue", "Wed"];
+    const MONTHS: [&str; 12] = ["Jan", "Feb", "Mar", "Apr", "May", "Jun",
+                                "Jul", "Aug", "Sep", "Oct", "Nov", "Dec"];
+
+    let days = unix_time / 86400;
+    let secs = unix_time % 86400;
+
+    // Converts days since the epoch to a civil date (see
+    // http://howardhinnant.github.io/date_algorithms.html#civil_from_days)
+    let z = days as i64 + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    format!("{} {} {} {:02}:{:02}:{:02} UTC {}",
+            WEEKDAYS[(days % 7) as usize], MONTHS[(month - 1) as usize], day,
+            secs / 3600, secs % 3600 / 60, secs % 60, year)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn srp() {
+        use num_bigint::BigUint;
+
+        assert_eq!(format_timestamp(1609833789), "Tue Jan 5 08:03:09 UTC 2021");
+        assert_eq!(format_timestamp(951782400), "Tue Feb 29 00:00:00 UTC 2000");
+
+        // Plays the server's side of the handshake, which must derive the same key
+        let (n, g) = (n(), BigUint::from(2u32));
+        let salt = BigUint::from(0xabcdefu32);
+        let credentials_hash = ring::digest::digest(&ring::digest::SHA256, b"AbCd1234user-0:password");
+        let x = hash_to_int(&[&pad(&salt), credentials_hash.as_ref()]);
+        let verifier = g.modpow(&x, &n);
+        let k = hash_to_int(&[&pad(&n), &pad(&g)]);
+        let b = BigUint::from_bytes_be(&[3u8; 128]);
+        let big_b = (k * &verifier + g.modpow(&b, &n)) % &n;
+
+        let srp = SrpSession::with_secret(&[7u8; 128]);
+        let u = hash_to_int(&[&pad(&srp.big_a), &pad(&big_b)]);
+        let server_s = (&srp.big_a * verifier.modpow(&u, &n)).modpow(&b, &n);
+
+        let key = srp.password_key("AbCd1234", "user-0", "password", &salt, &big_b).unwrap();
+        assert_eq!(key, derive_key(&server_s, &u).unwrap());
+        assert_ne!(key, srp.password_key("AbCd1234", "user-0", "wrong", &salt, &big_b).unwrap());
+        assert!(matches!(srp.password_key("AbCd1234", "user-0", "password", &salt, &n),
+                         Err(Error::InvalidUserPoolResponse(_))));
+
+        let signature = password_claim_signature(&key, "AbCd1234", "user-0", "c2VjcmV0",
+                                                       "Tue Jan 5 08:03:09 UTC 2021").unwrap();
+        assert_eq!(signature.len(), 44);
+    }
+
+    #[test]
+    fn invalid_responses() {
+        let error = match parse_response(reqwest::StatusCode::OK, b"<html>") {
+            Err(error @ Error::InvalidUserPoolResponse(_)) => error,
+            _ => panic!("Expected an InvalidUserPoolResponse error"),
+        };
+        assert_eq!(error.code(), "invalid_user_pool_response");
+        assert_eq!(error.public_message(), "Authentication request failed");
+
+        let resp = parse_response(reqwest::StatusCode::OK, b"{}").unwrap();
+        assert!(matches!(auth_result(resp), Err(Error::InvalidUserPoolResponse(_))));
+        assert!(matches!(parse_hex("xyz"), Err(Error::InvalidUserPoolResponse(_))));
+    }
+}
@@ -0,0 +1,281 @@
+//! Integration with the [axum](https://crates.io/crates/axum) web framework
+//!
+//! A [CognitoAuthLayer] verifies the bearer token of each request with a KeySet
+//! and Verifier, rejecting requests that don't have a valid token with an
+//! [AuthRejection] response. The verified claims are added to the request
+//! extensions (as [VerifiedClaims]) and can be extracted in handlers with
+//! [CognitoClaims].
+//!
+//! ```no_run
+//! # use jsonwebtokens_cognito::{IdTokenClaims, KeySet};
+//! # use jsonwebtokens_cognito::axum::{CognitoAuthLayer, CognitoClaims};
+//! # use axum::{Router, routing::get};
+//! async fn handler(CognitoClaims(claims): CognitoClaims<IdTokenClaims>) -> String {
+//!     format!("Hello {}", claims.username)
+//! }
+//!
+//! # fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! let keyset = KeySet::new("eu-west-1", "eu-west-1_AbCd1234")?;
+//! let verifier = keyset.new_id_token_verifier(&["client-id-0"]).build()?;
+//!
+//! let app: Router = Router::new()
+//!     .route("/", get(handler))
+//!     .layer(CognitoAuthLayer::new(keyset, verifier));
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Unlike the framework-agnostic [tower](crate::tower) layer, whose rejections
+//! have an empty body, rejections here have a plain text body saying why the
+//! request was rejected, such as "Missing bearer token" or the error's message.
+//!
+//! _Requires the `axum` feature_
+
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+use ::axum::extract::FromRequestParts;
+use ::axum::http::header::WWW_AUTHENTICATE;
+use ::axum::http::request::Parts;
+use ::axum::http::{Request, StatusCode};
+use ::axum::response::{IntoResponse, Response};
+use async_trait::async_trait;
+use serde::de::DeserializeOwned;
+use serde_json::value::Value;
+use tower_layer::Layer;
+use tower_service::Service;
+
+use jsonwebtokens::Verifier;
+
+use crate::{Error, KeySet, Policy};
+use crate::tower::{bearer_token, verify_request};
+
+pub use crate::tower::VerifiedClaims;
+
+/// A tower layer that verifies bearer tokens with a KeySet and Verifier (see the
+/// [module documentation](self))
+#[derive(Clone)]
+pub struct CognitoAuthLayer {
+    keyset: KeySet,
+    verifier: Arc<Verifier>,
+    policy: Arc<Policy>,
+}
+
+impl CognitoAuthLayer {
+    /// Constructs a layer that verifies tokens with the given KeySet and Verifier
+    pub fn new(keyset: KeySet, verifier: impl Into<Arc<Verifier>>) -> Self {
+        CognitoAuthLayer {
+            keyset,
+            verifier: verifier.into(),
+            policy: Arc::new(Policy::new()),
+        }
+    }
+
+    /// Rejects requests whose verified claims are denied by the given policy,
+    /// with a `403 Forbidden` status
+    pub fn policy(mut self, policy: Policy) -> Self {
+        self.policy = Arc::new(policy);
+        self
+    }
+}
+
+impl<S> Layer<S> for CognitoAuthLayer {
+    type Service = CognitoAuthService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        CognitoAuthService {
+            inner,
+            keyset: self.keyset.clone(),
+            verifier: self.verifier.clone(),
+            policy: self.policy.clone(),
+        }
+    }
+}
+
+/// The service created by a [CognitoAuthLayer]
+#[derive(Clone)]
+pub struct CognitoAuthService<S> {
+    inner: S,
+    keyset: KeySet,
+    verifier: Arc<Verifier>,
+    policy: Arc<Policy>,
+}
+
+impl<S, B> Service<Request<B>> for CognitoAuthService<S>
+where
+    S: Service<Request<B>, Response = Response> + Clone + Send + 'static,
+    S::Future: Send + 'static,
+    B: Send + 'static,
+{
+    type Response = Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Response, S::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut req: Request<B>) -> Self::Future {
+        // Take the service that was driven to readiness, leaving a clone in its place
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+        let keyset = self.keyset.clone();
+        let verifier = self.verifier.clone();
+        let policy = self.policy.clone();
+        let token = bearer_token(req.headers()).map(String::from);
+
+        Box::pin(async move {
+            let token = match token {
+                Some(token) => token,
+                None => return Ok(AuthRejection::MissingToken.into_response()),
+            };
+
+            match verify_request(&keyset, &verifier, &policy, &token).await {
+                Ok(claims) => {
+                    req.extensions_mut().insert(VerifiedClaims(claims));
+                    inner.call(req).await
+                }
+                Err(e) => Ok(AuthRejection::InvalidToken(e).into_response()),
+            }
+        })
+    }
+}
+
+/// Extracts the claims that were verified by a [CognitoAuthLayer]
+///
+/// The claims are deserialized into `T`, which can be `serde_json::Value`,
+/// `IdTokenClaims`, `AccessTokenClaims` or any other type that implements
+/// `Deserialize`.
+#[derive(Debug, Clone)]
+pub struct CognitoClaims<T = Value>(pub T);
+
+#[async_trait]
+impl<S, T> FromRequestParts<S> for CognitoClaims<T>
+where
+    S: Send + Sync,
+    T: DeserializeOwned,
+{
+    type Rejection = AuthRejection;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        match parts.extensions.get::<VerifiedClaims>() {
+            Some(VerifiedClaims(claims)) => {
+                let claims = crate::deserialize_claims(claims.clone())
+                    .map_err(AuthRejection::InvalidToken)?;
+                Ok(CognitoClaims(claims))
+            }
+            None => Err(AuthRejection::MissingLayer),
+        }
+    }
+}
+
+/// Why a request was rejected by a [CognitoAuthLayer] or the [CognitoClaims]
+/// extractor
+#[derive(Debug)]
+pub enum AuthRejection {
+    /// The request had no `Authorization: Bearer` token
+    MissingToken,
+
+    /// The token couldn't be verified
+    InvalidToken(Error),
+
+    /// No verified claims were found, because there's no [CognitoAuthLayer] for
+    /// the route
+    MissingLayer,
+}
+
+impl IntoResponse for AuthRejection {
+    fn into_response(self) -> Response {
+        match self {
+            AuthRejection::MissingToken => error_response(StatusCode::UNAUTHORIZED, "Missing bearer token".to_string()),
+            AuthRejection::InvalidToken(e) => e.into_response(),
+            AuthRejection::MissingLayer => error_response(StatusCode::INTERNAL_SERVER_ERROR,
+                                                          "No CognitoAuthLayer for route".to_string()),
+        }
+    }
+}
+
+/// Responds with the error's `http_status()` and message
+impl IntoResponse for Error {
+    fn into_response(self) -> Response {
+        error_response(self.http_status(), self.to_string())
+    }
+}
+
+fn error_response(status: StatusCode, message: String) -> Response {
+    if status == StatusCode::UNAUTHORIZED {
+        (status, [(WWW_AUTHENTICATE, "Bearer")], message).into_response()
+    } else {
+        (status, message).into_response()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::Infallible;
+    use std::future::Ready;
+
+    use ::axum::body::HttpBody;
+    use ::axum::http::header::AUTHORIZATION;
+    use ::axum::http::HeaderValue;
+    use serde_json::json;
+
+    use super::*;
+    use crate::test_util::*;
+
+    #[derive(Clone)]
+    struct OkService;
+
+    impl Service<Request<()>> for OkService {
+        type Response = Response;
+        type Error = Infallible;
+        type Future = Ready<Result<Response, Infallible>>;
+
+        fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn call(&mut self, _req: Request<()>) -> Self::Future {
+            std::future::ready(Ok("ok".into_response()))
+        }
+    }
+
+    #[test]
+    fn auth_layer_rejections() {
+        let keyset = test_keyset();
+        let verifier = keyset.new_issuer_verifier().build().unwrap();
+        let mut service = CognitoAuthLayer::new(keyset, verifier).layer(OkService);
+        let mut call = |authorization: Option<&str>| {
+            let mut req = Request::new(());
+            if let Some(authorization) = authorization {
+                req.headers_mut().insert(AUTHORIZATION, HeaderValue::from_str(authorization).unwrap());
+            }
+            async_std::task::block_on(async {
+                let response = service.call(req).await.unwrap();
+                let (parts, mut body) = response.into_parts();
+                let mut bytes = Vec::new();
+                while let Some(chunk) = body.data().await {
+                    bytes.extend_from_slice(&chunk.unwrap());
+                }
+                (parts, String::from_utf8(bytes).unwrap())
+            })
+        };
+
+        let token = hs256_token(&json!({ "iss": TEST_ISS }));
+        let (parts, body) = call(Some(&format!("Bearer {}", token)));
+        assert_eq!(parts.status, StatusCode::OK);
+        assert_eq!(body, "ok");
+
+        let (parts, body) = call(None);
+        assert_eq!(parts.status, StatusCode::UNAUTHORIZED);
+        assert_eq!(parts.headers[WWW_AUTHENTICATE], "Bearer");
+        assert_eq!(body, "Missing bearer token");
+
+        let token = hs256_token(&json!({ "iss": "https://example.com" }));
+        let (parts, body) = call(Some(&format!("Bearer {}", token)));
+        assert_eq!(parts.status, StatusCode::UNAUTHORIZED);
+        assert_eq!(body, format!("JWT claim 'iss' was \"https://example.com\", expected \"{}\"", TEST_ISS));
+    }
+}
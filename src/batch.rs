@@ -0,0 +1,151 @@
+use serde_json::value::Value;
+
+use jsonwebtokens as jwt;
+use jwt::Verifier;
+
+use crate::{unix_time_now, Error, KeySet};
+
+impl KeySet {
+    /// Verify a batch of tokens' signatures and claims, returning the result for
+    /// each token in the same order
+    ///
+    /// This is for stream or queue consumers that receive many tokens at once.
+    /// The tokens are first verified with the cached keys and then, if any of
+    /// their keys aren't cached, the key set is fetched at most once before
+    /// verifying the remaining tokens. Otherwise tokens are handled the same as
+    /// by `verify()`.
+    ///
+    /// ```no_run
+    /// # use jsonwebtokens_cognito::KeySet;
+    /// # use async_std::prelude::*;
+    /// # #[async_std::main]
+    /// # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// # let tokens = vec!["header.payload.signature"];
+    /// let keyset = KeySet::new("eu-west-1", "eu-west-1_AbCd1234")?;
+    /// let verifier = keyset.new_access_token_verifier(&["client-id-0"]).build()?;
+    /// for result in keyset.verify_many(&tokens, &verifier).await {
+    ///     match result {
+    ///         Ok(claims) => println!("Verified {}", claims["sub"]),
+    ///         Err(e) => println!("Rejected token: {}", e),
+    ///     }
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// With the `rayon` feature the tokens are verified in parallel on rayon's
+    /// global thread pool.
+    pub async fn verify_many(
+        &self,
+        tokens: &[&str],
+        verifier: &Verifier
+    ) -> Vec<Result<Value, Error>> {
+        let all: Vec<usize> = (0..tokens.len()).collect();
+        let mut results = self.try_verify_each(tokens, &all, verifier);
+
+        let mut pending = Vec::new();
+        for (i, result) in results.iter_mut().enumerate() {
+            match result {
+                Err(Error::CacheMiss { .. }) => {
+                    match self.check_on_demand_fetch(token_kid(tokens[i]).as_deref()) {
+                        Ok(()) => pending.push(i),
+                        Err(Error::Throttled { retry_after }) if self.stale_while_revalidate => {
+                            *result = unix_time_now().and_then(|time_now| {
+                                self.verify_throttled_with_cached_keys(tokens[i], verifier, time_now, retry_after)
+                            }).map(|(_kid, claims)| claims);
+                        }
+                        Err(e) => *result = Err(e),
+                    }
+                }
+                Err(Error::InvalidSignature()) if self.refetch_on_invalid_signature => pending.push(i),
+                _ => {}
+            }
+        }
+        if pending.is_empty() {
+            return results;
+        }
+
+        // Only tokens with missing keys are worth refetching the key set for
+        if pending.iter().all(|&i| matches!(results[i], Err(Error::InvalidSignature()))) &&
+           self.throttle_remaining().is_some() {
+            return results;
+        }
+
+        if let Err(e) = self.prefetch_jwks().await {
+            for &i in pending.iter() {
+                if let Err(Error::CacheMiss { .. }) = results[i] {
+                    results[i] = Err(e.duplicate());
+                }
+            }
+            return results;
+        }
+
+        let mut retry = Vec::new();
+        for &i in pending.iter() {
+            match token_kid(tokens[i]) {
+                Some(kid) => match self.lookup_fetched_algorithm(&kid) {
+                    Ok(_) => retry.push(i),
+                    Err(e) => results[i] = Err(e),
+                },
+                None => retry.push(i),
+            }
+        }
+        for (i, result) in retry.iter().zip(self.try_verify_each(tokens, &retry, verifier)) {
+            results[*i] = result;
+        }
+
+        results
+    }
+
+    // Verifies the tokens at the given indices with the cached keys
+    fn try_verify_each(&self, tokens: &[&str], indices: &[usize], verifier: &Verifier) -> Vec<Result<Value, Error>> {
+        #[cfg(feature = "rayon")]
+        {
+            use rayon::prelude::*;
+            indices.par_iter().map(|&i| self.try_verify(tokens[i], verifier)).collect()
+        }
+        #[cfg(not(feature = "rayon"))]
+        {
+            indices.iter().map(|&i| self.try_verify(tokens[i], verifier)).collect()
+        }
+    }
+}
+
+fn token_kid(token: &str) -> Option<String> {
+    let header = jwt::raw::decode_header_only(token).ok()?;
+    header.get("kid").and_then(Value::as_str).map(String::from)
+}
+
+#[cfg(test)]
+mod tests {
+    use jwt::{Algorithm, AlgorithmID};
+    use serde_json::json;
+
+    use super::*;
+    use crate::test_util::*;
+
+    #[test]
+    fn verify_many() {
+        let fetcher = TestFetcher::jwks();
+        let keyset = KeySet::builder("eu-west-1", "eu-west-1_AbCd1234")
+            .jwks_fetcher(fetcher.clone())
+            .build()
+            .unwrap();
+        let verifier = keyset.new_issuer_verifier().build().unwrap();
+
+        let signer = Algorithm::new_rsa_pem_signer(AlgorithmID::RS256,
+                                                   include_bytes!("../testdata/rsa-private.pem")).unwrap();
+        let claims = json!({ "iss": TEST_ISS });
+        let token = rs256_token(&claims);
+        let unknown_kid_token = jwt::encode(&json!({ "alg": "RS256", "kid": "unknown-key" }), &claims, &signer).unwrap();
+
+        let tokens = [token.as_str(), unknown_kid_token.as_str(), "not-a-token", token.as_str()];
+        let results = async_std::task::block_on(keyset.verify_many(&tokens, &verifier));
+        assert_eq!(fetcher.fetch_count(), 1);
+        assert_eq!(results.len(), 4);
+        assert_eq!(results[0].as_ref().unwrap()["iss"], claims["iss"]);
+        assert!(matches!(results[1], Err(Error::UnknownKeyId(ref kid)) if kid == "unknown-key"));
+        assert!(matches!(results[2], Err(Error::MalformedToken(_))));
+        assert!(results[3].is_ok());
+    }
+}
@@ -0,0 +1,146 @@
+// Decodes and verifies a Cognito token, for debugging tokens that fail
+// verification
+//
+// Requires the `cli` feature:
+//
+//   cargo run --features cli --bin cognito-jwt -- eu-west-1 eu-west-1_AbCd1234 <token>
+
+use std::convert::TryInto;
+use std::io::Read;
+use std::process::ExitCode;
+use std::time::SystemTime;
+
+use serde_json::value::Value;
+
+use jsonwebtokens_cognito::{decode_unverified, Error, ErrorDetails, KeySet};
+
+const USAGE: &str = "\
+Usage: cognito-jwt <region> <pool-id> <token> [--client-id <client-id>]...
+
+Prints a token's decoded header and claims and how long until it expires, then
+verifies it with the user pool's JWKS key set. Give the token as - to read it
+from stdin.
+
+Without a --client-id, only the token's signature, issuer and expiry are
+verified. With one or more, the token is verified as an ID or access token
+(depending on its token_use claim) for one of the client IDs.
+
+Exits with status 1 if the token fails verification, or 2 if it can't be
+decoded.";
+
+struct Args {
+    region: String,
+    pool_id: String,
+    token: String,
+    client_ids: Vec<String>,
+}
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> Option<Args> {
+    let mut positional = Vec::new();
+    let mut client_ids = Vec::new();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--client-id" => client_ids.push(args.next()?),
+            "-h" | "--help" => return None,
+            _ => positional.push(arg),
+        }
+    }
+
+    let [region, pool_id, token]: [String; 3] = positional.try_into().ok()?;
+    Some(Args { region, pool_id, token, client_ids })
+}
+
+fn main() -> ExitCode {
+    let args = match parse_args(std::env::args().skip(1)) {
+        Some(args) => args,
+        None => {
+            eprintln!("{}", USAGE);
+            return ExitCode::from(2);
+        }
+    };
+
+    let mut token = args.token.clone();
+    if token == "-" {
+        token.clear();
+        if let Err(e) = std::io::stdin().read_to_string(&mut token) {
+            eprintln!("Failed to read token from stdin: {}", e);
+            return ExitCode::from(2);
+        }
+    }
+    // Tokens are often copied from an Authorization header
+    let token = token.trim();
+    let token = token.strip_prefix("Bearer ").unwrap_or(token);
+
+    let unverified = match decode_unverified(token) {
+        Ok(unverified) => unverified,
+        Err(e) => {
+            eprintln!("Failed to decode token: {}", e);
+            return ExitCode::from(2);
+        }
+    };
+    println!("Header:\n{}\n", to_pretty_json(&unverified.header));
+    println!("Claims:\n{}\n", to_pretty_json(&unverified.claims));
+    match unverified.claims.get("exp").and_then(Value::as_u64) {
+        Some(exp) => println!("Expiry: {}", describe_expiry(exp)),
+        None => println!("Expiry: no 'exp' claim"),
+    }
+
+    match verify(&args, token, &unverified.claims) {
+        Ok(kid) => {
+            println!("Verified: OK (kid {})", kid);
+            ExitCode::SUCCESS
+        }
+        Err(e) => {
+            println!("Verified: FAILED ({}): {}", e.code(), e);
+            ExitCode::from(1)
+        }
+    }
+}
+
+// Verifies the token, returning the kid of the key that verified it
+fn verify(args: &Args, token: &str, claims: &Value) -> Result<String, Error> {
+    let keyset = KeySet::new(args.region.as_str(), args.pool_id.as_str())?;
+    let client_ids: Vec<&str> = args.client_ids.iter().map(String::as_str).collect();
+    let verifier = if client_ids.is_empty() {
+        keyset.new_issuer_verifier()
+    } else if claims.get("token_use").and_then(Value::as_str) == Some("access") {
+        keyset.new_access_token_verifier(&client_ids)
+    } else {
+        keyset.new_id_token_verifier(&client_ids)
+    };
+    let verifier = verifier.build()?;
+
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .map_err(|e| Error::InvalidConfiguration(ErrorDetails::map("Failed to start tokio runtime", e)))?;
+    let data = runtime.block_on(keyset.verify_full(token, &verifier))?;
+    Ok(data.kid)
+}
+
+fn to_pretty_json(value: &Value) -> String {
+    serde_json::to_string_pretty(value).unwrap_or_else(|_| value.to_string())
+}
+
+fn describe_expiry(exp: u64) -> String {
+    let now = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map(|now| now.as_secs())
+        .unwrap_or(0);
+    if exp > now {
+        format!("{} (expires in {})", exp, format_secs(exp - now))
+    } else {
+        format!("{} (expired {} ago)", exp, format_secs(now - exp))
+    }
+}
+
+// Formats a number of seconds like "1h 2m 3s"
+fn format_secs(secs: u64) -> String {
+    let (days, hours, mins, secs) = (secs / 86400, secs / 3600 % 24, secs / 60 % 60, secs % 60);
+    match (days, hours, mins) {
+        (0, 0, 0) => format!("{}s", secs),
+        (0, 0, _) => format!("{}m {}s", mins, secs),
+        (0, _, _) => format!("{}h {}m {}s", hours, mins, secs),
+        _ => format!("{}d {}h {}m {}s", days, hours, mins, secs),
+    }
+}
@@ -0,0 +1,126 @@
+use std::sync::{Arc, OnceLock};
+
+use serde::de::DeserializeOwned;
+use serde_json::value::Value;
+
+use jsonwebtokens::Verifier;
+
+use crate::{DEFAULT_REQUEST_TIMEOUT, Error, JwksRequest, KeySet};
+use crate::fetcher::fetch_jwks_blocking;
+
+impl KeySet {
+    /// Set's the `reqwest::blocking::Client` used by `prefetch_jwks_blocking()`
+    /// and `verify_blocking()`
+    ///
+    /// By default a client (with a 10 second request timeout) is created the
+    /// first time one is needed. The blocking APIs don't use the KeySet's
+    /// [JwksFetcher](crate::JwksFetcher).
+    ///
+    /// _Requires the `blocking` feature_
+    pub fn set_blocking_http_client(&mut self, client: reqwest::blocking::Client) {
+        self.blocking_client = Arc::new(OnceLock::from(client));
+    }
+
+    /// Ensure the remote Json Web Key Set is downloaded and cached, blocking the
+    /// current thread (see `prefetch_jwks()`)
+    ///
+    /// This updates the same cache as the async APIs, and must not be called
+    /// from within an async runtime.
+    ///
+    /// _Requires the `blocking` feature_
+    pub fn prefetch_jwks_blocking(&self) -> Result<(), Error> {
+        if self.network_disabled {
+            return Err(Error::NetworkDisabled());
+        }
+        let client = self.blocking_client.get_or_init(|| {
+            let client = reqwest::blocking::Client::builder().timeout(DEFAULT_REQUEST_TIMEOUT);
+            #[cfg(feature = "rustls-tls")]
+            let client = client.use_rustls_tls();
+            client.build()
+                .expect("Failed to build default blocking HTTP client")
+        });
+        let etag = self.revalidation_etag();
+        let request = JwksRequest { url: &self.jwks_url, if_none_match: etag.as_deref() };
+
+        let mut retries = 0;
+        let resp = loop {
+            #[cfg(feature = "tracing")]
+            let started = web_time::Instant::now();
+            let result = fetch_jwks_blocking(client, &request);
+            self.stats.record_fetch(&result);
+            #[cfg(feature = "tracing")]
+            crate::trace::fetch_result(&self.jwks_url, &result, started, retries + 1);
+            if !self.retry_policy.should_retry(retries, &result) {
+                break result?;
+            }
+            std::thread::sleep(self.retry_policy.backoff_for(retries));
+            retries += 1;
+        };
+
+        self.handle_jwks_response(resp, etag.is_some())?;
+        Ok(())
+    }
+
+    /// Verify a token's signature and its claims, blocking the current thread if
+    /// the key set needs to be fetched (see `verify()`)
+    ///
+    /// This must not be called from within an async runtime.
+    ///
+    /// ```no_run
+    /// # use jsonwebtokens_cognito::KeySet;
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// # let token = "header.payload.signature";
+    /// let keyset = KeySet::new("eu-west-1", "eu-west-1_AbCd1234")?;
+    /// let verifier = keyset.new_id_token_verifier(&["client-id-0"]).build()?;
+    /// let claims = keyset.verify_blocking(token, &verifier)?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// _Requires the `blocking` feature_
+    pub fn verify_blocking(
+        &self,
+        token: &str,
+        verifier: &Verifier
+    ) -> Result<Value, Error> {
+        match self.try_verify(token, verifier) {
+            Err(Error::CacheMiss { .. }) => {
+                let header = jsonwebtokens::raw::decode_header_only(token)?;
+                let kid = header.get("kid").and_then(Value::as_str);
+                match self.check_on_demand_fetch(kid) {
+                    Err(Error::Throttled { retry_after }) if self.stale_while_revalidate => {
+                        let (_kid, claims) = self.verify_throttled_with_cached_keys(
+                            token, verifier, crate::unix_time_now()?, retry_after)?;
+                        return Ok(claims);
+                    }
+                    result => result?,
+                }
+                self.prefetch_jwks_blocking()?;
+                if let Some(kid) = kid {
+                    self.lookup_fetched_algorithm(kid)?;
+                }
+                self.try_verify(token, verifier)
+            }
+            Err(Error::InvalidSignature()) if self.refetch_on_invalid_signature &&
+                                              self.throttle_remaining().is_none() => {
+                self.prefetch_jwks_blocking()?;
+                self.try_verify(token, verifier)
+            }
+            result => result,
+        }
+    }
+
+    /// Verify a token's signature and its claims, blocking the current thread if
+    /// the key set needs to be fetched, and deserialize the claims into `T` (see
+    /// `verify_blocking()` and `verify_into()`)
+    ///
+    /// _Requires the `blocking` feature_
+    pub fn verify_into_blocking<T: DeserializeOwned>(
+        &self,
+        token: &str,
+        verifier: &Verifier
+    ) -> Result<T, Error> {
+        let claims = self.verify_blocking(token, verifier)?;
+        crate::deserialize_claims(claims)
+    }
+}
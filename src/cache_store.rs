@@ -0,0 +1,147 @@
+use std::fmt::Debug;
+use std::sync::Arc;
+
+use async_trait::async_trait;
+
+use crate::{CacheSnapshot, Error, JwksRefreshReport, KeySet};
+
+/// A shared store for cached keys, such as Redis or DynamoDB, so that a fleet of
+/// processes doesn't need to fetch the JWKS key set independently
+///
+/// When a KeySet with a store needs to fetch the key set, it first gets the
+/// store's [CacheSnapshot](crate::CacheSnapshot) (serialized as JSON, keyed
+/// by the JWKS URL). The snapshot is used instead of fetching the key set if it
+/// was fetched more recently than the KeySet's own keys (and includes the
+/// token's `kid`, when looking up a key). Otherwise the key set is fetched as
+/// normal and the new snapshot is put into the store.
+///
+/// The store is only an optimization, so errors getting or putting snapshots
+/// don't fail verification. The `blocking` feature's `prefetch_jwks_blocking()`
+/// doesn't use the store.
+///
+/// With the `redis` feature, [RedisKeyCacheStore](crate::redis::RedisKeyCacheStore)
+/// is an implementation for Redis.
+///
+/// _The [async_trait](crate::async_trait) attribute is re-exported for
+/// implementing this trait_
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+pub trait KeyCacheStore: Debug + Send + Sync {
+    /// Returns the value stored for the given key, if any
+    async fn get(&self, key: &str) -> Result<Option<Vec<u8>>, Error>;
+
+    /// Stores the value for the given key, replacing any previous value
+    async fn put(&self, key: &str, value: Vec<u8>) -> Result<(), Error>;
+}
+
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+impl<T: KeyCacheStore + ?Sized> KeyCacheStore for Arc<T> {
+    async fn get(&self, key: &str) -> Result<Option<Vec<u8>>, Error> {
+        (**self).get(key).await
+    }
+
+    async fn put(&self, key: &str, value: Vec<u8>) -> Result<(), Error> {
+        (**self).put(key, value).await
+    }
+}
+
+impl KeySet {
+    // Restores the store's snapshot if it's newer than the cached keys (and
+    // has the given kid), returning whether it was restored
+    pub(crate) async fn restore_from_store(&self, store: &dyn KeyCacheStore, kid: Option<&str>) -> Option<JwksRefreshReport> {
+        let snapshot: CacheSnapshot = match store.get(&self.jwks_url).await {
+            Ok(Some(json)) => match serde_json::from_slice(&json) {
+                Ok(snapshot) => snapshot,
+                Err(_e) => {
+                    #[cfg(feature = "tracing")]
+                    tracing::debug!(error = %_e, "Failed to parse key cache store snapshot");
+                    return None;
+                }
+            },
+            Ok(None) => return None,
+            Err(_e) => {
+                #[cfg(feature = "tracing")]
+                tracing::debug!(error = %_e, "Failed to get key cache store snapshot");
+                return None;
+            }
+        };
+        if snapshot.jwks_url != self.jwks_url {
+            return None;
+        }
+        if let Some(kid) = kid {
+            if !snapshot.kids().contains(&kid) {
+                return None;
+            }
+        }
+        self.restore_newer_cache(snapshot).unwrap_or(None)
+    }
+
+    // Puts a snapshot of the cached keys into the store
+    pub(crate) async fn put_to_store(&self, store: &dyn KeyCacheStore) {
+        let json = match self.cache_snapshot().map(|snapshot| serde_json::to_vec(&snapshot)) {
+            Some(Ok(json)) => json,
+            _ => return,
+        };
+        if let Err(_e) = store.put(&self.jwks_url, json).await {
+            #[cfg(feature = "tracing")]
+            tracing::debug!(error = %_e, "Failed to put key cache store snapshot");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::sync::Mutex;
+    use std::time::Duration;
+
+    use super::*;
+    use crate::test_util::*;
+
+    #[test]
+    fn key_cache_store() {
+        #[derive(Debug, Default)]
+        struct MemoryStore(Mutex<HashMap<String, Vec<u8>>>);
+
+        #[async_trait]
+        impl KeyCacheStore for MemoryStore {
+            async fn get(&self, key: &str) -> Result<Option<Vec<u8>>, Error> {
+                Ok(self.0.lock().unwrap().get(key).cloned())
+            }
+
+            async fn put(&self, key: &str, value: Vec<u8>) -> Result<(), Error> {
+                self.0.lock().unwrap().insert(key.to_string(), value);
+                Ok(())
+            }
+        }
+
+        let store = Arc::new(MemoryStore::default());
+        let fetcher = TestFetcher::jwks();
+        let new_keyset = || KeySet::builder("eu-west-1", "eu-west-1_AbCd1234")
+            .jwks_fetcher(fetcher.clone())
+            .key_cache_store(store.clone())
+            .min_jwks_fetch_interval(Duration::from_secs(0))
+            .build()
+            .unwrap();
+
+        let keyset0 = new_keyset();
+        async_std::task::block_on(keyset0.prefetch_jwks()).unwrap();
+        assert_eq!(fetcher.fetch_count(), 1);
+        assert_eq!(store.0.lock().unwrap().len(), 1);
+
+        // Newer keys are taken from the store
+        let keyset1 = new_keyset();
+        assert!(async_std::task::block_on(keyset1.wait_and_cache_lookup_algorithm("test-key-0")).is_ok());
+        assert_eq!(fetcher.fetch_count(), 1);
+        assert_eq!(keyset1.cached_kids(), ["test-key-0", "test-key-1"]);
+
+        // But the key set is fetched if the store doesn't have newer keys, or
+        // doesn't have the kid
+        async_std::task::block_on(keyset1.prefetch_jwks()).unwrap();
+        assert_eq!(fetcher.fetch_count(), 2);
+        let keyset2 = new_keyset();
+        assert!(async_std::task::block_on(keyset2.wait_and_cache_lookup_algorithm("test-key-2")).is_err());
+        assert_eq!(fetcher.fetch_count(), 3);
+    }
+}
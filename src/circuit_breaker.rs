@@ -0,0 +1,167 @@
+use std::time::Duration;
+
+use web_time::Instant;
+
+use crate::Error;
+
+/// Configures a circuit breaker that stops requesting the JWKS key set while
+/// the JWKS endpoint is failing
+///
+/// After `failure_threshold` consecutive failed requests (transport errors,
+/// timeouts, unsuccessful statuses or a response that isn't a valid JSON key
+/// set, i.e. `NetworkError`, `NetworkTimeout` and `JwksHttpStatus` errors) the
+/// circuit opens,
+/// and fetching the key set fails with a `CircuitOpen` error instead of
+/// making a request. Once the cool-down has passed the circuit is half-open:
+/// a single probe request is allowed, which closes the circuit if it succeeds
+/// or reopens it with double the cool-down (up to a maximum) if it fails.
+///
+/// ```
+/// # use jsonwebtokens_cognito::{CircuitBreaker, KeySet};
+/// # use std::time::Duration;
+/// # fn main() -> Result<(), jsonwebtokens_cognito::Error> {
+/// let keyset = KeySet::builder("eu-west-1", "eu-west-1_AbCd1234")
+///     .circuit_breaker(CircuitBreaker::new(3)
+///         .cool_down(Duration::from_secs(30), Duration::from_secs(600)))
+///     .build()?;
+/// # Ok(())
+/// # }
+/// ```
+///
+/// Retries of a request (see [RetryPolicy](crate::RetryPolicy)) only count as
+/// one failure. There's no circuit breaker by default.
+#[derive(Debug, Clone)]
+pub struct CircuitBreaker {
+    failure_threshold: u32,
+    initial_cool_down: Duration,
+    max_cool_down: Duration,
+}
+
+impl CircuitBreaker {
+    /// Constructs a circuit breaker that opens after `failure_threshold`
+    /// consecutive failures, with a cool-down starting at one minute and
+    /// doubling up to 15 minutes
+    pub fn new(failure_threshold: u32) -> Self {
+        CircuitBreaker {
+            failure_threshold: failure_threshold.max(1),
+            initial_cool_down: Duration::from_secs(60),
+            max_cool_down: Duration::from_secs(15 * 60),
+        }
+    }
+
+    /// Sets the cool-down before the first probe after the circuit opens, which
+    /// doubles after each failed probe up to `max`
+    pub fn cool_down(mut self, initial: Duration, max: Duration) -> Self {
+        self.initial_cool_down = initial;
+        self.max_cool_down = max;
+        self
+    }
+
+    pub fn failure_threshold(&self) -> u32 {
+        self.failure_threshold
+    }
+
+    // The cool-down after the circuit has opened the given number of times
+    // (counting from one) without a successful request
+    fn cool_down_for(&self, trips: u32) -> Duration {
+        self.initial_cool_down
+            .checked_mul(2u32.saturating_pow(trips.saturating_sub(1)))
+            .unwrap_or(self.max_cool_down)
+            .min(self.max_cool_down)
+    }
+}
+
+// The state of a KeySet's circuit, which is shared by its clones
+#[derive(Debug, Clone, Default)]
+pub(crate) struct CircuitState {
+    consecutive_failures: u32,
+
+    // How many times the circuit has opened since the last successful request
+    trips: u32,
+
+    // When the next probe is allowed, while the circuit is open
+    open_until: Option<Instant>,
+}
+
+impl CircuitState {
+    // Checks that a request is allowed, returning a `CircuitOpen` error if not
+    pub(crate) fn check(&mut self, breaker: &CircuitBreaker, now: Instant) -> Result<(), Error> {
+        let open_until = match self.open_until {
+            Some(open_until) => open_until,
+            None => return Ok(()),
+        };
+
+        if now < open_until {
+            return Err(Error::CircuitOpen { retry_after: open_until - now });
+        }
+
+        // Half-open: the circuit stays open for other requests while this one
+        // probes the endpoint, even if the probe is cancelled
+        self.open_until = Some(now + breaker.cool_down_for(self.trips));
+        Ok(())
+    }
+
+    pub(crate) fn record_success(&mut self) {
+        *self = CircuitState::default();
+    }
+
+    pub(crate) fn record_failure(&mut self, breaker: &CircuitBreaker, now: Instant) {
+        self.consecutive_failures = self.consecutive_failures.saturating_add(1);
+        if self.consecutive_failures >= breaker.failure_threshold {
+            self.trips = self.trips.saturating_add(1);
+            self.open_until = Some(now + breaker.cool_down_for(self.trips));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use reqwest::StatusCode;
+
+    use super::*;
+    use crate::{JwksResponse, KeySet, ManualClock};
+    use crate::test_util::*;
+
+    #[test]
+    fn circuit_breaker() {
+        // The first three fetches fail, the second with an invalid key set
+        let failure = || Ok(JwksResponse::new(StatusCode::INTERNAL_SERVER_ERROR, ""));
+        let fetcher = TestFetcher::new(vec![failure(), Ok(JwksResponse::new(StatusCode::OK, "<html>")), failure(),
+                                            Ok(JwksResponse::new(StatusCode::OK, TEST_JWKS))]);
+        let clock = ManualClock::new();
+        let keyset = KeySet::builder("eu-west-1", "eu-west-1_AbCd1234")
+            .jwks_fetcher(fetcher.clone())
+            .clock(clock.clone())
+            .min_jwks_fetch_interval(Duration::from_secs(0))
+            .circuit_breaker(CircuitBreaker::new(2)
+                .cool_down(Duration::from_secs(100), Duration::from_secs(1000)))
+            .build()
+            .unwrap();
+        let fetch_count = || fetcher.fetch_count();
+
+        // The circuit opens after two failures
+        let result = async_std::task::block_on(keyset.prefetch_jwks());
+        assert!(matches!(result, Err(Error::JwksHttpStatus(StatusCode::INTERNAL_SERVER_ERROR, _))));
+        let result = async_std::task::block_on(keyset.prefetch_jwks());
+        assert!(matches!(result, Err(Error::NetworkError(_))));
+        let result = async_std::task::block_on(keyset.prefetch_jwks());
+        assert!(matches!(result, Err(Error::CircuitOpen { retry_after }) if retry_after == Duration::from_secs(100)));
+        assert_eq!(fetch_count(), 2);
+
+        // A failed probe doubles the cool-down
+        clock.advance(Duration::from_secs(100));
+        assert!(async_std::task::block_on(keyset.prefetch_jwks()).is_err());
+        assert_eq!(fetch_count(), 3);
+        let result = async_std::task::block_on(keyset.prefetch_jwks());
+        assert!(matches!(result, Err(Error::CircuitOpen { retry_after }) if retry_after == Duration::from_secs(200)));
+        assert_eq!(fetch_count(), 3);
+
+        // And a successful probe closes the circuit
+        clock.advance(Duration::from_secs(199));
+        assert!(matches!(async_std::task::block_on(keyset.prefetch_jwks()), Err(Error::CircuitOpen { .. })));
+        clock.advance(Duration::from_secs(1));
+        async_std::task::block_on(keyset.prefetch_jwks()).unwrap();
+        async_std::task::block_on(keyset.prefetch_jwks()).unwrap();
+        assert_eq!(fetch_count(), 5);
+    }
+}
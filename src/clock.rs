@@ -0,0 +1,129 @@
+use std::fmt::Debug;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use web_time::Instant;
+
+/// The source of the current time for a KeySet's throttling and caching
+///
+/// This is used for the minimum JWKS fetch interval, the unknown `kid` TTL,
+/// the circuit breaker's cool-down and the age of cache snapshots, so that
+/// tests can use a [ManualClock] to fast-forward time instead of sleeping. It
+/// isn't used for checking token expiry, which is based on the system's unix
+/// time.
+///
+/// The default is the [SystemClock] (see `KeySetBuilder::clock()`).
+pub trait Clock: Debug + Send + Sync {
+    /// Returns the current time
+    fn now(&self) -> Instant;
+}
+
+impl<T: Clock + ?Sized> Clock for Arc<T> {
+    fn now(&self) -> Instant {
+        (**self).now()
+    }
+}
+
+/// The real, monotonic clock
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+}
+
+/// A clock that only advances when told to, for testing time-dependent
+/// behaviour deterministically
+///
+/// Clones share the same time, so a clone can be given to a KeySet and the
+/// original advanced by the test:
+///
+/// ```
+/// # use jsonwebtokens_cognito::{KeySet, ManualClock};
+/// # use std::time::Duration;
+/// # fn main() -> Result<(), jsonwebtokens_cognito::Error> {
+/// let clock = ManualClock::new();
+/// let keyset = KeySet::builder("eu-west-1", "eu-west-1_AbCd1234")
+///     .clock(clock.clone())
+///     .build()?;
+/// // ...
+/// clock.advance(Duration::from_secs(60));
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct ManualClock {
+    start: Instant,
+    elapsed: Arc<Mutex<Duration>>,
+}
+
+impl ManualClock {
+    /// Constructs a clock that's stopped at the current time
+    pub fn new() -> Self {
+        ManualClock {
+            start: Instant::now(),
+            elapsed: Default::default(),
+        }
+    }
+
+    /// Moves the clock forward
+    pub fn advance(&self, duration: Duration) {
+        *self.elapsed.lock().unwrap() += duration;
+    }
+}
+
+impl Default for ManualClock {
+    fn default() -> Self {
+        ManualClock::new()
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> Instant {
+        self.start + *self.elapsed.lock().unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::{Error, KeySet, DEFAULT_MIN_JWKS_FETCH_INTERVAL, DEFAULT_UNKNOWN_KID_TTL};
+    use crate::test_util::*;
+
+    #[test]
+    fn manual_clock() {
+        let fetcher = TestFetcher::jwks();
+        let fetch_count = || fetcher.fetch_count();
+        let clock = ManualClock::new();
+        let keyset = KeySet::builder("eu-west-1", "eu-west-1_AbCd1234")
+            .jwks_fetcher(fetcher.clone())
+            .clock(clock.clone())
+            .build()
+            .unwrap();
+        let verifier = keyset.new_issuer_verifier().build().unwrap();
+        let token = test_token(json!({ "alg": "HS256", "kid": "unknown-kid" }), "secret");
+        let verify = || async_std::task::block_on(keyset.verify(&token, &verifier));
+
+        async_std::task::block_on(keyset.prefetch_jwks()).unwrap();
+        assert!(matches!(verify(), Err(Error::Throttled { retry_after })
+                         if retry_after == DEFAULT_MIN_JWKS_FETCH_INTERVAL));
+        assert_eq!(fetch_count(), 1);
+
+        clock.advance(DEFAULT_MIN_JWKS_FETCH_INTERVAL);
+        assert!(keyset.needs_refresh());
+        assert!(matches!(verify(), Err(Error::UnknownKeyId(_))));
+        assert_eq!(fetch_count(), 2);
+
+        // The unknown kid isn't looked for again until its TTL has passed
+        clock.advance(DEFAULT_MIN_JWKS_FETCH_INTERVAL);
+        assert!(matches!(verify(), Err(Error::UnknownKeyId(_))));
+        assert_eq!(fetch_count(), 2);
+        clock.advance(DEFAULT_UNKNOWN_KID_TTL);
+        assert!(matches!(verify(), Err(Error::UnknownKeyId(_))));
+        assert_eq!(fetch_count(), 3);
+    }
+}
@@ -0,0 +1,105 @@
+use reqwest::StatusCode;
+use serde::Deserialize;
+
+use crate::fetcher::{JwksFetcher, JwksRequest};
+use crate::{body_snippet, Error, ErrorDetails, NetworkErrorKind};
+
+// The parts of an OpenID Connect discovery document that configure a KeySet
+#[derive(Debug, Deserialize)]
+pub(crate) struct OidcConfiguration {
+    pub(crate) issuer: String,
+    pub(crate) jwks_uri: String,
+}
+
+/// Returns the URL of the OpenID Connect discovery document for an issuer
+pub(crate) fn oidc_discovery_url(issuer_url: &str) -> String {
+    format!("{}/.well-known/openid-configuration", issuer_url.trim_end_matches('/'))
+}
+
+// Fetches an issuer's discovery document, checking that it's for the issuer
+pub(crate) async fn fetch_oidc_configuration(fetcher: &dyn JwksFetcher, issuer: &str) -> Result<OidcConfiguration, Error> {
+    let discovery_url = oidc_discovery_url(issuer);
+    let request = JwksRequest { url: &discovery_url, if_none_match: None };
+    let resp = fetcher.fetch_jwks(&request).await?;
+    if resp.status == StatusCode::FORBIDDEN || resp.status == StatusCode::NOT_FOUND {
+        return Err(Error::InvalidConfiguration(ErrorDetails::new(format!(
+            "OpenID Connect discovery request for {} failed with status {} (check the issuer URL)",
+            discovery_url, resp.status))));
+    }
+    if !resp.status.is_success() {
+        return Err(Error::NetworkError(ErrorDetails::new(format!(
+            "OpenID Connect discovery request for {} failed with status {}: {}",
+            discovery_url, resp.status, body_snippet(&resp.body))).with_kind(NetworkErrorKind::Http(resp.status))));
+    }
+
+    let config: OidcConfiguration = serde_json::from_slice(&resp.body).map_err(|e| {
+        Error::InvalidConfiguration(ErrorDetails::map(
+            format!("Failed to parse OpenID Connect discovery document from {}", discovery_url), e))
+    })?;
+    // Otherwise one issuer could impersonate another (OpenID Connect Discovery 4.3)
+    if config.issuer != issuer {
+        return Err(Error::InvalidConfiguration(ErrorDetails::new(format!(
+            "OpenID Connect discovery document from {} is for issuer \"{}\", not \"{}\"",
+            discovery_url, config.issuer, issuer))));
+    }
+    Ok(config)
+}
+
+#[cfg(test)]
+mod tests {
+    use async_trait::async_trait;
+
+    use super::*;
+    use crate::{JwksResponse, KeySet};
+    use crate::test_util::*;
+
+    #[test]
+    fn oidc_discovery() {
+        #[derive(Debug)]
+        struct DiscoveryFetcher;
+
+        #[async_trait]
+        impl JwksFetcher for DiscoveryFetcher {
+            async fn fetch_jwks(&self, request: &JwksRequest<'_>) -> Result<JwksResponse, Error> {
+                match request.url {
+                    "http://localhost:9229/local_AbCd1234/.well-known/openid-configuration" => {
+                        Ok(JwksResponse::new(StatusCode::OK, r#"{
+                            "issuer": "http://localhost:9229/local_AbCd1234",
+                            "jwks_uri": "http://localhost:9229/local_AbCd1234/.well-known/jwks.json",
+                            "response_types_supported": ["code"]
+                        }"#))
+                    }
+                    "http://localhost:9229/local_AbCd1234/.well-known/jwks.json" => {
+                        Ok(JwksResponse::new(StatusCode::OK, TEST_JWKS))
+                    }
+                    _ => Ok(JwksResponse::new(StatusCode::NOT_FOUND, "")),
+                }
+            }
+        }
+
+        let keyset = async_std::task::block_on(
+            KeySet::builder_with_oidc_discovery("http://localhost:9229/local_AbCd1234")
+                .jwks_fetcher(DiscoveryFetcher)
+                .build_with_oidc_discovery()
+        ).unwrap();
+        assert_eq!(keyset.iss(), "http://localhost:9229/local_AbCd1234");
+        assert_eq!(keyset.jwks_url(), "http://localhost:9229/local_AbCd1234/.well-known/jwks.json");
+        async_std::task::block_on(keyset.prefetch_jwks()).unwrap();
+        assert!(!keyset.cached_kids().is_empty());
+
+        let discover = |issuer_url: &str| async_std::task::block_on(
+            KeySet::builder_with_oidc_discovery(issuer_url)
+                .jwks_fetcher(DiscoveryFetcher)
+                .build_with_oidc_discovery()
+        );
+        assert!(matches!(discover("http://localhost:9229/unknown"), Err(Error::InvalidConfiguration(_))));
+
+        // The document must be for the issuer it was fetched for
+        match discover("http://localhost:9229/local_AbCd1234/") {
+            Err(Error::InvalidConfiguration(details)) => {
+                assert!(details.description().contains("is for issuer"));
+            }
+            other => panic!("Expected an InvalidConfiguration error, got {:?}", other),
+        }
+    }
+}
@@ -1,14 +1,18 @@
 use std::error::Error as StdError;
 use std::fmt;
-use std::time::Instant;
+use std::time::Duration;
+
+use web_time::{Instant, SystemTime};
 
 use jsonwebtokens as jwt;
+use reqwest::StatusCode;
 use jwt::error::Error as JwtError;
 
 #[derive(Debug)]
 pub struct ErrorDetails {
     desc: String,
     src: Option<Box<dyn StdError + Send>>,
+    kind: Option<NetworkErrorKind>,
 
     #[doc(hidden)]
     _extensible: (),
@@ -19,6 +23,7 @@ impl ErrorDetails {
         ErrorDetails {
             desc: desc.into(),
             src: None,
+            kind: None,
             _extensible: ()
         }
     }
@@ -27,22 +32,146 @@ impl ErrorDetails {
         ErrorDetails {
             desc: desc.into(),
             src: Some(Box::new(src)),
+            kind: None,
             _extensible: ()
         }
     }
 }
 
+impl ErrorDetails {
+    /// The description of what failed, without the source error's message
+    pub fn description(&self) -> &str {
+        &self.desc
+    }
+
+    // Sets what kind of network failure the details are for
+    pub(crate) fn with_kind(mut self, kind: NetworkErrorKind) -> ErrorDetails {
+        self.kind = Some(kind);
+        self
+    }
+
+    // A copy of the details, with the source (if any) flattened into the description
+    fn duplicate(&self) -> ErrorDetails {
+        let details = match &self.src {
+            Some(src) => ErrorDetails::new(format!("{}: {}", self.desc, src)),
+            None => ErrorDetails::new(self.desc.clone()),
+        };
+        ErrorDetails { kind: self.kind, ..details }
+    }
+}
+
+/// What kind of failure a `NetworkError` was, for telling a DNS problem in a
+/// VPC apart from a TLS intercepting proxy or a bad response (see
+/// `Error::network_error_kind()`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum NetworkErrorKind {
+    /// The endpoint's host name couldn't be resolved
+    Dns,
+
+    /// A connection to the endpoint couldn't be established, or was lost
+    Connect,
+
+    /// The TLS handshake failed, such as due to an untrusted certificate
+    Tls,
+
+    /// The endpoint responded with an unsuccessful HTTP status
+    Http(StatusCode),
+
+    /// The response couldn't be decoded, such as a key set that isn't valid JSON
+    Decode,
+
+    /// Any other failure
+    Other,
+}
+
+impl NetworkErrorKind {
+    /// A short, stable name for the kind, such as `dns`, for logs and metrics
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            NetworkErrorKind::Dns => "dns",
+            NetworkErrorKind::Connect => "connect",
+            NetworkErrorKind::Tls => "tls",
+            NetworkErrorKind::Http(_) => "http",
+            NetworkErrorKind::Decode => "decode",
+            NetworkErrorKind::Other => "other",
+        }
+    }
+
+    // Classifies a reqwest error by its source chain, since the resolver and
+    // TLS errors are hyper, rustls or native-tls types that reqwest doesn't
+    // expose
+    fn of_reqwest_error(e: &reqwest::Error) -> NetworkErrorKind {
+        if let Some(status) = e.status() {
+            return NetworkErrorKind::Http(status);
+        }
+        if e.is_decode() {
+            return NetworkErrorKind::Decode;
+        }
+
+        #[cfg_attr(target_arch = "wasm32", allow(unused_mut))]
+        let mut connecting = false;
+        let mut source = e.source();
+        while let Some(err) = source {
+            if is_tls_error(err) {
+                return NetworkErrorKind::Tls;
+            }
+
+            // `io::Error::source()` skips over a wrapped error (which is where
+            // rustls' errors end up), so nested IO errors have to be unwrapped
+            // by hand
+            let mut io_error = err.downcast_ref::<std::io::Error>();
+            while let Some(inner) = io_error.and_then(|e| e.get_ref()) {
+                if is_tls_error(inner) {
+                    return NetworkErrorKind::Tls;
+                }
+                io_error = inner.downcast_ref::<std::io::Error>();
+            }
+
+            // hyper's `ConnectError` isn't public, but it's always the source of
+            // a connect error and names resolver failures "dns error"
+            if connecting && err.to_string().starts_with("dns error") {
+                return NetworkErrorKind::Dns;
+            }
+            #[cfg(not(target_arch = "wasm32"))]
+            if let Some(hyper_error) = err.downcast_ref::<hyper::Error>() {
+                connecting = hyper_error.is_connect();
+            }
+            source = err.source();
+        }
+
+        #[cfg(not(target_arch = "wasm32"))]
+        if connecting || e.is_connect() {
+            return NetworkErrorKind::Connect;
+        }
+        NetworkErrorKind::Other
+    }
+
+    fn description(&self) -> &'static str {
+        match self {
+            NetworkErrorKind::Dns => "DNS lookup failed",
+            NetworkErrorKind::Connect => "Connection failed",
+            NetworkErrorKind::Tls => "TLS handshake failed",
+            NetworkErrorKind::Http(_) => "Unsuccessful HTTP status",
+            NetworkErrorKind::Decode => "Failed to decode response",
+            NetworkErrorKind::Other => "Request failed",
+        }
+    }
+}
+
 impl From<String> for ErrorDetails {
     fn from(s: String) -> Self {
         ErrorDetails {
             desc: s,
             src: None,
+            kind: None,
             _extensible: ()
         }
     }
 }
 
 #[derive(Debug)]
+#[non_exhaustive]
 pub enum Error {
 
     /// The token header didn't have a 'kid' key ID value
@@ -52,24 +181,372 @@ pub enum Error {
     InvalidSignature(),
 
     /// The token expired at this time (unix epoch timestamp)
+    ///
+    /// See `Error::expired_at()` and `Error::expired_for()` for the time as a
+    /// `SystemTime` and how long ago it was.
     TokenExpiredAt(u64),
 
+    /// The token's `nbf` (not before) time is in the future, which can be due
+    /// to clock skew between the issuer and verifier (see
+    /// `KeySet::set_verifier_leeway()`)
+    TokenNotYetValid(),
+
+    /// The token header's `alg` didn't match the algorithm of the key it
+    /// refers to, or was missing or `none`
+    AlgorithmMismatch(),
+
+    /// The token header's `alg` isn't one of the KeySet's allowed algorithms
+    /// (see `KeySet::set_allowed_algorithms()`), so its signature wasn't checked
+    DisallowedAlgorithm(String),
+
+    /// The token was verified but has been revoked (see `KeySet::set_revocation_check()`)
+    TokenRevoked(),
+
     /// Any of: header.payload.signature split error, json parser error, header or claim validation error
     MalformedToken(ErrorDetails),
 
+    /// The token, or its header, was larger than allowed and was rejected
+    /// without being decoded (see `KeySet::set_max_token_len()` and
+    /// `KeySet::set_max_header_len()`)
+    ///
+    /// `size` and `max_size` are the length in bytes of the encoded token, or
+    /// of its encoded header if that was too large.
+    TokenTooLarge { size: usize, max_size: usize },
+
+    /// A claim that the verifier checks was missing or didn't have the expected
+    /// value, such as the token's `aud`, `iss` or `token_use`
+    ///
+    /// `expected` and `actual` are included when they're known. For example the
+    /// expected value isn't known when a claim had to match one of a set of
+    /// values, or a callback. Neither is known when the claim was missing or had
+    /// the wrong type.
+    ClaimMismatch {
+        claim: String,
+        expected: Option<String>,
+        actual: Option<String>,
+    },
+
+    /// The token was verified but its claims were denied by a [Policy](crate::Policy)
+    PolicyDenied(crate::Denial),
+
     /// Failed to fetch remote jwks key set
     NetworkError(ErrorDetails),
 
+    /// The key set wasn't fetched because it only has pinned keys (see
+    /// `KeySet::with_pinned_keys()`), such as for a token signed with another key
+    NetworkDisabled(),
+
+    /// Fetching the remote jwks key set timed out
+    ///
+    /// See `KeySetBuilder::request_timeout()` and `KeySetBuilder::connect_timeout()`
+    NetworkTimeout(ErrorDetails),
+
+    /// The JWKS endpoint responded with an unsuccessful HTTP status
+    ///
+    /// Includes the start of the response body, to help diagnose the problem
+    JwksHttpStatus(StatusCode, String),
+
+    /// The key needed to verify a token wasn't cached and the key set couldn't
+    /// be fetched because the last attempt was too recent
+    ///
+    /// `retry_after` is how long until the next attempt to fetch the key set is
+    /// allowed (see `KeySet::set_min_jwks_fetch_interval()`)
+    Throttled { retry_after: Duration },
+
+    /// The key set wasn't fetched because the JWKS endpoint has been failing and
+    /// the circuit breaker is open (see [CircuitBreaker](crate::CircuitBreaker))
+    ///
+    /// `retry_after` is how long until the next probe of the endpoint is allowed
+    CircuitOpen { retry_after: Duration },
+
+    /// The key set configuration is invalid
+    ///
+    /// For example the JWKS endpoint reported that the key set doesn't exist
+    /// (403 or 404 status), which usually implies the wrong region or user pool
+    /// ID was given. Unlike a `NetworkError` this isn't expected to be fixed by
+    /// retrying.
+    InvalidConfiguration(ErrorDetails),
+
+    /// A `KeySetRegistry` has no KeySet for the token's issuer
+    ///
+    /// Includes the token's `iss` claim, if it had one
+    UnknownIssuer(Option<String>),
+
+    /// The token's key ID (`kid`) wasn't found in the key set
+    ///
+    /// Unknown kids are remembered for a while (see
+    /// `KeySet::set_unknown_kid_ttl()`), during which this is returned without
+    /// refetching the key set.
+    UnknownKeyId(String),
+
     /// try_verify() failed because the required Algorithm/key wasn't cached
+    CacheMiss {
+        /// The key ID (`kid`) that was looked up, if the token had one
+        kid: Option<String>,
+
+        /// When the cache was last updated, if ever
+        last_updated: Option<Instant>,
+
+        /// The key IDs that are currently cached, sorted
+        cached_kids: Vec<String>,
+
+        /// How long until the key set may be fetched again, if fetching is
+        /// currently throttled (see `KeySet::set_min_jwks_fetch_interval()`)
+        retry_after: Option<Duration>,
+    },
+
+    /// An OAuth endpoint request failed (see the `oauth` module)
     ///
-    /// The included Instant indicates when the cache was last updated (if not None)
-    CacheMiss(Option<Instant>),
+    /// `error` is the OAuth error code, such as `invalid_grant`, or the start of
+    /// the response body if it wasn't an OAuth error response.
+    OAuthError {
+        status: StatusCode,
+        error: String,
+        error_description: Option<String>,
+    },
 
-    #[doc(hidden)]
-    __Nonexhaustive
+    /// A Cognito user pool API request failed (see the `auth` module)
+    ///
+    /// `error_type` is the name of the exception, such as
+    /// `NotAuthorizedException` for an incorrect username or password.
+    UserPoolApiError {
+        status: StatusCode,
+        error_type: String,
+        message: Option<String>,
+    },
+
+    /// A successful user pool API response couldn't be used, such as a
+    /// password verifier challenge with a missing or invalid SRP parameter (see
+    /// the `auth` module)
+    InvalidUserPoolResponse(ErrorDetails),
+
+    /// Authentication requires a response to a challenge that isn't supported,
+    /// such as `NEW_PASSWORD_REQUIRED` or an MFA challenge (see the `auth` module)
+    ///
+    /// The `session` can be used to respond to the challenge with the
+    /// `RespondToAuthChallenge` API.
+    UnsupportedChallenge {
+        challenge: String,
+        session: Option<String>,
+    },
 }
 
-impl StdError for Error {}
+impl Error {
+    /// The HTTP status that's appropriate for responding to a request whose
+    /// token failed verification with this error
+    ///
+    /// - `401 Unauthorized` if the token is missing, malformed, expired or has
+    ///   an invalid signature or claims
+    /// - `403 Forbidden` if the token is valid but lacks a required `scope` or
+    ///   Cognito group (see [VerifierBuilderExt](crate::VerifierBuilderExt)),
+    ///   or was denied by a [Policy](crate::Policy)
+    /// - `502 Bad Gateway` if the keys for verifying the token couldn't be
+    ///   fetched
+    /// - `503 Service Unavailable` if the keys couldn't be fetched because
+    ///   fetching them is throttled or the circuit breaker is open, or they
+    ///   haven't been fetched yet
+    /// - `500 Internal Server Error` if the KeySet is misconfigured
+    pub fn http_status(&self) -> StatusCode {
+        match self {
+            Error::ClaimMismatch { claim, .. } if claim == "scope" || claim == "cognito:groups" => {
+                StatusCode::FORBIDDEN
+            }
+            Error::PolicyDenied(_) => StatusCode::FORBIDDEN,
+            Error::NetworkError(_) | Error::NetworkTimeout(_) | Error::JwksHttpStatus(_, _) => StatusCode::BAD_GATEWAY,
+            Error::Throttled { .. } | Error::CircuitOpen { .. } | Error::CacheMiss { .. } => {
+                StatusCode::SERVICE_UNAVAILABLE
+            }
+            Error::InvalidConfiguration(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            _ => StatusCode::UNAUTHORIZED,
+        }
+    }
+
+    /// When the token expired, for a `TokenExpiredAt` error
+    pub fn expired_at(&self) -> Option<SystemTime> {
+        match self {
+            Error::TokenExpiredAt(when) => Some(SystemTime::UNIX_EPOCH + Duration::from_secs(*when)),
+            _ => None,
+        }
+    }
+
+    /// How long ago the token expired, for a `TokenExpiredAt` error
+    ///
+    /// This is relative to the current time, so should be read soon after
+    /// verification, e.g. for a 401 response like "Token expired 5 minutes ago".
+    pub fn expired_for(&self) -> Option<Duration> {
+        let expired_at = self.expired_at()?;
+        Some(SystemTime::now().duration_since(expired_at).unwrap_or_default())
+    }
+
+    /// What kind of network failure a `NetworkError` or `JwksHttpStatus` error
+    /// was, such as a DNS or TLS failure
+    ///
+    /// ```no_run
+    /// # use jsonwebtokens_cognito::{Error, KeySet, NetworkErrorKind};
+    /// # async fn example(keyset: &KeySet) {
+    /// if let Err(e) = keyset.prefetch_jwks().await {
+    ///     if e.network_error_kind() == Some(NetworkErrorKind::Dns) {
+    ///         eprintln!("Can't resolve the JWKS host, check the VPC's DNS settings: {}", e);
+    ///     }
+    /// }
+    /// # }
+    /// ```
+    ///
+    /// Returns `None` for other kinds of error, including timeouts.
+    pub fn network_error_kind(&self) -> Option<NetworkErrorKind> {
+        match self {
+            Error::NetworkError(details) => Some(details.kind.unwrap_or(NetworkErrorKind::Other)),
+            Error::JwksHttpStatus(status, _) => Some(NetworkErrorKind::Http(*status)),
+            _ => None,
+        }
+    }
+
+    /// The details of the error, for the kinds of error that have them, such as
+    /// a `NetworkError`
+    ///
+    /// The details' source error (such as a `reqwest::Error`) is the error's
+    /// `source()`.
+    pub fn details(&self) -> Option<&ErrorDetails> {
+        match self {
+            Error::MalformedToken(details) | Error::NetworkError(details) | Error::NetworkTimeout(details)
+                | Error::InvalidConfiguration(details) | Error::InvalidUserPoolResponse(details) => Some(details),
+            _ => None,
+        }
+    }
+
+    /// A stable, machine-readable code for the error, such as `token_expired`,
+    /// for clients to branch on instead of the error message
+    ///
+    /// The codes are `snake_case` and won't change for an existing kind of
+    /// error, although new codes may be added. With the `serde` feature the code
+    /// is included when serializing an error.
+    pub fn code(&self) -> &'static str {
+        match self {
+            Error::NoKeyID() => "missing_kid",
+            Error::InvalidSignature() => "invalid_signature",
+            Error::TokenExpiredAt(_) => "token_expired",
+            Error::TokenNotYetValid() => "token_not_yet_valid",
+            Error::AlgorithmMismatch() => "algorithm_mismatch",
+            Error::DisallowedAlgorithm(_) => "disallowed_algorithm",
+            Error::TokenRevoked() => "token_revoked",
+            Error::MalformedToken(_) => "malformed_token",
+            Error::TokenTooLarge { .. } => "token_too_large",
+            Error::ClaimMismatch { .. } => "claim_mismatch",
+            Error::PolicyDenied(_) => "policy_denied",
+            Error::NetworkError(_) => "jwks_unreachable",
+            Error::NetworkTimeout(_) => "jwks_timeout",
+            Error::NetworkDisabled() => "network_disabled",
+            Error::JwksHttpStatus(_, _) => "jwks_http_status",
+            Error::Throttled { .. } => "jwks_throttled",
+            Error::CircuitOpen { .. } => "jwks_circuit_open",
+            Error::InvalidConfiguration(_) => "invalid_configuration",
+            Error::UnknownIssuer(_) => "unknown_issuer",
+            Error::UnknownKeyId(_) => "unknown_kid",
+            Error::CacheMiss { .. } => "key_not_cached",
+            Error::OAuthError { .. } => "oauth_error",
+            Error::UserPoolApiError { .. } => "user_pool_api_error",
+            Error::InvalidUserPoolResponse(_) => "invalid_user_pool_response",
+            Error::UnsupportedChallenge { .. } => "unsupported_challenge",
+        }
+    }
+
+    /// A short message for the error that's safe to show to clients, such as
+    /// "Token expired"
+    ///
+    /// Unlike the `Display` message it doesn't include any of the error's
+    /// details, such as a claim's expected value or why the key set couldn't be
+    /// fetched.
+    pub fn public_message(&self) -> &'static str {
+        match self {
+            Error::NoKeyID() | Error::AlgorithmMismatch() | Error::DisallowedAlgorithm(_)
+                | Error::MalformedToken(_) => "Malformed token",
+            Error::InvalidSignature() => "Invalid token signature",
+            Error::TokenExpiredAt(_) => "Token expired",
+            Error::TokenNotYetValid() => "Token not yet valid",
+            Error::TokenRevoked() => "Token revoked",
+            Error::TokenTooLarge { .. } => "Token too large",
+            Error::ClaimMismatch { .. } if self.http_status() == StatusCode::FORBIDDEN => "Insufficient permissions",
+            Error::ClaimMismatch { .. } => "Invalid token claims",
+            Error::PolicyDenied(_) => "Access denied",
+            Error::UnknownIssuer(_) | Error::UnknownKeyId(_) => "Unknown token issuer or key",
+            Error::NetworkError(_) | Error::NetworkTimeout(_) | Error::JwksHttpStatus(_, _) => {
+                "Unable to fetch the keys for verifying the token"
+            }
+            Error::NetworkDisabled() | Error::Throttled { .. } | Error::CircuitOpen { .. } | Error::CacheMiss { .. } => {
+                "The keys for verifying the token are unavailable"
+            }
+            Error::InvalidConfiguration(_) => "Invalid authentication configuration",
+            Error::OAuthError { .. } | Error::UserPoolApiError { .. } | Error::InvalidUserPoolResponse(_) => {
+                "Authentication request failed"
+            }
+            Error::UnsupportedChallenge { .. } => "Unsupported authentication challenge",
+        }
+    }
+
+    /// The error, with all of its details, for serializing to logs or other
+    /// internal diagnostics (see [DiagnosticError])
+    #[cfg(feature = "serde")]
+    pub fn diagnostic(&self) -> DiagnosticError<'_> {
+        DiagnosticError(self)
+    }
+
+    // A copy of the error, for reporting one failure for several tokens
+    pub(crate) fn duplicate(&self) -> Error {
+        match self {
+            Error::NoKeyID() => Error::NoKeyID(),
+            Error::InvalidSignature() => Error::InvalidSignature(),
+            Error::TokenExpiredAt(when) => Error::TokenExpiredAt(*when),
+            Error::TokenNotYetValid() => Error::TokenNotYetValid(),
+            Error::AlgorithmMismatch() => Error::AlgorithmMismatch(),
+            Error::DisallowedAlgorithm(alg) => Error::DisallowedAlgorithm(alg.clone()),
+            Error::TokenRevoked() => Error::TokenRevoked(),
+            Error::MalformedToken(details) => Error::MalformedToken(details.duplicate()),
+            Error::TokenTooLarge { size, max_size } => Error::TokenTooLarge { size: *size, max_size: *max_size },
+            Error::ClaimMismatch { claim, expected, actual } => Error::ClaimMismatch {
+                claim: claim.clone(),
+                expected: expected.clone(),
+                actual: actual.clone(),
+            },
+            Error::PolicyDenied(denial) => Error::PolicyDenied(denial.clone()),
+            Error::NetworkError(details) => Error::NetworkError(details.duplicate()),
+            Error::NetworkTimeout(details) => Error::NetworkTimeout(details.duplicate()),
+            Error::NetworkDisabled() => Error::NetworkDisabled(),
+            Error::JwksHttpStatus(status, body) => Error::JwksHttpStatus(*status, body.clone()),
+            Error::Throttled { retry_after } => Error::Throttled { retry_after: *retry_after },
+            Error::CircuitOpen { retry_after } => Error::CircuitOpen { retry_after: *retry_after },
+            Error::InvalidConfiguration(details) => Error::InvalidConfiguration(details.duplicate()),
+            Error::UnknownIssuer(iss) => Error::UnknownIssuer(iss.clone()),
+            Error::UnknownKeyId(kid) => Error::UnknownKeyId(kid.clone()),
+            Error::CacheMiss { kid, last_updated, cached_kids, retry_after } => Error::CacheMiss {
+                kid: kid.clone(),
+                last_updated: *last_updated,
+                cached_kids: cached_kids.clone(),
+                retry_after: *retry_after,
+            },
+            Error::OAuthError { status, error, error_description } => Error::OAuthError {
+                status: *status,
+                error: error.clone(),
+                error_description: error_description.clone(),
+            },
+            Error::UserPoolApiError { status, error_type, message } => Error::UserPoolApiError {
+                status: *status,
+                error_type: error_type.clone(),
+                message: message.clone(),
+            },
+            Error::InvalidUserPoolResponse(details) => Error::InvalidUserPoolResponse(details.duplicate()),
+            Error::UnsupportedChallenge { challenge, session } => Error::UnsupportedChallenge {
+                challenge: challenge.clone(),
+                session: session.clone(),
+            },
+        }
+    }
+}
+
+impl StdError for Error {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        self.details()?.src.as_deref().map(|src| src as &(dyn StdError + 'static))
+    }
+}
 
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
@@ -77,15 +554,59 @@ impl fmt::Display for Error {
             Error::NoKeyID() => write!(f, "Token had no 'kid' value"),
             Error::InvalidSignature() => write!(f, "JWT Signature Invalid"),
             Error::TokenExpiredAt(when) => write!(f, "JWT token expired at {}", when),
+            Error::TokenNotYetValid() => write!(f, "JWT token isn't valid yet (its 'nbf' time is in the future)"),
+            Error::AlgorithmMismatch() => write!(f, "JWT 'alg' algorithm doesn't match the key"),
+            Error::DisallowedAlgorithm(alg) => write!(f, "JWT 'alg' algorithm \"{}\" isn't allowed", alg),
+            Error::TokenRevoked() => write!(f, "JWT token has been revoked"),
             Error::MalformedToken(details) => {
                 match &details.src {
                     Some(src) => src.fmt(f),
                     None => write!(f, "JWT claims invalid: {}", details.desc),
                 }
             }
+            Error::TokenTooLarge { size, max_size } => write!(f, "JWT token too large ({} bytes, the maximum is {})",
+                                                              size, max_size),
+            Error::ClaimMismatch { claim, expected, actual } => {
+                write!(f, "JWT claim '{}' ", claim)?;
+                match (expected, actual) {
+                    (None, None) => write!(f, "missing or invalid"),
+                    (Some(expected), None) => write!(f, "didn't contain \"{}\"", expected),
+                    (Some(expected), Some(actual)) => write!(f, "was \"{}\", expected \"{}\"", actual, expected),
+                    (None, Some(actual)) => write!(f, "had unexpected value \"{}\"", actual),
+                }
+            }
+            Error::PolicyDenied(denial) => denial.fmt(f),
             Error::NetworkError(details) => write!(f, "Error fetching JWKS key set: {}", details.desc),
-            Error::CacheMiss(_) => write!(f, "Failed to lookup corresponding Algorithm / key"),
-            Error::__Nonexhaustive => { write!(f, "Unknown error") }
+            Error::NetworkTimeout(details) => write!(f, "Timed out fetching JWKS key set: {}", details.desc),
+            Error::NetworkDisabled() => write!(f, "Fetching the JWKS key set is disabled for pinned keys"),
+            Error::JwksHttpStatus(status, body) => write!(f, "JWKS key set request failed with status {}: {}",
+                                                          status, body),
+            Error::Throttled { retry_after } => write!(f, "Key set is currently unreachable (throttled, retry after {}s)",
+                                                       retry_after.as_secs()),
+            Error::CircuitOpen { retry_after } => write!(f, "JWKS endpoint is failing (circuit open, retry after {}s)",
+                                                         retry_after.as_secs()),
+            Error::InvalidConfiguration(details) => write!(f, "Invalid key set configuration: {}", details.desc),
+            Error::UnknownIssuer(Some(iss)) => write!(f, "No key set for token issuer \"{}\"", iss),
+            Error::UnknownIssuer(None) => write!(f, "Token had no 'iss' value"),
+            Error::UnknownKeyId(kid) => write!(f, "Unknown key ID (kid) \"{}\"", kid),
+            Error::CacheMiss { kid: Some(kid), .. } => write!(f, "Failed to lookup corresponding Algorithm / key for key ID (kid) \"{}\"", kid),
+            Error::CacheMiss { kid: None, .. } => write!(f, "Failed to lookup corresponding Algorithm / key"),
+            Error::OAuthError { status, error, error_description: Some(description) } => {
+                write!(f, "OAuth request failed with status {}: {} ({})", status, error, description)
+            }
+            Error::OAuthError { status, error, error_description: None } => {
+                write!(f, "OAuth request failed with status {}: {}", status, error)
+            }
+            Error::UserPoolApiError { status, error_type, message: Some(message) } => {
+                write!(f, "User pool request failed with status {}: {}: {}", status, error_type, message)
+            }
+            Error::UserPoolApiError { status, error_type, message: None } => {
+                write!(f, "User pool request failed with status {}: {}", status, error_type)
+            }
+            Error::InvalidUserPoolResponse(details) => write!(f, "Invalid user pool response: {}", details.desc),
+            Error::UnsupportedChallenge { challenge, .. } => {
+                write!(f, "Unsupported authentication challenge \"{}\"", challenge)
+            }
         }
     }
 }
@@ -95,15 +616,431 @@ impl From<jwt::error::Error> for Error {
         match e {
             JwtError::InvalidSignature() => Error::InvalidSignature(),
             JwtError::TokenExpiredAt(when) => Error::TokenExpiredAt(when),
-            JwtError::MalformedToken(_) => Error::MalformedToken(ErrorDetails::map("Malformed JWT", e)),
-            JwtError::AlgorithmMismatch() => Error::MalformedToken(ErrorDetails::map("Unexpected 'alg' algorithm specified", e)),
+            JwtError::MalformedToken(_) => match claim_mismatch(&e) {
+                Some(mismatch) => mismatch,
+                None => Error::MalformedToken(ErrorDetails::map("Malformed JWT", e)),
+            },
+            JwtError::AlgorithmMismatch() => Error::AlgorithmMismatch(),
             _ => Error::MalformedToken(ErrorDetails::map("Decode failure", e)),
         }
     }
 }
 
+// jsonwebtokens only describes claim verification failures in its error
+// messages, like "Claim aud: foo != bar", and a Verifier's expected values
+// aren't accessible, so the messages are parsed to find which claim failed.
+// The formats are those of jsonwebtokens 1.2 (the version is pinned in
+// Cargo.toml and the claim_mismatch_messages test checks them). A failure
+// that can't be parsed unambiguously is reported as a mismatch of the claim
+// without expected or actual values, rather than guessing at them.
+fn claim_mismatch(e: &JwtError) -> Option<Error> {
+    let message = e.to_string();
+    let (claim, failure) = message.strip_prefix("JWT claims invalid: Claim ")?.split_once(": ")?;
+    let (expected, actual) = parse_claim_failure(failure).unwrap_or((None, None));
+    Some(Error::ClaimMismatch { claim: claim.to_string(), expected, actual })
+}
+
+// The expected and actual values described by a claim failure message
+fn parse_claim_failure(failure: &str) -> Option<(Option<String>, Option<String>)> {
+    if failure == "missing" {
+        Some((None, None))
+    } else if let Some(value) = failure.strip_prefix("verifier callback returned false for '") {
+        let value = value.strip_suffix('\'')?;
+        let actual = match serde_json::from_str(value).ok()? {
+            serde_json::Value::String(value) => value,
+            value => value.to_string(),
+        };
+        Some((None, Some(actual)))
+    } else if let Some(expected) = failure.strip_prefix("array did not contain '") {
+        Some((Some(expected.strip_suffix('\'')?.to_string()), None))
+    } else if let Some(actual) = failure.strip_suffix(" not in set") {
+        Some((None, Some(actual.to_string())))
+    } else if failure.matches(" != ").count() == 1 {
+        // The actual value comes from the token, so if it contains " != " the
+        // values can't be told apart
+        let (actual, expected) = failure.split_once(" != ")?;
+        Some((Some(expected.to_string()), Some(actual.to_string())))
+    } else {
+        // E.g. the claim had an unexpected type
+        None
+    }
+}
+
+// Whether an error in a request's source chain came from the TLS backend
+fn is_tls_error(err: &(dyn StdError + 'static)) -> bool {
+    #[cfg(all(feature = "rustls-tls", not(target_arch = "wasm32")))]
+    if err.is::<rustls::Error>() {
+        return true;
+    }
+    #[cfg(all(feature = "native-tls", not(target_arch = "wasm32")))]
+    if err.is::<native_tls_crate::Error>() {
+        return true;
+    }
+    let _ = err;
+    false
+}
+
 impl From<reqwest::Error> for Error {
     fn from(e: reqwest::Error) -> Self {
-        Error::NetworkError(ErrorDetails::map("Reqwest error", e))
+        if e.is_timeout() {
+            Error::NetworkTimeout(ErrorDetails::map("Request timed out", e))
+        } else {
+            let kind = NetworkErrorKind::of_reqwest_error(&e);
+            Error::NetworkError(ErrorDetails::map(kind.description(), e).with_kind(kind))
+        }
+    }
+}
+
+#[cfg(feature = "reqwest-middleware")]
+impl From<reqwest_middleware::Error> for Error {
+    fn from(e: reqwest_middleware::Error) -> Self {
+        match e {
+            reqwest_middleware::Error::Reqwest(e) => e.into(),
+            reqwest_middleware::Error::Middleware(e) => {
+                Error::NetworkError(ErrorDetails::new(format!("Middleware error: {}", e)))
+            }
+        }
+    }
+}
+
+/// Serializes the error for an API response body, like:
+///
+/// ```json
+/// { "code": "token_expired", "message": "Token expired" }
+/// ```
+///
+/// `code` is the error's `code()` and `message` is its `public_message()`, so
+/// no details of the error are included. See `Error::diagnostic()` for
+/// serializing all of them.
+#[cfg(feature = "serde")]
+impl serde::Serialize for Error {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use serde::ser::SerializeMap;
+
+        let mut map = serializer.serialize_map(Some(2))?;
+        map.serialize_entry("code", self.code())?;
+        map.serialize_entry("message", self.public_message())?;
+        map.end()
+    }
+}
+
+/// Serializes an error with all of its details, for logs and other internal
+/// diagnostics (see `Error::diagnostic()`), like:
+///
+/// ```json
+/// { "code": "claim_mismatch", "status": 401, "message": "JWT claim 'aud' was \"a\", expected \"b\"",
+///   "claim": "aud", "expected": "b", "actual": "a" }
+/// ```
+///
+/// `status` is the error's `http_status()` and `message` is its `Display`
+/// message. Other fields depend on the kind of error, such as the `claim`,
+/// `expected` and `actual` values of a `claim_mismatch`, and the `details` of a
+/// network error with the messages of its chain of `sources`. This can reveal
+/// how tokens are verified, so shouldn't be sent to clients. Secrets, such as
+/// an `UnsupportedChallenge`'s session, aren't included.
+#[cfg(feature = "serde")]
+#[derive(Debug)]
+pub struct DiagnosticError<'a>(&'a Error);
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for DiagnosticError<'_> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use serde::ser::SerializeMap;
+
+        let error = self.0;
+        let mut map = serializer.serialize_map(None)?;
+        map.serialize_entry("code", error.code())?;
+        map.serialize_entry("status", &error.http_status().as_u16())?;
+        map.serialize_entry("message", &error.to_string())?;
+        if let Some(details) = error.details() {
+            map.serialize_entry("details", &DiagnosticDetails(details))?;
+        }
+        match error {
+            Error::TokenExpiredAt(when) => map.serialize_entry("expired_at", when)?,
+            Error::TokenTooLarge { max_size, .. } => map.serialize_entry("max_size", max_size)?,
+            Error::ClaimMismatch { claim, expected, actual } => {
+                map.serialize_entry("claim", claim)?;
+                map.serialize_entry("expected", expected)?;
+                map.serialize_entry("actual", actual)?;
+            }
+            Error::Throttled { retry_after } | Error::CircuitOpen { retry_after } => {
+                map.serialize_entry("retry_after", &retry_after.as_secs())?
+            }
+            Error::UnknownIssuer(iss) => map.serialize_entry("iss", iss)?,
+            Error::UnknownKeyId(kid) => map.serialize_entry("kid", kid)?,
+            Error::NetworkError(_) | Error::JwksHttpStatus(_, _) => {
+                if let Some(kind) = error.network_error_kind() {
+                    map.serialize_entry("network_error_kind", kind.as_str())?;
+                }
+                if let Error::JwksHttpStatus(status, _) = error {
+                    map.serialize_entry("jwks_status", &status.as_u16())?;
+                }
+            }
+            Error::DisallowedAlgorithm(alg) => map.serialize_entry("alg", alg)?,
+            Error::CacheMiss { kid, retry_after, .. } => {
+                map.serialize_entry("kid", kid)?;
+                map.serialize_entry("retry_after", &retry_after.map(|retry_after| retry_after.as_secs()))?;
+            }
+            Error::OAuthError { error, error_description, .. } => {
+                map.serialize_entry("error", error)?;
+                map.serialize_entry("error_description", error_description)?;
+            }
+            Error::UserPoolApiError { error_type, .. } => map.serialize_entry("error_type", error_type)?,
+            Error::UnsupportedChallenge { challenge, .. } => map.serialize_entry("challenge", challenge)?,
+            _ => {}
+        }
+        map.end()
+    }
+}
+
+// Serializes details as their description and the messages of their chain of
+// source errors
+#[cfg(feature = "serde")]
+struct DiagnosticDetails<'a>(&'a ErrorDetails);
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for DiagnosticDetails<'_> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use serde::ser::SerializeMap;
+
+        let mut sources = Vec::new();
+        let mut source = self.0.src.as_deref().map(|src| src as &(dyn StdError + 'static));
+        while let Some(src) = source {
+            sources.push(src.to_string());
+            source = src.source();
+        }
+
+        let mut map = serializer.serialize_map(None)?;
+        map.serialize_entry("description", &self.0.desc)?;
+        if !sources.is_empty() {
+            map.serialize_entry("sources", &sources)?;
+        }
+        map.end()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::value::Value;
+    use serde_json::json;
+
+    use super::*;
+    use crate::KeySet;
+    use crate::test_util::*;
+
+    #[test]
+    fn error_source() {
+        use std::error::Error as StdError;
+
+        let json_error = serde_json::from_str::<Value>("{").unwrap_err();
+        let error = Error::NetworkError(ErrorDetails::map("Failed to parse JWKS key set", json_error));
+        assert_eq!(error.details().unwrap().description(), "Failed to parse JWKS key set");
+        assert!(error.source().unwrap().is::<serde_json::Error>());
+
+        assert!(Error::NetworkError(ErrorDetails::new("Connection refused")).source().is_none());
+        assert!(Error::InvalidSignature().source().is_none());
+    }
+
+    #[test]
+    fn network_error_kinds() {
+        let prefetch = |fetcher| {
+            let keyset = KeySet::builder("eu-west-1", "eu-west-1_AbCd1234").jwks_fetcher(fetcher).build().unwrap();
+            async_std::task::block_on(keyset.prefetch_jwks()).unwrap_err()
+        };
+        let error = prefetch(TestFetcher::status(StatusCode::OK, "<html>"));
+        assert_eq!(error.network_error_kind(), Some(NetworkErrorKind::Decode));
+        assert_eq!(error.duplicate().network_error_kind(), Some(NetworkErrorKind::Decode));
+        assert_eq!(prefetch(TestFetcher::status(StatusCode::BAD_GATEWAY, "")).network_error_kind(),
+                   Some(NetworkErrorKind::Http(StatusCode::BAD_GATEWAY)));
+
+        // reqwest needs a tokio runtime. `.invalid` names never resolve,
+        // nothing listens on port 1, and the local listener only speaks
+        // plaintext HTTP.
+        #[cfg(feature = "tokio")]
+        {
+            use std::io::Write;
+
+            let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+            let port = listener.local_addr().unwrap().port();
+            std::thread::spawn(move || {
+                for mut stream in listener.incoming().flatten() {
+                    let _ = stream.write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\n{}");
+                }
+            });
+
+            let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap();
+            let fetch = |url: String| {
+                let keyset = KeySet::builder("eu-west-1", "eu-west-1_AbCd1234").jwks_url(&url).build().unwrap();
+                runtime.block_on(keyset.prefetch_jwks()).unwrap_err()
+            };
+            let error = fetch("http://cognito-idp.invalid/jwks.json".to_string());
+            assert_eq!(error.network_error_kind(), Some(NetworkErrorKind::Dns));
+            assert_eq!(error.to_string(), "Error fetching JWKS key set: DNS lookup failed");
+            let error = fetch("http://127.0.0.1:1/jwks.json".to_string());
+            assert_eq!(error.network_error_kind(), Some(NetworkErrorKind::Connect));
+            assert_eq!(error.to_string(), "Error fetching JWKS key set: Connection failed");
+            #[cfg(any(feature = "rustls-tls", feature = "native-tls"))]
+            {
+                let error = fetch(format!("https://127.0.0.1:{}/jwks.json", port));
+                assert_eq!(error.network_error_kind(), Some(NetworkErrorKind::Tls));
+                assert_eq!(error.to_string(), "Error fetching JWKS key set: TLS handshake failed");
+            }
+        }
+
+        assert_eq!(Error::InvalidSignature().network_error_kind(), None);
+    }
+
+    #[test]
+    fn error_codes() {
+        assert_eq!(Error::TokenExpiredAt(1600000000).code(), "token_expired");
+        assert_eq!(Error::InvalidSignature().code(), "invalid_signature");
+        assert_eq!(Error::NetworkError(ErrorDetails::new("Connection refused")).code(), "jwks_unreachable");
+        assert_eq!(Error::TokenTooLarge { size: 70000, max_size: 65536 }.code(), "token_too_large");
+        assert_eq!(Error::DisallowedAlgorithm("HS256".to_string()).code(), "disallowed_algorithm");
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn error_serialization() {
+        let json = serde_json::to_value(Error::TokenExpiredAt(1600000000)).unwrap();
+        assert_eq!(json, serde_json::json!({
+            "code": "token_expired",
+            "message": "Token expired"
+        }));
+
+        // Only the diagnostic serialization includes the details
+        let error = Error::ClaimMismatch { claim: "aud".into(), expected: Some("client-id-0".into()), actual: None };
+        assert_eq!(serde_json::to_value(&error).unwrap(), serde_json::json!({
+            "code": "claim_mismatch",
+            "message": "Invalid token claims"
+        }));
+        let json = serde_json::to_value(error.diagnostic()).unwrap();
+        assert_eq!(json["code"], "claim_mismatch");
+        assert_eq!(json["status"], 401);
+        assert_eq!(json["claim"], "aud");
+        assert_eq!(json["expected"], "client-id-0");
+        assert_eq!(json["actual"], serde_json::Value::Null);
+
+        let error = Error::UnsupportedChallenge { challenge: "SMS_MFA".into(), session: Some("secret".into()) };
+        let json = serde_json::to_value(error.diagnostic()).unwrap();
+        assert_eq!(json["challenge"], "SMS_MFA");
+        assert!(!json.to_string().contains("secret"));
+
+        let io_error = std::io::Error::new(std::io::ErrorKind::ConnectionRefused, "Connection refused");
+        let error = Error::NetworkError(ErrorDetails::map("Connection failed", io_error));
+        let json = serde_json::to_value(&error).unwrap();
+        assert!(!json.to_string().contains("refused"));
+        let json = serde_json::to_value(error.diagnostic()).unwrap();
+        assert_eq!(json["status"], 502);
+        assert_eq!(json["details"], serde_json::json!({
+            "description": "Connection failed",
+            "sources": ["Connection refused"]
+        }));
+    }
+
+    #[test]
+    fn claim_mismatch() {
+        let keyset = test_keyset();
+        let verifier = keyset.new_issuer_verifier()
+            .string_equals("token_use", "access")
+            .string_equals_one_of("client_id", &["client-id-0", "client-id-1"])
+            .build()
+            .unwrap();
+
+        let claims = json!({ "iss": TEST_ISS,
+                             "token_use": "id", "client_id": "client-id-0" });
+        let token = hs256_token(&claims);
+        match keyset.try_verify(&token, &verifier) {
+            Err(Error::ClaimMismatch { claim, expected, actual }) => {
+                assert_eq!(claim, "token_use");
+                assert_eq!(expected.as_deref(), Some("access"));
+                assert_eq!(actual.as_deref(), Some("id"));
+            }
+            other => panic!("Expected a ClaimMismatch error, got {:?}", other),
+        }
+
+        let claims = json!({ "iss": TEST_ISS,
+                             "token_use": "access", "client_id": "client-id-2" });
+        let token = hs256_token(&claims);
+        match keyset.try_verify(&token, &verifier) {
+            Err(Error::ClaimMismatch { claim, expected: None, actual }) => {
+                assert_eq!(claim, "client_id");
+                assert_eq!(actual.as_deref(), Some("client-id-2"));
+            }
+            other => panic!("Expected a ClaimMismatch error, got {:?}", other),
+        }
+
+        let token = hs256_token(&json!({ "iss": TEST_ISS }));
+        match keyset.try_verify(&token, &verifier) {
+            Err(Error::ClaimMismatch { claim, expected: None, actual: None }) => {
+                assert!(claim == "token_use" || claim == "client_id");
+            }
+            other => panic!("Expected a ClaimMismatch error, got {:?}", other),
+        }
+    }
+
+    // Pins the formats of jsonwebtokens' claim failure messages that
+    // claim_mismatch() parses, so an upgrade that changes them fails here
+    #[test]
+    fn claim_mismatch_messages() {
+        let verifier = jwt::Verifier::create()
+            .string_equals("token_use", "access")
+            .string_equals_one_of("client_id", &["client-id-0"])
+            .string_or_array_contains("aud", "aud-0")
+            .claim_callback("auth_time", |value| value.is_u64())
+            .build()
+            .unwrap();
+        let claims = json!({ "token_use": "access", "client_id": "client-id-0", "aud": ["aud-0"], "auth_time": 0 });
+        let failure = |claim: &str, value: Value| {
+            let mut claims = claims.clone();
+            match value {
+                Value::Null => claims.as_object_mut().unwrap().remove(claim),
+                value => claims.as_object_mut().unwrap().insert(claim.to_string(), value),
+            };
+            let e = verifier.verify_claims_only(&claims, 0).unwrap_err();
+            (e.to_string(), Error::from(e))
+        };
+        let mismatch = |claim: &str, expected: Option<&str>, actual: Option<&str>| Error::ClaimMismatch {
+            claim: claim.to_string(),
+            expected: expected.map(str::to_string),
+            actual: actual.map(str::to_string),
+        };
+
+        let cases = vec![
+            (failure("token_use", json!("id")), "JWT claims invalid: Claim token_use: id != access",
+             mismatch("token_use", Some("access"), Some("id"))),
+            (failure("token_use", json!("a != b")), "JWT claims invalid: Claim token_use: a != b != access",
+             mismatch("token_use", None, None)),
+            (failure("client_id", json!("client-id-1")), "JWT claims invalid: Claim client_id: client-id-1 not in set",
+             mismatch("client_id", None, Some("client-id-1"))),
+            (failure("aud", json!(["aud-1"])), "JWT claims invalid: Claim aud: array did not contain 'aud-0'",
+             mismatch("aud", Some("aud-0"), None)),
+            (failure("auth_time", json!("now")),
+             "JWT claims invalid: Claim auth_time: verifier callback returned false for '\"now\"'",
+             mismatch("auth_time", None, Some("now"))),
+            (failure("token_use", Value::Null), "JWT claims invalid: Claim token_use: missing",
+             mismatch("token_use", None, None)),
+            (failure("token_use", json!(1)), "JWT claims invalid: Claim token_use: has unexpected type (Number)",
+             mismatch("token_use", None, None)),
+        ];
+        for ((message, error), expected_message, expected) in cases {
+            assert_eq!(message, expected_message);
+            assert_eq!(format!("{:?}", error), format!("{:?}", expected));
+        }
+    }
+
+    #[test]
+    fn http_status() {
+        assert_eq!(Error::InvalidSignature().http_status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(Error::TokenExpiredAt(0).http_status(), StatusCode::UNAUTHORIZED);
+        let mismatch = |claim: &str| Error::ClaimMismatch { claim: claim.to_string(), expected: None, actual: None };
+        assert_eq!(mismatch("aud").http_status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(mismatch("scope").http_status(), StatusCode::FORBIDDEN);
+        assert_eq!(mismatch("cognito:groups").http_status(), StatusCode::FORBIDDEN);
+        assert_eq!(Error::NetworkTimeout(ErrorDetails::new("Request timed out")).http_status(), StatusCode::BAD_GATEWAY);
+        assert_eq!(Error::Throttled { retry_after: Duration::from_secs(1) }.http_status(),
+                   StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(Error::InvalidConfiguration(ErrorDetails::new("No issuer given")).http_status(),
+                   StatusCode::INTERNAL_SERVER_ERROR);
     }
 }
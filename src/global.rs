@@ -0,0 +1,98 @@
+use std::collections::HashMap;
+use std::sync::{Mutex, OnceLock};
+
+use crate::{AccessTokenClaims, Error, IdTokenClaims, KeySet};
+
+// The KeySets used by `verify_id_token()` and `verify_access_token()`, by
+// region and user pool ID, so that keys are only fetched once per process
+static KEYSETS: OnceLock<Mutex<HashMap<(String, String), KeySet>>> = OnceLock::new();
+
+pub(crate) fn global_keyset(region: &str, pool_id: &str) -> Result<KeySet, Error> {
+    let mut keysets = KEYSETS.get_or_init(Default::default).lock().unwrap();
+    let key = (region.to_string(), pool_id.to_string());
+    if let Some(keyset) = keysets.get(&key) {
+        return Ok(keyset.clone());
+    }
+    let keyset = KeySet::new(region, pool_id)?;
+    keysets.insert(key, keyset.clone());
+    Ok(keyset)
+}
+
+/// Verifies a Cognito ID token issued to the given app client, in one call
+///
+/// This is a shortcut for small scripts and Lambda functions. The KeySet for
+/// the user pool is created on first use and kept for the life of the
+/// process, so its keys are only fetched once, and the token is verified with
+/// `KeySet::id_token_verifier_cached()`.
+///
+/// ```no_run
+/// # async fn example(token: &str) -> Result<(), jsonwebtokens_cognito::Error> {
+/// let claims = jsonwebtokens_cognito::verify_id_token("eu-west-1", "eu-west-1_AbCd1234",
+///                                                     "client-id-0", token).await?;
+/// println!("Hello {}", claims.username);
+/// # Ok(())
+/// # }
+/// ```
+///
+/// A [KeySet] should be used instead for anything more, such as custom claims
+/// or configuring how the key set is fetched.
+pub async fn verify_id_token(
+    region: &str,
+    pool_id: &str,
+    client_id: &str,
+    token: &str
+) -> Result<IdTokenClaims, Error> {
+    let keyset = global_keyset(region, pool_id)?;
+    let verifier = keyset.id_token_verifier_cached(&[client_id])?;
+    keyset.verify_into(token, &verifier).await
+}
+
+/// Verifies a Cognito access token issued to the given app client, in one call
+/// (see [verify_id_token])
+pub async fn verify_access_token(
+    region: &str,
+    pool_id: &str,
+    client_id: &str,
+    token: &str
+) -> Result<AccessTokenClaims, Error> {
+    let keyset = global_keyset(region, pool_id)?;
+    let verifier = keyset.access_token_verifier_cached(&[client_id])?;
+    keyset.verify_into(token, &verifier).await
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::unix_time_now;
+    use crate::test_util::*;
+
+    #[test]
+    fn one_shot_verify_id_token() {
+        // The global KeySet is shared, so its key can be inserted up front
+        let keyset = global_keyset("eu-west-1", "eu-west-1_OneShot12").unwrap();
+        assert_eq!(keyset, global_keyset("eu-west-1", "eu-west-1_OneShot12").unwrap());
+        insert_test_key(&keyset, "test-key", "secret");
+        let now = unix_time_now().unwrap();
+        let claims = json!({
+            "sub": "aaaaaaaa-bbbb-cccc-dddd-eeeeeeeeeeee",
+            "iss": "https://cognito-idp.eu-west-1.amazonaws.com/eu-west-1_OneShot12",
+            "aud": "client-id-0",
+            "token_use": "id",
+            "exp": now + 60,
+            "iat": now,
+            "auth_time": now,
+            "cognito:username": "alice",
+        });
+        let token = hs256_token(&claims);
+
+        let verify = |client_id| async_std::task::block_on(
+            verify_id_token("eu-west-1", "eu-west-1_OneShot12", client_id, &token));
+        assert_eq!(verify("client-id-0").unwrap().username, "alice");
+        assert!(matches!(verify("client-id-1"), Err(Error::ClaimMismatch { .. })));
+        assert!(matches!(async_std::task::block_on(
+            verify_access_token("eu-west-1", "eu-west-1_OneShot12", "client-id-0", &token)),
+            Err(Error::ClaimMismatch { .. })));
+    }
+}
@@ -0,0 +1,225 @@
+//! Verification of access tokens for [tonic](https://crates.io/crates/tonic)
+//! gRPC services
+//!
+//! Tokens are read from the `authorization` metadata of requests (as
+//! `Bearer <token>`) and the verified claims are added to the request
+//! extensions as [VerifiedClaims].
+//!
+//! There are two ways of verifying tokens:
+//!
+//! - [CognitoInterceptor] is a tonic `Interceptor`. Interceptors can't be
+//!   async, so tokens are verified with `KeySet::try_verify()` and only keys
+//!   that are already cached can be used. The key set should be kept warm in
+//!   the background, such as with `KeySet::spawn_refresh_task()` (with the
+//!   `tokio` feature).
+//! - [CognitoGrpcLayer] is a tower layer that can be added to a tonic server
+//!   and verifies tokens asynchronously with `KeySet::verify()`, fetching keys
+//!   as needed.
+//!
+//! Invalid or missing tokens are rejected with `Status::unauthenticated` (or
+//! `Status::permission_denied` for tokens that lack a required scope or group,
+//! or whose claims are denied by a [Policy] given to `policy()`),
+//! while failing to get the keys needed to verify a token results in
+//! `Status::unavailable`.
+//!
+//! ```no_run
+//! # use jsonwebtokens_cognito::KeySet;
+//! # use jsonwebtokens_cognito::grpc::{CognitoInterceptor, VerifiedClaims};
+//! # use tonic::{Request, Status};
+//! fn username(request: &Request<()>) -> Result<String, Status> {
+//!     let claims = request.extensions().get::<VerifiedClaims>()
+//!         .ok_or_else(|| Status::internal("Missing claims"))?;
+//!     Ok(claims.0["username"].as_str().unwrap_or_default().to_string())
+//! }
+//!
+//! # fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! let keyset = KeySet::new("eu-west-1", "eu-west-1_AbCd1234")?;
+//! let verifier = keyset.new_access_token_verifier(&["client-id-0"]).build()?;
+//!
+//! let interceptor = CognitoInterceptor::new(keyset, verifier);
+//! // MyServiceServer::with_interceptor(my_service, interceptor)
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! _Requires the `grpc` feature_
+
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+use http::header::AUTHORIZATION;
+use http::{Request, Response, StatusCode};
+use tonic::body::BoxBody;
+use tonic::metadata::MetadataMap;
+use tonic::service::Interceptor;
+use tonic::Status;
+use tower_layer::Layer;
+use serde_json::value::Value;
+use tower_service::Service;
+
+use jsonwebtokens::Verifier;
+
+use crate::{Error, KeySet, Policy};
+use crate::http_util::parse_bearer;
+
+pub use crate::tower::VerifiedClaims;
+
+fn missing_token() -> Status {
+    Status::unauthenticated("Missing bearer token")
+}
+
+// Maps errors to gRPC statuses in line with the HTTP statuses used by the
+// other integrations
+fn grpc_status(e: &Error) -> Status {
+    match e.http_status() {
+        StatusCode::INTERNAL_SERVER_ERROR => Status::internal(e.to_string()),
+        StatusCode::FORBIDDEN => Status::permission_denied(e.to_string()),
+        status if status.is_server_error() => Status::unavailable(e.to_string()),
+        _ => Status::unauthenticated(e.to_string()),
+    }
+}
+
+fn check_policy(policy: &Policy, claims: Value) -> Result<Value, Error> {
+    policy.check(&claims).map_err(Error::PolicyDenied)?;
+    Ok(claims)
+}
+
+// Returns the token from `authorization: Bearer <token>` metadata
+fn bearer_token(metadata: &MetadataMap) -> Option<&str> {
+    parse_bearer(metadata.get(AUTHORIZATION.as_str())?.to_str().ok()?)
+}
+
+/// A tonic interceptor that verifies access tokens with already cached keys
+/// (see the [module documentation](self))
+#[derive(Clone)]
+pub struct CognitoInterceptor {
+    keyset: KeySet,
+    verifier: Arc<Verifier>,
+    policy: Arc<Policy>,
+}
+
+impl CognitoInterceptor {
+    /// Constructs an interceptor that verifies tokens with the given KeySet and Verifier
+    pub fn new(keyset: KeySet, verifier: impl Into<Arc<Verifier>>) -> Self {
+        CognitoInterceptor {
+            keyset,
+            verifier: verifier.into(),
+            policy: Arc::new(Policy::new()),
+        }
+    }
+
+    /// Rejects requests whose verified claims are denied by the given policy,
+    /// with `Status::permission_denied`
+    pub fn policy(mut self, policy: Policy) -> Self {
+        self.policy = Arc::new(policy);
+        self
+    }
+}
+
+impl Interceptor for CognitoInterceptor {
+    fn call(&mut self, mut request: tonic::Request<()>) -> Result<tonic::Request<()>, Status> {
+        let token = bearer_token(request.metadata()).ok_or_else(missing_token)?;
+        let claims = self.keyset.try_verify(token, &self.verifier)
+            .and_then(|claims| check_policy(&self.policy, claims))
+            .map_err(|e| grpc_status(&e))?;
+        request.extensions_mut().insert(VerifiedClaims(claims));
+        Ok(request)
+    }
+}
+
+/// A tower layer for tonic servers that verifies access tokens, fetching keys
+/// as needed (see the [module documentation](self))
+#[derive(Clone)]
+pub struct CognitoGrpcLayer {
+    keyset: KeySet,
+    verifier: Arc<Verifier>,
+    policy: Arc<Policy>,
+}
+
+impl CognitoGrpcLayer {
+    /// Constructs a layer that verifies tokens with the given KeySet and Verifier
+    pub fn new(keyset: KeySet, verifier: impl Into<Arc<Verifier>>) -> Self {
+        CognitoGrpcLayer {
+            keyset,
+            verifier: verifier.into(),
+            policy: Arc::new(Policy::new()),
+        }
+    }
+
+    /// Rejects requests whose verified claims are denied by the given policy,
+    /// with `Status::permission_denied`
+    pub fn policy(mut self, policy: Policy) -> Self {
+        self.policy = Arc::new(policy);
+        self
+    }
+}
+
+impl<S> Layer<S> for CognitoGrpcLayer {
+    type Service = CognitoGrpcService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        CognitoGrpcService {
+            inner,
+            keyset: self.keyset.clone(),
+            verifier: self.verifier.clone(),
+            policy: self.policy.clone(),
+        }
+    }
+}
+
+/// The service created by a [CognitoGrpcLayer]
+#[derive(Clone)]
+pub struct CognitoGrpcService<S> {
+    inner: S,
+    keyset: KeySet,
+    verifier: Arc<Verifier>,
+    policy: Arc<Policy>,
+}
+
+impl<S, ReqBody> Service<Request<ReqBody>> for CognitoGrpcService<S>
+where
+    S: Service<Request<ReqBody>, Response = Response<BoxBody>> + Clone + Send + 'static,
+    S::Future: Send + 'static,
+    ReqBody: Send + 'static,
+{
+    type Response = Response<BoxBody>;
+    type Error = S::Error;
+    #[allow(clippy::type_complexity)]
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, S::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut req: Request<ReqBody>) -> Self::Future {
+        // Take the service that was driven to readiness, leaving a clone in its place
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+        let keyset = self.keyset.clone();
+        let verifier = self.verifier.clone();
+        let policy = self.policy.clone();
+        let token = req.headers().get(AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(parse_bearer)
+            .map(String::from);
+
+        Box::pin(async move {
+            let token = match token {
+                Some(token) => token,
+                None => return Ok(missing_token().to_http()),
+            };
+
+            let result = keyset.verify(&token, &verifier).await
+                .and_then(|claims| check_policy(&policy, claims));
+            match result {
+                Ok(claims) => {
+                    req.extensions_mut().insert(VerifiedClaims(claims));
+                    inner.call(req).await
+                }
+                Err(e) => Ok(grpc_status(&e).to_http()),
+            }
+        })
+    }
+}
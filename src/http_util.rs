@@ -0,0 +1,17 @@
+// Helpers shared by the web framework integrations
+
+// Returns the token from an `Authorization: Bearer <token>` header value
+pub(crate) fn parse_bearer(authorization: &str) -> Option<&str> {
+    let (scheme, token) = authorization.split_once(' ')?;
+    if scheme.eq_ignore_ascii_case("bearer") {
+        Some(token.trim())
+    } else {
+        None
+    }
+}
+
+// Checks the space separated `scope` claim of an access token
+#[cfg(feature = "actix")]
+pub(crate) fn has_scope(claims: &serde_json::Value, scope: &str) -> bool {
+    crate::Scopes::from_claims(claims).contains(scope)
+}
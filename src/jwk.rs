@@ -0,0 +1,262 @@
+use std::fmt;
+use std::sync::Arc;
+
+use base64::Engine;
+use base64::engine::general_purpose::{STANDARD, URL_SAFE_NO_PAD};
+use serde::Deserialize;
+use serde_json::value::Value;
+
+use jsonwebtokens as jwt;
+use jwt::{Algorithm, AlgorithmID};
+
+#[derive(Debug, Deserialize, Clone)]
+struct RSAKey {
+    kid: String,
+    alg: Option<String>,
+    n: String,
+    e: String,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+struct ECKey {
+    kid: String,
+    alg: Option<String>,
+    crv: String,
+    x: String,
+    y: String,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+#[serde(tag = "kty")]
+enum Jwk {
+    #[serde(rename = "RSA")]
+    Rsa(RSAKey),
+    #[serde(rename = "EC")]
+    Ec(ECKey),
+}
+
+// The DER encoded SubjectPublicKeyInfo header for an uncompressed P-256 or
+// P-384 public key, which is followed by the key's x and y coordinates
+const P256_SPKI_PREFIX: &[u8] = &[
+    0x30, 0x59, 0x30, 0x13, 0x06, 0x07, 0x2a, 0x86, 0x48, 0xce, 0x3d, 0x02, 0x01,
+    0x06, 0x08, 0x2a, 0x86, 0x48, 0xce, 0x3d, 0x03, 0x01, 0x07, 0x03, 0x42, 0x00, 0x04,
+];
+const P384_SPKI_PREFIX: &[u8] = &[
+    0x30, 0x76, 0x30, 0x10, 0x06, 0x07, 0x2a, 0x86, 0x48, 0xce, 0x3d, 0x02, 0x01,
+    0x06, 0x05, 0x2b, 0x81, 0x04, 0x00, 0x22, 0x03, 0x62, 0x00, 0x04,
+];
+
+impl RSAKey {
+    fn to_algorithm(&self) -> Result<Algorithm, String> {
+        // Cognito keys always specify an alg, and it's always RS256
+        let id = match self.alg.as_deref().unwrap_or("RS256") {
+            "RS256" => AlgorithmID::RS256,
+            "RS384" => AlgorithmID::RS384,
+            "RS512" => AlgorithmID::RS512,
+            "PS256" => AlgorithmID::PS256,
+            "PS384" => AlgorithmID::PS384,
+            "PS512" => AlgorithmID::PS512,
+            alg => return Err(format!("Unsupported algorithm '{}'", alg)),
+        };
+
+        Algorithm::new_rsa_n_e_b64_verifier(id, &self.n, &self.e)
+            .map_err(|e| format!("Invalid RSA key: {}", e))
+    }
+}
+
+impl ECKey {
+    fn to_algorithm(&self) -> Result<Algorithm, String> {
+        let (id, prefix, coord_len) = match self.crv.as_str() {
+            "P-256" => (AlgorithmID::ES256, P256_SPKI_PREFIX, 32),
+            "P-384" => (AlgorithmID::ES384, P384_SPKI_PREFIX, 48),
+            crv => return Err(format!("Unsupported curve '{}'", crv)),
+        };
+        if let Some(alg) = &self.alg {
+            let expected: &str = id.into();
+            if alg != expected {
+                return Err(format!("Algorithm '{}' doesn't match curve '{}'", alg, self.crv));
+            }
+        }
+
+        let x = URL_SAFE_NO_PAD.decode(&self.x).map_err(|e| format!("Invalid EC key x coordinate: {}", e))?;
+        let y = URL_SAFE_NO_PAD.decode(&self.y).map_err(|e| format!("Invalid EC key y coordinate: {}", e))?;
+        if x.len() != coord_len || y.len() != coord_len {
+            return Err(format!("Invalid EC key coordinates for curve '{}'", self.crv));
+        }
+
+        // jsonwebtokens can only construct an ECDSA verifier from a PEM key
+        let mut der = prefix.to_vec();
+        der.extend_from_slice(&x);
+        der.extend_from_slice(&y);
+        let pem = format!("-----BEGIN PUBLIC KEY-----\n{}\n-----END PUBLIC KEY-----\n", STANDARD.encode(der));
+
+        Algorithm::new_ecdsa_pem_verifier(id, pem.as_bytes())
+            .map_err(|e| format!("Invalid EC key: {}", e))
+    }
+}
+
+// Keys are parsed individually so that one malformed or unsupported key
+// doesn't stop us from using the rest of the set
+#[derive(Debug, Deserialize)]
+pub(crate) struct JwkSet {
+    keys: Vec<Value>,
+}
+
+/// A key from a JWKS key set that was skipped because it's malformed or not
+/// supported
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SkippedKey {
+    /// The key's `kid`, if it had one
+    pub kid: Option<String>,
+
+    /// Why the key was skipped
+    pub reason: String,
+}
+
+#[derive(Clone)]
+pub(crate) struct SkippedKeyCallback(pub(crate) Arc<dyn Fn(&SkippedKey) + Send + Sync>);
+
+impl fmt::Debug for SkippedKeyCallback {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "SkippedKeyCallback")
+    }
+}
+
+impl JwkSet {
+    pub(crate) fn new(keys: Vec<Value>) -> Self {
+        JwkSet { keys }
+    }
+
+    // Returns an Algorithm, with its kid and JWK, for each supported key along
+    // with the details of any keys that were skipped
+    pub(crate) fn into_algorithms(self) -> (Vec<(String, Algorithm, Value)>, Vec<SkippedKey>) {
+        let mut algorithms = Vec::new();
+        let mut skipped = Vec::new();
+
+        for value in self.keys.into_iter() {
+            let kid = match value.get("kid") {
+                Some(Value::String(kid)) => Some(kid.clone()),
+                _ => None,
+            };
+            let skip = |reason: String| SkippedKey { kid: kid.clone(), reason };
+
+            // Only signing keys can verify tokens (a key without a `use` may be
+            // used for anything)
+            match value.get("use") {
+                None => {}
+                Some(Value::String(key_use)) if key_use == "sig" => {}
+                Some(key_use) => {
+                    skipped.push(skip(format!("Not a signing key (its 'use' is {})", key_use)));
+                    continue;
+                }
+            }
+
+            let key: Jwk = match serde_json::from_value(value.clone()) {
+                Ok(key) => key,
+                Err(e) => {
+                    skipped.push(skip(format!("Not a supported RSA or EC key: {}", e)));
+                    continue;
+                }
+            };
+
+            let (kid, algorithm) = match &key {
+                Jwk::Rsa(key) => (&key.kid, key.to_algorithm()),
+                Jwk::Ec(key) => (&key.kid, key.to_algorithm()),
+            };
+            match algorithm {
+                Ok(mut algorithm) => {
+                    // By associating a kid here we will essentially be double checking
+                    // that we only verify a token with the key matching its associated kid
+                    // (once by us and jsonwebtokens will also check too)
+                    algorithm.set_kid(kid);
+                    algorithms.push((kid.clone(), algorithm, value));
+                }
+                Err(reason) => skipped.push(skip(reason)),
+            }
+        }
+
+        (algorithms, skipped)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use async_trait::async_trait;
+    use reqwest::StatusCode;
+    use serde_json::json;
+
+    use super::*;
+    use crate::{Error, JwksFetcher, JwksRequest, JwksResponse, KeySet};
+    use crate::test_util::*;
+
+    #[test]
+    fn tolerant_jwks_parsing() {
+        let mut jwks: Value = serde_json::from_str(TEST_JWKS).unwrap();
+        let keys = jwks["keys"].as_array_mut().unwrap();
+        keys.push(json!({ "kid": "bad-ec-key", "kty": "EC", "alg": "ES256", "crv": "P-256", "x": "x", "y": "y" }));
+        keys.push(json!({ "kid": "no-exponent", "kty": "RSA", "alg": "RS256", "n": "AQAB" }));
+        keys.push(json!({ "kty": "oct" }));
+
+        let jwks: JwkSet = serde_json::from_value(jwks).unwrap();
+        let (algorithms, skipped) = jwks.into_algorithms();
+        let kids: Vec<&str> = algorithms.iter().map(|(kid, _, _)| kid.as_str()).collect();
+        assert_eq!(kids, ["test-key-0", "test-key-1"]);
+        let skipped_kids: Vec<Option<&str>> = skipped.iter().map(|key| key.kid.as_deref()).collect();
+        assert_eq!(skipped_kids, [Some("bad-ec-key"), Some("no-exponent"), None]);
+    }
+
+    #[test]
+    fn key_use() {
+        let mut jwks: Value = serde_json::from_str(TEST_JWKS).unwrap();
+        let keys = jwks["keys"].as_array_mut().unwrap();
+        let mut encryption_key = keys[0].clone();
+        encryption_key["kid"] = json!("enc-key");
+        encryption_key["use"] = json!("enc");
+        let mut unspecified_key = keys[1].clone();
+        unspecified_key["kid"] = json!("any-key");
+        unspecified_key.as_object_mut().unwrap().remove("use");
+        keys.push(encryption_key);
+        keys.push(unspecified_key);
+
+        let jwks: JwkSet = serde_json::from_value(jwks).unwrap();
+        let (algorithms, skipped) = jwks.into_algorithms();
+        let kids: Vec<&str> = algorithms.iter().map(|(kid, _, _)| kid.as_str()).collect();
+        assert_eq!(kids, ["test-key-0", "test-key-1", "any-key"]);
+        assert_eq!(skipped, [SkippedKey {
+            kid: Some("enc-key".to_string()),
+            reason: "Not a signing key (its 'use' is \"enc\")".to_string(),
+        }]);
+    }
+
+    #[test]
+    fn skipped_keys_report() {
+        #[derive(Debug)]
+        struct JwksFetcherWithBadKey;
+
+        #[async_trait]
+        impl JwksFetcher for JwksFetcherWithBadKey {
+            async fn fetch_jwks(&self, _request: &JwksRequest<'_>) -> Result<JwksResponse, Error> {
+                let mut jwks: Value = serde_json::from_str(TEST_JWKS).unwrap();
+                jwks["keys"].as_array_mut().unwrap()
+                    .push(json!({ "kid": "no-exponent", "kty": "RSA", "alg": "RS256", "n": "AQAB" }));
+                Ok(JwksResponse::new(StatusCode::OK, jwks.to_string()))
+            }
+        }
+
+        let skipped_count = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let skipped_count_clone = skipped_count.clone();
+        let keyset = KeySet::builder("eu-west-1", "eu-west-1_AbCd1234")
+            .jwks_fetcher(JwksFetcherWithBadKey)
+            .on_skipped_key(move |_| { skipped_count_clone.fetch_add(1, std::sync::atomic::Ordering::SeqCst); })
+            .build()
+            .unwrap();
+        assert!(keyset.skipped_keys().is_empty());
+
+        let report = async_std::task::block_on(keyset.refresh_jwks()).unwrap();
+        assert_eq!(report.added, vec!["test-key-0", "test-key-1"]);
+        assert_eq!(report.skipped.len(), 1);
+        assert_eq!(report.skipped[0].kid.as_deref(), Some("no-exponent"));
+        assert_eq!(keyset.skipped_keys(), report.skipped);
+        assert_eq!(skipped_count.load(std::sync::atomic::Ordering::SeqCst), 1);
+    }
+}
@@ -0,0 +1,319 @@
+//! Helpers for writing API Gateway Lambda authorizers
+//!
+//! [LambdaAuthorizer] takes an authorizer event (deserialized as an
+//! [AuthorizerRequest]), verifies its token with a KeySet and produces either
+//! an IAM policy [PolicyResponse] (for REST API authorizers) or a
+//! [SimpleResponse] (for HTTP API authorizers using the simple response
+//! format). The verified claims are mapped into the authorizer `context`.
+//!
+//! The types only depend on serde, so they can be used with any Lambda
+//! runtime, such as `lambda_runtime`:
+//!
+//! ```no_run
+//! # use jsonwebtokens_cognito::KeySet;
+//! # use jsonwebtokens_cognito::lambda::{AuthorizerRequest, LambdaAuthorizer, PolicyResponse};
+//! async fn handler(authorizer: &LambdaAuthorizer, event: AuthorizerRequest)
+//!     -> Result<PolicyResponse, &'static str>
+//! {
+//!     // API Gateway responds with a 401 status if the authorizer fails with
+//!     // "Unauthorized"
+//!     authorizer.policy_response(&event).await.map_err(|_| "Unauthorized")
+//! }
+//!
+//! # fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! let keyset = KeySet::new("eu-west-1", "eu-west-1_AbCd1234")?;
+//! let verifier = keyset.new_access_token_verifier(&["client-id-0"]).build()?;
+//! let authorizer = LambdaAuthorizer::new(keyset, verifier);
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! _Requires the `lambda` feature_
+
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+use serde_json::value::Value;
+
+use jsonwebtokens::Verifier;
+
+use crate::{Error, ErrorDetails, KeySet, Policy};
+use crate::http_util::parse_bearer;
+
+/// An API Gateway authorizer event
+///
+/// This covers the `TOKEN` and `REQUEST` authorizer events of REST APIs and
+/// the version 1.0 and 2.0 authorizer events of HTTP APIs, and only includes
+/// the fields needed to find the token and build a response.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AuthorizerRequest {
+    /// `TOKEN` or `REQUEST`
+    #[serde(rename = "type", default)]
+    pub type_: Option<String>,
+
+    /// The payload format version of HTTP API events
+    #[serde(default)]
+    pub version: Option<String>,
+
+    /// The token of `TOKEN` authorizer events
+    #[serde(default)]
+    pub authorization_token: Option<String>,
+
+    /// The ARN of the method being called (REST API and version 1.0 events)
+    #[serde(default)]
+    pub method_arn: Option<String>,
+
+    /// The ARN of the route being called (version 2.0 events)
+    #[serde(default)]
+    pub route_arn: Option<String>,
+
+    /// The identity sources of `REQUEST` events, which is a string for
+    /// version 1.0 events and an array of strings for version 2.0 events
+    #[serde(default)]
+    pub identity_source: Option<Value>,
+
+    #[serde(default)]
+    pub headers: Option<HashMap<String, String>>,
+}
+
+impl AuthorizerRequest {
+    /// Returns the token of the request
+    ///
+    /// This is the `authorizationToken` of `TOKEN` events, otherwise the
+    /// `Authorization` header or the first identity source. A `Bearer` prefix
+    /// is removed, if present.
+    pub fn token(&self) -> Option<&str> {
+        let header = self.headers.as_ref().and_then(|headers| {
+            headers.iter()
+                .find(|(name, _)| name.eq_ignore_ascii_case("authorization"))
+                .map(|(_, value)| value.as_str())
+        });
+        let identity_source = match &self.identity_source {
+            Some(Value::String(source)) => Some(source.as_str()),
+            Some(Value::Array(sources)) => sources.first().and_then(Value::as_str),
+            _ => None,
+        };
+
+        let token = self.authorization_token.as_deref().or(header).or(identity_source)?;
+        Some(parse_bearer(token).unwrap_or(token))
+    }
+
+    /// Returns the ARN of the method or route being called
+    pub fn resource_arn(&self) -> Option<&str> {
+        self.method_arn.as_deref().or(self.route_arn.as_deref())
+    }
+}
+
+/// A statement of an IAM policy document
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct PolicyStatement {
+    pub action: String,
+    pub effect: String,
+    pub resource: String,
+}
+
+/// An IAM policy document
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct PolicyDocument {
+    pub version: String,
+    pub statement: Vec<PolicyStatement>,
+}
+
+/// An IAM policy response for REST API (and version 1.0 HTTP API) authorizers
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PolicyResponse {
+    pub principal_id: String,
+    pub policy_document: PolicyDocument,
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub context: HashMap<String, Value>,
+}
+
+impl PolicyResponse {
+    fn new(principal_id: &str, effect: &str, resource: &str) -> Self {
+        PolicyResponse {
+            principal_id: principal_id.to_string(),
+            policy_document: PolicyDocument {
+                version: "2012-10-17".to_string(),
+                statement: vec![PolicyStatement {
+                    action: "execute-api:Invoke".to_string(),
+                    effect: effect.to_string(),
+                    resource: resource.to_string(),
+                }],
+            },
+            context: HashMap::new(),
+        }
+    }
+
+    /// Constructs a policy that allows the principal to invoke the resource
+    pub fn allow(principal_id: &str, resource: &str) -> Self {
+        PolicyResponse::new(principal_id, "Allow", resource)
+    }
+
+    /// Constructs a policy that denies the principal from invoking the
+    /// resource, which API Gateway responds to with a 403 status
+    pub fn deny(principal_id: &str, resource: &str) -> Self {
+        PolicyResponse::new(principal_id, "Deny", resource)
+    }
+}
+
+/// A simple response for HTTP API authorizers
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SimpleResponse {
+    pub is_authorized: bool,
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub context: HashMap<String, Value>,
+}
+
+/// Maps claims into an authorizer context
+///
+/// Context values can only be strings, numbers or booleans, so other values
+/// (such as `cognito:groups`) are serialized as JSON strings.
+pub fn claims_context(claims: &Value) -> HashMap<String, Value> {
+    let claims = match claims.as_object() {
+        Some(claims) => claims,
+        None => return HashMap::new(),
+    };
+
+    claims.iter()
+        .filter(|(_, value)| !value.is_null())
+        .map(|(name, value)| {
+            let value = match value {
+                Value::String(_) | Value::Number(_) | Value::Bool(_) => value.clone(),
+                _ => Value::String(value.to_string()),
+            };
+            (name.clone(), value)
+        })
+        .collect()
+}
+
+/// Verifies the tokens of API Gateway authorizer events (see the
+/// [module documentation](self))
+#[derive(Clone)]
+pub struct LambdaAuthorizer {
+    keyset: KeySet,
+    verifier: Verifier,
+    policy: Policy,
+}
+
+impl LambdaAuthorizer {
+    /// Constructs an authorizer that verifies tokens with the given KeySet and Verifier
+    pub fn new(keyset: KeySet, verifier: Verifier) -> Self {
+        LambdaAuthorizer { keyset, verifier, policy: Policy::new() }
+    }
+
+    /// Denies requests whose verified claims aren't allowed by the given policy
+    pub fn policy(mut self, policy: Policy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    /// Verifies the token of an authorizer event and returns its claims
+    ///
+    /// Claims that are denied by the authorizer's policy result in a
+    /// `PolicyDenied` error.
+    pub async fn verify_request(&self, request: &AuthorizerRequest) -> Result<Value, Error> {
+        let claims = self.verify_token(request).await?;
+        self.policy.check(&claims).map_err(Error::PolicyDenied)?;
+        Ok(claims)
+    }
+
+    async fn verify_token(&self, request: &AuthorizerRequest) -> Result<Value, Error> {
+        let token = request.token()
+            .ok_or_else(|| Error::MalformedToken(ErrorDetails::new("No token in authorizer request")))?;
+        self.keyset.verify(token, &self.verifier).await
+    }
+
+    /// Verifies the token of an authorizer event and returns a policy that
+    /// allows the token's subject to invoke the method
+    ///
+    /// The claims of the token are included in the response context. An error
+    /// is returned if the token couldn't be verified, in which case the
+    /// authorizer should fail with "Unauthorized" for API Gateway to respond
+    /// with a 401 status (or respond with `PolicyResponse::deny()` for a 403
+    /// status). Tokens whose claims are denied by the authorizer's policy get
+    /// a deny policy, for a 403 status.
+    pub async fn policy_response(&self, request: &AuthorizerRequest) -> Result<PolicyResponse, Error> {
+        let claims = self.verify_token(request).await?;
+        let principal_id = claims.get("sub").and_then(Value::as_str).unwrap_or_default();
+        let resource = request.resource_arn().unwrap_or("*");
+        if self.policy.check(&claims).is_err() {
+            return Ok(PolicyResponse::deny(principal_id, resource));
+        }
+
+        let mut response = PolicyResponse::allow(principal_id, resource);
+        response.context = claims_context(&claims);
+        Ok(response)
+    }
+
+    /// Verifies the token of an authorizer event and returns a simple response
+    ///
+    /// The response is authorized (with the token's claims in its context)
+    /// only if the token could be verified.
+    pub async fn simple_response(&self, request: &AuthorizerRequest) -> SimpleResponse {
+        match self.verify_request(request).await {
+            Ok(claims) => SimpleResponse {
+                is_authorized: true,
+                context: claims_context(&claims),
+            },
+            Err(_) => SimpleResponse {
+                is_authorized: false,
+                context: HashMap::new(),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::Denial;
+    use crate::test_util::*;
+
+    #[test]
+    fn lambda_authorizer_responses() {
+
+        let keyset = test_keyset();
+        let verifier = keyset.new_issuer_verifier().build().unwrap();
+        let authorizer = LambdaAuthorizer::new(keyset, verifier);
+        let token = test_token(json!({ "alg": "HS256", "kid": "test-key" }), "secret");
+
+        let request: AuthorizerRequest = serde_json::from_value(json!({
+            "type": "TOKEN",
+            "authorizationToken": format!("Bearer {}", token),
+            "methodArn": "arn:aws:execute-api:eu-west-1:123456789012:abcdef/prod/GET/",
+        })).unwrap();
+        let response = async_std::task::block_on(authorizer.policy_response(&request)).unwrap();
+        let response = serde_json::to_value(response).unwrap();
+        assert_eq!(response["policyDocument"]["Statement"][0]["Effect"], "Allow");
+        assert_eq!(response["context"]["iss"], "https://cognito-idp.eu-west-1.amazonaws.com/eu-west-1_AbCd1234");
+
+        let request: AuthorizerRequest = serde_json::from_value(json!({
+            "version": "2.0",
+            "type": "REQUEST",
+            "routeArn": "arn:aws:execute-api:eu-west-1:123456789012:abcdef/$default/GET/",
+            "identitySource": [token],
+            "headers": { "authorization": "Bearer not-a-token" },
+        })).unwrap();
+        let response = async_std::task::block_on(authorizer.simple_response(&request));
+        assert!(!response.is_authorized);
+
+        let request: AuthorizerRequest = serde_json::from_value(json!({
+            "type": "TOKEN",
+            "authorizationToken": format!("Bearer {}", token),
+            "methodArn": "arn:aws:execute-api:eu-west-1:123456789012:abcdef/prod/GET/",
+        })).unwrap();
+        let authorizer = authorizer.policy(Policy::new().group("admins"));
+        let response = async_std::task::block_on(authorizer.policy_response(&request)).unwrap();
+        let response = serde_json::to_value(response).unwrap();
+        assert_eq!(response["policyDocument"]["Statement"][0]["Effect"], "Deny");
+        assert!(matches!(async_std::task::block_on(authorizer.verify_request(&request)),
+                         Err(Error::PolicyDenied(Denial::MissingGroup { .. }))));
+    }
+}
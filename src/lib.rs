@@ -1,42 +1,373 @@
 use std::sync::Arc;
-use std::sync::RwLock;
-use std::time::{Duration, Instant};
+use std::sync::{Mutex, RwLock};
+use std::time::Duration;
 use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::io::Read;
 
-use serde::{Deserialize};
+use arc_swap::ArcSwap;
+use web_time::{Instant, SystemTime};
+
+use serde::de::DeserializeOwned;
 use serde_json::value::Value;
-use serde_json;
 
-use reqwest::{self, Response};
+use reqwest::StatusCode;
 
 use jsonwebtokens as jwt;
 use jwt::{Algorithm, AlgorithmID, Verifier, VerifierBuilder};
 
 mod error;
-pub use error::{Error, ErrorDetails};
-
-#[derive(Debug, Deserialize, Clone)]
-struct RSAKey {
-    kid: String,
-    alg: String,
-    n: String,
-    e: String,
+pub use error::{Error, ErrorDetails, NetworkErrorKind};
+#[cfg(feature = "serde")]
+pub use error::DiagnosticError;
+
+mod claims;
+pub use claims::{
+    decode_unverified, AccessTokenClaims, Claims, ClaimsWithWarning, IdTokenClaims, RawClaims, Scopes, UnverifiedTokenData,
+    VerifiedToken, VerifiedTokenData,
+};
+
+mod verifier_ext;
+pub use verifier_ext::VerifierBuilderExt;
+
+mod jwk;
+use jwk::{JwkSet, SkippedKeyCallback};
+pub use jwk::SkippedKey;
+
+mod builder;
+pub use builder::KeySetBuilder;
+
+mod partition;
+pub use partition::Partition;
+
+mod registry;
+pub use registry::KeySetRegistry;
+
+mod alb;
+pub use alb::AlbKeySet;
+
+mod fetcher;
+pub use fetcher::{JwksFetcher, JwksRequest, JwksResponse, ReqwestFetcher};
+
+mod retry;
+pub use retry::RetryPolicy;
+
+mod audit;
+pub use audit::{AuditEvent, AuditOutcome, AuditSink};
+#[cfg(feature = "tracing")]
+pub use audit::TracingAuditSink;
+
+mod clock;
+pub use clock::{Clock, ManualClock, SystemClock};
+
+mod circuit_breaker;
+use circuit_breaker::CircuitState;
+pub use circuit_breaker::CircuitBreaker;
+
+mod token_cache;
+use token_cache::TokenCache;
+
+mod batch;
+
+mod global;
+pub use global::{verify_access_token, verify_id_token};
+
+mod discovery;
+
+mod persist;
+pub use persist::CacheSnapshot;
+
+mod cache_store;
+pub use cache_store::KeyCacheStore;
+
+mod token_hash;
+
+mod revocation;
+pub use revocation::{RevocationCheck, RevocationList};
+
+mod policy;
+pub use policy::{Denial, Policy};
+
+mod token_verifier;
+pub use token_verifier::{KeySetVerifier, MockVerifier, TokenVerifier};
+
+mod stats;
+use stats::Stats;
+pub use stats::KeySetStats;
+
+#[cfg(feature = "tracing")]
+mod trace;
+#[doc(no_inline)]
+pub use async_trait::async_trait;
+
+mod runtime;
+
+#[cfg(any(feature = "tokio", feature = "rt-async-std"))]
+mod refresh;
+#[cfg(any(feature = "tokio", feature = "rt-async-std"))]
+pub use refresh::RefreshTaskHandle;
+
+#[cfg(feature = "blocking")]
+mod blocking;
+
+#[cfg(any(feature = "tower", feature = "actix", feature = "rocket", feature = "lambda"))]
+mod http_util;
+
+#[cfg(feature = "tower")]
+pub mod tower;
+
+#[cfg(feature = "axum")]
+pub mod axum;
+
+#[cfg(feature = "actix")]
+pub mod actix;
+
+#[cfg(feature = "rocket")]
+pub mod rocket;
+
+#[cfg(feature = "poem")]
+pub mod poem;
+
+#[cfg(feature = "grpc")]
+pub mod grpc;
+
+#[cfg(feature = "lambda")]
+pub mod lambda;
+
+#[cfg(feature = "oauth")]
+pub mod oauth;
+
+#[cfg(feature = "auth")]
+pub mod auth;
+
+#[cfg(any(feature = "oauth", feature = "auth"))]
+mod secret_hash;
+
+#[cfg(feature = "testing")]
+pub mod testing;
+
+#[cfg(all(feature = "redis", not(target_arch = "wasm32")))]
+pub mod redis;
+
+mod validate;
+#[cfg(test)]
+mod test_util;
+#[doc(hidden)]
+pub use validate::check_keyset_macro_args as __check_keyset_macro_args;
+
+/// Controls whether a KeySet must have fetched the remote JWKS key set before
+/// it's handed out by [KeySet::new_with_startup_policy]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum StartupPolicy {
+    /// Defer fetching the JWKS key set until it's first needed to verify a token
+    /// (the behaviour of [KeySet::new])
+    #[default]
+    Lazy,
+
+    /// Fetch the JWKS key set during construction and return an error if it
+    /// can't be fetched, for services that shouldn't start without their keys
+    FailFast,
+}
+
+/// Per-call options for [KeySet::verify_with_options]
+///
+/// More options may be added, so these are constructed with `new()` (or
+/// `default()`) and the builder-style setters, like
+/// `VerifyOptions::new().force_jwks_refresh(true)`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[non_exhaustive]
+pub struct VerifyOptions {
+    /// Fetches the remote JWKS key set before verifying the token, regardless
+    /// of the minimum JWKS fetch interval or a [KeyCacheStore], e.g. right after
+    /// a known key rotation
+    pub force_jwks_refresh: bool,
+
+    /// Verifies the token's signature on tokio's blocking thread pool (via
+    /// `spawn_blocking`), so that CPU-bound RSA checks don't hold up other
+    /// tasks on the async executor's threads
+    ///
+    /// This adds the overhead of handing off to another thread, so is only
+    /// worthwhile under load. Without the `tokio` feature, or outside of a tokio
+    /// runtime, the signature is verified inline. Tokens found in the token
+    /// cache (see `KeySet::set_token_cache()`) aren't verified again either way.
+    pub offload_cpu: bool,
+}
+
+impl VerifyOptions {
+    /// Constructs the default options, which verify the token like
+    /// `KeySet::verify()`
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets whether the remote JWKS key set is fetched before verifying the
+    /// token (see `force_jwks_refresh`)
+    pub fn force_jwks_refresh(mut self, force_jwks_refresh: bool) -> Self {
+        self.force_jwks_refresh = force_jwks_refresh;
+        self
+    }
+
+    /// Sets whether the token's signature is verified on tokio's blocking thread
+    /// pool (see `offload_cpu`)
+    pub fn offload_cpu(mut self, offload_cpu: bool) -> Self {
+        self.offload_cpu = offload_cpu;
+        self
+    }
+}
+
+/// The key IDs (`kid`s) that changed when the key set was refreshed, as
+/// returned by [KeySet::refresh_jwks]
+///
+/// Each list of key IDs is sorted.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct JwksRefreshReport {
+    /// Keys that weren't cached before the refresh
+    pub added: Vec<String>,
+
+    /// Keys that were evicted because they're no longer in the key set (see
+    /// `KeySet::set_evict_removed_keys()`)
+    pub removed: Vec<String>,
+
+    /// Keys that are no longer in the key set but are still accepted for the
+    /// retired key grace period (see `KeySet::set_retired_key_grace()`)
+    pub retired: Vec<String>,
+
+    /// Keys that were cached before and after the refresh
+    pub unchanged: Vec<String>,
+
+    /// Keys in the key set that weren't cached because they're malformed or
+    /// not supported (see `KeySet::skipped_keys()`)
+    pub skipped: Vec<SkippedKey>,
+
+    /// When the cached key set was fetched, which is earlier than the refresh
+    /// if the key set was taken from a [KeyCacheStore]
+    pub fetched_at: SystemTime,
+}
+
+impl JwksRefreshReport {
+    /// Returns true if any keys were added, removed or retired
+    pub fn is_changed(&self) -> bool {
+        !self.added.is_empty() || !self.removed.is_empty() || !self.retired.is_empty()
+    }
+}
+
+#[derive(Clone)]
+struct KeysRotatedCallback(Arc<dyn Fn(&JwksRefreshReport) + Send + Sync>);
+
+impl std::fmt::Debug for KeysRotatedCallback {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "KeysRotatedCallback")
+    }
 }
 
-#[derive(Debug, Deserialize)]
-struct JwkSet {
-    keys: Vec<RSAKey>,
+/// A KeySet's readiness to verify tokens, as returned by [KeySet::health_check]
+#[derive(Debug)]
+pub struct HealthStatus {
+    /// The number of currently cached keys
+    pub cached_keys: usize,
+
+    /// When the key set was last successfully fetched (or revalidated), or
+    /// `None` if it hasn't been
+    pub last_refresh: Option<SystemTime>,
+
+    /// The result of requesting the remote JWKS key set, if it was probed
+    pub jwks_probe: Option<Result<(), Error>>,
+}
+
+impl HealthStatus {
+    /// Returns true if any keys are cached and probing the JWKS endpoint (if it
+    /// was probed) succeeded
+    pub fn is_ready(&self) -> bool {
+        self.cached_keys > 0 && !matches!(self.jwks_probe, Some(Err(_)))
+    }
 }
 
+const DEFAULT_MIN_JWKS_FETCH_INTERVAL: Duration = Duration::from_secs(60);
+const MAX_DERIVED_JWKS_FETCH_INTERVAL: Duration = Duration::from_secs(300);
+const DEFAULT_UNKNOWN_KID_TTL: Duration = Duration::from_secs(300);
+// So that a hung JWKS endpoint can't stall verification indefinitely
+#[cfg(not(target_arch = "wasm32"))]
+const DEFAULT_REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+// Bounds the memory used to remember unknown kids
+const MAX_UNKNOWN_KIDS: usize = 1024;
+// Generous for tokens with many groups or custom attributes, but stops
+// multi-megabyte bogus tokens from being decoded
+const DEFAULT_MAX_TOKEN_LEN: usize = 64 * 1024;
+const DEFAULT_MAX_HEADER_LEN: usize = 4 * 1024;
+// Older cache snapshots are ignored, so a leaked or stale snapshot can't pin
+// keys indefinitely
+const DEFAULT_MAX_CACHE_SNAPSHOT_AGE: Duration = Duration::from_secs(24 * 60 * 60);
+
 #[derive(Debug, Clone)]
+struct CachedKey {
+    algorithm: Arc<Algorithm>,
+
+    // The key as it was in the JWKS key set, for persisting the cache (not set
+    // for keys inserted with `insert_key()`)
+    jwk: Option<Arc<Value>>,
+
+    // When the key was first seen in the remote key set
+    added: Instant,
+
+    // When the key was found to be no longer in the remote key set, if it's
+    // being kept for the retired key grace period
+    retired: Option<Instant>,
+}
+
+#[derive(Debug, Clone, Default)]
 struct Cache {
     last_jwks_get_time: Option<Instant>,
-    algorithms: HashMap<String, Arc<Algorithm>>,
+
+    // Configuration that can be changed on a shared KeySet. If None the
+    // interval is derived from the JWKS Cache-Control max-age
+    min_jwks_fetch_interval: Option<Duration>,
+
+    // Caching details from the last JWKS response
+    etag: Option<String>,
+    max_age: Option<Duration>,
+
+    // Set if the JWKS endpoint reported that the key set doesn't exist, which
+    // isn't something that will be fixed by retrying
+    jwks_rejected_status: Option<StatusCode>,
+
+    // Key IDs that weren't found in the key set, and when they were last looked for
+    unknown_kids: HashMap<String, Instant>,
+
+    // Keys from the cached key set that were skipped
+    skipped_keys: Vec<SkippedKey>,
+
+    // Set while a background refresh is running, so only one is spawned at a
+    // time (see `start_refresh()`)
+    #[cfg(any(feature = "tokio", feature = "rt-async-std"))]
+    revalidating: bool,
+
+    // Only used with a circuit breaker
+    circuit: CircuitState,
+}
+
+// The cached keys, by kid
+//
+// This is kept outside of the Cache lock so that looking up keys never blocks.
+// It's only replaced (copy-on-write) while holding the Cache's write lock, so
+// updates aren't lost.
+type KeyMap = HashMap<String, CachedKey>;
+
+// Prebuilt verifiers, by the `token_use` they check and their sorted client IDs
+// (see `id_token_verifier_cached()`)
+type VerifierKey = (&'static str, Vec<String>);
+
+#[derive(Default)]
+struct VerifierCache(Mutex<HashMap<VerifierKey, Arc<Verifier>>>);
+
+impl std::fmt::Debug for VerifierCache {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("VerifierCache").field(&self.0.lock().unwrap().len()).finish()
+    }
 }
 
 /// Abstracts a remote Amazon Cognito JWKS key set
 ///
-/// The key set represents the public key information for one or more RSA keys that
+/// The key set represents the public key information for one or more RSA (or EC) keys that
 /// Amazon Cognito uses to sign tokens. To verify a token from Cognito the token's
 /// `kid` is used to look up the corresponding public key from this set which can
 /// be used to verify the token's signature.
@@ -53,7 +384,7 @@ struct Cache {
 /// # use async_std::prelude::*;
 /// # #[async_std::main]
 /// # async fn main() -> Result<(), Box<dyn std::error::Error>> {
-/// let keyset = KeySet::new("eu-west-1", "my-user-pool-id")?;
+/// let keyset = KeySet::new("eu-west-1", "eu-west-1_AbCd1234")?;
 /// let verifier = keyset.new_id_token_verifier(&["client-id-0", "client-id-1"])
 ///     .string_equals("custom_claim0", "value")
 ///     .string_equals("custom_claim1", "value")
@@ -65,7 +396,7 @@ struct Cache {
 /// ```
 ///
 /// Internally a KeySet holds a cache of Algorithm structs (see the jsonwebtokens
-/// API for further details) where each Algorithm represents one RSA public key.
+/// API for further details) where each Algorithm represents one RSA or EC public key.
 ///
 /// Although `keyset.verify()` can be very convenient, if you need to avoid network
 /// I/O when verifying tokens it's also possible to prefetch the remote JWKS key
@@ -78,7 +409,7 @@ struct Cache {
 /// # use async_std::prelude::*;
 /// # #[async_std::main]
 /// # async fn main() -> Result<(), Box<dyn std::error::Error>> {
-/// let keyset = KeySet::new("eu-west-1", "my-user-pool-id")?;
+/// let keyset = KeySet::new("eu-west-1", "eu-west-1_AbCd1234")?;
 /// keyset.prefetch_jwks().await?;
 /// let verifier = keyset.new_id_token_verifier(&["client-id-0", "client-id-1"])
 ///     .string_equals("custom_claim0", "value")
@@ -100,7 +431,7 @@ struct Cache {
 /// # #[async_std::main]
 /// # async fn main() -> Result<(), Box<dyn std::error::Error>> {
 /// # let token = "header.payload.signature";
-/// let keyset = KeySet::new("eu-west-1", "my-user-pool-id")?;
+/// let keyset = KeySet::new("eu-west-1", "eu-west-1_AbCd1234")?;
 /// keyset.prefetch_jwks().await?;
 ///
 /// let verifier = keyset.new_id_token_verifier(&["client-id-0", "client-id-1"])
@@ -120,225 +451,2937 @@ struct Cache {
 /// # Ok(())
 /// # }
 /// ```
-
-///
 #[derive(Debug, Clone)]
 pub struct KeySet {
-    _region: String,
-    _pool_id: String,
+    region: Option<String>,
+    pool_id: Option<String>,
     jwks_url: String,
     iss: String,
+    additional_issuers: Vec<String>,
     cache: Arc<RwLock<Cache>>,
-    min_jwks_fetch_interval: Duration,
+    keys: Arc<ArcSwap<KeyMap>>,
+    fetcher: Arc<dyn JwksFetcher>,
+    clock: Arc<dyn Clock>,
+    no_kid_fallback_max_keys: Option<usize>,
+    max_token_len: usize,
+    max_header_len: usize,
+    allowed_algorithms: Option<Vec<AlgorithmID>>,
+    refetch_on_invalid_signature: bool,
+    on_skipped_key: Option<SkippedKeyCallback>,
+    on_keys_rotated: Option<KeysRotatedCallback>,
+    verifier_leeway: Option<Duration>,
+    verifiers: Arc<VerifierCache>,
+    unknown_kid_ttl: Duration,
+    evict_removed_keys: bool,
+    retired_key_grace: Duration,
+    retry_policy: RetryPolicy,
+    circuit_breaker: Option<CircuitBreaker>,
+    stale_while_revalidate: bool,
+    token_cache: Option<Arc<Mutex<TokenCache>>>,
+    revocation_check: Option<Arc<dyn RevocationCheck>>,
+    audit_sink: Option<Arc<dyn AuditSink>>,
+    // Set for pinned keys, which are never fetched
+    network_disabled: bool,
+    key_cache_store: Option<Arc<dyn KeyCacheStore>>,
+    max_cache_snapshot_age: Duration,
+    stats: Arc<Stats>,
+    #[cfg(feature = "blocking")]
+    blocking_client: Arc<std::sync::OnceLock<reqwest::blocking::Client>>,
 }
 
 impl KeySet {
 
     /// Constructs a key set that corresponds to a remote Json Web Key Set published
     /// by Amazon for a given region and Cognito User Pool ID.
+    ///
+    /// Returns an `InvalidConfiguration` error if the region isn't a valid AWS
+    /// region name or the pool ID doesn't look like `<region>_<id>`.
     pub fn new(region: impl Into<String>,
                pool_id: impl Into<String>
     ) -> Result<Self, Error> {
-
-        let region_str = region.into();
-        let pool_id_str = pool_id.into();
-        let jwks_url = format!("https://cognito-idp.{}.amazonaws.com/{}/.well-known/jwks.json",
-                                       region_str, pool_id_str).into();
-        let iss = format!("https://cognito-idp.{}.amazonaws.com/{}", region_str, pool_id_str);
-
-        Ok(KeySet {
-            _region: region_str,
-            _pool_id: pool_id_str,
-            jwks_url: jwks_url,
-            iss: iss,
-            cache: Arc::new(RwLock::new(Cache {
-                last_jwks_get_time: None,
-                algorithms: HashMap::new()
-            })),
-            min_jwks_fetch_interval: Duration::from_secs(60),
-        })
+        KeySet::builder(region, pool_id).build()
     }
 
-    /// Returns a `VerifierBuilder` that has been pre-configured to validate an
-    /// AWS Cognito ID token. This can be further configured for verifying other
-    /// custom claims before calling `.build()` to create a `Verifier`
-    pub fn new_id_token_verifier(&self, client_ids: &[&str]) -> VerifierBuilder {
-        let mut builder = Verifier::create();
-
-        builder
-            .string_equals("iss", &self.iss)
-            .string_equals_one_of("aud", client_ids)
-            .string_equals("token_use", "id");
-
-        builder
+    /// Returns a [KeySetBuilder] for configuring a key set for the given region and
+    /// Cognito User Pool ID before constructing it
+    pub fn builder(region: impl Into<String>,
+                   pool_id: impl Into<String>
+    ) -> KeySetBuilder {
+        KeySetBuilder::new(region, pool_id)
     }
 
-    /// Set's the minimum time between attempts to fetch the remote JWKS key set
+    /// Constructs a key set for a generic OpenID Connect issuer, such as Auth0 or
+    /// Keycloak, that isn't an Amazon Cognito User Pool
     ///
-    /// By default this is one minute, to throttle requests in case there is a
-    /// transient network problem
-    pub fn set_min_jwks_fetch_interval(&mut self, interval: Duration) {
-        self.min_jwks_fetch_interval = interval;
+    /// Instead of deriving the JWKS URL and issuer from a region and pool ID,
+    /// they are given explicitly.
+    ///
+    /// ```no_run
+    /// # use jsonwebtokens_cognito::KeySet;
+    /// # fn main() -> Result<(), jsonwebtokens_cognito::Error> {
+    /// let keyset = KeySet::new_with_issuer("https://example.eu.auth0.com/",
+    ///                                      "https://example.eu.auth0.com/.well-known/jwks.json")?;
+    /// let verifier = keyset.new_issuer_verifier()
+    ///     .audience("my-api")
+    ///     .build()?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// _The `new_id_token_verifier()` and `new_access_token_verifier()` helpers
+    /// check Cognito specific claims (such as `token_use`) so `new_issuer_verifier()`
+    /// is more likely to be appropriate for other issuers_
+    pub fn new_with_issuer(issuer: impl Into<String>,
+                           jwks_url: impl Into<String>
+    ) -> Result<Self, Error> {
+        KeySet::builder_with_issuer(issuer, jwks_url).build()
     }
 
-    /// Get's the minimum time between attempts to fetch the remote JWKS key set
-    pub fn min_jwks_fetch_interval(&mut self) -> Duration {
-        self.min_jwks_fetch_interval
+    /// Returns a [KeySetBuilder] for configuring a key set for a generic OpenID
+    /// Connect issuer (see [KeySet::new_with_issuer])
+    pub fn builder_with_issuer(issuer: impl Into<String>,
+                               jwks_url: impl Into<String>
+    ) -> KeySetBuilder {
+        KeySetBuilder::new_with_issuer(Some(issuer.into()), Some(jwks_url.into()))
     }
 
-    /// Returns a `VerifierBuilder` that has been pre-configured to validate an
-    /// AWS Cognito access token. This can be further configured for verifying other
-    /// custom claims before calling `.build()` to create a `Verifier`
-    pub fn new_access_token_verifier(&self, client_ids: &[&str]) -> VerifierBuilder {
-        let mut builder = Verifier::create();
+    /// Constructs a key set by fetching an issuer's OpenID Connect discovery
+    /// document
+    ///
+    /// Instead of deriving or templating the JWKS URL, the `jwks_uri` and
+    /// `issuer` are read from `<issuer_url>/.well-known/openid-configuration`.
+    /// This works for Cognito user pools (whose issuer URL is
+    /// `https://cognito-idp.<region>.amazonaws.com/<pool_id>`), Cognito
+    /// emulators and other OpenID Connect issuers.
+    ///
+    /// ```no_run
+    /// # use jsonwebtokens_cognito::KeySet;
+    /// # async fn example() -> Result<(), jsonwebtokens_cognito::Error> {
+    /// let keyset = KeySet::from_oidc_discovery("http://localhost:9229/local_AbCd1234").await?;
+    /// let verifier = keyset.new_issuer_verifier().build()?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// Returns an `InvalidConfiguration` error if the discovery document isn't
+    /// found, can't be parsed or its `issuer` isn't identical to `issuer_url`.
+    /// See [KeySet::builder_with_oidc_discovery] to configure the key set (such
+    /// as its request timeout) before discovery.
+    pub async fn from_oidc_discovery(issuer_url: impl Into<String>) -> Result<Self, Error> {
+        KeySet::builder_with_oidc_discovery(issuer_url).build_with_oidc_discovery().await
+    }
 
-        builder
-            .string_equals("iss", &self.iss)
-            .string_equals_one_of("client_id", client_ids)
-            .string_equals("token_use", "access");
+    /// Returns a [KeySetBuilder] for configuring a key set whose JWKS URL and
+    /// issuer will be discovered from the given issuer URL by
+    /// `KeySetBuilder::build_with_oidc_discovery()` (see [KeySet::from_oidc_discovery])
+    pub fn builder_with_oidc_discovery(issuer_url: impl Into<String>) -> KeySetBuilder {
+        KeySetBuilder::new_with_issuer(Some(issuer_url.into()), None)
+    }
 
-        builder
+    /// Constructs a key set for the user pool identified by the given ARN
+    ///
+    /// The region and user pool ID are parsed from an ARN like
+    /// `arn:aws:cognito-idp:eu-west-1:123456789012:userpool/eu-west-1_AbCd1234`,
+    /// as output by tools like CloudFormation, CDK or Terraform.
+    ///
+    /// Returns an `InvalidConfiguration` error if the ARN isn't a Cognito user
+    /// pool ARN.
+    pub fn from_user_pool_arn(arn: &str) -> Result<Self, Error> {
+        match parse_user_pool_arn(arn) {
+            Some((region, pool_id)) => KeySet::new(region, pool_id),
+            None => Err(Error::InvalidConfiguration(ErrorDetails::new(
+                format!("Not a Cognito user pool ARN: \"{}\"", arn)))),
+        }
     }
 
-    /// Looks for a cached Algorithm based on the given JWT token's key ID ('kid')
+    /// Constructs a key set whose cache is populated from a JWKS JSON document
     ///
-    /// This is a lower-level API in case you need to use the jsonwebtokens
-    /// Algorithm API directly.
+    /// This is for deployments that bundle the pool's `jwks.json` with the
+    /// service, so that `try_verify()` can verify tokens without any network I/O.
     ///
-    /// Returns an `Arc<Algorithm>` corresponding to the give key ID (`kid`) or returns
-    /// a `CacheMiss` error if the Algorithm / key is not cached.
-    pub fn try_cache_lookup_algorithm(&self, kid: &str) -> Result<Arc<Algorithm>, Error> {
+    /// ```no_run
+    /// # use jsonwebtokens_cognito::KeySet;
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// let jwks = std::fs::read_to_string("jwks.json")?;
+    /// let keyset = KeySet::from_jwks_json("eu-west-1", "eu-west-1_AbCd1234", &jwks)?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// Returns an `InvalidConfiguration` error if the document can't be parsed.
+    pub fn from_jwks_json(region: impl Into<String>,
+                          pool_id: impl Into<String>,
+                          json: &str
+    ) -> Result<Self, Error> {
+        let jwks: JwkSet = serde_json::from_str(json)
+            .map_err(|e| Error::InvalidConfiguration(ErrorDetails::map("Failed to parse JWKS key set", e)))?;
 
-        // We unwrap, because poisoning would imply something else had gone
-        // badly wrong (there should be nothing that can cause a panic while
-        // holding the cache's lock)
-        let readable_cache = self.cache.read().unwrap();
+        let keyset = KeySet::new(region, pool_id)?;
+        keyset.cache_jwks(jwks, None, None)?;
+        Ok(keyset)
+    }
 
-        let a = readable_cache.algorithms.get(kid);
-        if let Some(alg) = a {
-            return Ok(alg.clone());
-        } else {
-            return Err(Error::CacheMiss(readable_cache.last_jwks_get_time));
+    /// Constructs a key set whose cache is populated from a JWKS JSON document
+    /// read from the given reader (see [KeySet::from_jwks_json])
+    pub fn from_jwks_reader(region: impl Into<String>,
+                            pool_id: impl Into<String>,
+                            reader: impl Read
+    ) -> Result<Self, Error> {
+        let jwks: JwkSet = serde_json::from_reader(reader)
+            .map_err(|e| Error::InvalidConfiguration(ErrorDetails::map("Failed to read JWKS key set", e)))?;
+
+        let keyset = KeySet::new(region, pool_id)?;
+        keyset.cache_jwks(jwks, None, None)?;
+        Ok(keyset)
+    }
+
+    /// Constructs a key set with pinned RS256 public keys, for air-gapped
+    /// deployments that can't reach the JWKS URL
+    ///
+    /// The keys are given by their key ID (`kid`), in PEM format or as DER
+    /// encoded `SubjectPublicKeyInfo`, such as keys exported from the user
+    /// pool's key set in advance:
+    ///
+    /// ```no_run
+    /// # use jsonwebtokens_cognito::KeySet;
+    /// # fn main() -> Result<(), jsonwebtokens_cognito::Error> {
+    /// let keyset = KeySet::with_pinned_keys("eu-west-1", "eu-west-1_AbCd1234", [
+    ///     ("key-id-0", std::fs::read("keys/key-id-0.pem").unwrap()),
+    ///     ("key-id-1", std::fs::read("keys/key-id-1.pem").unwrap()),
+    /// ])?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// The key set is never fetched: tokens signed with any other key, and
+    /// calls like `prefetch_jwks()`, fail with a `NetworkDisabled` error.
+    ///
+    /// Returns an `InvalidConfiguration` error if a key can't be parsed.
+    pub fn with_pinned_keys<K: AsRef<str>, P: AsRef<[u8]>>(
+        region: impl Into<String>,
+        pool_id: impl Into<String>,
+        keys: impl IntoIterator<Item = (K, P)>
+    ) -> Result<Self, Error> {
+        let mut keyset = KeySet::new(region, pool_id)?;
+        keyset.network_disabled = true;
+        for (kid, key) in keys {
+            let kid = kid.as_ref();
+            let algorithm = Algorithm::new_rsa_pem_verifier(AlgorithmID::RS256, &pem_public_key(key.as_ref()))
+                .map_err(|e| Error::InvalidConfiguration(ErrorDetails::map(
+                    format!("Invalid pinned key for key ID (kid) \"{}\"", kid), e)))?;
+            keyset.insert_key(kid, algorithm);
         }
+        Ok(keyset)
     }
 
-    async fn wait_and_cache_lookup_algorithm(&self, kid: &str) -> Result<Arc<Algorithm>, Error> {
-        match self.try_cache_lookup_algorithm(kid) {
-            Err(Error::CacheMiss(last_update_time)) => {
-                let duration = match last_update_time {
-                    Some(last_jwks_get_time) => Instant::now().duration_since(last_jwks_get_time),
-                    None => self.min_jwks_fetch_interval
-                };
-
-                if duration < self.min_jwks_fetch_interval {
-                    return Err(Error::NetworkError(ErrorDetails::new("Key set is currently unreachable (throttled)")))
-                }
+    /// Constructs a key set like [KeySet::new] while applying the given
+    /// [StartupPolicy]
+    ///
+    /// With `StartupPolicy::FailFast` the remote JWKS key set is fetched before
+    /// returning, and any error fetching the keys is returned instead of a KeySet.
+    ///
+    /// ```no_run
+    /// # use jsonwebtokens_cognito::{KeySet, StartupPolicy};
+    /// # use async_std::prelude::*;
+    /// # #[async_std::main]
+    /// # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// let keyset = KeySet::new_with_startup_policy("eu-west-1", "eu-west-1_AbCd1234",
+    ///                                              StartupPolicy::FailFast).await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn new_with_startup_policy(region: impl Into<String>,
+                                         pool_id: impl Into<String>,
+                                         policy: StartupPolicy
+    ) -> Result<Self, Error> {
+        let keyset = KeySet::new(region, pool_id)?;
 
-                self.prefetch_jwks().await?;
-                self.try_cache_lookup_algorithm(kid)
-            },
-            Err(e) => {
-                // try_cache_lookup_algorithm shouldn't return any other kind of error...
-                unreachable!("Unexpected error looking up JWT Algorithm for key ID: {:?}", e);
-            }
-            Ok(alg) => Ok(alg)
+        if policy == StartupPolicy::FailFast {
+            keyset.ready().await?;
         }
-    }
 
-    /// Verify a token's signature and its claims
-    pub async fn verify(
-        &self,
-        token: &str,
-        verifier: &Verifier
-    ) -> Result<serde_json::value::Value, Error> {
+        Ok(keyset)
+    }
 
-        let header = jwt::raw::decode_header_only(token)?;
+    /// Waits until the remote JWKS key set has been fetched and cached
+    ///
+    /// If the key set has already been fetched this returns immediately, otherwise
+    /// it will fetch the key set, as with `prefetch_jwks()`. A KeySet made with
+    /// [KeySet::with_pinned_keys] is always ready, since its keys are never fetched.
+    pub async fn ready(&self) -> Result<(), Error> {
+        if self.network_disabled {
+            return Ok(());
+        }
 
-        let kid = match header.get("kid") {
-            Some(Value::String(kid)) => kid,
-            _ => return Err(Error::NoKeyID()),
+        let (fetched, rejected_status) = {
+            let readable_cache = self.cache.read().unwrap();
+            (readable_cache.last_jwks_get_time.is_some(), readable_cache.jwks_rejected_status)
         };
 
-        let algorithm = self.wait_and_cache_lookup_algorithm(kid).await?;
+        if let Some(status) = rejected_status {
+            return Err(rejected_status_error(&self.jwks_url, status));
+        }
+        if fetched {
+            return Ok(());
+        }
 
-        let claims = verifier.verify(token, &algorithm)?;
-        Ok(claims)
+        self.prefetch_jwks().await
     }
 
-    /// Verify a token's signature and its claims, given a specific unix epoch timestamp
-    pub async fn verify_for_time(
-        &self,
-        token: &str,
-        verifier: &Verifier,
-        time_now: u64
-    ) -> Result<jsonwebtokens::TokenData, Error> {
-
-        let header = jwt::raw::decode_header_only(token)?;
+    // A VerifierBuilder with any leeway configured for the KeySet
+    fn new_verifier_builder(&self) -> VerifierBuilder {
+        let mut builder = Verifier::create();
+        if let Some(leeway) = self.verifier_leeway {
+            builder.leeway(u32::try_from(leeway.as_secs()).unwrap_or(u32::MAX));
+        }
+        builder
+    }
 
-        let kid = match header.get("kid") {
-            Some(Value::String(kid)) => kid,
-            _ => return Err(Error::NoKeyID()),
-        };
+    /// Returns a `VerifierBuilder` that has been pre-configured to only check the
+    /// token's issuer (`iss`) claim. This can be further configured for verifying
+    /// other claims before calling `.build()` to create a `Verifier`
+    ///
+    /// The issuer must be `iss()` or one of the `additional_issuers()`.
+    pub fn new_issuer_verifier(&self) -> VerifierBuilder {
+        let mut builder = self.new_verifier_builder();
 
-        let algorithm = self.wait_and_cache_lookup_algorithm(kid).await?;
+        if self.additional_issuers.is_empty() {
+            builder.string_equals("iss", &self.iss);
+        } else {
+            let issuers: Vec<&str> = std::iter::once(&self.iss)
+                .chain(&self.additional_issuers)
+                .map(String::as_str)
+                .collect();
+            builder.string_equals_one_of("iss", &issuers);
+        }
 
-        let token_data = verifier.verify_for_time(token, &algorithm, time_now)?;
-        Ok(token_data)
+        builder
     }
 
-    /// Try and verify a token's signature and claims without performing any network I/O
-    ///
-    /// To be able to verify a token in a synchronous context (but without blocking) this
-    /// API lets you try and verify a token, and if the required Algorithm / key has not
-    /// been cached yet then it will return a `CacheMiss` error.
-    pub fn try_verify(
-        &self,
-        token: &str,
-        verifier: &Verifier
-    ) -> Result<serde_json::value::Value, Error> {
+    /// Returns a `VerifierBuilder` that has been pre-configured to validate an
+    /// AWS Cognito ID token. This can be further configured for verifying other
+    /// custom claims before calling `.build()` to create a `Verifier`
+    pub fn new_id_token_verifier(&self, client_ids: &[&str]) -> VerifierBuilder {
+        let mut builder = self.new_issuer_verifier();
 
-        let header = jwt::raw::decode_header_only(token)?;
+        builder
+            .string_equals_one_of("aud", client_ids)
+            .string_equals("token_use", "id");
 
-        let kid = match header.get("kid") {
-            Some(Value::String(kid)) => kid,
-            _ => return Err(Error::NoKeyID()),
-        };
+        builder
+    }
 
-        let alg = self.try_cache_lookup_algorithm(kid)?;
-        let claims = verifier.verify(token, &alg)?;
-        Ok(claims)
+    /// Set's the minimum time between attempts to fetch the remote JWKS key set
+    ///
+    /// By default this is one minute, to throttle requests in case there is a
+    /// transient network problem, unless the JWKS endpoint responds with a
+    /// `Cache-Control: max-age`, in which case that's used instead (up to a
+    /// maximum of five minutes, so that new keys are still picked up promptly).
+    /// A shorter max-age, such as `max-age=0`, doesn't lower the interval below
+    /// one minute, so that it can't disable throttling.
+    ///
+    /// This can be changed while the `KeySet` is shared (e.g. via an `Arc`), and
+    /// applies to all of its clones. It takes effect for the next fetch attempt,
+    /// so it can also be used to end the current throttling period early.
+    pub fn set_min_jwks_fetch_interval(&self, interval: Duration) {
+        self.cache.write().unwrap().min_jwks_fetch_interval = Some(interval);
     }
 
-    /// Ensure the remote Json Web Key Set is downloaded and cached
-    pub async fn prefetch_jwks(&self) -> Result<(), Error> {
-        let resp: Response = reqwest::get(&self.jwks_url).await?;
-        let jwks: JwkSet = resp.json().await?;
+    /// Get's the minimum time between attempts to fetch the remote JWKS key set
+    pub fn min_jwks_fetch_interval(&self) -> Duration {
+        let readable_cache = self.cache.read().unwrap();
+        self.effective_min_jwks_fetch_interval(&readable_cache)
+    }
 
-        // We unwrap, because poisoning would imply something else had gone
-        // badly wrong (there should be nothing that can cause a panic while
-        // holding the cache's lock)
-        let mut writeable_cache = self.cache.write().unwrap();
+    fn effective_min_jwks_fetch_interval(&self, cache: &Cache) -> Duration {
+        match (cache.min_jwks_fetch_interval, cache.max_age) {
+            (Some(interval), _) => interval,
+            (None, Some(max_age)) => max_age.clamp(DEFAULT_MIN_JWKS_FETCH_INTERVAL, MAX_DERIVED_JWKS_FETCH_INTERVAL),
+            (None, None) => DEFAULT_MIN_JWKS_FETCH_INTERVAL,
+        }
+    }
 
-        writeable_cache.last_jwks_get_time = Some(Instant::now());
+    /// Enables verifying tokens that have no `kid` by trying cached keys in turn
+    ///
+    /// Some (e.g. federated or legacy) issuers don't include a key ID (`kid`) in
+    /// their token headers, which normally results in a `NoKeyID` error. With a
+    /// limit set, `verify()` and `try_verify()` will instead try up to
+    /// `max_keys` of the cached keys, newest first, until one of them verifies
+    /// the token's signature.
+    ///
+    /// This is disabled (`None`) by default.
+    pub fn set_no_kid_fallback(&mut self, max_keys: Option<usize>) {
+        self.no_kid_fallback_max_keys = max_keys;
+    }
 
-        for key in jwks.keys.into_iter() {
-            // For now we assume AWS Cognito only ever uses RS256 keys
-            if key.alg != "RS256" {
-                continue;
-            }
-            let mut algorithm = Algorithm::new_rsa_n_e_b64_verifier(AlgorithmID::RS256, &key.n, &key.e)?;
-            // By associating a kid here we will essentially be double checking
-            // that we only verify a token with the key matching its associated kid
-            // (once by us and jsonwebtokens will also check too)
-            algorithm.set_kid(&key.kid);
-            writeable_cache.algorithms.insert(key.kid.clone(), Arc::new(algorithm));
-        }
+    /// Enables refetching the JWKS key set once and retrying verification if a
+    /// token's signature is invalid
+    ///
+    /// This can avoid spurious `InvalidSignature` errors if the key for a cached
+    /// `kid` has changed. The key set won't be refetched more often than the
+    /// minimum JWKS fetch interval allows.
+    ///
+    /// This is disabled by default.
+    pub fn set_refetch_on_invalid_signature(&mut self, refetch: bool) {
+        self.refetch_on_invalid_signature = refetch;
+    }
 
-        Ok(())
+    /// Enables verifying tokens with the cached keys instead of failing while the
+    /// key set can't be fetched, and refreshing the key set in the background
+    ///
+    /// When a token's `kid` isn't cached and fetching the key set is throttled
+    /// (see `set_min_jwks_fetch_interval()`), `verify()` would normally fail with
+    /// a `Throttled` error. With this enabled the token is instead checked
+    /// against the other cached keys, and `Throttled` is only returned if none
+    /// of them verify its signature.
+    ///
+    /// With the `tokio` feature, verifying a token with a cached key after the
+    /// minimum fetch interval has passed also spawns a task to refresh the key
+    /// set, rather than waiting for a cache miss. This requires `verify()` to be
+    /// called within the context of a tokio runtime, otherwise the key set
+    /// isn't refreshed in the background. With the `rt-async-std` feature the
+    /// task is otherwise spawned on async-std's executor.
+    ///
+    /// This is disabled by default.
+    pub fn set_stale_while_revalidate(&mut self, enable: bool) {
+        self.stale_while_revalidate = enable;
     }
-}
 
-#[cfg(test)]
-mod tests {
-    // TODO
+    /// Enables caching the claims of verified tokens, so that verifying the same
+    /// token again skips checking its signature
+    ///
+    /// Up to `capacity` tokens are remembered (least recently used tokens are
+    /// evicted first) until they expire, keyed by the SHA-256 digest of the
+    /// token. The claims of a cached token are still checked by the verifier
+    /// each time it's verified, and a token is no longer accepted from the cache
+    /// once its key has been removed from the key set. Tokens without an `exp`
+    /// claim aren't cached.
+    ///
+    /// This applies to `verify()` (and the APIs based on it, like `verify_into()`)
+    /// and `try_verify()`. It's disabled by default.
+    pub fn set_token_cache(&mut self, capacity: usize) {
+        self.token_cache = Some(Arc::new(Mutex::new(TokenCache::new(capacity))));
+    }
+
+    /// Set's a [RevocationCheck] that's consulted after a token's signature and
+    /// claims are verified, to reject revoked tokens
+    ///
+    /// Revoked tokens fail verification with a `TokenRevoked` error. This applies
+    /// to all verification APIs, including tokens from the token cache (see
+    /// `set_token_cache()`).
+    pub fn set_revocation_check(&mut self, check: impl RevocationCheck + 'static) {
+        self.revocation_check = Some(Arc::new(check));
+    }
+
+    /// Set's an [AuditSink] that records the outcome of every `verify()` and
+    /// `try_verify()` (and the APIs based on them, like `verify_into()`)
+    ///
+    /// A `try_verify()` that fails with a `CacheMiss` error isn't recorded,
+    /// since the token wasn't verified or rejected.
+    pub fn set_audit_sink(&mut self, sink: impl AuditSink + 'static) {
+        self.audit_sink = Some(Arc::new(sink));
+    }
+
+    /// Sets a [KeyCacheStore] for sharing fetched keys with other processes
+    ///
+    /// Before fetching the key set, the store is checked for keys that another
+    /// process fetched more recently, and keys that are fetched are put into
+    /// the store.
+    pub fn set_key_cache_store(&mut self, store: impl KeyCacheStore + 'static) {
+        self.key_cache_store = Some(Arc::new(store));
+    }
+
+    /// Sets the maximum age of a cache snapshot that will be restored (see
+    /// `restore_cache()`), including snapshots from a [KeyCacheStore]
+    ///
+    /// Older snapshots are ignored and the key set is fetched instead. The
+    /// default is 24 hours.
+    pub fn set_max_cache_snapshot_age(&mut self, max_age: Duration) {
+        self.max_cache_snapshot_age = max_age;
+    }
+
+    /// Set's the policy for retrying failed JWKS key set requests
+    ///
+    /// By default failed requests aren't retried.
+    pub fn set_retry_policy(&mut self, policy: RetryPolicy) {
+        self.retry_policy = policy;
+    }
+
+    /// Set's a circuit breaker that stops requesting the JWKS key set while the
+    /// JWKS endpoint is failing (see [CircuitBreaker]), or `None` to disable it
+    ///
+    /// While the circuit is open, fetching the key set (including with
+    /// `prefetch_jwks()`) fails with a `CircuitOpen` error. There's no circuit
+    /// breaker by default.
+    pub fn set_circuit_breaker(&mut self, breaker: Option<CircuitBreaker>) {
+        self.circuit_breaker = breaker;
+    }
+
+    /// Set's whether cached keys that are no longer in the remote key set are
+    /// evicted when the key set is refetched
+    ///
+    /// By default the cache is replaced by each key set that's fetched, so
+    /// tokens signed with keys that have been rotated out are no longer
+    /// accepted. Disabling this keeps all keys that have been seen cached.
+    pub fn set_evict_removed_keys(&mut self, evict: bool) {
+        self.evict_removed_keys = evict;
+    }
+
+    /// Set's how long keys that are no longer in the remote key set are still
+    /// accepted before they're evicted
+    ///
+    /// During a key rotation, tokens signed with the old key may still be in
+    /// use after the key has been dropped from the key set. With a grace
+    /// period, a removed key is kept as a retired key (see `retired_kids()`)
+    /// and continues to verify tokens until the grace period has passed. If the
+    /// key reappears in the key set it's no longer retired.
+    ///
+    /// The default is zero, which evicts removed keys as soon as the key set is
+    /// refetched. This has no effect if evicting removed keys is disabled (see
+    /// `set_evict_removed_keys()`).
+    pub fn set_retired_key_grace(&mut self, grace: Duration) {
+        self.retired_key_grace = grace;
+    }
+
+    // Returns true for a retired key whose grace period has passed, which is
+    // no longer used even though it hasn't been evicted yet
+    fn is_expired_key(&self, key: &CachedKey) -> bool {
+        match key.retired {
+            Some(retired) => self.clock.now().saturating_duration_since(retired) >= self.retired_key_grace,
+            None => false,
+        }
+    }
+
+    /// Set's how long a key ID (`kid`) that isn't found in the remote key set is
+    /// remembered as unknown
+    ///
+    /// While a kid is remembered, verifying tokens with that kid fails
+    /// immediately with an `UnknownKeyId` error instead of refetching the key
+    /// set. This stops tokens with random kids from causing repeated JWKS
+    /// requests. The default is five minutes and a zero duration disables it.
+    pub fn set_unknown_kid_ttl(&mut self, ttl: Duration) {
+        self.unknown_kid_ttl = ttl;
+    }
+
+    /// Set's the maximum length in bytes of a token that will be verified
+    ///
+    /// Longer tokens are rejected with a `TokenTooLarge` error before any
+    /// base64 or JSON decoding, so that bogus multi-megabyte tokens can't be
+    /// used to make the verifier allocate large amounts of memory. The default
+    /// is 64 KiB.
+    pub fn set_max_token_len(&mut self, max_len: usize) {
+        self.max_token_len = max_len;
+    }
+
+    /// Set's the maximum length in bytes of a token's encoded header
+    ///
+    /// Like `set_max_token_len()`, tokens with a longer header are rejected
+    /// with a `TokenTooLarge` error before being decoded. The default is 4 KiB.
+    pub fn set_max_header_len(&mut self, max_len: usize) {
+        self.max_header_len = max_len;
+    }
+
+    // Rejects tokens that are too large to be worth decoding
+    fn check_token_len(&self, token: &str) -> Result<(), Error> {
+        check_token_len(token, self.max_token_len, self.max_header_len)
+    }
+
+    /// Set's the only algorithms that tokens may be signed with
+    ///
+    /// As defense in depth, tokens whose header `alg` isn't in the list are
+    /// rejected with a `DisallowedAlgorithm` error before their signature is
+    /// checked, regardless of which keys the JWKS key set has. For example
+    /// Cognito only signs tokens with `RS256`:
+    ///
+    /// ```
+    /// # use jsonwebtokens::AlgorithmID;
+    /// # use jsonwebtokens_cognito::KeySet;
+    /// # fn main() -> Result<(), jsonwebtokens_cognito::Error> {
+    /// let mut keyset = KeySet::new("eu-west-1", "eu-west-1_AbCd1234")?;
+    /// keyset.set_allowed_algorithms(&[AlgorithmID::RS256]);
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// By default any algorithm that matches the token's key is allowed.
+    pub fn set_allowed_algorithms(&mut self, algorithms: &[AlgorithmID]) {
+        self.allowed_algorithms = Some(algorithms.to_vec());
+    }
+
+    // Rejects tokens whose header `alg` isn't allowed, if there's an allow-list
+    fn check_algorithm(&self, header: &Value) -> Result<(), Error> {
+        let allowed = match &self.allowed_algorithms {
+            Some(allowed) => allowed,
+            None => return Ok(()),
+        };
+        match header.get("alg") {
+            Some(Value::String(alg)) => match alg.parse::<AlgorithmID>() {
+                Ok(id) if allowed.contains(&id) => Ok(()),
+                _ => Err(Error::DisallowedAlgorithm(alg.clone())),
+            },
+            _ => Err(Error::AlgorithmMismatch()),
+        }
+    }
+
+    /// Set's the leeway allowed for clock skew when the prebuilt verifiers
+    /// check the `exp`, `nbf` and `iat` claims
+    ///
+    /// This applies to verifiers created after it's set, via
+    /// `new_issuer_verifier()`, `new_id_token_verifier()` or
+    /// `new_access_token_verifier()`, and is rounded down to whole seconds.
+    /// Tokens are checked strictly by default.
+    pub fn set_verifier_leeway(&mut self, leeway: Duration) {
+        self.verifier_leeway = Some(leeway);
+        // Without affecting the verifiers cached for any clones
+        self.verifiers = Default::default();
+    }
+
+    /// Set's a callback that's called for each key in a JWKS key set that's
+    /// skipped because it's malformed or not supported
+    ///
+    /// Skipped keys don't stop the rest of the key set from being cached.
+    pub fn set_on_skipped_key(&mut self, callback: impl Fn(&SkippedKey) + Send + Sync + 'static) {
+        self.on_skipped_key = Some(SkippedKeyCallback(Arc::new(callback)));
+    }
+
+    /// Set's a callback that's called whenever refreshing the key set adds or
+    /// removes cached keys
+    ///
+    /// This is called for every change to the cached key set, whether it's
+    /// refetched while verifying a token, by `refresh_jwks()`, by a background
+    /// refresh or taken from a [KeyCacheStore], e.g. for invalidating
+    /// downstream caches or raising a security event for an unexpected
+    /// rotation. It's called while refreshing the key set so it shouldn't block;
+    /// to handle the report asynchronously it can be sent to a channel.
+    ///
+    /// ```
+    /// # use jsonwebtokens_cognito::KeySet;
+    /// # fn main() -> Result<(), jsonwebtokens_cognito::Error> {
+    /// let mut keyset = KeySet::new("eu-west-1", "eu-west-1_AbCd1234")?;
+    /// keyset.set_on_keys_rotated(|report| {
+    ///     println!("JWKS keys added: {:?}, removed: {:?}", report.added, report.removed);
+    /// });
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn set_on_keys_rotated(&mut self, callback: impl Fn(&JwksRefreshReport) + Send + Sync + 'static) {
+        self.on_keys_rotated = Some(KeysRotatedCallback(Arc::new(callback)));
+    }
+
+    /// Set's a `reqwest_middleware` client to use for fetching the remote JWKS key set
+    ///
+    /// This makes it possible to apply an existing stack of middleware (such as
+    /// for retries, tracing or caching) to JWKS requests.
+    ///
+    /// _Requires the `reqwest-middleware` feature_
+    #[cfg(feature = "reqwest-middleware")]
+    pub fn set_middleware_client(&mut self, client: reqwest_middleware::ClientWithMiddleware) {
+        self.set_jwks_fetcher(client);
+    }
+
+    /// Set's the `reqwest::Client` used to fetch the remote JWKS key set
+    ///
+    /// By default each KeySet creates its own client (shared by any clones of
+    /// the KeySet) but this makes it possible to share an externally configured
+    /// client, with its connection pool.
+    pub fn set_http_client(&mut self, client: reqwest::Client) {
+        self.set_jwks_fetcher(ReqwestFetcher::with_client(client));
+    }
+
+    /// Set's the [JwksFetcher] used to fetch the remote JWKS key set
+    ///
+    /// By default a [ReqwestFetcher] is used.
+    pub fn set_jwks_fetcher(&mut self, fetcher: impl JwksFetcher + 'static) {
+        self.fetcher = Arc::new(fetcher);
+    }
+
+    /// Returns a `VerifierBuilder` that has been pre-configured to validate an
+    /// AWS Cognito access token. This can be further configured for verifying other
+    /// custom claims before calling `.build()` to create a `Verifier`
+    pub fn new_access_token_verifier(&self, client_ids: &[&str]) -> VerifierBuilder {
+        let mut builder = self.new_issuer_verifier();
+
+        builder
+            .string_equals_one_of("client_id", client_ids)
+            .string_equals("token_use", "access");
+
+        builder
+    }
+
+    /// Returns a shared `Verifier` for AWS Cognito ID tokens issued to the given
+    /// app clients, as built from `new_id_token_verifier()`
+    ///
+    /// Verifiers are memoized by their client IDs (in any order), so this can be
+    /// called for every request instead of building a new `Verifier` each
+    /// time. The verifiers are shared by clones of the KeySet.
+    ///
+    /// ```no_run
+    /// # use jsonwebtokens_cognito::KeySet;
+    /// # async fn example(keyset: &KeySet, token: &str) -> Result<(), jsonwebtokens_cognito::Error> {
+    /// let verifier = keyset.id_token_verifier_cached(&["client-id-0"])?;
+    /// let claims = keyset.verify(token, &verifier).await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// A verifier is cached for each distinct set of client IDs, so they
+    /// shouldn't come from untrusted input. The web framework integrations
+    /// (such as `tower::CognitoAuthLayer::new()`) also accept the returned
+    /// `Arc<Verifier>`.
+    pub fn id_token_verifier_cached(&self, client_ids: &[&str]) -> Result<Arc<Verifier>, Error> {
+        self.cached_verifier("id", client_ids, || self.new_id_token_verifier(client_ids))
+    }
+
+    /// Returns a shared `Verifier` for AWS Cognito access tokens issued to the
+    /// given app clients, as built from `new_access_token_verifier()` (see
+    /// `id_token_verifier_cached()`)
+    pub fn access_token_verifier_cached(&self, client_ids: &[&str]) -> Result<Arc<Verifier>, Error> {
+        self.cached_verifier("access", client_ids, || self.new_access_token_verifier(client_ids))
+    }
+
+    fn cached_verifier(
+        &self,
+        token_use: &'static str,
+        client_ids: &[&str],
+        new_builder: impl FnOnce() -> VerifierBuilder
+    ) -> Result<Arc<Verifier>, Error> {
+        let mut sorted_ids: Vec<String> = client_ids.iter().map(|id| id.to_string()).collect();
+        sorted_ids.sort();
+        sorted_ids.dedup();
+        let key = (token_use, sorted_ids);
+
+        if let Some(verifier) = self.verifiers.0.lock().unwrap().get(&key) {
+            return Ok(verifier.clone());
+        }
+        let verifier = Arc::new(new_builder().build()?);
+        Ok(self.verifiers.0.lock().unwrap().entry(key).or_insert(verifier).clone())
+    }
+
+    /// Returns a `VerifierBuilder` that has been pre-configured to validate an
+    /// AWS Cognito access token issued to any app client
+    ///
+    /// This is like `new_access_token_verifier()` but without checking the
+    /// `client_id` claim, such as for a resource server that accepts
+    /// `client_credentials` tokens from any of the pool's machine-to-machine
+    /// clients. Such a verifier should normally also require a scope:
+    ///
+    /// ```no_run
+    /// # use jsonwebtokens_cognito::{KeySet, VerifierBuilderExt};
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// let keyset = KeySet::new("eu-west-1", "eu-west-1_AbCd1234")?;
+    /// let verifier = keyset.new_access_token_verifier_any_client()
+    ///     .require_scope("resource-server/read")
+    ///     .build()?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn new_access_token_verifier_any_client(&self) -> VerifierBuilder {
+        let mut builder = self.new_issuer_verifier();
+
+        builder.string_equals("token_use", "access");
+
+        builder
+    }
+
+    /// Looks for a cached Algorithm based on the given JWT token's key ID ('kid')
+    ///
+    /// This is a lower-level API in case you need to use the jsonwebtokens
+    /// Algorithm API directly.
+    ///
+    /// Returns an `Arc<Algorithm>` corresponding to the give key ID (`kid`) or returns
+    /// a `CacheMiss` error if the Algorithm / key is not cached.
+    pub fn try_cache_lookup_algorithm(&self, kid: &str) -> Result<Arc<Algorithm>, Error> {
+        if let Some(key) = self.keys.load().get(kid).filter(|key| !self.is_expired_key(key)) {
+            #[cfg(feature = "tracing")]
+            tracing::trace!(kid, "Key cache hit");
+            self.stats.record_cache_hit();
+            return Ok(key.algorithm.clone());
+        }
+        self.stats.record_cache_miss();
+
+        #[cfg(feature = "tracing")]
+        tracing::debug!(kid, "Key cache miss");
+
+        Err(self.cache_miss_error(Some(kid)))
+    }
+
+    fn cache_miss_error(&self, kid: Option<&str>) -> Error {
+        // We unwrap, because poisoning would imply something else had gone
+        // badly wrong (there should be nothing that can cause a panic while
+        // holding the cache's lock)
+        let readable_cache = self.cache.read().unwrap();
+        Error::CacheMiss {
+            kid: kid.map(String::from),
+            last_updated: readable_cache.last_jwks_get_time,
+            cached_kids: self.cached_kids(),
+            retry_after: self.throttle_remaining_locked(&readable_cache),
+        }
+    }
+
+    /// Returns a snapshot of the key set's cache and JWKS fetch statistics
+    ///
+    /// ```no_run
+    /// # use jsonwebtokens_cognito::KeySet;
+    /// # fn main() -> Result<(), jsonwebtokens_cognito::Error> {
+    /// let keyset = KeySet::new("eu-west-1", "eu-west-1_AbCd1234")?;
+    /// // ...
+    /// let stats = keyset.stats();
+    /// println!("JWKS fetch failures: {}/{}", stats.jwks_fetch_failures, stats.jwks_fetches);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn stats(&self) -> KeySetStats {
+        self.stats.snapshot()
+    }
+
+    /// The AWS region of the Cognito User Pool, or `None` for a generic issuer
+    pub fn region(&self) -> Option<&str> {
+        self.region.as_deref()
+    }
+
+    /// The ID of the Cognito User Pool, or `None` for a generic issuer
+    pub fn pool_id(&self) -> Option<&str> {
+        self.pool_id.as_deref()
+    }
+
+    /// The issuer (`iss` claim) that verified tokens must have
+    pub fn iss(&self) -> &str {
+        &self.iss
+    }
+
+    /// Other issuers that the prebuilt verifiers accept (see
+    /// `KeySetBuilder::additional_issuers()`)
+    pub fn additional_issuers(&self) -> &[String] {
+        &self.additional_issuers
+    }
+
+    /// The URL that the JWKS key set is fetched from
+    pub fn jwks_url(&self) -> &str {
+        &self.jwks_url
+    }
+
+    /// When the key set was last successfully fetched (or revalidated), or
+    /// `None` if it hasn't been
+    pub fn last_refresh_time(&self) -> Option<SystemTime> {
+        self.stats.last_refresh()
+    }
+
+    /// The number of currently cached keys, including retired keys
+    pub fn cached_key_count(&self) -> usize {
+        self.keys.load().values().filter(|key| !self.is_expired_key(key)).count()
+    }
+
+    /// Returns whether the key set is due to be fetched again, i.e. fetching it
+    /// now wouldn't be throttled by the minimum JWKS fetch interval
+    ///
+    /// This is true if the key set hasn't been fetched yet, and becomes true
+    /// again once the interval has passed since the JWKS endpoint last
+    /// responded with a key set or an HTTP error status. Requests that fail
+    /// without a response (such as DNS, connection, TLS or timeout failures),
+    /// or whose key set can't be parsed, don't restart the interval, so this
+    /// stays true after them. E.g. for a health check to report a stale cache:
+    ///
+    /// ```no_run
+    /// # use jsonwebtokens_cognito::KeySet;
+    /// # fn main() -> Result<(), jsonwebtokens_cognito::Error> {
+    /// let keyset = KeySet::new("eu-west-1", "eu-west-1_AbCd1234")?;
+    /// // ...
+    /// if keyset.cached_key_count() == 0 || keyset.needs_refresh() {
+    ///     println!("JWKS keys last refreshed at {:?}", keyset.last_refresh_time());
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn needs_refresh(&self) -> bool {
+        self.throttle_remaining().is_none()
+    }
+
+    /// Returns the keys of the last fetched key set that weren't cached because
+    /// they're malformed or not supported (e.g. an unexpected `alg` or `kty`)
+    ///
+    /// See also `set_on_skipped_key()` for a callback when keys are skipped.
+    pub fn skipped_keys(&self) -> Vec<SkippedKey> {
+        self.cache.read().unwrap().skipped_keys.clone()
+    }
+
+    /// Returns the key IDs (`kid`s) of the currently cached keys, sorted
+    ///
+    /// This includes retired keys (see `retired_kids()`).
+    pub fn cached_kids(&self) -> Vec<String> {
+        let mut kids: Vec<String> = self.keys.load().iter()
+            .filter(|(_, key)| !self.is_expired_key(key))
+            .map(|(kid, _)| kid.clone())
+            .collect();
+        kids.sort();
+        kids
+    }
+
+    /// Returns the key IDs (`kid`s) of the cached keys that are no longer in the
+    /// remote key set but are still accepted for the retired key grace period
+    /// (see `set_retired_key_grace()`), sorted
+    pub fn retired_kids(&self) -> Vec<String> {
+        let mut kids: Vec<String> = self.keys.load().iter()
+            .filter(|(_, key)| key.retired.is_some() && !self.is_expired_key(key))
+            .map(|(kid, _)| kid.clone())
+            .collect();
+        kids.sort();
+        kids
+    }
+
+    /// Clears all cached keys, verified tokens and caching state
+    ///
+    /// The key set will be fetched again the next time it's needed, without being
+    /// throttled by the minimum JWKS fetch interval.
+    pub fn invalidate_cache(&self) {
+        let mut writeable_cache = self.cache.write().unwrap();
+        *writeable_cache = Cache {
+            min_jwks_fetch_interval: writeable_cache.min_jwks_fetch_interval,
+            ..Cache::default()
+        };
+        self.keys.store(Arc::new(KeyMap::new()));
+        if let Some(token_cache) = &self.token_cache {
+            token_cache.lock().unwrap().clear();
+        }
+    }
+
+    /// Inserts a key into the cache, as if it had been fetched from the remote
+    /// key set
+    ///
+    /// The algorithm's kid is set to the given `kid`. Unless evicting removed
+    /// keys is disabled (see `set_evict_removed_keys()`), the key will be evicted
+    /// the next time the key set is fetched if it isn't in the remote key set.
+    pub fn insert_key(&self, kid: &str, mut algorithm: Algorithm) {
+        algorithm.set_kid(kid);
+
+        let mut writeable_cache = self.cache.write().unwrap();
+        writeable_cache.unknown_kids.remove(kid);
+        let mut keys = KeyMap::clone(&self.keys.load());
+        keys.insert(kid.to_string(), CachedKey {
+            algorithm: Arc::new(algorithm),
+            jwk: None,
+            added: self.clock.now(),
+            retired: None,
+        });
+        self.keys.store(Arc::new(keys));
+    }
+
+    /// Inserts an RS256 public key in PEM format into the cache (see `insert_key()`)
+    ///
+    /// This can be used to pin known keys, such as in environments that can't
+    /// reach the JWKS URL.
+    pub fn insert_key_pem(&self, kid: &str, pem: &[u8]) -> Result<(), Error> {
+        let algorithm = Algorithm::new_rsa_pem_verifier(AlgorithmID::RS256, pem)?;
+        self.insert_key(kid, algorithm);
+        Ok(())
+    }
+
+    // Returns how long until fetching the key set is allowed again, if throttled
+    fn throttle_remaining(&self) -> Option<Duration> {
+        let readable_cache = self.cache.read().unwrap();
+        self.throttle_remaining_locked(&readable_cache)
+    }
+
+    fn throttle_remaining_locked(&self, cache: &Cache) -> Option<Duration> {
+        let duration = match cache.last_jwks_get_time {
+            Some(last_jwks_get_time) => self.clock.now().duration_since(last_jwks_get_time),
+            None => return None,
+        };
+
+        let interval = self.effective_min_jwks_fetch_interval(cache);
+        if duration < interval {
+            Some(interval - duration)
+        } else {
+            None
+        }
+    }
+
+    // Checks that the key set may be fetched on demand, after a cache miss
+    // for the given kid
+    fn check_on_demand_fetch(&self, kid: Option<&str>) -> Result<(), Error> {
+        {
+            let readable_cache = self.cache.read().unwrap();
+            if let Some(status) = readable_cache.jwks_rejected_status {
+                #[cfg(feature = "tracing")]
+                tracing::debug!(kid, status = status.as_u16(), "Not fetching rejected JWKS key set");
+                return Err(rejected_status_error(&self.jwks_url, status));
+            }
+
+            let last_looked_for = kid.and_then(|kid| readable_cache.unknown_kids.get(kid));
+            if let (Some(kid), Some(last_looked_for)) = (kid, last_looked_for) {
+                if self.clock.now().duration_since(*last_looked_for) < self.unknown_kid_ttl {
+                    #[cfg(feature = "tracing")]
+                    tracing::debug!(kid, "Not fetching JWKS key set for recently unknown kid");
+                    return Err(Error::UnknownKeyId(kid.to_string()));
+                }
+            }
+        }
+
+        if let Some(retry_after) = self.throttle_remaining() {
+            #[cfg(feature = "tracing")]
+            tracing::debug!(kid, retry_after_ms = retry_after.as_millis() as u64, "JWKS key set fetch throttled");
+            return Err(Error::Throttled { retry_after });
+        }
+
+        Ok(())
+    }
+
+    // Looks up a key after fetching the key set, remembering the kid if it's
+    // unknown so the key set won't be fetched for it again for a while
+    fn lookup_fetched_algorithm(&self, kid: &str) -> Result<Arc<Algorithm>, Error> {
+        if let Ok(algorithm) = self.try_cache_lookup_algorithm(kid) {
+            return Ok(algorithm);
+        }
+
+        if self.unknown_kid_ttl > Duration::from_secs(0) {
+            let mut writeable_cache = self.cache.write().unwrap();
+            let (ttl, now) = (self.unknown_kid_ttl, self.clock.now());
+            writeable_cache.unknown_kids.retain(|_, last_looked_for| now.duration_since(*last_looked_for) < ttl);
+            if writeable_cache.unknown_kids.len() >= MAX_UNKNOWN_KIDS {
+                writeable_cache.unknown_kids.clear();
+            }
+            writeable_cache.unknown_kids.insert(kid.to_string(), now);
+        }
+
+        Err(Error::UnknownKeyId(kid.to_string()))
+    }
+
+    async fn wait_and_cache_lookup_algorithm(&self, kid: &str) -> Result<Arc<Algorithm>, Error> {
+        match self.try_cache_lookup_algorithm(kid) {
+            Err(Error::CacheMiss { .. }) => {
+                self.check_on_demand_fetch(Some(kid))?;
+                self.fetch_jwks(Some(kid)).await?;
+                self.lookup_fetched_algorithm(kid)
+            },
+            Err(e) => {
+                // try_cache_lookup_algorithm shouldn't return any other kind of error...
+                unreachable!("Unexpected error looking up JWT Algorithm for key ID: {:?}", e);
+            }
+            Ok(alg) => {
+                if self.stale_while_revalidate {
+                    self.revalidate_in_background();
+                }
+                Ok(alg)
+            }
+        }
+    }
+
+    // Spawns a task to refresh the key set if it may be stale, without waiting
+    // for it (see `set_stale_while_revalidate()`)
+    fn revalidate_in_background(&self) {
+        #[cfg(any(feature = "tokio", feature = "rt-async-std"))]
+        if runtime::can_spawn() {
+            if let Some(refresh) = self.start_refresh() {
+                runtime::spawn(refresh);
+            }
+        }
+    }
+
+    // Returns a future to spawn that refreshes the key set, unless fetching it
+    // is throttled or a background refresh is already running
+    #[cfg(any(feature = "tokio", feature = "rt-async-std"))]
+    fn start_refresh(&self) -> Option<impl std::future::Future<Output = ()> + Send + 'static> {
+        if self.throttle_remaining().is_some() {
+            return None;
+        }
+
+        {
+            let mut writeable_cache = self.cache.write().unwrap();
+            if writeable_cache.revalidating {
+                return None;
+            }
+            writeable_cache.revalidating = true;
+        }
+
+        let keyset = self.clone();
+        Some(async move {
+            let _ = keyset.prefetch_jwks().await;
+            keyset.cache.write().unwrap().revalidating = false;
+        })
+    }
+
+    // When fetching a token's key is throttled, this checks if another cached
+    // key verifies the token instead (see `set_stale_while_revalidate()`)
+    fn verify_throttled_with_cached_keys(
+        &self,
+        token: &str,
+        verifier: &Verifier,
+        time_now: u64,
+        retry_after: Duration
+    ) -> Result<(String, serde_json::value::Value), Error> {
+        match self.verify_with_cached_keys_for_time(token, verifier, usize::MAX, time_now) {
+            Err(Error::InvalidSignature()) | Err(Error::CacheMiss { .. }) => Err(Error::Throttled { retry_after }),
+            result => result,
+        }
+    }
+
+    /// Verify a token's signature and its claims
+    pub async fn verify(
+        &self,
+        token: &str,
+        verifier: &Verifier
+    ) -> Result<serde_json::value::Value, Error> {
+        let data = self.verify_full(token, verifier).await?;
+        Ok(data.claims)
+    }
+
+    /// Verify a token's signature and its claims, with per-call options
+    ///
+    /// ```no_run
+    /// # use jsonwebtokens_cognito::{KeySet, VerifyOptions};
+    /// # async fn example(keyset: &KeySet, verifier: &jsonwebtokens::Verifier, token: &str)
+    /// #     -> Result<(), jsonwebtokens_cognito::Error> {
+    /// // The user pool's keys were just rotated
+    /// let options = VerifyOptions::new().force_jwks_refresh(true);
+    /// let claims = keyset.verify_with_options(token, verifier, options).await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// If a forced refresh fails, its error is returned without verifying the
+    /// token.
+    ///
+    /// With the `tokio` feature, `offload_cpu` moves signature verification off
+    /// the async executor's threads:
+    ///
+    /// ```no_run
+    /// # use jsonwebtokens_cognito::{KeySet, VerifyOptions};
+    /// # async fn example(keyset: &KeySet, verifier: &jsonwebtokens::Verifier, token: &str)
+    /// #     -> Result<(), jsonwebtokens_cognito::Error> {
+    /// let options = VerifyOptions::new().offload_cpu(true);
+    /// let claims = keyset.verify_with_options(token, verifier, options).await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn verify_with_options(
+        &self,
+        token: &str,
+        verifier: &Verifier,
+        options: VerifyOptions
+    ) -> Result<serde_json::value::Value, Error> {
+        if options.force_jwks_refresh {
+            self.fetch_remote_jwks().await?;
+        }
+        let result = self.verify_full_unaudited(token, verifier, options).await;
+        self.audit(token, &result);
+        Ok(result?.claims)
+    }
+
+    /// Verify a token's signature and its claims, accepting a token that expired
+    /// less than `grace` ago
+    ///
+    /// This is for long-lived connections, such as websockets, that would rather
+    /// ask the client to refresh its token than drop the connection as soon as
+    /// the token expires. A recently expired token is returned as
+    /// `ClaimsWithWarning::ExpiredWithin` instead of failing with a
+    /// `TokenExpiredAt` error. Every other check, including the signature, the
+    /// verifier's other claims and any revocation check, still applies.
+    ///
+    /// ```no_run
+    /// # use jsonwebtokens_cognito::{ClaimsWithWarning, KeySet};
+    /// # use std::time::Duration;
+    /// # async fn example(keyset: &KeySet, verifier: &jsonwebtokens::Verifier, token: &str)
+    /// #     -> Result<(), jsonwebtokens_cognito::Error> {
+    /// match keyset.verify_with_grace(token, verifier, Duration::from_secs(30)).await? {
+    ///     ClaimsWithWarning::Valid(claims) => { /* ... */ }
+    ///     ClaimsWithWarning::ExpiredWithin(expired_for, claims) => {
+    ///         // Accept the message, but ask the client for a fresh token
+    ///     }
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn verify_with_grace(
+        &self,
+        token: &str,
+        verifier: &Verifier,
+        grace: Duration
+    ) -> Result<ClaimsWithWarning, Error> {
+        let result = self.verify_with_grace_unaudited(token, verifier, grace).await;
+        // Only the final outcome is audited, not an expiry within the grace period
+        let (result, expired_for) = match result {
+            Ok((data, expired_for)) => (Ok(data), expired_for),
+            Err(e) => (Err(e), None),
+        };
+        self.audit(token, &result);
+        let claims = result?.claims;
+        Ok(match expired_for {
+            Some(expired_for) => ClaimsWithWarning::ExpiredWithin(expired_for, claims),
+            None => ClaimsWithWarning::Valid(claims),
+        })
+    }
+
+    // Returns how long ago the token expired, if it's within the grace period
+    async fn verify_with_grace_unaudited(
+        &self,
+        token: &str,
+        verifier: &Verifier,
+        grace: Duration
+    ) -> Result<(VerifiedTokenData, Option<Duration>), Error> {
+        let exp = match self.verify_full_unaudited(token, verifier, VerifyOptions::default()).await {
+            Ok(data) => return Ok((data, None)),
+            Err(Error::TokenExpiredAt(exp)) => exp,
+            Err(e) => return Err(e),
+        };
+
+        let expired_for = Duration::from_secs(unix_time_now()?.saturating_sub(exp));
+        if expired_for > grace {
+            return Err(Error::TokenExpiredAt(exp));
+        }
+        // Verifying as of just before the token expired only relaxes the `exp`
+        // check, since the `nbf` and `iat` checks are stricter at an earlier time
+        let jwt::TokenData { header, claims, .. } = self.verify_for_time(token, verifier, exp.saturating_sub(1)).await?;
+        let kid = header.get("kid").and_then(Value::as_str).unwrap_or_default().to_string();
+        Ok((VerifiedTokenData { header, claims, kid }, Some(expired_for)))
+    }
+
+    /// Verify a token's signature and its claims, returning the token's header
+    /// and the key ID (`kid`) of the key that verified it along with the claims
+    ///
+    /// The kid is useful for logging or auditing, and will also be known for tokens
+    /// without a `kid` that were verified via the no-kid fallback (see
+    /// `set_no_kid_fallback()`).
+    pub async fn verify_full(
+        &self,
+        token: &str,
+        verifier: &Verifier
+    ) -> Result<VerifiedTokenData, Error> {
+        let result = self.verify_full_unaudited(token, verifier, VerifyOptions::default()).await;
+        self.audit(token, &result);
+        result
+    }
+
+    async fn verify_full_unaudited(
+        &self,
+        token: &str,
+        verifier: &Verifier,
+        options: VerifyOptions
+    ) -> Result<VerifiedTokenData, Error> {
+        let (data, _payload) = self.verify_payload_unaudited(token, verifier, options).await?;
+        Ok(data)
+    }
+
+    // Verifies a token, also returning its decoded claims payload unless the
+    // token was found in the token cache
+    async fn verify_payload_unaudited(
+        &self,
+        token: &str,
+        verifier: &Verifier,
+        options: VerifyOptions
+    ) -> Result<(VerifiedTokenData, Option<Vec<u8>>), Error> {
+        self.check_token_len(token)?;
+        if let Some(data) = self.lookup_verified_token(token, verifier)? {
+            return Ok((data, None));
+        }
+
+        let (data, payload) = match self.verify_full_uncached(token, verifier, options).await {
+            Ok(verified) => verified,
+            Err(e) => {
+                #[cfg(feature = "tracing")]
+                trace::verify_failure(token, &e);
+                return Err(e);
+            }
+        };
+        self.check_revoked(&data.claims)?;
+        self.cache_verified_token(token, &data);
+        Ok((data, Some(payload)))
+    }
+
+    async fn verify_full_uncached(
+        &self,
+        token: &str,
+        verifier: &Verifier,
+        options: VerifyOptions
+    ) -> Result<(VerifiedTokenData, Vec<u8>), Error> {
+
+        let jwt::raw::TokenSlices { message, signature, header, claims: encoded_claims } = jwt::raw::split_token(token)?;
+        let header = jwt::raw::decode_json_token_slice(header)?;
+        self.check_algorithm(&header)?;
+
+        let kid = match header.get("kid") {
+            Some(Value::String(kid)) => kid.clone(),
+            _ => {
+                let max_keys = self.no_kid_fallback_max_keys.ok_or(Error::NoKeyID())?;
+                self.ready().await?;
+                let (kid, claims) = self.try_verify_with_cached_keys(token, verifier, max_keys)?;
+                return Ok((VerifiedTokenData { header, claims, kid }, decode_payload(encoded_claims)?));
+            }
+        };
+
+        let algorithm = match self.wait_and_cache_lookup_algorithm(&kid).await {
+            Err(Error::Throttled { retry_after }) if self.stale_while_revalidate => {
+                let (kid, claims) = self.verify_throttled_with_cached_keys(token, verifier, unix_time_now()?,
+                                                                           retry_after)?;
+                return Ok((VerifiedTokenData { header, claims, kid }, decode_payload(encoded_claims)?));
+            }
+            result => result?,
+        };
+
+        match verify_signature(&header, message, signature, &algorithm, options).await {
+            Err(jwt::error::Error::InvalidSignature()) if self.refetch_on_invalid_signature => {
+                let algorithm = self.refetch_algorithm(&kid).await?;
+                verify_signature(&header, message, signature, &algorithm, options).await?;
+            }
+            result => result?,
+        }
+
+        let payload = decode_payload(encoded_claims)?;
+        let claims = parse_claims(&payload)?;
+        verify_claims(verifier, &claims, unix_time_now()?)?;
+        Ok((VerifiedTokenData { header, claims, kid }, payload))
+    }
+
+    /// Verify a token's signature and its claims, given a specific unix epoch timestamp
+    pub async fn verify_for_time(
+        &self,
+        token: &str,
+        verifier: &Verifier,
+        time_now: u64
+    ) -> Result<jsonwebtokens::TokenData, Error> {
+        let token_data = self.verify_for_time_unchecked(token, verifier, time_now).await?;
+        self.check_revoked(&token_data.claims)?;
+        Ok(token_data)
+    }
+
+    // Verifies the token without checking if it's been revoked
+    async fn verify_for_time_unchecked(
+        &self,
+        token: &str,
+        verifier: &Verifier,
+        time_now: u64
+    ) -> Result<jsonwebtokens::TokenData, Error> {
+
+        self.check_token_len(token)?;
+        let jwt::raw::TokenSlices { message, signature, header, claims } = jwt::raw::split_token(token)?;
+        let header = jwt::raw::decode_json_token_slice(header)?;
+        self.check_algorithm(&header)?;
+
+        let kid = match header.get("kid") {
+            Some(Value::String(kid)) => kid,
+            _ => {
+                let max_keys = self.no_kid_fallback_max_keys.ok_or(Error::NoKeyID())?;
+                self.ready().await?;
+                self.verify_with_cached_keys_for_time(token, verifier, max_keys, time_now)?;
+                // Signature and claims are verified so we just need to unpack the
+                // token data
+                return Ok(jwt::raw::decode_only(token)?);
+            }
+        };
+
+        let algorithm = match self.wait_and_cache_lookup_algorithm(kid).await {
+            Err(Error::Throttled { retry_after }) if self.stale_while_revalidate => {
+                self.verify_throttled_with_cached_keys(token, verifier, time_now, retry_after)?;
+                return Ok(jwt::raw::decode_only(token)?);
+            }
+            result => result?,
+        };
+
+        match jwt::raw::verify_signature_only(&header, message, signature, &algorithm) {
+            Err(jwt::error::Error::InvalidSignature()) if self.refetch_on_invalid_signature => {
+                let algorithm = self.refetch_algorithm(kid).await?;
+                jwt::raw::verify_signature_only(&header, message, signature, &algorithm)?;
+            }
+            result => result?,
+        }
+
+        let claims = jwt::raw::decode_json_token_slice(claims)?;
+        verify_claims(verifier, &claims, time_now)?;
+        Ok(jsonwebtokens::TokenData { header, claims, _extensible: () })
+    }
+
+    // Returns the data for a previously verified token from the token cache, if
+    // it's still valid (see `set_token_cache()`)
+    fn lookup_verified_token(&self, token: &str, verifier: &Verifier) -> Result<Option<VerifiedTokenData>, Error> {
+        let token_cache = match &self.token_cache {
+            Some(token_cache) => token_cache,
+            None => return Ok(None),
+        };
+
+        let time_now = unix_time_now()?;
+        let data = match token_cache.lock().unwrap().get(token, time_now) {
+            Some(data) => data,
+            None => return Ok(None),
+        };
+        // (Not Option::is_none_or(), which needs Rust 1.82)
+        let key_cached = matches!(self.keys.load().get(&data.kid), Some(key) if !self.is_expired_key(key));
+        if !key_cached {
+            return Ok(None);
+        }
+
+        verify_claims(verifier, &data.claims, time_now)?;
+        self.check_revoked(&data.claims)?;
+        Ok(Some(data))
+    }
+
+    fn check_revoked(&self, claims: &Value) -> Result<(), Error> {
+        match &self.revocation_check {
+            Some(check) if check.is_revoked(claims) => Err(Error::TokenRevoked()),
+            _ => Ok(()),
+        }
+    }
+
+    fn audit(&self, token: &str, result: &Result<VerifiedTokenData, Error>) {
+        if let Some(sink) = &self.audit_sink {
+            sink.record(&AuditEvent::new(token, result));
+        }
+    }
+
+    fn cache_verified_token(&self, token: &str, data: &VerifiedTokenData) {
+        if let (Some(token_cache), Some(exp)) = (&self.token_cache, data.claims.get("exp").and_then(Value::as_u64)) {
+            token_cache.lock().unwrap().insert(token, data, exp);
+        }
+    }
+
+    // After a signature failure this refetches the key set (unless throttled) in
+    // case the key for the given kid has changed
+    async fn refetch_algorithm(&self, kid: &str) -> Result<Arc<Algorithm>, Error> {
+        if self.throttle_remaining().is_some() {
+            // The key was fetched recently enough that it's unlikely to be stale
+            return Err(Error::InvalidSignature());
+        }
+
+        self.prefetch_jwks().await?;
+        self.lookup_fetched_algorithm(kid)
+    }
+
+    /// Try and verify a token's signature and claims without performing any network I/O
+    ///
+    /// To be able to verify a token in a synchronous context (but without blocking) this
+    /// API lets you try and verify a token, and if the required Algorithm / key has not
+    /// been cached yet then it will return a `CacheMiss` error.
+    pub fn try_verify(
+        &self,
+        token: &str,
+        verifier: &Verifier
+    ) -> Result<serde_json::value::Value, Error> {
+        let result = self.try_verify_unaudited(token, verifier);
+        if !matches!(result, Err(Error::CacheMiss { .. })) {
+            self.audit(token, &result);
+        }
+        Ok(result?.claims)
+    }
+
+    /// Like `try_verify()`, but a `CacheMiss` also spawns a task on the given
+    /// tokio runtime to fetch the key set, without waiting for it
+    ///
+    /// This is for synchronous code that can't wait for the key set, such as a
+    /// request handler that responds to a cache miss with a 503 status so that
+    /// the client retries once the keys have been fetched. Only one refresh is
+    /// spawned at a time, and none while fetching is throttled (in which case
+    /// the `CacheMiss` has a `retry_after`).
+    ///
+    /// ```no_run
+    /// # use jsonwebtokens_cognito::{Error, KeySet};
+    /// # fn example(keyset: &KeySet, verifier: &jsonwebtokens::Verifier, token: &str,
+    /// #            runtime: &tokio::runtime::Handle) -> u16 {
+    /// match keyset.try_verify_or_spawn_refresh(token, verifier, runtime) {
+    ///     Ok(claims) => 200,
+    ///     Err(Error::CacheMiss { .. }) => 503,
+    ///     Err(e) => e.http_status().as_u16(),
+    /// }
+    /// # }
+    /// ```
+    ///
+    /// _Requires the `tokio` feature_
+    #[cfg(feature = "tokio")]
+    pub fn try_verify_or_spawn_refresh(
+        &self,
+        token: &str,
+        verifier: &Verifier,
+        runtime: &tokio::runtime::Handle
+    ) -> Result<serde_json::value::Value, Error> {
+        let result = self.try_verify(token, verifier);
+        if let Err(Error::CacheMiss { .. }) = result {
+            if let Some(refresh) = self.start_refresh() {
+                runtime.spawn(refresh);
+            }
+        }
+        result
+    }
+
+    fn try_verify_unaudited(
+        &self,
+        token: &str,
+        verifier: &Verifier
+    ) -> Result<VerifiedTokenData, Error> {
+        self.check_token_len(token)?;
+        if let Some(data) = self.lookup_verified_token(token, verifier)? {
+            return Ok(data);
+        }
+
+        let data = match self.try_verify_uncached(token, verifier) {
+            Ok(data) => data,
+            Err(e) => {
+                // Cache misses are traced when looking up the key
+                #[cfg(feature = "tracing")]
+                if !matches!(e, Error::CacheMiss { .. }) {
+                    trace::verify_failure(token, &e);
+                }
+                return Err(e);
+            }
+        };
+        self.check_revoked(&data.claims)?;
+        self.cache_verified_token(token, &data);
+        Ok(data)
+    }
+
+    fn try_verify_uncached(
+        &self,
+        token: &str,
+        verifier: &Verifier
+    ) -> Result<VerifiedTokenData, Error> {
+
+        let jwt::raw::TokenSlices { message, signature, header, claims } = jwt::raw::split_token(token)?;
+        let header = jwt::raw::decode_json_token_slice(header)?;
+        self.check_algorithm(&header)?;
+
+        let (kid, claims) = match header.get("kid") {
+            Some(Value::String(kid)) => {
+                let alg = self.try_cache_lookup_algorithm(kid)?;
+                jwt::raw::verify_signature_only(&header, message, signature, &alg)?;
+                let claims = jwt::raw::decode_json_token_slice(claims)?;
+                verify_claims(verifier, &claims, unix_time_now()?)?;
+                (kid.clone(), claims)
+            }
+            _ => {
+                let max_keys = self.no_kid_fallback_max_keys.ok_or(Error::NoKeyID())?;
+                self.try_verify_with_cached_keys(token, verifier, max_keys)?
+            }
+        };
+
+        Ok(VerifiedTokenData { header, claims, kid })
+    }
+
+    /// Verify a token's signature and its claims and deserialize the claims into `T`
+    ///
+    /// ```no_run
+    /// # use jsonwebtokens_cognito::KeySet;
+    /// # use async_std::prelude::*;
+    /// # use serde::Deserialize;
+    /// #[derive(Deserialize)]
+    /// struct MyClaims {
+    ///     sub: String,
+    ///     email: String,
+    /// }
+    ///
+    /// # #[async_std::main]
+    /// # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// # let token = "header.payload.signature";
+    /// let keyset = KeySet::new("eu-west-1", "eu-west-1_AbCd1234")?;
+    /// let verifier = keyset.new_id_token_verifier(&["client-id-0"]).build()?;
+    /// let claims: MyClaims = keyset.verify_into(token, &verifier).await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// Returns a `MalformedToken` error if the claims can't be deserialized.
+    pub async fn verify_into<T: DeserializeOwned>(
+        &self,
+        token: &str,
+        verifier: &Verifier
+    ) -> Result<T, Error> {
+        let claims = self.verify(token, verifier).await?;
+        deserialize_claims(claims)
+    }
+
+    /// Verify a token's signature and its claims, returning the claims as their
+    /// decoded JSON payload
+    ///
+    /// The token's header and claims are only decoded once, and
+    /// `RawClaims::deserialize()` lets typed claims borrow from the payload,
+    /// which avoids the copies made by `verify_into()`. The claims are still
+    /// parsed once for the verifier's checks.
+    ///
+    /// Otherwise this verifies a token in the same way as `verify()`, including
+    /// any token cache, revocation check and audit sink.
+    pub async fn verify_raw(
+        &self,
+        token: &str,
+        verifier: &Verifier
+    ) -> Result<RawClaims, Error> {
+        let (result, payload) = match self.verify_payload_unaudited(token, verifier, VerifyOptions::default()).await {
+            Ok((data, payload)) => (Ok(data), payload),
+            Err(e) => (Err(e), None),
+        };
+        self.audit(token, &result);
+
+        let VerifiedTokenData { header, kid, .. } = result?;
+        let payload = match payload {
+            Some(payload) => payload,
+            // The token was found in the token cache
+            None => decode_payload(jwt::raw::split_token(token)?.claims)?,
+        };
+        Ok(RawClaims { header, payload, kid })
+    }
+
+    /// Verify an ID or access token, depending on its `token_use` claim
+    ///
+    /// This is for services that accept both kinds of token. The token is verified
+    /// with either `new_id_token_verifier(client_ids)` or
+    /// `new_access_token_verifier(client_ids)` and its claims are returned as a
+    /// [VerifiedToken] for the matching type of token.
+    ///
+    /// ```no_run
+    /// # use jsonwebtokens_cognito::{KeySet, VerifiedToken};
+    /// # use async_std::prelude::*;
+    /// # #[async_std::main]
+    /// # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// # let token = "header.payload.signature";
+    /// let keyset = KeySet::new("eu-west-1", "eu-west-1_AbCd1234")?;
+    /// match keyset.verify_auto(token, &["client-id-0"]).await? {
+    ///     VerifiedToken::Id(claims) => println!("ID token for {}", claims.username),
+    ///     VerifiedToken::Access(claims) => println!("Access token for {}", claims.sub),
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// Returns a `MalformedToken` error if the `token_use` claim is missing or
+    /// isn't "id" or "access".
+    pub async fn verify_auto(
+        &self,
+        token: &str,
+        client_ids: &[&str]
+    ) -> Result<VerifiedToken, Error> {
+        self.check_token_len(token)?;
+        // The claims are only trusted after verification, with the verifier
+        // checking token_use again
+        let unverified = decode_unverified(token)?;
+        match unverified.claims.get("token_use").and_then(Value::as_str) {
+            Some("id") => {
+                let verifier = self.new_id_token_verifier(client_ids).build()?;
+                Ok(VerifiedToken::Id(self.verify_into(token, &verifier).await?))
+            }
+            Some("access") => {
+                let verifier = self.new_access_token_verifier(client_ids).build()?;
+                Ok(VerifiedToken::Access(self.verify_into(token, &verifier).await?))
+            }
+            _ => Err(Error::MalformedToken(ErrorDetails::new("Expected 'token_use' claim to be \"id\" or \"access\""))),
+        }
+    }
+
+    /// Try and verify a token's signature and claims without performing any network
+    /// I/O and deserialize the claims into `T` (see `try_verify()` and `verify_into()`)
+    pub fn try_verify_into<T: DeserializeOwned>(
+        &self,
+        token: &str,
+        verifier: &Verifier
+    ) -> Result<T, Error> {
+        let claims = self.try_verify(token, verifier)?;
+        deserialize_claims(claims)
+    }
+
+    /// Try and verify a token's signature and claims against each cached key in turn,
+    /// without performing any network I/O
+    ///
+    /// This ignores any `kid` in the token header and tries up to `max_keys`
+    /// cached keys, newest first, until one of them verifies the token's signature.
+    /// It's intended for tokens from issuers that don't include a `kid` (see
+    /// `set_no_kid_fallback()`).
+    ///
+    /// Returns the `kid` of the key that verified the token, along with its claims,
+    /// or an `InvalidSignature` error if none of the keys matched. A `CacheMiss`
+    /// error is returned if no keys have been cached yet.
+    pub fn try_verify_with_cached_keys(
+        &self,
+        token: &str,
+        verifier: &Verifier,
+        max_keys: usize
+    ) -> Result<(String, serde_json::value::Value), Error> {
+        self.verify_with_cached_keys_for_time(token, verifier, max_keys, unix_time_now()?)
+    }
+
+    fn verify_with_cached_keys_for_time(
+        &self,
+        token: &str,
+        verifier: &Verifier,
+        max_keys: usize,
+        time_now: u64
+    ) -> Result<(String, serde_json::value::Value), Error> {
+
+        self.check_token_len(token)?;
+        let mut keys: Vec<(String, CachedKey)> = {
+            let cached_keys = self.keys.load();
+            if cached_keys.is_empty() {
+                return Err(self.cache_miss_error(None));
+            }
+            cached_keys.iter()
+                .filter(|(_, key)| !self.is_expired_key(key))
+                .map(|(kid, key)| (kid.clone(), key.clone()))
+                .collect()
+        };
+        keys.sort_by(|(kid_a, a), (kid_b, b)| b.added.cmp(&a.added).then_with(|| kid_a.cmp(kid_b)));
+
+        let jwt::raw::TokenSlices { message, signature, header, claims } = jwt::raw::split_token(token)?;
+        let header = jwt::raw::decode_json_token_slice(header)?;
+        self.check_algorithm(&header)?;
+        let alg = match header.get("alg") {
+            Some(Value::String(alg)) => alg.parse::<AlgorithmID>()?,
+            _ => return Err(jwt::error::Error::AlgorithmMismatch().into()),
+        };
+
+        for (kid, key) in keys.into_iter().take(max_keys) {
+            if key.algorithm.id() != alg {
+                continue;
+            }
+
+            // Passing the key's own kid skips the Algorithm's check that the token's
+            // kid matches
+            match key.algorithm.verify(Some(&kid), message, signature) {
+                Ok(()) => {
+                    let claims = jwt::raw::decode_json_token_slice(claims)?;
+                    verify_claims(verifier, &claims, time_now)?;
+                    return Ok((kid, claims));
+                }
+                Err(jwt::error::Error::InvalidSignature()) => continue,
+                Err(e) => return Err(e.into()),
+            }
+        }
+
+        Err(Error::InvalidSignature())
+    }
+
+    /// Ensure the remote Json Web Key Set is downloaded and cached
+    ///
+    /// Any other unsuccessful HTTP status results in a `JwksHttpStatus` error.
+    ///
+    /// If the JWKS endpoint responds with a 403 or 404 status (e.g. due to an
+    /// incorrect user pool ID, or a deleted pool) this returns an
+    /// `InvalidConfiguration` error and verification will stop trying to fetch
+    /// the key set on demand. Calling `prefetch_jwks()` explicitly will always
+    /// try to fetch the key set again.
+    ///
+    /// With a [KeyCacheStore], keys that are newer than the cached keys are
+    /// taken from the store instead of fetching the key set.
+    ///
+    /// See `refresh_jwks()` for a report of which keys changed.
+    pub async fn prefetch_jwks(&self) -> Result<(), Error> {
+        self.refresh_jwks().await?;
+        Ok(())
+    }
+
+    /// Fetches the remote Json Web Key Set like `prefetch_jwks()`, reporting
+    /// which keys were added or removed
+    ///
+    /// ```no_run
+    /// # use jsonwebtokens_cognito::KeySet;
+    /// # async fn example(keyset: &KeySet) -> Result<(), jsonwebtokens_cognito::Error> {
+    /// let report = keyset.refresh_jwks().await?;
+    /// if report.is_changed() {
+    ///     println!("JWKS keys added: {:?}, removed: {:?}", report.added, report.removed);
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn refresh_jwks(&self) -> Result<JwksRefreshReport, Error> {
+        self.fetch_jwks(None).await
+    }
+
+    /// Reports whether keys are cached, for readiness probes, optionally also
+    /// checking that the JWKS endpoint is reachable
+    ///
+    /// With `probe_jwks` the key set is requested once (without retries),
+    /// regardless of the minimum JWKS fetch interval or circuit breaker, and the
+    /// response is checked to be a valid key set. The probe doesn't change the
+    /// cached keys, so keys should still be loaded at startup, e.g. with
+    /// `prefetch_jwks()`.
+    ///
+    /// ```no_run
+    /// # use jsonwebtokens_cognito::KeySet;
+    /// # async fn ready(keyset: &KeySet) -> u16 {
+    /// let health = keyset.health_check(false).await;
+    /// if health.is_ready() { 200 } else { 503 }
+    /// # }
+    /// ```
+    pub async fn health_check(&self, probe_jwks: bool) -> HealthStatus {
+        let jwks_probe = if probe_jwks {
+            Some(self.probe_jwks().await)
+        } else {
+            None
+        };
+        HealthStatus {
+            cached_keys: self.cached_key_count(),
+            last_refresh: self.last_refresh_time(),
+            jwks_probe,
+        }
+    }
+
+    async fn probe_jwks(&self) -> Result<(), Error> {
+        if self.network_disabled {
+            return Err(Error::NetworkDisabled());
+        }
+        let request = JwksRequest { url: &self.jwks_url, if_none_match: None };
+        let resp = self.fetcher.fetch_jwks(&request).await?;
+        if resp.status == StatusCode::FORBIDDEN || resp.status == StatusCode::NOT_FOUND {
+            return Err(rejected_status_error(&self.jwks_url, resp.status));
+        }
+        if !resp.status.is_success() {
+            return Err(Error::JwksHttpStatus(resp.status, body_snippet(&resp.body)));
+        }
+        serde_json::from_slice::<JwkSet>(&resp.body)
+            .map_err(|e| {
+                Error::NetworkError(ErrorDetails::map("Failed to parse JWKS key set", e).with_kind(NetworkErrorKind::Decode))
+            })?;
+        Ok(())
+    }
+
+    // Fetches the key set, unless the key cache store has newer keys (that
+    // include the given kid), returning which keys changed
+    async fn fetch_jwks(&self, kid: Option<&str>) -> Result<JwksRefreshReport, Error> {
+        if self.network_disabled {
+            return Err(Error::NetworkDisabled());
+        }
+        if let Some(store) = &self.key_cache_store {
+            if let Some(report) = self.restore_from_store(store.as_ref(), kid).await {
+                return Ok(report);
+            }
+        }
+        self.fetch_remote_jwks().await
+    }
+
+    #[cfg_attr(feature = "tracing", tracing::instrument(name = "prefetch_jwks", skip(self), fields(url = %self.jwks_url)))]
+    async fn fetch_remote_jwks(&self) -> Result<JwksRefreshReport, Error> {
+        if self.network_disabled {
+            return Err(Error::NetworkDisabled());
+        }
+        let breaker = match &self.circuit_breaker {
+            Some(breaker) => breaker,
+            None => return self.request_jwks().await,
+        };
+
+        let check = self.cache.write().unwrap().circuit.check(breaker, self.clock.now());
+        #[cfg(feature = "tracing")]
+        if let Err(e) = &check {
+            tracing::debug!(error = %e, "JWKS key set fetch blocked by circuit breaker");
+        }
+        check?;
+        let result = self.request_jwks().await;
+        let mut writeable_cache = self.cache.write().unwrap();
+        match &result {
+            Err(Error::NetworkError(_)) | Err(Error::NetworkTimeout(_)) | Err(Error::JwksHttpStatus(_, _)) => {
+                writeable_cache.circuit.record_failure(breaker, self.clock.now());
+            }
+            _ => writeable_cache.circuit.record_success(),
+        }
+        result
+    }
+
+    async fn request_jwks(&self) -> Result<JwksRefreshReport, Error> {
+        let etag = self.revalidation_etag();
+        let request = JwksRequest { url: &self.jwks_url, if_none_match: etag.as_deref() };
+
+        let mut retries = 0;
+        let resp = loop {
+            #[cfg(feature = "tracing")]
+            let started = Instant::now();
+            let result = self.fetcher.fetch_jwks(&request).await;
+            self.stats.record_fetch(&result);
+            #[cfg(feature = "tracing")]
+            trace::fetch_result(&self.jwks_url, &result, started, retries + 1);
+            if !self.retry_policy.should_retry(retries, &result) {
+                break result?;
+            }
+            runtime::sleep(self.retry_policy.backoff_for(retries)).await;
+            retries += 1;
+        };
+
+        let report = self.handle_jwks_response(resp, etag.is_some())?;
+        if let Some(store) = &self.key_cache_store {
+            self.put_to_store(store.as_ref()).await;
+        }
+        Ok(report)
+    }
+
+    // The ETag to revalidate the cached key set with, if any
+    fn revalidation_etag(&self) -> Option<String> {
+        // Only revalidate if we still have the keys the etag refers to
+        if self.keys.load().is_empty() {
+            None
+        } else {
+            self.cache.read().unwrap().etag.clone()
+        }
+    }
+
+    fn handle_jwks_response(&self, resp: JwksResponse, revalidating: bool) -> Result<JwksRefreshReport, Error> {
+        let status = resp.status;
+        if status == StatusCode::NOT_MODIFIED && revalidating {
+            let skipped = {
+                let mut writeable_cache = self.cache.write().unwrap();
+                writeable_cache.last_jwks_get_time = Some(self.clock.now());
+                writeable_cache.max_age = resp.max_age;
+                writeable_cache.skipped_keys.clone()
+            };
+            self.stats.record_refresh();
+            let retired = self.retired_kids();
+            return Ok(JwksRefreshReport {
+                added: Vec::new(),
+                removed: Vec::new(),
+                retired: Vec::new(),
+                unchanged: self.cached_kids().into_iter().filter(|kid| !retired.contains(kid)).collect(),
+                skipped,
+                fetched_at: SystemTime::now(),
+            });
+        }
+
+        if status == StatusCode::FORBIDDEN || status == StatusCode::NOT_FOUND {
+            let mut writeable_cache = self.cache.write().unwrap();
+            writeable_cache.last_jwks_get_time = Some(self.clock.now());
+            writeable_cache.jwks_rejected_status = Some(status);
+            return Err(rejected_status_error(&self.jwks_url, status));
+        }
+        if !status.is_success() {
+            self.cache.write().unwrap().last_jwks_get_time = Some(self.clock.now());
+            return Err(Error::JwksHttpStatus(status, body_snippet(&resp.body)));
+        }
+
+        let jwks: JwkSet = match serde_json::from_slice(&resp.body) {
+            Ok(jwks) => jwks,
+            Err(e) => {
+                self.stats.record_fetch_failure();
+                return Err(Error::NetworkError(ErrorDetails::map("Failed to parse JWKS key set", e)
+                    .with_kind(NetworkErrorKind::Decode)));
+            }
+        };
+
+        let report = self.cache_jwks(jwks, resp.etag, resp.max_age)?;
+        self.stats.record_refresh();
+        self.notify_keys_rotated(&report);
+        Ok(report)
+    }
+
+    // Caches the keys of a key set, returning which keys changed
+    fn cache_jwks(&self, jwks: JwkSet, etag: Option<String>, max_age: Option<Duration>) -> Result<JwksRefreshReport, Error> {
+        // We unwrap, because poisoning would imply something else had gone
+        // badly wrong (there should be nothing that can cause a panic while
+        // holding the cache's lock)
+        let mut writeable_cache = self.cache.write().unwrap();
+
+        let now = self.clock.now();
+        writeable_cache.last_jwks_get_time = Some(now);
+        writeable_cache.jwks_rejected_status = None;
+        writeable_cache.etag = etag;
+        writeable_cache.max_age = max_age;
+
+        let (algorithms, skipped) = jwks.into_algorithms();
+        let mut keys = KeyMap::clone(&self.keys.load());
+        keys.retain(|_, key| !self.is_expired_key(key));
+        let mut before: Vec<String> = keys.keys().cloned().collect();
+        before.sort();
+        let mut retired = Vec::new();
+        if self.evict_removed_keys {
+            let grace = self.retired_key_grace;
+            keys.retain(|kid, key| {
+                if algorithms.iter().any(|(new_kid, _, _)| new_kid == kid) {
+                    return true;
+                }
+                if key.retired.is_none() && grace > Duration::from_secs(0) {
+                    key.retired = Some(now);
+                    retired.push(kid.clone());
+                }
+                key.retired.is_some()
+            });
+            retired.sort();
+        }
+        for (kid, algorithm, jwk) in algorithms.into_iter() {
+            writeable_cache.unknown_kids.remove(&kid);
+            let added = match keys.get(&kid) {
+                Some(cached) => cached.added,
+                None => now,
+            };
+            keys.insert(kid, CachedKey {
+                algorithm: Arc::new(algorithm),
+                jwk: Some(Arc::new(jwk)),
+                added,
+                retired: None,
+            });
+        }
+        #[cfg(feature = "tracing")]
+        tracing::debug!(keys = keys.len(), skipped = skipped.len(), "Cached JWKS key set");
+        let mut after: Vec<String> = keys.keys().cloned().collect();
+        after.sort();
+        // Retired keys, including any retired by an earlier refresh, are still
+        // cached but aren't unchanged
+        let mut unchanged: Vec<String> = keys.iter()
+            .filter(|(kid, key)| key.retired.is_none() && before.contains(kid))
+            .map(|(kid, _)| kid.clone())
+            .collect();
+        unchanged.sort();
+        self.keys.store(Arc::new(keys));
+        writeable_cache.skipped_keys = skipped.clone();
+        drop(writeable_cache);
+
+        for key in skipped.iter() {
+            #[cfg(feature = "tracing")]
+            trace::skipped_key(key);
+            if let Some(SkippedKeyCallback(callback)) = &self.on_skipped_key {
+                callback(key);
+            }
+        }
+
+        Ok(JwksRefreshReport {
+            added: after.iter().filter(|kid| !before.contains(kid)).cloned().collect(),
+            removed: before.iter().filter(|kid| !after.contains(kid)).cloned().collect(),
+            unchanged,
+            retired,
+            skipped,
+            fetched_at: SystemTime::now(),
+        })
+    }
+
+    fn notify_keys_rotated(&self, report: &JwksRefreshReport) {
+        if let Some(KeysRotatedCallback(callback)) = &self.on_keys_rotated {
+            if report.is_changed() {
+                callback(report);
+            }
+        }
+    }
+}
+
+// Verifies a token's signature, on tokio's blocking thread pool if requested
+#[cfg_attr(not(feature = "tokio"), allow(unused_variables))]
+async fn verify_signature(
+    header: &Value,
+    message: &str,
+    signature: &str,
+    algorithm: &Arc<Algorithm>,
+    options: VerifyOptions
+) -> Result<(), jwt::error::Error> {
+    #[cfg(feature = "tokio")]
+    if options.offload_cpu && tokio::runtime::Handle::try_current().is_ok() {
+        let (owned_header, owned_message, owned_signature, owned_algorithm) =
+            (header.clone(), message.to_owned(), signature.to_owned(), algorithm.clone());
+        let task = tokio::task::spawn_blocking(move || {
+            jwt::raw::verify_signature_only(&owned_header, &owned_message, &owned_signature, &owned_algorithm)
+        });
+        match task.await {
+            Ok(result) => return result,
+            Err(e) if e.is_panic() => std::panic::resume_unwind(e.into_panic()),
+            // Cancelled because the runtime is shutting down
+            Err(_) => {}
+        }
+    }
+    jwt::raw::verify_signature_only(header, message, signature, algorithm)
+}
+
+// Returns a PEM public key, converting a DER encoded SubjectPublicKeyInfo
+fn pem_public_key(key: &[u8]) -> Vec<u8> {
+    if key.starts_with(b"-----BEGIN") {
+        return key.to_vec();
+    }
+
+    let b64 = base64::Engine::encode(&base64::engine::general_purpose::STANDARD, key);
+    let mut pem = String::from("-----BEGIN PUBLIC KEY-----\n");
+    for line in b64.as_bytes().chunks(64) {
+        pem.push_str(std::str::from_utf8(line).unwrap());
+        pem.push('\n');
+    }
+    pem.push_str("-----END PUBLIC KEY-----\n");
+    pem.into_bytes()
+}
+
+// A short, printable prefix of a response body for error messages
+fn body_snippet(body: &[u8]) -> String {
+    const MAX_SNIPPET_LEN: usize = 200;
+
+    let body = String::from_utf8_lossy(body);
+    match body.char_indices().nth(MAX_SNIPPET_LEN) {
+        Some((end, _)) => format!("{}...", &body[..end]),
+        None => body.into_owned(),
+    }
+}
+
+impl std::fmt::Display for KeySet {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} (JWKS {})", self.iss, self.jwks_url)
+    }
+}
+
+/// Key sets are equal if they have the same region, pool ID, issuers and JWKS
+/// URL, regardless of their other configuration or cached keys
+impl PartialEq for KeySet {
+    fn eq(&self, other: &Self) -> bool {
+        self.region == other.region
+            && self.pool_id == other.pool_id
+            && self.iss == other.iss
+            && self.additional_issuers == other.additional_issuers
+            && self.jwks_url == other.jwks_url
+    }
+}
+
+impl Eq for KeySet {}
+
+fn deserialize_claims<T: DeserializeOwned>(claims: Value) -> Result<T, Error> {
+    serde_json::from_value(claims)
+        .map_err(|e| Error::MalformedToken(ErrorDetails::map("Failed to deserialize claims", e)))
+}
+
+// Decodes a token's base64 encoded claims, leaving them as JSON
+fn decode_payload(encoded_claims: &str) -> Result<Vec<u8>, Error> {
+    base64::Engine::decode(&base64::engine::general_purpose::URL_SAFE_NO_PAD, encoded_claims)
+        .map_err(|e| Error::MalformedToken(ErrorDetails::map("Failed to decode claims", e)))
+}
+
+fn parse_claims(payload: &[u8]) -> Result<Value, Error> {
+    serde_json::from_slice(payload)
+        .map_err(|e| Error::MalformedToken(ErrorDetails::map("Failed to parse claims", e)))
+}
+
+// Verifies the claims with the verifier, reporting a future `nbf` time as
+// TokenNotYetValid and checking a missing `auth_time` as the `iat` time
+//
+// jsonwebtokens reports a future `nbf` as a MalformedToken error, so if the
+// claims fail with one while their `nbf` is in the future they're checked
+// again without it. Passing then means the `nbf` check (with the verifier's
+// own leeway) was what failed, otherwise the next failed check is reported.
+//
+// Tokens without an `auth_time` (such as client credentials tokens) were
+// authenticated when they were issued, so if a check of `auth_time` (see
+// `VerifierBuilderExt::max_age()`) fails because it's missing the claims are
+// checked again with the `iat` time as the `auth_time`.
+fn verify_claims(verifier: &jwt::Verifier, claims: &Value, time_now: u64) -> Result<(), Error> {
+    let error = match verifier.verify_claims_only(claims, time_now) {
+        Ok(()) => return Ok(()),
+        Err(e) => e,
+    };
+    let retry_claims = match claims {
+        Value::Object(claims) => claims,
+        _ => return Err(error.into()),
+    };
+
+    match (&error, claims.get("nbf").and_then(Value::as_u64)) {
+        (jwt::error::Error::MalformedToken(_), Some(nbf)) if nbf > time_now => {
+            let mut without_nbf = retry_claims.clone();
+            without_nbf.remove("nbf");
+            verify_claims(verifier, &Value::Object(without_nbf), time_now)?;
+            return Err(Error::TokenNotYetValid());
+        }
+        _ => {}
+    }
+
+    match (Error::from(error), retry_claims.get("iat")) {
+        (Error::ClaimMismatch { claim, expected: None, actual: None }, Some(iat))
+            if claim == "auth_time" && !retry_claims.contains_key("auth_time") =>
+        {
+            let mut with_auth_time = retry_claims.clone();
+            with_auth_time.insert("auth_time".to_string(), iat.clone());
+            verify_claims(verifier, &Value::Object(with_auth_time), time_now)
+        }
+        (error, _) => Err(error),
+    }
+}
+
+// Checks the token's length, and the length of its header (up to the first '.'),
+// before it's decoded
+fn check_token_len(token: &str, max_token_len: usize, max_header_len: usize) -> Result<(), Error> {
+    if token.len() > max_token_len {
+        return Err(Error::TokenTooLarge { size: token.len(), max_size: max_token_len });
+    }
+    let header_len = token.find('.').unwrap_or(token.len());
+    if header_len > max_header_len {
+        return Err(Error::TokenTooLarge { size: header_len, max_size: max_header_len });
+    }
+    Ok(())
+}
+
+fn unix_time_now() -> Result<u64, Error> {
+    match SystemTime::now().duration_since(SystemTime::UNIX_EPOCH) {
+        Ok(n) => Ok(n.as_secs()),
+        Err(_) => Err(Error::MalformedToken(ErrorDetails::new("SystemTime before UNIX EPOCH!"))),
+    }
+}
+
+// Returns the (region, pool_id) from a user pool ARN like
+// arn:aws:cognito-idp:<region>:<account>:userpool/<pool_id>
+fn parse_user_pool_arn(arn: &str) -> Option<(&str, &str)> {
+    let parts: Vec<&str> = arn.split(':').collect();
+    if parts.len() != 6 || parts[0] != "arn" || parts[2] != "cognito-idp" {
+        return None;
+    }
+
+    let region = parts[3];
+    let pool_id = parts[5].strip_prefix("userpool/")?;
+    if region.is_empty() || pool_id.is_empty() {
+        return None;
+    }
+
+    Some((region, pool_id))
+}
+
+fn rejected_status_error(jwks_url: &str, status: StatusCode) -> Error {
+    Error::InvalidConfiguration(ErrorDetails::new(
+        format!("JWKS key set request for {} failed with status {} (check the region and user pool ID)",
+                jwks_url, status)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::*;
+    use serde_json::json;
+
+    #[test]
+    fn user_pool_arn() {
+        assert_eq!(parse_user_pool_arn("arn:aws:cognito-idp:eu-west-1:123456789012:userpool/eu-west-1_AbCd1234"),
+                   Some(("eu-west-1", "eu-west-1_AbCd1234")));
+        assert_eq!(parse_user_pool_arn("arn:aws-cn:cognito-idp:cn-north-1:123456789012:userpool/cn-north-1_AbCd1234"),
+                   Some(("cn-north-1", "cn-north-1_AbCd1234")));
+        assert_eq!(parse_user_pool_arn("arn:aws:cognito-identity:eu-west-1:123456789012:identitypool/eu-west-1:1234"), None);
+        assert_eq!(parse_user_pool_arn("arn:aws:cognito-idp:eu-west-1:123456789012:userpool/"), None);
+        assert_eq!(parse_user_pool_arn("eu-west-1_AbCd1234"), None);
+    }
+
+    #[test]
+    fn no_kid_fallback() {
+        let mut keyset = KeySet::new("eu-west-1", "eu-west-1_AbCd1234").unwrap();
+        insert_test_key(&keyset, "key0", "secret0");
+        insert_test_key(&keyset, "key1", "secret1");
+        let verifier = Verifier::create().issuer(&keyset.iss).build().unwrap();
+
+        let token = test_token(json!({ "alg": "HS256", "typ": "JWT" }), "secret1");
+        assert!(matches!(keyset.try_verify(&token, &verifier), Err(Error::NoKeyID())));
+
+        keyset.set_no_kid_fallback(Some(2));
+        assert!(keyset.try_verify(&token, &verifier).is_ok());
+        let (kid, _claims) = keyset.try_verify_with_cached_keys(&token, &verifier, 2).unwrap();
+        assert_eq!(kid, "key1");
+
+        let token = test_token(json!({ "alg": "HS256", "typ": "JWT" }), "unknown");
+        assert!(matches!(keyset.try_verify(&token, &verifier), Err(Error::InvalidSignature())));
+    }
+
+    #[test]
+    fn force_jwks_refresh() {
+        let fetcher = TestFetcher::jwks();
+        let keyset = KeySet::builder("eu-west-1", "eu-west-1_AbCd1234")
+            .jwks_fetcher(fetcher.clone())
+            .build()
+            .unwrap();
+        let verifier = keyset.new_issuer_verifier().build().unwrap();
+
+        let token = rs256_token(&json!({ "iss": TEST_ISS }));
+
+        async_std::task::block_on(keyset.verify(&token, &verifier)).unwrap();
+        async_std::task::block_on(keyset.verify_with_options(&token, &verifier, VerifyOptions::default())).unwrap();
+        assert_eq!(fetcher.fetch_count(), 1);
+
+        // The fetch isn't throttled by the minimum fetch interval
+        let options = VerifyOptions::default().force_jwks_refresh(true);
+        async_std::task::block_on(keyset.verify_with_options(&token, &verifier, options)).unwrap();
+        assert_eq!(fetcher.fetch_count(), 2);
+    }
+
+    #[test]
+    fn offload_cpu() {
+        let keyset = KeySet::builder("eu-west-1", "eu-west-1_AbCd1234")
+            .jwks_fetcher(TestFetcher::jwks())
+            .build()
+            .unwrap();
+        let verifier = keyset.new_issuer_verifier().build().unwrap();
+        let claims = json!({ "iss": TEST_ISS });
+        let token = rs256_token(&claims);
+        let other = rs256_token(&json!({}));
+        let forged = format!("{}.{}", token.rsplit_once('.').unwrap().0, other.rsplit_once('.').unwrap().1);
+        let options = VerifyOptions::default().offload_cpu(true);
+
+        // Outside of a tokio runtime the signature is verified inline
+        let verify = |token| async_std::task::block_on(keyset.verify_with_options(token, &verifier, options));
+        assert_eq!(verify(&token).unwrap(), claims);
+        assert!(matches!(verify(&forged), Err(Error::InvalidSignature())));
+
+        #[cfg(feature = "tokio")]
+        {
+            let runtime = tokio::runtime::Builder::new_current_thread().build().unwrap();
+            let verify = |token| runtime.block_on(keyset.verify_with_options(token, &verifier, options));
+            assert_eq!(verify(&token).unwrap(), claims);
+            assert!(matches!(verify(&forged), Err(Error::InvalidSignature())));
+        }
+    }
+
+    #[cfg(feature = "tokio")]
+    #[test]
+    fn try_verify_or_spawn_refresh() {
+        let fetcher = TestFetcher::jwks();
+        let keyset = KeySet::builder("eu-west-1", "eu-west-1_AbCd1234")
+            .jwks_fetcher(fetcher.clone())
+            .build()
+            .unwrap();
+        let verifier = keyset.new_issuer_verifier().build().unwrap();
+        let token = rs256_token(&json!({ "iss": TEST_ISS }));
+
+        // Only one refresh is spawned for concurrent misses
+        let runtime = tokio::runtime::Builder::new_current_thread().build().unwrap();
+        for _ in 0..2 {
+            let result = keyset.try_verify_or_spawn_refresh(&token, &verifier, runtime.handle());
+            assert!(matches!(result, Err(Error::CacheMiss { .. })));
+        }
+        while keyset.cached_key_count() == 0 {
+            runtime.block_on(tokio::task::yield_now());
+        }
+        assert_eq!(fetcher.fetch_count(), 1);
+        assert!(keyset.try_verify_or_spawn_refresh(&token, &verifier, runtime.handle()).is_ok());
+    }
+
+    #[test]
+    fn not_yet_valid_and_algorithm_mismatch() {
+        let keyset = test_keyset();
+        let verifier = Verifier::create().build().unwrap();
+
+        let nbf = unix_time_now().unwrap() + 3600;
+        let token = hs256_token(&json!({ "nbf": nbf }));
+        let result = async_std::task::block_on(keyset.verify(&token, &verifier));
+        assert!(matches!(result, Err(Error::TokenNotYetValid())));
+
+        // The verifier's leeway, and ignore_nbf(), are respected
+        let verifier = Verifier::create().leeway(3600).build().unwrap();
+        assert!(async_std::task::block_on(keyset.verify(&token, &verifier)).is_ok());
+        let verifier = Verifier::create().ignore_nbf().build().unwrap();
+        assert!(async_std::task::block_on(keyset.verify(&token, &verifier)).is_ok());
+
+        // The other claims are still checked
+        let verifier = Verifier::create().string_equals("token_use", "access").build().unwrap();
+        let result = async_std::task::block_on(keyset.verify(&token, &verifier));
+        assert!(matches!(result, Err(Error::ClaimMismatch { ref claim, .. }) if claim == "token_use"));
+        let token = hs256_token(&json!({ "nbf": nbf, "token_use": "access" }));
+        let result = async_std::task::block_on(keyset.verify(&token, &verifier));
+        assert!(matches!(result, Err(Error::TokenNotYetValid())));
+        let verifier = Verifier::create().build().unwrap();
+
+        let token = test_token(json!({ "alg": "none", "kid": "test-key" }), "secret");
+        let result = async_std::task::block_on(keyset.verify(&token, &verifier));
+        assert!(matches!(result, Err(Error::AlgorithmMismatch())));
+    }
+
+    #[test]
+    fn expired_token_times() {
+        let error = Error::TokenExpiredAt(1600000000);
+        assert_eq!(error.expired_at(), Some(SystemTime::UNIX_EPOCH + Duration::from_secs(1600000000)));
+        let expired_for = SystemTime::now().duration_since(error.expired_at().unwrap()).unwrap();
+        assert!(error.expired_for().unwrap() >= expired_for);
+        assert!(error.expired_for().unwrap() < expired_for + Duration::from_secs(60));
+
+        let now = unix_time_now().unwrap();
+        let error = Error::TokenExpiredAt(now + 60);
+        assert_eq!(error.expired_for(), Some(Duration::ZERO));
+
+        assert_eq!(Error::InvalidSignature().expired_at(), None);
+        assert_eq!(Error::InvalidSignature().expired_for(), None);
+    }
+
+    #[test]
+    fn refresh_state() {
+        let keyset = KeySet::builder("eu-west-1", "eu-west-1_AbCd1234")
+            .jwks_fetcher(TestFetcher::jwks())
+            .build()
+            .unwrap();
+        assert_eq!(keyset.last_refresh_time(), None);
+        assert_eq!(keyset.cached_key_count(), 0);
+        assert!(keyset.needs_refresh());
+
+        let before = SystemTime::now() - Duration::from_secs(1);
+        async_std::task::block_on(keyset.prefetch_jwks()).unwrap();
+        assert!(keyset.last_refresh_time().unwrap() >= before);
+        assert_eq!(keyset.cached_key_count(), 2);
+        assert!(!keyset.needs_refresh());
+
+        keyset.set_min_jwks_fetch_interval(Duration::from_secs(0));
+        assert!(keyset.needs_refresh());
+    }
+
+    #[test]
+    fn keys_rotated_callback() {
+        let reports = Arc::new(Mutex::new(Vec::new()));
+        let reports_clone = reports.clone();
+        let keyset = KeySet::builder("eu-west-1", "eu-west-1_AbCd1234")
+            .jwks_fetcher(TestFetcher::jwks())
+            .min_jwks_fetch_interval(Duration::from_secs(0))
+            .on_keys_rotated(move |report| reports_clone.lock().unwrap().push(report.clone()))
+            .build()
+            .unwrap();
+        insert_test_key(&keyset, "old-key", "secret");
+
+        async_std::task::block_on(keyset.prefetch_jwks()).unwrap();
+        {
+            let reports = reports.lock().unwrap();
+            assert_eq!(reports.len(), 1);
+            assert_eq!(reports[0].added, vec!["test-key-0", "test-key-1"]);
+            assert_eq!(reports[0].removed, vec!["old-key"]);
+        }
+
+        // Refreshing without any changes doesn't call the callback
+        async_std::task::block_on(keyset.prefetch_jwks()).unwrap();
+        assert_eq!(reports.lock().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn health_check() {
+        let keyset = KeySet::builder("eu-west-1", "eu-west-1_AbCd1234")
+            .jwks_fetcher(TestFetcher::jwks())
+            .build()
+            .unwrap();
+        let health = async_std::task::block_on(keyset.health_check(false));
+        assert_eq!(health.cached_keys, 0);
+        assert!(health.jwks_probe.is_none());
+        assert!(!health.is_ready());
+
+        // Probing doesn't cache the keys, and isn't throttled
+        insert_test_key(&keyset, "test-key", "secret");
+        for _ in 0..2 {
+            let health = async_std::task::block_on(keyset.health_check(true));
+            assert!(matches!(health.jwks_probe, Some(Ok(()))));
+            assert_eq!(health.cached_keys, 1);
+            assert!(health.is_ready());
+        }
+        assert_eq!(keyset.stats().jwks_fetches, 0);
+
+        let keyset = KeySet::builder("eu-west-1", "eu-west-1_AbCd1234")
+            .jwks_fetcher(TestFetcher::status(StatusCode::BAD_GATEWAY, TEST_JWKS))
+            .build()
+            .unwrap();
+        insert_test_key(&keyset, "test-key", "secret");
+        let health = async_std::task::block_on(keyset.health_check(true));
+        assert!(matches!(health.jwks_probe, Some(Err(Error::JwksHttpStatus(StatusCode::BAD_GATEWAY, _)))));
+        assert!(!health.is_ready());
+    }
+
+    #[test]
+    fn verify_with_grace() {
+        let keyset = test_keyset();
+        let verifier = keyset.new_issuer_verifier().build().unwrap();
+        let now = unix_time_now().unwrap();
+        let token = |iss: &str, exp: u64| hs256_token(&json!({ "iss": iss, "exp": exp }));
+        let iss = "https://cognito-idp.eu-west-1.amazonaws.com/eu-west-1_AbCd1234";
+        let verify = |token: &str, grace| async_std::task::block_on(keyset.verify_with_grace(token, &verifier, grace));
+        let grace = Duration::from_secs(60);
+
+        let result = verify(&token(iss, now + 60), grace).unwrap();
+        assert!(matches!(result, ClaimsWithWarning::Valid(_)));
+        assert_eq!(result.expired_for(), None);
+
+        let result = verify(&token(iss, now - 10), grace).unwrap();
+        let expired_for = result.expired_for().unwrap();
+        assert!(expired_for >= Duration::from_secs(10) && expired_for < Duration::from_secs(15));
+        assert_eq!(result.claims()["exp"], now - 10);
+
+        assert!(matches!(verify(&token(iss, now - 120), grace), Err(Error::TokenExpiredAt(exp)) if exp == now - 120));
+        assert!(matches!(verify(&token("https://example.com", now - 10), grace), Err(Error::ClaimMismatch { .. })));
+    }
+
+    #[test]
+    fn verify_raw() {
+        #[derive(serde::Deserialize)]
+        struct BorrowedClaims<'a> {
+            iss: &'a str,
+            #[serde(borrow, rename = "cognito:groups")]
+            groups: Vec<&'a str>,
+        }
+
+        let mut keyset = test_keyset();
+        let verifier = keyset.new_issuer_verifier().build().unwrap();
+        let iss = "https://cognito-idp.eu-west-1.amazonaws.com/eu-west-1_AbCd1234";
+        let now = unix_time_now().unwrap();
+        let token = hs256_token(&json!({ "iss": iss, "exp": now + 60, "cognito:groups": ["admins"] }));
+
+        let raw = async_std::task::block_on(keyset.verify_raw(&token, &verifier)).unwrap();
+        assert_eq!(raw.kid(), "test-key");
+        assert_eq!(raw.header()["alg"], "HS256");
+        let claims: BorrowedClaims = raw.deserialize().unwrap();
+        assert_eq!(claims.iss, iss);
+        assert_eq!(claims.groups, ["admins"]);
+        assert_eq!(raw.to_value().unwrap()["exp"], now + 60);
+        assert!(matches!(raw.deserialize::<IdTokenClaims>(), Err(Error::MalformedToken(_))));
+
+        // Tokens found in the token cache still return their payload
+        keyset.set_token_cache(10);
+        async_std::task::block_on(keyset.verify_raw(&token, &verifier)).unwrap();
+        let cached = async_std::task::block_on(keyset.verify_raw(&token, &verifier)).unwrap();
+        assert_eq!(cached, raw);
+
+        let expired = hs256_token(&json!({ "iss": iss, "exp": now - 60 }));
+        assert!(matches!(async_std::task::block_on(keyset.verify_raw(&expired, &verifier)),
+                         Err(Error::TokenExpiredAt(_))));
+    }
+
+    #[test]
+    fn allowed_algorithms() {
+        let mut keyset = KeySet::builder("eu-west-1", "eu-west-1_AbCd1234")
+            .allowed_algorithms(&[AlgorithmID::RS256])
+            .no_kid_fallback(Some(2))
+            .build()
+            .unwrap();
+        insert_test_key(&keyset, "test-key", "secret");
+        let verifier = keyset.new_issuer_verifier().build().unwrap();
+
+        let token = test_token(json!({ "alg": "HS256", "kid": "test-key" }), "secret");
+        assert!(matches!(keyset.try_verify(&token, &verifier), Err(Error::DisallowedAlgorithm(alg)) if alg == "HS256"));
+        assert!(matches!(async_std::task::block_on(keyset.verify(&token, &verifier)),
+                         Err(Error::DisallowedAlgorithm(_))));
+        let no_kid_token = test_token(json!({ "alg": "HS256" }), "secret");
+        assert!(matches!(keyset.try_verify(&no_kid_token, &verifier), Err(Error::DisallowedAlgorithm(_))));
+        let unknown_alg_token = format!("{}.e30.", base64::Engine::encode(
+            &base64::engine::general_purpose::URL_SAFE_NO_PAD, r#"{"alg":"XX999","kid":"test-key"}"#));
+        assert!(matches!(keyset.try_verify(&unknown_alg_token, &verifier), Err(Error::DisallowedAlgorithm(_))));
+
+        keyset.set_allowed_algorithms(&[AlgorithmID::RS256, AlgorithmID::HS256]);
+        assert!(keyset.try_verify(&token, &verifier).is_ok());
+    }
+
+    #[test]
+    fn cached_verifiers() {
+        let mut keyset = KeySet::new("eu-west-1", "eu-west-1_AbCd1234").unwrap();
+        let verifier = keyset.id_token_verifier_cached(&["client-0", "client-1"]).unwrap();
+        assert!(Arc::ptr_eq(&verifier, &keyset.id_token_verifier_cached(&["client-1", "client-0"]).unwrap()));
+        assert!(Arc::ptr_eq(&verifier, &keyset.clone().id_token_verifier_cached(&["client-0", "client-1"]).unwrap()));
+        assert!(!Arc::ptr_eq(&verifier, &keyset.id_token_verifier_cached(&["client-0"]).unwrap()));
+        assert!(!Arc::ptr_eq(&verifier, &keyset.access_token_verifier_cached(&["client-0", "client-1"]).unwrap()));
+
+        insert_test_key(&keyset, "test-key", "secret");
+        let claims = json!({
+            "iss": TEST_ISS,
+            "aud": "client-1",
+            "token_use": "id",
+        });
+        let token = hs256_token(&claims);
+        assert!(keyset.try_verify(&token, &verifier).is_ok());
+        let access_verifier = keyset.access_token_verifier_cached(&["client-1"]).unwrap();
+        assert!(keyset.try_verify(&token, &access_verifier).is_err());
+
+        // Changing the leeway changes the verifiers that are built
+        keyset.set_verifier_leeway(Duration::from_secs(60));
+        assert!(!Arc::ptr_eq(&verifier, &keyset.id_token_verifier_cached(&["client-0", "client-1"]).unwrap()));
+    }
+
+    #[test]
+    fn max_token_len() {
+        let mut keyset = test_keyset();
+        let verifier = keyset.new_issuer_verifier().build().unwrap();
+
+        let bogus = format!("{}.e30.", "A".repeat(DEFAULT_MAX_TOKEN_LEN));
+        assert!(matches!(keyset.try_verify(&bogus, &verifier),
+                         Err(Error::TokenTooLarge { size, max_size: DEFAULT_MAX_TOKEN_LEN }) if size == bogus.len()));
+
+        let padding = "x".repeat(DEFAULT_MAX_HEADER_LEN);
+        let token = test_token(json!({ "alg": "HS256", "kid": "test-key", "padding": padding }), "secret");
+        assert!(matches!(keyset.try_verify(&token, &verifier),
+                         Err(Error::TokenTooLarge { max_size: DEFAULT_MAX_HEADER_LEN, .. })));
+        assert!(matches!(async_std::task::block_on(keyset.verify(&token, &verifier)),
+                         Err(Error::TokenTooLarge { .. })));
+        assert!(matches!(async_std::task::block_on(keyset.verify_auto(&bogus, &["client-id"])),
+                         Err(Error::TokenTooLarge { .. })));
+
+        keyset.set_max_header_len(2 * DEFAULT_MAX_HEADER_LEN);
+        assert!(keyset.try_verify(&token, &verifier).is_ok());
+        keyset.set_max_token_len(token.len() - 1);
+        assert!(matches!(keyset.try_verify(&token, &verifier), Err(Error::TokenTooLarge { .. })));
+    }
+
+    #[test]
+    fn shared_min_jwks_fetch_interval() {
+        let keyset = Arc::new(KeySet::new("eu-west-1", "eu-west-1_AbCd1234").unwrap());
+        let clone = (*keyset).clone();
+        assert_eq!(keyset.min_jwks_fetch_interval(), DEFAULT_MIN_JWKS_FETCH_INTERVAL);
+
+        insert_test_key(&keyset, "test-key", "secret");
+        assert!(!keyset.needs_refresh());
+
+        keyset.set_min_jwks_fetch_interval(Duration::from_secs(0));
+        assert_eq!(clone.min_jwks_fetch_interval(), Duration::from_secs(0));
+        assert!(clone.needs_refresh());
+
+        // The configured interval isn't cleared along with the cache
+        keyset.set_min_jwks_fetch_interval(Duration::from_secs(300));
+        keyset.invalidate_cache();
+        assert_eq!(keyset.min_jwks_fetch_interval(), Duration::from_secs(300));
+    }
+
+    #[test]
+    fn configuration_accessors() {
+        let keyset = KeySet::new("eu-west-1", "eu-west-1_AbCd1234").unwrap();
+        assert_eq!(keyset.region(), Some("eu-west-1"));
+        assert_eq!(keyset.pool_id(), Some("eu-west-1_AbCd1234"));
+        assert_eq!(keyset.iss(), "https://cognito-idp.eu-west-1.amazonaws.com/eu-west-1_AbCd1234");
+        assert_eq!(keyset.jwks_url(),
+                   "https://cognito-idp.eu-west-1.amazonaws.com/eu-west-1_AbCd1234/.well-known/jwks.json");
+        assert_eq!(keyset.to_string(),
+                   "https://cognito-idp.eu-west-1.amazonaws.com/eu-west-1_AbCd1234 \
+                    (JWKS https://cognito-idp.eu-west-1.amazonaws.com/eu-west-1_AbCd1234/.well-known/jwks.json)");
+
+        let other = KeySet::builder("eu-west-1", "eu-west-1_AbCd1234")
+            .min_jwks_fetch_interval(Duration::from_secs(0))
+            .build()
+            .unwrap();
+        assert_eq!(keyset, other);
+        assert_ne!(keyset, KeySet::new("eu-west-1", "eu-west-1_EfGh5678").unwrap());
+
+        let generic = KeySet::new_with_issuer("https://example.com/", "https://example.com/jwks.json").unwrap();
+        assert_eq!(generic.region(), None);
+        assert_eq!(generic.pool_id(), None);
+        assert_ne!(keyset, generic);
+    }
+
+    #[test]
+    fn refresh_report() {
+        #[derive(Debug)]
+        struct RotatingFetcher(Arc<std::sync::atomic::AtomicBool>);
+
+        #[async_trait]
+        impl JwksFetcher for RotatingFetcher {
+            async fn fetch_jwks(&self, request: &JwksRequest<'_>) -> Result<JwksResponse, Error> {
+                let rotated = self.0.load(std::sync::atomic::Ordering::SeqCst);
+                let etag = if rotated { "rotated" } else { "initial" };
+                if request.if_none_match == Some(etag) {
+                    return Ok(JwksResponse::new(StatusCode::NOT_MODIFIED, ""));
+                }
+                let mut jwks: Value = serde_json::from_str(TEST_JWKS).unwrap();
+                if rotated {
+                    jwks["keys"][0]["kid"] = json!("test-key-2");
+                }
+                Ok(JwksResponse { etag: Some(etag.to_string()), ..JwksResponse::new(StatusCode::OK, jwks.to_string()) })
+            }
+        }
+
+        let rotated = Arc::new(std::sync::atomic::AtomicBool::new(false));
+        let keyset = KeySet::builder("eu-west-1", "eu-west-1_AbCd1234")
+            .jwks_fetcher(RotatingFetcher(rotated.clone()))
+            .min_jwks_fetch_interval(Duration::from_secs(0))
+            .build()
+            .unwrap();
+
+        let before = SystemTime::now() - Duration::from_secs(1);
+        let report = async_std::task::block_on(keyset.refresh_jwks()).unwrap();
+        assert_eq!(report.added, ["test-key-0", "test-key-1"]);
+        assert!(report.removed.is_empty() && report.unchanged.is_empty());
+        assert!(report.is_changed());
+        assert!(report.fetched_at >= before);
+
+        // Not modified
+        let report = async_std::task::block_on(keyset.refresh_jwks()).unwrap();
+        assert!(!report.is_changed());
+        assert_eq!(report.unchanged, ["test-key-0", "test-key-1"]);
+
+        rotated.store(true, std::sync::atomic::Ordering::SeqCst);
+        let report = async_std::task::block_on(keyset.refresh_jwks()).unwrap();
+        assert_eq!(report.added, ["test-key-2"]);
+        assert_eq!(report.removed, ["test-key-0"]);
+        assert_eq!(report.unchanged, ["test-key-1"]);
+    }
+
+    #[test]
+    fn pinned_keys() {
+        let pem = include_str!("../testdata/rsa-public.pem");
+        let b64: String = pem.lines().filter(|line| !line.starts_with("-----")).collect();
+        let der = base64::Engine::decode(&base64::engine::general_purpose::STANDARD, b64).unwrap();
+        let mut keyset = KeySet::with_pinned_keys("eu-west-1", "eu-west-1_AbCd1234", [
+            ("pinned-pem", pem.as_bytes().to_vec()),
+            ("pinned-der", der),
+        ]).unwrap();
+        assert_eq!(keyset.cached_kids(), ["pinned-der", "pinned-pem"]);
+        let verifier = keyset.new_issuer_verifier().build().unwrap();
+
+        let signer = Algorithm::new_rsa_pem_signer(AlgorithmID::RS256,
+                                                   include_bytes!("../testdata/rsa-private.pem")).unwrap();
+        let claims = json!({ "iss": TEST_ISS });
+        for kid in ["pinned-pem", "pinned-der"] {
+            let token = jwt::encode(&json!({ "alg": "RS256", "kid": kid }), &claims, &signer).unwrap();
+            assert!(async_std::task::block_on(keyset.verify(&token, &verifier)).is_ok());
+        }
+
+        let token = jwt::encode(&json!({ "alg": "RS256", "kid": "other-key" }), &claims, &signer).unwrap();
+        assert!(matches!(async_std::task::block_on(keyset.verify(&token, &verifier)), Err(Error::NetworkDisabled())));
+        assert!(matches!(async_std::task::block_on(keyset.prefetch_jwks()), Err(Error::NetworkDisabled())));
+        async_std::task::block_on(keyset.ready()).unwrap();
+
+        // Kid-less tokens can be verified against the pinned keys
+        keyset.set_no_kid_fallback(Some(2));
+        let token = jwt::encode(&json!({ "alg": "RS256" }), &claims, &signer).unwrap();
+        let data = async_std::task::block_on(keyset.verify_full(&token, &verifier)).unwrap();
+        assert!(data.kid == "pinned-pem" || data.kid == "pinned-der");
+
+        let result = KeySet::with_pinned_keys("eu-west-1", "eu-west-1_AbCd1234", [("bad-key", b"not a key")]);
+        assert!(matches!(result, Err(Error::InvalidConfiguration(_))));
+    }
+
+    #[test]
+    fn offline_jwks() {
+        let keyset = KeySet::from_jwks_json("eu-west-1", "eu-west-1_AbCd1234",
+                                            TEST_JWKS).unwrap();
+        let verifier = keyset.new_id_token_verifier(&["client-id"]).build().unwrap();
+
+        let signer = Algorithm::new_rsa_pem_signer(AlgorithmID::RS256,
+                                                   include_bytes!("../testdata/rsa-private.pem")).unwrap();
+        let header = json!({ "alg": "RS256", "kid": "test-key-0" });
+        let claims = json!({
+            "iss": TEST_ISS,
+            "aud": "client-id",
+            "token_use": "id",
+            "exp": unix_time_now().unwrap() + 60,
+        });
+        let token = jwt::encode(&header, &claims, &signer).unwrap();
+        assert!(keyset.try_verify(&token, &verifier).is_ok());
+
+        let signer = Algorithm::new_ecdsa_pem_signer(AlgorithmID::ES256,
+                                                     include_bytes!("../testdata/ec-private.pem")).unwrap();
+        let header = json!({ "alg": "ES256", "kid": "test-key-1" });
+        let token = jwt::encode(&header, &claims, &signer).unwrap();
+        assert!(keyset.try_verify(&token, &verifier).is_ok());
+
+        assert!(matches!(KeySet::from_jwks_json("eu-west-1", "eu-west-1_AbCd1234", "{}"),
+                         Err(Error::InvalidConfiguration(_))));
+    }
+
+    #[test]
+    fn derived_min_jwks_fetch_interval() {
+        let keyset = KeySet::new("eu-west-1", "eu-west-1_AbCd1234").unwrap();
+        let jwks = || serde_json::from_str::<JwkSet>(TEST_JWKS).unwrap();
+
+        keyset.cache_jwks(jwks(), None, Some(Duration::from_secs(0))).unwrap();
+        assert_eq!(keyset.min_jwks_fetch_interval(), DEFAULT_MIN_JWKS_FETCH_INTERVAL);
+        assert!(keyset.throttle_remaining().is_some());
+        keyset.cache_jwks(jwks(), None, Some(Duration::from_secs(120))).unwrap();
+        assert_eq!(keyset.min_jwks_fetch_interval(), Duration::from_secs(120));
+        keyset.cache_jwks(jwks(), None, Some(Duration::from_secs(3600))).unwrap();
+        assert_eq!(keyset.min_jwks_fetch_interval(), MAX_DERIVED_JWKS_FETCH_INTERVAL);
+    }
+
+    #[test]
+    fn additional_issuers() {
+        let keyset = KeySet::builder("eu-west-1", "eu-west-1_AbCd1234")
+            .additional_issuers(&["https://auth.example.com"])
+            .build()
+            .unwrap();
+        assert_eq!(keyset.additional_issuers(), ["https://auth.example.com"]);
+        insert_test_key(&keyset, "test-key", "secret");
+        let token = |iss: &str| {
+            hs256_token(&json!({ "iss": iss, "token_use": "access" }))
+        };
+
+        let verifier = keyset.new_access_token_verifier_any_client().build().unwrap();
+        assert!(keyset.try_verify(&token("https://cognito-idp.eu-west-1.amazonaws.com/eu-west-1_AbCd1234"),
+                                  &verifier).is_ok());
+        assert!(keyset.try_verify(&token("https://auth.example.com"), &verifier).is_ok());
+        assert!(matches!(keyset.try_verify(&token("https://evil.example.com"), &verifier),
+                         Err(Error::ClaimMismatch { claim, .. }) if claim == "iss"));
+
+        // The registry routes tokens from any of the key set's issuers
+        let mut registry = KeySetRegistry::new();
+        registry.insert(keyset, verifier);
+        assert_eq!(registry.keysets().count(), 1);
+        assert!(registry.try_verify(&token("https://auth.example.com")).is_ok());
+        registry.remove("https://cognito-idp.eu-west-1.amazonaws.com/eu-west-1_AbCd1234");
+        assert!(registry.get("https://auth.example.com").is_none());
+    }
+
+    #[test]
+    fn access_token_any_client() {
+        let keyset = test_keyset();
+        let token = |token_use: &str| {
+            let claims = json!({
+                "iss": TEST_ISS,
+                "client_id": "m2m-client-id",
+                "token_use": token_use,
+            });
+            hs256_token(&claims)
+        };
+
+        let verifier = keyset.new_access_token_verifier(&["client-id-0"]).build().unwrap();
+        assert!(keyset.try_verify(&token("access"), &verifier).is_err());
+        let verifier = keyset.new_access_token_verifier_any_client().build().unwrap();
+        assert!(keyset.try_verify(&token("access"), &verifier).is_ok());
+        assert!(keyset.try_verify(&token("id"), &verifier).is_err());
+    }
+
+    #[test]
+    fn verifier_leeway() {
+        let mut keyset = test_keyset();
+        let claims = json!({
+            "iss": TEST_ISS,
+            "iat": unix_time_now().unwrap() + 10,
+        });
+        let token = hs256_token(&claims);
+
+        let verifier = keyset.new_issuer_verifier().build().unwrap();
+        assert!(keyset.try_verify(&token, &verifier).is_err());
+
+        keyset.set_verifier_leeway(Duration::from_secs(30));
+        let verifier = keyset.new_issuer_verifier().build().unwrap();
+        assert!(keyset.try_verify(&token, &verifier).is_ok());
+    }
+
+    #[test]
+    fn verify_auto_token_use() {
+        let keyset = test_keyset();
+        let now = unix_time_now().unwrap();
+        let iss = "https://cognito-idp.eu-west-1.amazonaws.com/eu-west-1_AbCd1234";
+
+        let claims = json!({
+            "sub": "user", "iss": iss, "aud": "client-id", "token_use": "id",
+            "exp": now + 60, "iat": now, "auth_time": now, "cognito:username": "user",
+        });
+        let token = hs256_token(&claims);
+        let verified = async_std::task::block_on(keyset.verify_auto(&token, &["client-id"])).unwrap();
+        assert!(matches!(verified, VerifiedToken::Id(_)));
+
+        let claims = json!({
+            "sub": "user", "iss": iss, "client_id": "client-id", "token_use": "access",
+            "exp": now + 60, "iat": now,
+        });
+        let token = hs256_token(&claims);
+        let verified = async_std::task::block_on(keyset.verify_auto(&token, &["client-id"])).unwrap();
+        assert!(matches!(verified, VerifiedToken::Access(_)));
+        assert!(async_std::task::block_on(keyset.verify_auto(&token, &["other-client"])).is_err());
+
+        let token = hs256_token(&json!({ "iss": TEST_ISS }));
+        assert!(matches!(async_std::task::block_on(keyset.verify_auto(&token, &["client-id"])),
+                         Err(Error::MalformedToken(_))));
+    }
+
+    #[test]
+    fn verify_full_kid() {
+        let keyset = KeySet::builder("eu-west-1", "eu-west-1_AbCd1234")
+            .no_kid_fallback(Some(2))
+            .build()
+            .unwrap();
+        insert_test_key(&keyset, "test-key", "secret");
+        let verifier = keyset.new_issuer_verifier().build().unwrap();
+
+        let token = test_token(json!({ "alg": "HS256", "kid": "test-key" }), "secret");
+        let data = async_std::task::block_on(keyset.verify_full(&token, &verifier)).unwrap();
+        assert_eq!(data.kid, "test-key");
+        assert_eq!(data.header["alg"], "HS256");
+        assert_eq!(data.claims["iss"], "https://cognito-idp.eu-west-1.amazonaws.com/eu-west-1_AbCd1234");
+
+        let token = test_token(json!({ "alg": "HS256" }), "secret");
+        let data = async_std::task::block_on(keyset.verify_full(&token, &verifier)).unwrap();
+        assert_eq!(data.kid, "test-key");
+    }
+
+    #[test]
+    fn unknown_kid_negative_cache() {
+        let fetcher = TestFetcher::jwks();
+        let keyset = KeySet::builder("eu-west-1", "eu-west-1_AbCd1234")
+            .jwks_fetcher(fetcher.clone())
+            .min_jwks_fetch_interval(Duration::from_secs(0))
+            .build()
+            .unwrap();
+        let verifier = keyset.new_issuer_verifier().build().unwrap();
+
+        let token = test_token(json!({ "alg": "HS256", "kid": "random-kid" }), "secret");
+        for _ in 0..3 {
+            let result = async_std::task::block_on(keyset.verify(&token, &verifier));
+            assert!(matches!(result, Err(Error::UnknownKeyId(kid)) if kid == "random-kid"));
+        }
+        assert_eq!(fetcher.fetch_count(), 1);
+    }
+
+    #[test]
+    fn evict_removed_keys() {
+        let mut keyset = KeySet::new("eu-west-1", "eu-west-1_AbCd1234").unwrap();
+        insert_test_key(&keyset, "old-key", "secret");
+
+        let jwks: JwkSet = serde_json::from_str(TEST_JWKS).unwrap();
+        keyset.cache_jwks(jwks, None, None).unwrap();
+        assert!(keyset.try_cache_lookup_algorithm("test-key-0").is_ok());
+        assert!(keyset.try_cache_lookup_algorithm("old-key").is_err());
+
+        keyset.set_evict_removed_keys(false);
+        insert_test_key(&keyset, "old-key", "secret");
+        let jwks: JwkSet = serde_json::from_str(TEST_JWKS).unwrap();
+        keyset.cache_jwks(jwks, None, None).unwrap();
+        assert!(keyset.try_cache_lookup_algorithm("old-key").is_ok());
+    }
+
+    #[test]
+    fn retired_key_grace() {
+        let clock = ManualClock::new();
+        let keyset = KeySet::builder("eu-west-1", "eu-west-1_AbCd1234")
+            .clock(clock.clone())
+            .retired_key_grace(Duration::from_secs(600))
+            .build()
+            .unwrap();
+        insert_test_key(&keyset, "old-key", "secret");
+        let verifier = keyset.new_issuer_verifier().build().unwrap();
+        let token = test_token(json!({ "alg": "HS256", "kid": "old-key" }), "secret");
+        let jwks = || serde_json::from_str::<JwkSet>(TEST_JWKS).unwrap();
+
+        let report = keyset.cache_jwks(jwks(), None, None).unwrap();
+        assert_eq!(report.retired, ["old-key"]);
+        assert!(report.removed.is_empty() && report.unchanged.is_empty());
+        assert_eq!(keyset.retired_kids(), ["old-key"]);
+        assert_eq!(keyset.cached_kids(), ["old-key", "test-key-0", "test-key-1"]);
+        assert!(keyset.try_verify(&token, &verifier).is_ok());
+        assert_eq!(keyset.cache_snapshot().unwrap().kids(), ["test-key-0", "test-key-1"]);
+
+        // Still retired, rather than retired again, when the key set is refetched
+        clock.advance(Duration::from_secs(300));
+        let report = keyset.cache_jwks(jwks(), None, None).unwrap();
+        assert!(!report.is_changed());
+        assert_eq!(report.unchanged, ["test-key-0", "test-key-1"]);
+        assert!(keyset.try_verify(&token, &verifier).is_ok());
+
+        // Expired keys aren't used before they're evicted
+        clock.advance(Duration::from_secs(300));
+        assert!(keyset.retired_kids().is_empty());
+        assert_eq!(keyset.cached_key_count(), 2);
+        assert!(matches!(keyset.try_verify(&token, &verifier), Err(Error::CacheMiss { .. })));
+        keyset.cache_jwks(jwks(), None, None).unwrap();
+        assert!(!keyset.keys.load().contains_key("old-key"));
+
+        // A retired key that reappears in the key set is no longer retired
+        let mut jwks_value: Value = serde_json::from_str(TEST_JWKS).unwrap();
+        jwks_value["keys"].as_array_mut().unwrap().retain(|key| key["kid"] != "test-key-1");
+        keyset.cache_jwks(serde_json::from_value(jwks_value).unwrap(), None, None).unwrap();
+        assert_eq!(keyset.retired_kids(), ["test-key-1"]);
+        let report = keyset.cache_jwks(jwks(), None, None).unwrap();
+        assert!(keyset.retired_kids().is_empty());
+        assert_eq!(report.unchanged, ["test-key-0", "test-key-1"]);
+    }
+
+    #[test]
+    fn cache_management() {
+        let keyset = KeySet::new("eu-west-1", "eu-west-1_AbCd1234").unwrap();
+        insert_test_key(&keyset, "test-key-1", "secret");
+        keyset.insert_key_pem("test-key-0", include_bytes!("../testdata/rsa-public.pem")).unwrap();
+        assert_eq!(keyset.cached_kids(), ["test-key-0", "test-key-1"]);
+        assert!(keyset.insert_key_pem("bad-key", b"not a pem").is_err());
+
+        let token = rs256_token(&json!({ "iss": TEST_ISS }));
+        let verifier = keyset.new_issuer_verifier().build().unwrap();
+        assert!(keyset.try_verify(&token, &verifier).is_ok());
+
+        keyset.invalidate_cache();
+        assert!(keyset.cached_kids().is_empty());
+        assert!(keyset.throttle_remaining().is_none());
+        assert!(matches!(keyset.try_verify(&token, &verifier), Err(Error::CacheMiss { last_updated: None, .. })));
+    }
+
+    #[test]
+    fn stale_while_revalidate() {
+        let mut keyset = KeySet::new("eu-west-1", "eu-west-1_AbCd1234").unwrap();
+        insert_test_key(&keyset, "old-key", "secret");
+        let verifier = keyset.new_issuer_verifier().build().unwrap();
+
+        // The key set was just fetched, so fetching the new kid is throttled
+        let token = test_token(json!({ "alg": "HS256", "kid": "new-key" }), "secret");
+        match async_std::task::block_on(keyset.verify(&token, &verifier)) {
+            Err(Error::Throttled { .. }) => {}
+            other => panic!("Expected a Throttled error, got {:?}", other),
+        }
+
+        match keyset.try_verify(&token, &verifier) {
+            Err(Error::CacheMiss { kid, cached_kids, retry_after, .. }) => {
+                assert_eq!(kid.as_deref(), Some("new-key"));
+                assert_eq!(cached_kids, vec!["old-key".to_string()]);
+                assert!(retry_after.is_some());
+            }
+            other => panic!("Expected a CacheMiss error, got {:?}", other),
+        }
+
+        keyset.set_stale_while_revalidate(true);
+        let data = async_std::task::block_on(keyset.verify_full(&token, &verifier)).unwrap();
+        assert_eq!(data.kid, "old-key");
+
+        let token = test_token(json!({ "alg": "HS256", "kid": "new-key" }), "other-secret");
+        match async_std::task::block_on(keyset.verify(&token, &verifier)) {
+            Err(Error::Throttled { .. }) => {}
+            other => panic!("Expected a Throttled error, got {:?}", other),
+        }
+    }
 }
@@ -0,0 +1,731 @@
+//! A client for the Cognito hosted UI's OAuth 2.0 token endpoint
+//!
+//! [OAuthClient] exchanges the authorization code from a hosted UI sign in
+//! (optionally with a PKCE code verifier) for tokens, and refreshes tokens
+//! with a refresh token. The returned [TokenResponse] tokens can then be
+//! verified with a KeySet like any other token:
+//!
+//! ```no_run
+//! # use jsonwebtokens_cognito::KeySet;
+//! # use jsonwebtokens_cognito::oauth::OAuthClient;
+//! # #[async_std::main]
+//! # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! # let (code, code_verifier) = ("code", "verifier");
+//! let client = OAuthClient::new("https://my-domain.auth.eu-west-1.amazoncognito.com", "client-id-0")
+//!     .with_client_secret("client-secret");
+//! let tokens = client.exchange_code(code, "https://example.com/callback", Some(code_verifier)).await?;
+//!
+//! let keyset = KeySet::new("eu-west-1", "eu-west-1_AbCd1234")?;
+//! let verifier = keyset.new_access_token_verifier(&["client-id-0"]).build()?;
+//! let claims = keyset.verify(&tokens.access_token, &verifier).await?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! [OAuthClient::user_info()] fetches the profile attributes of the user that
+//! an access token was issued to.
+//!
+//! [TokenManager] keeps a user's tokens fresh for long-running workers that act
+//! on behalf of the user.
+//!
+//! The [OAuthClient::authorize_url()] and [OAuthClient::logout_url()] helpers
+//! build the hosted UI URLs that users are redirected to to sign in and out.
+//!
+//! _Requires the `oauth` feature_
+
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use ring::digest;
+use ring::rand::{SecureRandom, SystemRandom};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::value::Value;
+
+use jsonwebtokens::Verifier;
+
+use crate::{unix_time_now, Error, ErrorDetails, KeySet};
+
+pub use crate::secret_hash::secret_hash;
+
+/// The tokens issued by the token endpoint
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TokenResponse {
+    /// The access token, for authorizing requests
+    pub access_token: String,
+
+    /// The ID token, if the `openid` scope was requested
+    #[serde(default)]
+    pub id_token: Option<String>,
+
+    /// The refresh token, which isn't included when refreshing tokens
+    #[serde(default)]
+    pub refresh_token: Option<String>,
+
+    /// Always "Bearer"
+    pub token_type: String,
+
+    /// How many seconds until the access and ID tokens expire
+    pub expires_in: u64,
+}
+
+/// A user's attributes, as returned by the `/oauth2/userInfo` endpoint
+///
+/// Only the attributes that the access token's scopes allow are included, for
+/// example `email` requires the `email` scope. Any attributes that aren't
+/// explicitly handled (such as `custom:` attributes) can be found in `other`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct UserInfo {
+    pub sub: String,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub username: Option<String>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub email: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none", deserialize_with = "crate::claims::bool_or_string")]
+    pub email_verified: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub phone_number: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none", deserialize_with = "crate::claims::bool_or_string")]
+    pub phone_number_verified: Option<bool>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub given_name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub family_name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub preferred_username: Option<String>,
+
+    /// Any other attributes, including custom attributes
+    #[serde(flatten)]
+    pub other: HashMap<String, Value>,
+}
+
+/// A PKCE code verifier and its `S256` code challenge
+///
+/// The challenge is sent with the authorization request and the verifier is
+/// sent when exchanging the authorization code for tokens.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Pkce {
+    pub code_verifier: String,
+    pub code_challenge: String,
+}
+
+impl Pkce {
+    /// Generates a random code verifier
+    pub fn new() -> Self {
+        Pkce::from_verifier(random_string())
+    }
+
+    /// Derives the code challenge for the given code verifier
+    pub fn from_verifier(code_verifier: impl Into<String>) -> Self {
+        let code_verifier = code_verifier.into();
+        let hash = digest::digest(&digest::SHA256, code_verifier.as_bytes());
+        let code_challenge = base64::Engine::encode(&base64::engine::general_purpose::URL_SAFE_NO_PAD, hash);
+        Pkce { code_verifier, code_challenge }
+    }
+}
+
+impl Default for Pkce {
+    fn default() -> Self {
+        Pkce::new()
+    }
+}
+
+/// A URL for signing in with the hosted UI, and the values that must be kept
+/// (e.g. in the user's session) to complete the sign in
+///
+/// When the user is redirected back with a `code`, the redirect's `state`
+/// parameter must match `state`, and `pkce.code_verifier` is given to
+/// [OAuthClient::exchange_code()]. The ID token's `nonce` claim can be checked
+/// with `VerifierBuilderExt::with_nonce()`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AuthorizeRequest {
+    /// The `/oauth2/authorize` URL to redirect the user to
+    pub url: String,
+
+    /// A random value for preventing cross-site request forgery
+    pub state: String,
+
+    /// A random value for binding the ID token to this sign in
+    pub nonce: String,
+
+    /// The PKCE code verifier and challenge
+    pub pkce: Pkce,
+}
+
+#[derive(Deserialize)]
+struct ErrorResponse {
+    error: String,
+    #[serde(default)]
+    error_description: Option<String>,
+}
+
+/// A client for the `/oauth2/token` and `/oauth2/userInfo` endpoints of a
+/// Cognito user pool domain
+///
+/// Confidential app clients (with a client secret) authenticate with HTTP basic
+/// authentication, otherwise the client ID is sent with each request.
+#[derive(Clone)]
+pub struct OAuthClient {
+    domain: String,
+    client_id: String,
+    client_secret: Option<String>,
+    client: reqwest::Client,
+}
+
+impl fmt::Debug for OAuthClient {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("OAuthClient")
+            .field("domain", &self.domain)
+            .field("client_id", &self.client_id)
+            .field("client_secret", &self.client_secret.as_ref().map(|_| "<redacted>"))
+            .finish()
+    }
+}
+
+impl OAuthClient {
+    /// Constructs a client for the app client with the given ID
+    ///
+    /// The domain is the user pool's hosted UI domain, like
+    /// `https://my-domain.auth.eu-west-1.amazoncognito.com`, or a custom domain.
+    /// `https://` is assumed if the domain has no scheme.
+    pub fn new(domain: impl Into<String>, client_id: impl Into<String>) -> Self {
+        let domain = domain.into();
+        let domain = domain.trim_end_matches('/');
+        let domain = if domain.contains("://") {
+            domain.to_string()
+        } else {
+            format!("https://{}", domain)
+        };
+
+        OAuthClient {
+            domain,
+            client_id: client_id.into(),
+            client_secret: None,
+            client: crate::fetcher::default_client_builder().build().expect("Failed to build default HTTP client"),
+        }
+    }
+
+    /// Sets the app client's secret, for confidential clients
+    pub fn with_client_secret(mut self, client_secret: impl Into<String>) -> Self {
+        self.client_secret = Some(client_secret.into());
+        self
+    }
+
+    /// Uses an externally configured `reqwest::Client` for requests
+    pub fn with_http_client(mut self, client: reqwest::Client) -> Self {
+        self.client = client;
+        self
+    }
+
+    /// The URL of the token endpoint
+    pub fn token_url(&self) -> String {
+        format!("{}/oauth2/token", self.domain)
+    }
+
+    /// Fetches the attributes of the user that the access token was issued to,
+    /// from the `/oauth2/userInfo` endpoint
+    ///
+    /// The access token should be verified first, e.g. with `KeySet::verify()`,
+    /// though the endpoint will also reject invalid tokens.
+    pub async fn user_info(&self, access_token: &str) -> Result<UserInfo, Error> {
+        let resp = self.client.get(format!("{}/oauth2/userInfo", self.domain))
+            .bearer_auth(access_token)
+            .send().await?;
+        let status = resp.status();
+        let body = resp.bytes().await?;
+        parse_response(status, &body)
+    }
+
+    /// Builds an `/oauth2/authorize` URL for signing in with the hosted UI,
+    /// with a random `state`, `nonce` and PKCE code challenge
+    ///
+    /// ```
+    /// # use jsonwebtokens_cognito::oauth::OAuthClient;
+    /// let client = OAuthClient::new("https://my-domain.auth.eu-west-1.amazoncognito.com", "client-id-0");
+    /// let request = client.authorize_url("https://example.com/callback", &["openid", "email"]);
+    /// // Store request.state, request.nonce and request.pkce.code_verifier in
+    /// // the user's session and redirect them to request.url
+    /// ```
+    pub fn authorize_url(&self, redirect_uri: &str, scopes: &[&str]) -> AuthorizeRequest {
+        let state = random_string();
+        let nonce = random_string();
+        let pkce = Pkce::new();
+
+        let url = self.url("/oauth2/authorize", &[
+            ("response_type", "code"),
+            ("client_id", &self.client_id),
+            ("redirect_uri", redirect_uri),
+            ("scope", &scopes.join(" ")),
+            ("state", &state),
+            ("nonce", &nonce),
+            ("code_challenge", &pkce.code_challenge),
+            ("code_challenge_method", "S256"),
+        ]);
+
+        AuthorizeRequest { url, state, nonce, pkce }
+    }
+
+    /// Builds a `/logout` URL for signing out of the hosted UI, which redirects
+    /// to `logout_uri` afterwards
+    ///
+    /// `logout_uri` must be one of the app client's sign out URLs.
+    pub fn logout_url(&self, logout_uri: &str) -> String {
+        self.url("/logout", &[
+            ("client_id", &self.client_id),
+            ("logout_uri", logout_uri),
+        ])
+    }
+
+    fn url(&self, path: &str, params: &[(&str, &str)]) -> String {
+        let url = format!("{}{}", self.domain, path);
+        match reqwest::Url::parse_with_params(&url, params) {
+            Ok(url) => url.into(),
+            // Only if the domain is invalid, in which case requests will fail too
+            Err(_) => url,
+        }
+    }
+
+    /// Exchanges an authorization code for tokens (the `authorization_code` grant)
+    ///
+    /// `redirect_uri` must be the same redirect URI that the code was requested
+    /// with, and `code_verifier` is required if the code was requested with a
+    /// PKCE `code_challenge`.
+    pub async fn exchange_code(
+        &self,
+        code: &str,
+        redirect_uri: &str,
+        code_verifier: Option<&str>
+    ) -> Result<TokenResponse, Error> {
+        let mut params = vec![
+            ("grant_type", "authorization_code"),
+            ("code", code),
+            ("redirect_uri", redirect_uri),
+        ];
+        if let Some(code_verifier) = code_verifier {
+            params.push(("code_verifier", code_verifier));
+        }
+        self.request_tokens(params).await
+    }
+
+    /// Gets new access and ID tokens with a refresh token (the `refresh_token`
+    /// grant)
+    ///
+    /// The response doesn't include a new refresh token.
+    pub async fn refresh(&self, refresh_token: &str) -> Result<TokenResponse, Error> {
+        self.request_tokens(vec![
+            ("grant_type", "refresh_token"),
+            ("refresh_token", refresh_token),
+        ]).await
+    }
+
+    async fn request_tokens(&self, mut params: Vec<(&str, &str)>) -> Result<TokenResponse, Error> {
+        let mut req = self.client.post(self.token_url());
+        match &self.client_secret {
+            Some(client_secret) => req = req.basic_auth(&self.client_id, Some(client_secret)),
+            None => params.push(("client_id", &self.client_id)),
+        }
+
+        let resp = req.form(&params).send().await?;
+        let status = resp.status();
+        let body = resp.bytes().await?;
+        parse_response(status, &body)
+    }
+}
+
+// Parses an endpoint's JSON response, or its OAuth error response
+pub(crate) fn parse_response<T: DeserializeOwned>(status: reqwest::StatusCode, body: &[u8]) -> Result<T, Error> {
+    if status.is_success() {
+        return serde_json::from_slice(body).map_err(|e| Error::OAuthError {
+            status,
+            error: body_snippet(body),
+            error_description: Some(format!("Failed to parse OAuth response: {}", e)),
+        });
+    }
+
+    match serde_json::from_slice::<ErrorResponse>(body) {
+        Ok(resp) => Err(Error::OAuthError {
+            status,
+            error: resp.error,
+            error_description: resp.error_description,
+        }),
+        Err(_) => Err(Error::OAuthError {
+            status,
+            error: body_snippet(body),
+            error_description: None,
+        }),
+    }
+}
+
+// The start of a response body that isn't an OAuth response
+fn body_snippet(body: &[u8]) -> String {
+    String::from_utf8_lossy(body).chars().take(200).collect()
+}
+
+/// Keeps a user's access token fresh by refreshing it with a refresh token
+///
+/// The access token is refreshed via the token endpoint shortly before it
+/// expires (see `with_refresh_margin()`) and new tokens are verified with the
+/// KeySet before they're used. If Cognito issues a new refresh token (with
+/// refresh token rotation) it replaces the old one.
+///
+/// Only one refresh is made at a time, so concurrent callers that find the
+/// access token needs refreshing share the same refresh (which matters with
+/// refresh token rotation, where a refresh token can only be used once).
+///
+/// ```no_run
+/// # use jsonwebtokens_cognito::KeySet;
+/// # use jsonwebtokens_cognito::oauth::{OAuthClient, TokenManager};
+/// # #[async_std::main]
+/// # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// # let refresh_token = "refresh-token";
+/// let client = OAuthClient::new("https://my-domain.auth.eu-west-1.amazoncognito.com", "client-id-0");
+/// let keyset = KeySet::new("eu-west-1", "eu-west-1_AbCd1234")?;
+/// let verifier = keyset.new_access_token_verifier(&["client-id-0"]).build()?;
+/// let tokens = TokenManager::new(client, keyset, verifier, refresh_token);
+///
+/// // Before each outbound request
+/// let access_token = tokens.current_access_token().await?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct TokenManager {
+    client: OAuthClient,
+    keyset: KeySet,
+    verifier: Verifier,
+    id_token_verifier: Option<Verifier>,
+    refresh_margin: Duration,
+    state: Mutex<ManagedTokens>,
+    // Held while refreshing
+    refreshing: futures_util::lock::Mutex<()>,
+}
+
+#[derive(Debug, Clone, Default)]
+struct ManagedTokens {
+    refresh_token: String,
+    access_token: Option<String>,
+    id_token: Option<String>,
+
+    // The access token's `exp`, once it's been verified
+    expires_at: Option<u64>,
+}
+
+impl TokenManager {
+    /// Constructs a manager that will get its first tokens with the refresh token
+    ///
+    /// The verifier is used to verify the access tokens, e.g. from
+    /// `KeySet::new_access_token_verifier()`.
+    pub fn new(client: OAuthClient, keyset: KeySet, verifier: Verifier, refresh_token: impl Into<String>) -> Self {
+        TokenManager {
+            client,
+            keyset,
+            verifier,
+            id_token_verifier: None,
+            refresh_margin: Duration::from_secs(60),
+            state: Mutex::new(ManagedTokens {
+                refresh_token: refresh_token.into(),
+                ..Default::default()
+            }),
+            refreshing: Default::default(),
+        }
+    }
+
+    /// Constructs a manager starting with the tokens from a sign in, e.g. from
+    /// [OAuthClient::exchange_code()]
+    ///
+    /// Fails if the tokens don't include a refresh token. The tokens are
+    /// verified before they're first used.
+    pub fn with_tokens(client: OAuthClient, keyset: KeySet, verifier: Verifier, tokens: TokenResponse)
+        -> Result<Self, Error>
+    {
+        let refresh_token = tokens.refresh_token.ok_or_else(|| {
+            Error::InvalidConfiguration(ErrorDetails::new("Token response had no refresh token"))
+        })?;
+        let manager = TokenManager::new(client, keyset, verifier, refresh_token);
+        {
+            let mut state = manager.state.lock().unwrap();
+            state.access_token = Some(tokens.access_token);
+            state.id_token = tokens.id_token;
+        }
+        Ok(manager)
+    }
+
+    /// Sets a verifier for also verifying new ID tokens, e.g. from
+    /// `KeySet::new_id_token_verifier()`
+    pub fn with_id_token_verifier(mut self, verifier: Verifier) -> Self {
+        self.id_token_verifier = Some(verifier);
+        self
+    }
+
+    /// Sets how long before the access token expires that it's refreshed
+    /// (default: one minute)
+    pub fn with_refresh_margin(mut self, margin: Duration) -> Self {
+        self.refresh_margin = margin;
+        self
+    }
+
+    /// Returns a verified access token that won't expire within the refresh
+    /// margin, refreshing it first if necessary
+    pub async fn current_access_token(&self) -> Result<String, Error> {
+        if let Some(access_token) = self.fresh_access_token().await? {
+            return Ok(access_token);
+        }
+
+        let _refreshing = self.refreshing.lock().await;
+        // Another caller may have refreshed the token while this one waited
+        if let Some(access_token) = self.fresh_access_token().await? {
+            return Ok(access_token);
+        }
+        self.refresh_tokens().await?;
+        let state = self.state.lock().unwrap();
+        Ok(state.access_token.clone().unwrap_or_default())
+    }
+
+    // Returns the access token if it won't expire within the refresh margin
+    async fn fresh_access_token(&self) -> Result<Option<String>, Error> {
+        let (access_token, expires_at) = {
+            let state = self.state.lock().unwrap();
+            (state.access_token.clone(), state.expires_at)
+        };
+        let refresh_after = unix_time_now()? + self.refresh_margin.as_secs();
+
+        if let Some(access_token) = access_token {
+            let expires_at = match expires_at {
+                Some(expires_at) => Some(expires_at),
+                None => self.verified_expiry(&access_token).await?,
+            };
+            match expires_at {
+                Some(expires_at) if expires_at > refresh_after => return Ok(Some(access_token)),
+                _ => {}
+            }
+        }
+        Ok(None)
+    }
+
+    /// The current ID token, if the token endpoint returned one
+    ///
+    /// This isn't refreshed automatically, but is updated whenever the access
+    /// token is refreshed.
+    pub fn current_id_token(&self) -> Option<String> {
+        self.state.lock().unwrap().id_token.clone()
+    }
+
+    /// The current refresh token
+    pub fn current_refresh_token(&self) -> String {
+        self.state.lock().unwrap().refresh_token.clone()
+    }
+
+    /// Refreshes the tokens now, regardless of when they expire
+    pub async fn refresh(&self) -> Result<(), Error> {
+        let _refreshing = self.refreshing.lock().await;
+        self.refresh_tokens().await
+    }
+
+    // Must be called while holding the `refreshing` lock
+    async fn refresh_tokens(&self) -> Result<(), Error> {
+        let refresh_token = self.current_refresh_token();
+        let tokens = self.client.refresh(&refresh_token).await?;
+
+        let claims = self.keyset.verify(&tokens.access_token, &self.verifier).await?;
+        if let (Some(verifier), Some(id_token)) = (&self.id_token_verifier, &tokens.id_token) {
+            self.keyset.verify(id_token, verifier).await?;
+        }
+
+        let mut state = self.state.lock().unwrap();
+        state.expires_at = claims.get("exp").and_then(Value::as_u64);
+        state.access_token = Some(tokens.access_token);
+        if tokens.id_token.is_some() {
+            state.id_token = tokens.id_token;
+        }
+        if let Some(refresh_token) = tokens.refresh_token {
+            state.refresh_token = refresh_token;
+        }
+        Ok(())
+    }
+
+    // Verifies the initial access token, returning its expiry, or None if it's
+    // already expired
+    async fn verified_expiry(&self, access_token: &str) -> Result<Option<u64>, Error> {
+        let claims = match self.keyset.verify(access_token, &self.verifier).await {
+            Ok(claims) => claims,
+            Err(Error::TokenExpiredAt(_)) => return Ok(None),
+            Err(e) => return Err(e),
+        };
+        let expires_at = claims.get("exp").and_then(Value::as_u64);
+        let mut state = self.state.lock().unwrap();
+        if state.access_token.as_deref() == Some(access_token) {
+            state.expires_at = expires_at;
+        }
+        Ok(expires_at)
+    }
+}
+
+// A random URL safe string, with 256 bits of entropy
+fn random_string() -> String {
+    let mut bytes = [0u8; 32];
+    SystemRandom::new().fill(&mut bytes).expect("Failed to generate random bytes");
+    base64::Engine::encode(&base64::engine::general_purpose::URL_SAFE_NO_PAD, bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use reqwest::StatusCode;
+    use serde_json::json;
+
+    use super::*;
+    use crate::test_util::*;
+
+    #[test]
+    fn oauth_token_responses() {
+        let body = br#"{ "access_token": "a", "id_token": "i", "refresh_token": "r",
+                         "token_type": "Bearer", "expires_in": 3600 }"#;
+        let tokens: TokenResponse = parse_response(StatusCode::OK, body).unwrap();
+        assert_eq!(tokens.access_token, "a");
+        assert_eq!(tokens.id_token.as_deref(), Some("i"));
+        assert_eq!(tokens.expires_in, 3600);
+
+        let body = br#"{ "error": "invalid_grant" }"#;
+        assert!(matches!(parse_response::<TokenResponse>(StatusCode::BAD_REQUEST, body),
+                         Err(Error::OAuthError { ref error, error_description: None, .. }) if error == "invalid_grant"));
+
+        // A successful response that can't be parsed isn't a network error
+        let error = match parse_response::<TokenResponse>(StatusCode::OK, b"<html>") {
+            Err(error @ Error::OAuthError { status: StatusCode::OK, .. }) => error,
+            _ => panic!("Expected an OAuthError"),
+        };
+        assert_eq!(error.code(), "oauth_error");
+        assert!(error.to_string().starts_with("OAuth request failed with status 200 OK: <html> (Failed to parse"));
+
+        let body = br#"{ "sub": "user-0", "username": "alice", "email": "alice@example.com",
+                         "email_verified": "true", "custom:team": "blue" }"#;
+        let user: UserInfo = parse_response(StatusCode::OK, body).unwrap();
+        assert_eq!(user.email_verified, Some(true));
+        assert_eq!(user.phone_number_verified, None);
+        assert_eq!(user.other["custom:team"], "blue");
+
+        assert_eq!(secret_hash("user", "client-id", "secret"),
+                   "RSjsoPKBywjmQB5KPsU7XLGSHdokKXTPnTF9NAGFSYo=");
+        assert_eq!(OAuthClient::new("my-domain.auth.eu-west-1.amazoncognito.com/", "client-id").token_url(),
+                   "https://my-domain.auth.eu-west-1.amazoncognito.com/oauth2/token");
+    }
+
+    #[test]
+    fn oauth_urls() {
+        // The example from RFC 7636
+        let pkce = Pkce::from_verifier("dBjftJeZ4CVP-mB92K27uhbUJU1p1r_wW1gFWFOEjXk");
+        assert_eq!(pkce.code_challenge, "E9Melhoa2OwvFrEMTJguCHaoeK1t8URWbuGJSstw-cM");
+
+        let client = OAuthClient::new("https://auth.example.com", "client-id");
+        let request = client.authorize_url("https://example.com/callback", &["openid", "email"]);
+        assert_ne!(request.state, request.nonce);
+        assert_eq!(request.pkce, Pkce::from_verifier(request.pkce.code_verifier.clone()));
+        assert_eq!(request.url, format!(
+            "https://auth.example.com/oauth2/authorize?response_type=code&client_id=client-id&\
+             redirect_uri=https%3A%2F%2Fexample.com%2Fcallback&scope=openid+email&state={}&nonce={}&\
+             code_challenge={}&code_challenge_method=S256",
+            request.state, request.nonce, request.pkce.code_challenge));
+
+        assert_eq!(client.logout_url("https://example.com/"),
+                   "https://auth.example.com/logout?client_id=client-id&logout_uri=https%3A%2F%2Fexample.com%2F");
+    }
+
+    #[test]
+    fn token_manager() {
+        let keyset = test_keyset();
+        let token = |exp: u64| {
+            let claims = json!({ "iss": TEST_ISS,
+                                 "exp": exp });
+            hs256_token(&claims)
+        };
+        let tokens = |access_token: String| TokenResponse {
+            access_token,
+            id_token: None,
+            refresh_token: Some("refresh-token".to_string()),
+            token_type: "Bearer".to_string(),
+            expires_in: 3600,
+        };
+        let verifier = keyset.new_issuer_verifier().build().unwrap();
+        // Refreshing fails, without making a request, due to the invalid URL
+        let client = OAuthClient::new("http://[invalid", "client-id");
+        let now = unix_time_now().unwrap();
+
+        let fresh = token(now + 3600);
+        let manager = TokenManager::with_tokens(client.clone(), keyset.clone(), verifier.clone(),
+                                                       tokens(fresh.clone())).unwrap();
+        assert_eq!(async_std::task::block_on(manager.current_access_token()).unwrap(), fresh);
+        assert_eq!(manager.current_refresh_token(), "refresh-token");
+
+        // Within the refresh margin
+        let manager = TokenManager::with_tokens(client.clone(), keyset.clone(), verifier.clone(),
+                                                       tokens(token(now + 30))).unwrap();
+        assert!(matches!(async_std::task::block_on(manager.current_access_token()),
+                         Err(Error::NetworkError(_))));
+
+        let manager = TokenManager::with_tokens(client, keyset.clone(), verifier,
+                                                       tokens(token(now - 60))).unwrap();
+        assert!(matches!(async_std::task::block_on(manager.current_access_token()),
+                         Err(Error::NetworkError(_))));
+    }
+
+    #[cfg(feature = "tokio")]
+    #[test]
+    fn token_manager_single_refresh() {
+        use std::io::{Read, Write};
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use std::sync::Arc;
+
+        let now = unix_time_now().unwrap();
+        let fresh = hs256_token(&json!({ "iss": TEST_ISS, "exp": now + 3600 }));
+        let stale = hs256_token(&json!({ "iss": TEST_ISS, "exp": now - 60 }));
+
+        // A token endpoint that's slow to respond, so the refreshes overlap
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let domain = format!("http://{}", listener.local_addr().unwrap());
+        let requests = Arc::new(AtomicUsize::new(0));
+        let body = json!({ "access_token": fresh, "token_type": "Bearer", "expires_in": 3600 }).to_string();
+        std::thread::spawn({
+            let requests = requests.clone();
+            move || {
+                for mut stream in listener.incoming().flatten() {
+                    requests.fetch_add(1, Ordering::SeqCst);
+                    let mut request = Vec::new();
+                    let mut buf = [0u8; 1024];
+                    while let Ok(n) = stream.read(&mut buf) {
+                        request.extend_from_slice(&buf[..n]);
+                        if n == 0 || request.ends_with(b"refresh_token=refresh-token&client_id=client-id") {
+                            break;
+                        }
+                    }
+                    std::thread::sleep(Duration::from_millis(50));
+                    let _ = write!(stream, "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\n\
+                                            Content-Length: {}\r\nConnection: close\r\n\r\n{}",
+                                   body.len(), body);
+                }
+            }
+        });
+
+        let keyset = test_keyset();
+        let verifier = keyset.new_issuer_verifier().build().unwrap();
+        let manager = TokenManager::with_tokens(OAuthClient::new(domain, "client-id"), keyset, verifier,
+                                                TokenResponse {
+                                                    access_token: stale,
+                                                    id_token: None,
+                                                    refresh_token: Some("refresh-token".to_string()),
+                                                    token_type: "Bearer".to_string(),
+                                                    expires_in: 3600,
+                                                }).unwrap();
+
+        let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap();
+        let (first, second) = runtime.block_on(futures_util::future::join(manager.current_access_token(),
+                                                                          manager.current_access_token()));
+        assert_eq!(first.unwrap(), fresh);
+        assert_eq!(second.unwrap(), fresh);
+        assert_eq!(requests.load(Ordering::SeqCst), 1);
+    }
+}
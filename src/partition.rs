@@ -0,0 +1,76 @@
+/// An AWS partition, which determines the domain of a region's endpoints
+///
+/// The partition is normally derived from the region (see
+/// [Partition::for_region]), but can be overridden with
+/// `KeySetBuilder::partition()`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Partition {
+    /// The standard AWS regions, with endpoints under `amazonaws.com`
+    Aws,
+
+    /// The AWS China regions (`cn-north-1` and `cn-northwest-1`), with endpoints
+    /// under `amazonaws.com.cn`
+    AwsCn,
+
+    /// The AWS GovCloud (US) regions, with endpoints under `amazonaws.com`
+    AwsUsGov,
+}
+
+impl Partition {
+    /// Returns the partition that the given region belongs to
+    ///
+    /// ```
+    /// # use jsonwebtokens_cognito::Partition;
+    /// assert_eq!(Partition::for_region("eu-west-1"), Partition::Aws);
+    /// assert_eq!(Partition::for_region("cn-north-1"), Partition::AwsCn);
+    /// assert_eq!(Partition::for_region("us-gov-west-1"), Partition::AwsUsGov);
+    /// ```
+    pub fn for_region(region: &str) -> Partition {
+        if region.starts_with("cn-") {
+            Partition::AwsCn
+        } else if region.starts_with("us-gov-") {
+            Partition::AwsUsGov
+        } else {
+            Partition::Aws
+        }
+    }
+
+    /// The domain that the partition's endpoints are under
+    pub fn dns_suffix(&self) -> &'static str {
+        match self {
+            Partition::Aws | Partition::AwsUsGov => "amazonaws.com",
+            Partition::AwsCn => "amazonaws.com.cn",
+        }
+    }
+
+    // The host of the Cognito user pools endpoint for a region, which is also
+    // the host of the user pools' issuer URLs
+    pub(crate) fn cognito_idp_host(&self, region: &str, fips: bool) -> String {
+        let service = if fips { "cognito-idp-fips" } else { "cognito-idp" };
+        format!("{}.{}.{}", service, region, self.dns_suffix())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::KeySet;
+
+    #[test]
+    fn partitions() {
+        let keyset = KeySet::new("cn-north-1", "cn-north-1_AbCd1234").unwrap();
+        assert_eq!(keyset.jwks_url,
+                   "https://cognito-idp.cn-north-1.amazonaws.com.cn/cn-north-1_AbCd1234/.well-known/jwks.json");
+        assert_eq!(keyset.iss, "https://cognito-idp.cn-north-1.amazonaws.com.cn/cn-north-1_AbCd1234");
+
+        let keyset = KeySet::builder("us-gov-west-1", "us-gov-west-1_AbCd1234").fips(true).build().unwrap();
+        assert_eq!(keyset.jwks_url,
+                   "https://cognito-idp-fips.us-gov-west-1.amazonaws.com/us-gov-west-1_AbCd1234/.well-known/jwks.json");
+        assert_eq!(keyset.iss, "https://cognito-idp.us-gov-west-1.amazonaws.com/us-gov-west-1_AbCd1234");
+
+        let keyset = KeySet::builder("eu-west-1", "eu-west-1_AbCd1234").partition(Partition::AwsCn).build().unwrap();
+        assert_eq!(keyset.iss, "https://cognito-idp.eu-west-1.amazonaws.com.cn/eu-west-1_AbCd1234");
+
+        assert_eq!(Partition::for_region("cn-northwest-1").dns_suffix(), "amazonaws.com.cn");
+    }
+}
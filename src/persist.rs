@@ -0,0 +1,260 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use serde_json::value::Value;
+use web_time::SystemTime;
+
+use crate::jwk::JwkSet;
+use crate::{unix_time_now, Error, ErrorDetails, JwksRefreshReport, KeySet};
+
+const SNAPSHOT_TIME_RESOLUTION: Duration = Duration::from_secs(1);
+
+/// A serializable copy of a KeySet's cached keys, for restoring the cache in
+/// another process (see `KeySet::cache_snapshot()`)
+///
+/// A snapshot contains the public keys (as JWKs) and when they were fetched, so
+/// it doesn't need to be kept secret. It is however a trust anchor: restoring a
+/// snapshot makes the KeySet accept tokens signed by any key in it, without
+/// fetching the key set from the issuer. Anyone who can modify a persisted
+/// snapshot can therefore forge tokens, so it must be stored where only the
+/// processes that restore it can write (not a shared directory like `/tmp`).
+///
+/// Snapshots older than the KeySet's maximum snapshot age (see
+/// `KeySet::set_max_cache_snapshot_age()`) aren't restored.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CacheSnapshot {
+    pub(crate) jwks_url: String,
+    keys: Vec<Value>,
+
+    // When the key set was fetched (unix epoch timestamp)
+    fetched_at: u64,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    etag: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    max_age: Option<u64>,
+}
+
+impl CacheSnapshot {
+    /// The key IDs of the snapshot's keys
+    pub fn kids(&self) -> Vec<&str> {
+        self.keys.iter().filter_map(|key| key.get("kid").and_then(Value::as_str)).collect()
+    }
+
+    /// How long ago the snapshot's keys were fetched
+    pub fn age(&self) -> Duration {
+        let now = unix_time_now().unwrap_or(self.fetched_at);
+        Duration::from_secs(now.saturating_sub(self.fetched_at))
+    }
+}
+
+impl KeySet {
+    /// Returns a snapshot of the cached keys that can be persisted and later
+    /// restored with `restore_cache()`, such as to avoid fetching the key set
+    /// on every cold start of a short-lived process
+    ///
+    /// Returns `None` if the key set hasn't been fetched. Keys that were
+    /// inserted with `insert_key()`, and retired keys that are no longer in the
+    /// key set, aren't included.
+    pub fn cache_snapshot(&self) -> Option<CacheSnapshot> {
+        let readable_cache = self.cache.read().unwrap();
+        let last_jwks_get_time = readable_cache.last_jwks_get_time?;
+
+        let mut keys: Vec<(&String, Arc<Value>)> = Vec::new();
+        let cached_keys = self.keys.load();
+        for (kid, key) in cached_keys.iter() {
+            if let (Some(jwk), None) = (&key.jwk, key.retired) {
+                keys.push((kid, jwk.clone()));
+            }
+        }
+        if keys.is_empty() {
+            return None;
+        }
+        keys.sort_by_key(|(kid, _)| *kid);
+
+        let age = self.clock.now().duration_since(last_jwks_get_time).as_secs();
+        Some(CacheSnapshot {
+            jwks_url: self.jwks_url.clone(),
+            keys: keys.into_iter().map(|(_, jwk)| Value::clone(&jwk)).collect(),
+            fetched_at: unix_time_now().ok()?.saturating_sub(age),
+            etag: readable_cache.etag.clone(),
+            max_age: readable_cache.max_age.map(|max_age| max_age.as_secs()),
+        })
+    }
+
+    /// Populates the cache from a snapshot returned by `cache_snapshot()`, as
+    /// if the keys had been fetched when the snapshot was taken
+    ///
+    /// The snapshot must come from a trusted source (see [CacheSnapshot]).
+    ///
+    /// The snapshot is ignored, and false returned, if it's older than the
+    /// maximum snapshot age (see `set_max_cache_snapshot_age()`) or the key
+    /// set has been fetched since the snapshot was taken. As for fetched keys,
+    /// the key set will be fetched again if a token has an unknown `kid`.
+    ///
+    /// Returns an `InvalidConfiguration` error if the snapshot was taken from a
+    /// KeySet with a different JWKS URL.
+    pub fn restore_cache(&self, snapshot: CacheSnapshot) -> Result<bool, Error> {
+        if snapshot.jwks_url != self.jwks_url {
+            return Err(Error::InvalidConfiguration(ErrorDetails::new(format!(
+                "Cache snapshot is for a different key set ({})", snapshot.jwks_url))));
+        }
+        Ok(self.restore_newer_cache(snapshot)?.is_some())
+    }
+
+    // Restores the snapshot if it's newer than the cached keys and not too
+    // old, returning which keys changed if it was restored
+    pub(crate) fn restore_newer_cache(&self, snapshot: CacheSnapshot) -> Result<Option<JwksRefreshReport>, Error> {
+        let age = snapshot.age();
+        if age > self.max_cache_snapshot_age {
+            #[cfg(feature = "tracing")]
+            tracing::debug!(age_secs = age.as_secs(), "Ignoring expired cache snapshot");
+            return Ok(None);
+        }
+        let fetched_at = self.clock.now().checked_sub(age);
+        let last_jwks_get_time = self.cache.read().unwrap().last_jwks_get_time;
+        if let (Some(last_jwks_get_time), Some(fetched_at)) = (last_jwks_get_time, fetched_at) {
+            // Snapshot times are in whole seconds, so a restored snapshot
+            // mustn't look newer than itself
+            if last_jwks_get_time + SNAPSHOT_TIME_RESOLUTION >= fetched_at {
+                return Ok(None);
+            }
+        }
+
+        let max_age = snapshot.max_age.map(Duration::from_secs);
+        let mut report = self.cache_jwks(JwkSet::new(snapshot.keys), snapshot.etag, max_age)?;
+        if let Some(fetched_at) = fetched_at {
+            self.cache.write().unwrap().last_jwks_get_time = Some(fetched_at);
+            report.fetched_at = SystemTime::now() - age;
+        }
+        self.notify_keys_rotated(&report);
+        Ok(Some(report))
+    }
+
+    /// Writes a snapshot of the cached keys to a file (see `cache_snapshot()`)
+    ///
+    /// Nothing is written if the key set hasn't been fetched. The file is
+    /// replaced atomically, so concurrent processes won't read a partially
+    /// written snapshot. Since the file is trusted when it's loaded (see
+    /// [CacheSnapshot]), its directory must only be writable by the processes
+    /// that load it. On unix the file is only readable and writable by its
+    /// owner.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn save_cache(&self, path: impl AsRef<std::path::Path>) -> Result<(), Error> {
+        let snapshot = match self.cache_snapshot() {
+            Some(snapshot) => snapshot,
+            None => return Ok(()),
+        };
+
+        let path = path.as_ref();
+        let json = serde_json::to_vec(&snapshot)
+            .map_err(|e| Error::InvalidConfiguration(ErrorDetails::map("Failed to serialize cache", e)))?;
+        write_atomically(path, &json)
+            .map_err(|e| Error::InvalidConfiguration(ErrorDetails::map("Failed to write cache file", e)))
+    }
+
+    /// Restores the cached keys from a file written by `save_cache()` (see
+    /// `restore_cache()`)
+    ///
+    /// Returns false if the file doesn't exist or its snapshot isn't restored
+    /// (such as because it's too old), so that a missing cache file can be
+    /// ignored:
+    ///
+    /// ```no_run
+    /// # use jsonwebtokens_cognito::KeySet;
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// let keyset = KeySet::new("eu-west-1", "eu-west-1_AbCd1234")?;
+    /// // In a directory that only this service can write to
+    /// if !keyset.load_cache("/var/cache/my-service/jwks-cache.json")? {
+    ///     // The key set will be fetched on demand
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn load_cache(&self, path: impl AsRef<std::path::Path>) -> Result<bool, Error> {
+        let json = match std::fs::read(path) {
+            Ok(json) => json,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(false),
+            Err(e) => return Err(Error::InvalidConfiguration(ErrorDetails::map("Failed to read cache file", e))),
+        };
+        let snapshot: CacheSnapshot = serde_json::from_slice(&json)
+            .map_err(|e| Error::InvalidConfiguration(ErrorDetails::map("Failed to parse cache file", e)))?;
+        self.restore_cache(snapshot)
+    }
+}
+
+// Writes a file via a new temporary file in the same directory that's renamed
+// over it. The temporary file is created exclusively with a random name, so
+// that it can't be a pre-existing file or symlink planted by another user.
+#[cfg(not(target_arch = "wasm32"))]
+fn write_atomically(path: &std::path::Path, contents: &[u8]) -> std::io::Result<()> {
+    use std::collections::hash_map::RandomState;
+    use std::hash::BuildHasher;
+    use std::io::Write;
+
+    let mut tmp_path = path.as_os_str().to_owned();
+    tmp_path.push(format!(".{:016x}.tmp", RandomState::new().hash_one(std::process::id())));
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+
+    let mut file = options.open(&tmp_path)?;
+    let result = file.write_all(contents)
+        .and_then(|_| file.sync_all())
+        .and_then(|_| std::fs::rename(&tmp_path, path));
+    if result.is_err() {
+        let _ = std::fs::remove_file(&tmp_path);
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::*;
+
+    #[test]
+    fn cache_persistence() {
+        let keyset = KeySet::from_jwks_json("eu-west-1", "eu-west-1_AbCd1234",
+                                            TEST_JWKS).unwrap();
+        insert_test_key(&keyset, "inserted-key", "secret");
+        let snapshot = keyset.cache_snapshot().unwrap();
+        assert_eq!(snapshot.kids(), ["test-key-0", "test-key-1"]);
+        assert!(KeySet::new("eu-west-1", "eu-west-1_AbCd1234").unwrap().cache_snapshot().is_none());
+
+        let restored = KeySet::new("eu-west-1", "eu-west-1_AbCd1234").unwrap();
+        assert!(restored.restore_cache(snapshot.clone()).unwrap());
+        assert_eq!(restored.cached_kids(), ["test-key-0", "test-key-1"]);
+        assert!(restored.throttle_remaining().is_some());
+
+        let other = KeySet::new("eu-west-1", "eu-west-1_EfGh5678").unwrap();
+        assert!(matches!(other.restore_cache(snapshot), Err(Error::InvalidConfiguration(_))));
+
+        let path = std::env::temp_dir().join(format!("jwks-cache-{}.json", std::process::id()));
+        keyset.save_cache(&path).unwrap();
+        let loaded = KeySet::new("eu-west-1", "eu-west-1_AbCd1234").unwrap();
+        assert!(loaded.load_cache(&path).unwrap());
+        assert_eq!(loaded.cached_kids(), ["test-key-0", "test-key-1"]);
+        std::fs::remove_file(&path).unwrap();
+        assert!(!loaded.load_cache(&path).unwrap());
+    }
+
+    #[test]
+    fn expired_cache_snapshot() {
+        let keyset = KeySet::from_jwks_json("eu-west-1", "eu-west-1_AbCd1234", TEST_JWKS).unwrap();
+        let mut snapshot = keyset.cache_snapshot().unwrap();
+        snapshot.fetched_at -= 2 * 60 * 60;
+
+        let mut restored = KeySet::new("eu-west-1", "eu-west-1_AbCd1234").unwrap();
+        restored.set_max_cache_snapshot_age(Duration::from_secs(60 * 60));
+        assert!(!restored.restore_cache(snapshot.clone()).unwrap());
+        assert_eq!(restored.cached_key_count(), 0);
+
+        restored.set_max_cache_snapshot_age(Duration::from_secs(3 * 60 * 60));
+        assert!(restored.restore_cache(snapshot).unwrap());
+        assert_eq!(restored.cached_kids(), ["test-key-0", "test-key-1"]);
+    }
+}
@@ -0,0 +1,113 @@
+//! Integration with the [poem-openapi](https://crates.io/crates/poem-openapi)
+//! framework
+//!
+//! [CognitoBearer] is a bearer token `SecurityScheme` that verifies the token
+//! with the KeySet and Verifier of a [CognitoAuth] added to the endpoint's data,
+//! so that Cognito authentication is described in the generated OpenAPI
+//! specification and the verified claims are passed to the API's handlers.
+//!
+//! ```no_run
+//! # use jsonwebtokens_cognito::{AccessTokenClaims, KeySet};
+//! # use jsonwebtokens_cognito::poem::{CognitoAuth, CognitoBearer};
+//! # use poem::{EndpointExt, Route};
+//! # use poem_openapi::{OpenApi, OpenApiService, payload::PlainText};
+//! struct Api;
+//!
+//! #[OpenApi]
+//! impl Api {
+//!     #[oai(path = "/hello", method = "get")]
+//!     async fn hello(&self, auth: CognitoBearer) -> poem::Result<PlainText<String>> {
+//!         let claims: AccessTokenClaims = auth.claims()?;
+//!         Ok(PlainText(format!("Hello {}", claims.sub)))
+//!     }
+//! }
+//!
+//! # fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! let keyset = KeySet::new("eu-west-1", "eu-west-1_AbCd1234")?;
+//! let verifier = keyset.new_access_token_verifier(&["client-id-0"]).build()?;
+//!
+//! let api = OpenApiService::new(Api, "Hello", "1.0");
+//! let app = Route::new()
+//!     .nest("/", api)
+//!     .data(CognitoAuth::new(keyset, verifier));
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Requests without a valid token are rejected with the verification error's
+//! `http_status()`.
+//!
+//! _Requires the `poem` feature_
+
+use std::sync::Arc;
+
+use ::poem::http::StatusCode;
+use ::poem::Request;
+use poem_openapi::auth::Bearer;
+use poem_openapi::SecurityScheme;
+use serde::de::DeserializeOwned;
+use serde_json::value::Value;
+
+use jsonwebtokens::Verifier;
+
+use crate::{Error, KeySet};
+
+/// The KeySet and Verifier used by the [CognitoBearer] security scheme, which
+/// must be added to the endpoint's data (see the [module documentation](self))
+#[derive(Clone)]
+pub struct CognitoAuth {
+    keyset: KeySet,
+    verifier: Arc<Verifier>,
+}
+
+impl CognitoAuth {
+    /// Constructs the data for verifying tokens with the given KeySet and Verifier
+    pub fn new(keyset: KeySet, verifier: impl Into<Arc<Verifier>>) -> Self {
+        CognitoAuth {
+            keyset,
+            verifier: verifier.into(),
+        }
+    }
+}
+
+impl std::fmt::Debug for CognitoAuth {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("CognitoAuth")
+            .field("keyset", &self.keyset)
+            .finish_non_exhaustive()
+    }
+}
+
+/// A Cognito bearer token security scheme, holding the token's verified claims
+#[derive(Debug, Clone, SecurityScheme)]
+#[oai(
+    rename = "CognitoBearer",
+    ty = "bearer",
+    bearer_format = "JWT",
+    checker = "verify_bearer"
+)]
+pub struct CognitoBearer(pub Value);
+
+impl CognitoBearer {
+    /// Deserializes the verified claims into `T`, which can be `IdTokenClaims`,
+    /// `AccessTokenClaims` or any other type that implements `Deserialize`
+    pub fn claims<T: DeserializeOwned>(&self) -> Result<T, Error> {
+        crate::deserialize_claims(self.0.clone())
+    }
+}
+
+async fn verify_bearer(req: &Request, bearer: Bearer) -> ::poem::Result<Value> {
+    let auth = req.data::<CognitoAuth>().ok_or_else(|| {
+        ::poem::Error::from_string("No CognitoAuth in endpoint data", StatusCode::INTERNAL_SERVER_ERROR)
+    })?;
+    Ok(auth.keyset.verify(&bearer.token, &auth.verifier).await?)
+}
+
+/// Converts to a `poem::Error` with the error's `http_status()` and message
+impl From<Error> for ::poem::Error {
+    fn from(e: Error) -> Self {
+        let status = StatusCode::from_u16(e.http_status().as_u16())
+            .unwrap_or(StatusCode::UNAUTHORIZED);
+        ::poem::Error::from_string(e.to_string(), status)
+    }
+}
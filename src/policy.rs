@@ -0,0 +1,214 @@
+use std::fmt;
+use std::sync::Arc;
+
+use serde_json::value::Value;
+
+use crate::Scopes;
+
+/// Why a token's claims were denied by a [Policy]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Denial {
+    /// The token's `cognito:groups` claim didn't include any of the groups
+    MissingGroup { any_of: Vec<String> },
+
+    /// The token's `scope` claim didn't include any of the scopes
+    MissingScope { any_of: Vec<String> },
+
+    /// The claim was missing or didn't have the expected value
+    ClaimMismatch { claim: String, expected: Value },
+
+    /// A `Policy::custom()` check failed
+    Custom,
+}
+
+impl fmt::Display for Denial {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Denial::MissingGroup { any_of } if any_of.len() == 1 => {
+                write!(f, "Token isn't in group \"{}\"", any_of[0])
+            }
+            Denial::MissingGroup { any_of } => {
+                write!(f, "Token isn't in any of the groups \"{}\"", any_of.join("\", \""))
+            }
+            Denial::MissingScope { any_of } if any_of.len() == 1 => {
+                write!(f, "Token is missing scope \"{}\"", any_of[0])
+            }
+            Denial::MissingScope { any_of } => {
+                write!(f, "Token is missing all of the scopes \"{}\"", any_of.join("\", \""))
+            }
+            Denial::ClaimMismatch { claim, expected } => {
+                write!(f, "Token claim '{}' wasn't {}", claim, expected)
+            }
+            Denial::Custom => write!(f, "Token was denied by policy"),
+        }
+    }
+}
+
+#[derive(Clone)]
+enum Rule {
+    AnyGroup(Vec<String>),
+    AnyScope(Vec<String>),
+    ClaimEquals(String, Value),
+    Custom(Arc<dyn Fn(&Value) -> bool + Send + Sync>),
+}
+
+impl fmt::Debug for Rule {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Rule::AnyGroup(groups) => f.debug_tuple("AnyGroup").field(groups).finish(),
+            Rule::AnyScope(scopes) => f.debug_tuple("AnyScope").field(scopes).finish(),
+            Rule::ClaimEquals(claim, value) => f.debug_tuple("ClaimEquals").field(claim).field(value).finish(),
+            Rule::Custom(_) => f.write_str("Custom"),
+        }
+    }
+}
+
+/// Authorization rules that the claims of verified tokens must satisfy
+///
+/// Every rule must be satisfied, and the first rule that isn't determines the
+/// [Denial] reason.
+///
+/// ```
+/// # use jsonwebtokens_cognito::{Denial, Policy};
+/// # use serde_json::json;
+/// let policy = Policy::new()
+///     .any_group(["admins", "editors"])
+///     .scope("api/write")
+///     .custom(|claims| claims["email_verified"] == true);
+///
+/// let claims = json!({
+///     "cognito:groups": ["editors"],
+///     "scope": "api/read api/write",
+///     "email_verified": true
+/// });
+/// assert!(policy.check(&claims).is_ok());
+///
+/// let claims = json!({ "cognito:groups": ["editors"], "scope": "api/read" });
+/// assert_eq!(policy.check(&claims), Err(Denial::MissingScope { any_of: vec!["api/write".into()] }));
+/// ```
+///
+/// A policy can also be enforced by the web framework integrations (such as
+/// `CognitoAuthLayer::policy()`), which reject denied requests with a
+/// `PolicyDenied` error and a 403 status.
+#[derive(Debug, Clone, Default)]
+pub struct Policy {
+    rules: Vec<Rule>,
+}
+
+fn strings(values: impl IntoIterator<Item = impl Into<String>>) -> Vec<String> {
+    values.into_iter().map(Into::into).collect()
+}
+
+impl Policy {
+    /// Constructs a policy without any rules, which allows all tokens
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Requires the token's `cognito:groups` claim to include the given group
+    pub fn group(self, group: impl Into<String>) -> Self {
+        self.any_group([group])
+    }
+
+    /// Requires the token's `cognito:groups` claim to include at least one of
+    /// the given groups
+    pub fn any_group(mut self, groups: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        self.rules.push(Rule::AnyGroup(strings(groups)));
+        self
+    }
+
+    /// Requires the access token's `scope` claim to include the given scope
+    pub fn scope(self, scope: impl Into<String>) -> Self {
+        self.any_scope([scope])
+    }
+
+    /// Requires the access token's `scope` claim to include at least one of the
+    /// given scopes
+    pub fn any_scope(mut self, scopes: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        self.rules.push(Rule::AnyScope(strings(scopes)));
+        self
+    }
+
+    /// Requires the given claim to equal the given value
+    pub fn claim_equals(mut self, claim: impl Into<String>, value: impl Into<Value>) -> Self {
+        self.rules.push(Rule::ClaimEquals(claim.into(), value.into()));
+        self
+    }
+
+    /// Requires the given check of the claims to return true, otherwise the
+    /// claims are denied with `Denial::Custom`
+    pub fn custom(mut self, check: impl Fn(&Value) -> bool + Send + Sync + 'static) -> Self {
+        self.rules.push(Rule::Custom(Arc::new(check)));
+        self
+    }
+
+    /// Checks verified claims against the policy's rules
+    pub fn check(&self, claims: &Value) -> Result<(), Denial> {
+        for rule in &self.rules {
+            match rule {
+                Rule::AnyGroup(any_of) => {
+                    let groups: Vec<&str> = match claims.get("cognito:groups") {
+                        Some(Value::Array(groups)) => groups.iter().filter_map(Value::as_str).collect(),
+                        _ => Vec::new(),
+                    };
+                    if !any_of.iter().any(|group| groups.contains(&group.as_str())) {
+                        return Err(Denial::MissingGroup { any_of: any_of.clone() });
+                    }
+                }
+                Rule::AnyScope(any_of) => {
+                    let scopes = Scopes::from_claims(claims);
+                    if !any_of.iter().any(|scope| scopes.contains(scope)) {
+                        return Err(Denial::MissingScope { any_of: any_of.clone() });
+                    }
+                }
+                Rule::ClaimEquals(claim, expected) => {
+                    if claims.get(claim) != Some(expected) {
+                        return Err(Denial::ClaimMismatch { claim: claim.clone(), expected: expected.clone() });
+                    }
+                }
+                Rule::Custom(check) => {
+                    if !check(claims) {
+                        return Err(Denial::Custom);
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use reqwest::StatusCode;
+    use serde_json::json;
+
+    use super::*;
+    use crate::Error;
+
+    #[test]
+    fn policy() {
+        let claims = json!({
+            "cognito:groups": ["editors"],
+            "scope": "api/read api/write",
+            "tenant": "tenant-0",
+        });
+        assert!(Policy::new().check(&claims).is_ok());
+        assert!(Policy::new().any_group(["admins", "editors"]).any_scope(["api/admin", "api/write"])
+                .claim_equals("tenant", "tenant-0").custom(|claims| claims["tenant"].is_string())
+                .check(&claims).is_ok());
+
+        assert_eq!(Policy::new().group("admins").check(&claims),
+                   Err(Denial::MissingGroup { any_of: vec!["admins".to_string()] }));
+        assert_eq!(Policy::new().group("editors").check(&json!({})),
+                   Err(Denial::MissingGroup { any_of: vec!["editors".to_string()] }));
+        assert_eq!(Policy::new().scope("api/read").scope("api/admin").check(&claims),
+                   Err(Denial::MissingScope { any_of: vec!["api/admin".to_string()] }));
+        assert_eq!(Policy::new().claim_equals("tenant", "tenant-1").check(&claims),
+                   Err(Denial::ClaimMismatch { claim: "tenant".to_string(), expected: json!("tenant-1") }));
+        assert_eq!(Policy::new().custom(|_| false).check(&claims), Err(Denial::Custom));
+
+        let e = Error::PolicyDenied(Denial::MissingScope { any_of: vec!["api/admin".to_string()] });
+        assert_eq!(e.http_status(), StatusCode::FORBIDDEN);
+        assert_eq!(e.to_string(), "Token is missing scope \"api/admin\"");
+    }
+}
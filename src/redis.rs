@@ -0,0 +1,191 @@
+//! A [KeyCacheStore] for sharing cached keys between processes through
+//! [Redis](https://redis.io)
+//!
+//! ```no_run
+//! # use jsonwebtokens_cognito::KeySet;
+//! # use jsonwebtokens_cognito::redis::RedisKeyCacheStore;
+//! # fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! let store = RedisKeyCacheStore::open("redis://cache.example.com:6379")?;
+//! let keyset = KeySet::builder("eu-west-1", "eu-west-1_AbCd1234")
+//!     .key_cache_store(store)
+//!     .build()?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! The store uses an async connection, which requires a tokio runtime. A single
+//! multiplexed connection is opened on first use and shared by all requests
+//! (and clones of the store), and is reopened after a request fails. Each
+//! request is limited by the store's timeout (see
+//! [RedisKeyCacheStore::with_timeout]), so a slow Redis server can't stall
+//! fetching the key set.
+//!
+//! Snapshots from the store are trusted like the issuer's key set (see
+//! [CacheSnapshot](crate::CacheSnapshot)): anyone who can write to the Redis
+//! keys can make the KeySets that use the store accept forged tokens. Only use
+//! a Redis server that just your services can write to, with authentication
+//! and TLS (a `rediss://` URL) when it's reached over a network.
+//!
+//! _Requires the `redis` feature_
+
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Duration;
+
+use ::redis::aio::MultiplexedConnection;
+use ::redis::AsyncCommands;
+use async_trait::async_trait;
+
+use crate::{runtime, Error, ErrorDetails, KeyCacheStore};
+
+const DEFAULT_KEY_PREFIX: &str = "jsonwebtokens-cognito:";
+
+// Stops a snapshot from being used indefinitely if nothing refreshes it
+const DEFAULT_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+
+// Includes connecting, so should allow for a TLS handshake
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// A [KeyCacheStore] that stores snapshots of cached keys in Redis (see the
+/// [module documentation](self))
+#[derive(Clone)]
+pub struct RedisKeyCacheStore {
+    client: ::redis::Client,
+    // Opened on first use, and cleared after an error to reconnect
+    connection: Arc<futures_util::lock::Mutex<Option<MultiplexedConnection>>>,
+    key_prefix: String,
+    ttl: Option<Duration>,
+    timeout: Duration,
+}
+
+impl std::fmt::Debug for RedisKeyCacheStore {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("RedisKeyCacheStore")
+            .field("client", &self.client)
+            .field("key_prefix", &self.key_prefix)
+            .field("ttl", &self.ttl)
+            .field("timeout", &self.timeout)
+            .finish()
+    }
+}
+
+impl RedisKeyCacheStore {
+    /// Constructs a store that uses the given Redis client
+    pub fn new(client: ::redis::Client) -> Self {
+        RedisKeyCacheStore {
+            client,
+            connection: Default::default(),
+            key_prefix: DEFAULT_KEY_PREFIX.to_string(),
+            ttl: Some(DEFAULT_TTL),
+            timeout: DEFAULT_TIMEOUT,
+        }
+    }
+
+    /// Constructs a store for the Redis server at the given URL, such as
+    /// `redis://127.0.0.1:6379`
+    ///
+    /// Returns an `InvalidConfiguration` error if the URL is invalid. The
+    /// server isn't connected to until the store is used.
+    pub fn open(url: &str) -> Result<Self, Error> {
+        let client = ::redis::Client::open(url)
+            .map_err(|e| Error::InvalidConfiguration(ErrorDetails::map("Invalid Redis URL", e)))?;
+        Ok(RedisKeyCacheStore::new(client))
+    }
+
+    /// Sets the prefix of the Redis keys, which are followed by the key set's
+    /// JWKS URL (`jsonwebtokens-cognito:` by default)
+    pub fn with_key_prefix(mut self, key_prefix: impl Into<String>) -> Self {
+        self.key_prefix = key_prefix.into();
+        self
+    }
+
+    /// Sets how long snapshots are kept for since they were last stored, or
+    /// `None` to keep them indefinitely (24 hours by default)
+    pub fn with_ttl(mut self, ttl: Option<Duration>) -> Self {
+        self.ttl = ttl;
+        self
+    }
+
+    /// Sets how long each request may take, including connecting to Redis (2
+    /// seconds by default)
+    ///
+    /// A request that takes longer fails with a `NetworkTimeout` error.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    fn redis_key(&self, key: &str) -> String {
+        format!("{}{}", self.key_prefix, key)
+    }
+
+    async fn connection(&self) -> Result<MultiplexedConnection, ::redis::RedisError> {
+        let mut connection = self.connection.lock().await;
+        if let Some(connection) = &*connection {
+            return Ok(connection.clone());
+        }
+        let new_connection = self.client.get_multiplexed_async_connection().await?;
+        *connection = Some(new_connection.clone());
+        Ok(new_connection)
+    }
+
+    // Runs a request on the shared connection, with the store's timeout
+    async fn request<T, F, Fut>(&self, request: F) -> Result<T, Error>
+    where
+        F: FnOnce(MultiplexedConnection) -> Fut,
+        Fut: Future<Output = Result<T, ::redis::RedisError>>,
+    {
+        let result = runtime::timeout(self.timeout, async {
+            let connection = self.connection().await?;
+            request(connection).await
+        }).await;
+        match result {
+            Some(Ok(value)) => Ok(value),
+            Some(Err(e)) => {
+                *self.connection.lock().await = None;
+                Err(Error::NetworkError(ErrorDetails::map("Redis request failed", e)))
+            }
+            None => {
+                *self.connection.lock().await = None;
+                Err(Error::NetworkTimeout(ErrorDetails::new("Redis request timed out")))
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl KeyCacheStore for RedisKeyCacheStore {
+    async fn get(&self, key: &str) -> Result<Option<Vec<u8>>, Error> {
+        let redis_key = self.redis_key(key);
+        self.request(|mut connection| async move { connection.get(redis_key).await }).await
+    }
+
+    async fn put(&self, key: &str, value: Vec<u8>) -> Result<(), Error> {
+        let (redis_key, ttl) = (self.redis_key(key), self.ttl);
+        self.request(|mut connection| async move {
+            match ttl {
+                Some(ttl) => connection.set_ex(redis_key, value, ttl.as_secs().max(1)).await,
+                None => connection.set(redis_key, value).await,
+            }
+        }).await
+    }
+}
+
+#[cfg(all(test, feature = "tokio"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn redis_timeout() {
+        // Accepts connections but never responds
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("redis://{}", listener.local_addr().unwrap());
+        let store = RedisKeyCacheStore::open(&url).unwrap().with_timeout(Duration::from_millis(100));
+
+        let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap();
+        match runtime.block_on(store.get("key")) {
+            Err(Error::NetworkTimeout(_)) => {}
+            other => panic!("Expected a NetworkTimeout error, got {:?}", other),
+        }
+    }
+}
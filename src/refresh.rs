@@ -0,0 +1,77 @@
+use std::time::Duration;
+
+use crate::runtime::{self, Task};
+use crate::KeySet;
+
+/// A handle for a background task that periodically refreshes a KeySet's
+/// JWKS key set (see `KeySet::spawn_refresh_task()`)
+///
+/// Dropping the handle doesn't stop the task; call `stop()` to stop it.
+#[derive(Debug)]
+pub struct RefreshTaskHandle {
+    task: Task,
+}
+
+impl RefreshTaskHandle {
+    /// Stops the background refresh task
+    pub fn stop(self) {
+        self.task.abort();
+    }
+
+    /// Returns true if the task has stopped
+    pub fn is_finished(&self) -> bool {
+        self.task.is_finished()
+    }
+}
+
+impl KeySet {
+    /// Spawns a task that refreshes the JWKS key set every `interval`
+    ///
+    /// The first refresh happens immediately, which avoids the latency of
+    /// fetching the key set while verifying the first token and keeps the
+    /// cache warm for `try_verify()` across key rotations. Errors fetching the
+    /// key set are ignored and the next refresh is attempted after another
+    /// `interval`.
+    ///
+    /// The task is spawned on the current tokio runtime, if there is one, or
+    /// else on async-std's executor with the `rt-async-std` feature. Without
+    /// that feature this must be called within the context of a tokio runtime.
+    ///
+    /// _Requires the `tokio` (or `rt-tokio`) or `rt-async-std` feature_
+    pub fn spawn_refresh_task(&self, interval: Duration) -> RefreshTaskHandle {
+        let keyset = self.clone();
+        let task = runtime::spawn(async move {
+            loop {
+                let _ = keyset.prefetch_jwks().await;
+                runtime::sleep(interval).await;
+            }
+        });
+
+        RefreshTaskHandle { task }
+    }
+}
+
+#[cfg(all(test, feature = "rt-async-std"))]
+mod tests {
+    use super::*;
+    use crate::test_util::*;
+
+    #[test]
+    fn async_std_refresh_task() {
+        let fetcher = TestFetcher::jwks();
+        let keyset = KeySet::builder("eu-west-1", "eu-west-1_AbCd1234")
+            .jwks_fetcher(fetcher.clone())
+            .min_jwks_fetch_interval(Duration::from_millis(0))
+            .build()
+            .unwrap();
+
+        // Outside of a tokio runtime the task is spawned on async-std's executor
+        let task = keyset.spawn_refresh_task(Duration::from_millis(10));
+        while fetcher.fetch_count() < 2 {
+            std::thread::sleep(Duration::from_millis(5));
+        }
+        assert!(keyset.cached_key_count() > 0);
+        assert!(!task.is_finished());
+        task.stop();
+    }
+}
@@ -0,0 +1,156 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use serde_json::value::Value;
+
+use jsonwebtokens as jwt;
+use jwt::Verifier;
+
+use crate::{Error, KeySet};
+
+/// Routes token verification to one of multiple [KeySet]s based on the
+/// token's issuer
+///
+/// This is useful for services that accept tokens from several user pools
+/// (possibly in different regions). Each KeySet is registered with the
+/// `Verifier` that should be used for tokens from its pool and the token's
+/// (unverified) `iss` claim is used to decide which KeySet and Verifier to use.
+///
+/// ```no_run
+/// # use jsonwebtokens_cognito::{KeySet, KeySetRegistry};
+/// # use async_std::prelude::*;
+/// # #[async_std::main]
+/// # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// # let token = "header.payload.signature";
+/// let mut registry = KeySetRegistry::new();
+///
+/// let keyset = KeySet::new("eu-west-1", "eu-west-1_AbCd1234")?;
+/// let verifier = keyset.new_access_token_verifier(&["client-id-0"]).build()?;
+/// registry.insert(keyset, verifier);
+///
+/// let keyset = KeySet::new("us-east-1", "us-east-1_EfGh5678")?;
+/// let verifier = keyset.new_access_token_verifier(&["client-id-1"]).build()?;
+/// registry.insert(keyset, verifier);
+///
+/// let claims = registry.verify(token).await?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone, Default)]
+pub struct KeySetRegistry {
+    entries: HashMap<String, (KeySet, Verifier)>,
+}
+
+impl fmt::Debug for KeySetRegistry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("KeySetRegistry")
+            .field("issuers", &self.entries.keys().collect::<Vec<_>>())
+            .finish()
+    }
+}
+
+impl KeySetRegistry {
+    /// Constructs an empty registry
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers a KeySet, along with the Verifier to use for its tokens
+    ///
+    /// The KeySet is registered for its issuer and any additional issuers (see
+    /// `KeySetBuilder::additional_issuers()`), replacing any KeySet previously
+    /// registered for the same issuers.
+    pub fn insert(&mut self, keyset: KeySet, verifier: Verifier) {
+        for iss in keyset.additional_issuers.iter() {
+            self.entries.insert(iss.clone(), (keyset.clone(), verifier.clone()));
+        }
+        self.entries.insert(keyset.iss.clone(), (keyset, verifier));
+    }
+
+    /// Removes the KeySet registered for the given issuer, along with its
+    /// registrations for any other issuers
+    pub fn remove(&mut self, iss: &str) -> Option<KeySet> {
+        let (keyset, _) = self.entries.remove(iss)?;
+        self.entries.retain(|_, (other, _)| *other != keyset);
+        Some(keyset)
+    }
+
+    /// Looks up the KeySet registered for the given issuer
+    pub fn get(&self, iss: &str) -> Option<&KeySet> {
+        self.entries.get(iss).map(|(keyset, _)| keyset)
+    }
+
+    /// Iterates over all the registered KeySets
+    pub fn keysets(&self) -> impl Iterator<Item = &KeySet> {
+        // Skipping the registrations for additional issuers
+        self.entries.iter()
+            .filter(|(iss, (keyset, _))| **iss == keyset.iss)
+            .map(|(_, (keyset, _))| keyset)
+    }
+
+    /// Looks up the KeySet and Verifier for the given token's issuer
+    ///
+    /// The token isn't verified. Returns an `UnknownIssuer` error if there's no
+    /// KeySet for the token's issuer.
+    pub fn lookup_token(&self, token: &str) -> Result<(&KeySet, &Verifier), Error> {
+        // The KeySet checks its own limits, but the claims are decoded first to
+        // find it, so are limited by the most lenient KeySet
+        let max_token_len = self.keysets().map(|keyset| keyset.max_token_len).max()
+            .unwrap_or(crate::DEFAULT_MAX_TOKEN_LEN);
+        let max_header_len = self.keysets().map(|keyset| keyset.max_header_len).max()
+            .unwrap_or(crate::DEFAULT_MAX_HEADER_LEN);
+        crate::check_token_len(token, max_token_len, max_header_len)?;
+
+        let jwt::raw::TokenSlices { claims, .. } = jwt::raw::split_token(token)?;
+        let claims = jwt::raw::decode_json_token_slice(claims)?;
+
+        match claims.get("iss") {
+            Some(Value::String(iss)) => match self.entries.get(iss) {
+                Some((keyset, verifier)) => Ok((keyset, verifier)),
+                None => Err(Error::UnknownIssuer(Some(iss.clone()))),
+            },
+            _ => Err(Error::UnknownIssuer(None)),
+        }
+    }
+
+    /// Verify a token's signature and its claims with the KeySet and Verifier
+    /// registered for the token's issuer
+    pub async fn verify(&self, token: &str) -> Result<Value, Error> {
+        let (keyset, verifier) = self.lookup_token(token)?;
+        keyset.verify(token, verifier).await
+    }
+
+    /// Try and verify a token's signature and claims, with the KeySet and Verifier
+    /// registered for the token's issuer, without performing any network I/O
+    /// (see `KeySet::try_verify()`)
+    pub fn try_verify(&self, token: &str) -> Result<Value, Error> {
+        let (keyset, verifier) = self.lookup_token(token)?;
+        keyset.try_verify(token, verifier)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::test_util::*;
+
+    #[test]
+    fn registry_routes_by_issuer() {
+        let mut registry = KeySetRegistry::new();
+        for pool_id in ["eu-west-1_AbCd1234", "eu-west-1_EfGh5678"].iter() {
+            let keyset = KeySet::new("eu-west-1", *pool_id).unwrap();
+            insert_test_key(&keyset, pool_id, pool_id);
+            let verifier = keyset.new_issuer_verifier().build().unwrap();
+            registry.insert(keyset, verifier);
+        }
+
+        // test_token() uses the issuer for eu-west-1_AbCd1234
+        let token = test_token(json!({ "alg": "HS256", "kid": "eu-west-1_AbCd1234" }), "eu-west-1_AbCd1234");
+        assert!(registry.try_verify(&token).is_ok());
+
+        registry.remove("https://cognito-idp.eu-west-1.amazonaws.com/eu-west-1_AbCd1234");
+        assert!(matches!(registry.try_verify(&token), Err(Error::UnknownIssuer(Some(_)))));
+    }
+}
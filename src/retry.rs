@@ -0,0 +1,143 @@
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::time::Duration;
+
+use reqwest::StatusCode;
+
+use crate::{Error, JwksResponse};
+
+/// Configures retrying failed JWKS key set requests
+///
+/// Requests are retried after transport errors, timeouts and after `5xx` or
+/// `429 Too Many Requests` responses, with an exponential backoff between
+/// attempts. By default the backoff has random jitter, so that many processes
+/// don't retry at the same time.
+///
+/// ```
+/// # use jsonwebtokens_cognito::{KeySet, RetryPolicy};
+/// # use std::time::Duration;
+/// # fn main() -> Result<(), jsonwebtokens_cognito::Error> {
+/// let keyset = KeySet::builder("eu-west-1", "eu-west-1_AbCd1234")
+///     .retry_policy(RetryPolicy::new(3)
+///         .backoff(Duration::from_millis(200), Duration::from_secs(2)))
+///     .build()?;
+/// # Ok(())
+/// # }
+/// ```
+///
+/// The default policy doesn't retry.
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    max_retries: u32,
+    initial_backoff: Duration,
+    max_backoff: Duration,
+    jitter: bool,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy::new(0)
+    }
+}
+
+impl RetryPolicy {
+    /// Constructs a policy that retries up to `max_retries` times, with a backoff
+    /// starting at 100 milliseconds and doubling up to 5 seconds
+    pub fn new(max_retries: u32) -> Self {
+        RetryPolicy {
+            max_retries,
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(5),
+            jitter: true,
+        }
+    }
+
+    /// Sets the backoff before the first retry, which doubles for each retry up to
+    /// `max`
+    pub fn backoff(mut self, initial: Duration, max: Duration) -> Self {
+        self.initial_backoff = initial;
+        self.max_backoff = max;
+        self
+    }
+
+    /// Sets whether random jitter is applied to the backoff
+    ///
+    /// With jitter each backoff is somewhere between half and all of the
+    /// exponential backoff.
+    pub fn jitter(mut self, jitter: bool) -> Self {
+        self.jitter = jitter;
+        self
+    }
+
+    pub fn max_retries(&self) -> u32 {
+        self.max_retries
+    }
+
+    // Whether a request should be retried, given the number of retries so far
+    pub(crate) fn should_retry(&self, retries: u32, result: &Result<JwksResponse, Error>) -> bool {
+        if retries >= self.max_retries {
+            return false;
+        }
+
+        match result {
+            Ok(resp) => resp.status.is_server_error() || resp.status == StatusCode::TOO_MANY_REQUESTS,
+            Err(Error::NetworkError(_)) | Err(Error::NetworkTimeout(_)) => true,
+            Err(_) => false,
+        }
+    }
+
+    // The backoff before the given retry (counting from zero)
+    pub(crate) fn backoff_for(&self, retry: u32) -> Duration {
+        let backoff = self.initial_backoff
+            .checked_mul(2u32.saturating_pow(retry))
+            .unwrap_or(self.max_backoff)
+            .min(self.max_backoff);
+
+        if self.jitter {
+            let half = backoff / 2;
+            half + half.mul_f64(random_fraction())
+        } else {
+            backoff
+        }
+    }
+}
+
+// A random number in [0, 1), which is good enough for jitter without depending
+// on a random number generator crate
+fn random_fraction() -> f64 {
+    let random = RandomState::new().build_hasher().finish();
+    (random >> 11) as f64 / (1u64 << 53) as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ErrorDetails, KeySet};
+    use crate::test_util::*;
+
+    #[test]
+    fn retry_policy() {
+        let fetcher = TestFetcher::new(vec![
+            Err(Error::NetworkError(ErrorDetails::new("Connection reset"))),
+            Ok(JwksResponse::new(StatusCode::SERVICE_UNAVAILABLE, "")),
+            Ok(JwksResponse::new(StatusCode::OK, TEST_JWKS)),
+        ]);
+        let keyset = KeySet::builder("eu-west-1", "eu-west-1_AbCd1234")
+            .jwks_fetcher(fetcher.clone())
+            .retry_policy(RetryPolicy::new(2).backoff(Duration::from_millis(1), Duration::from_millis(1)))
+            .build()
+            .unwrap();
+        async_std::task::block_on(keyset.prefetch_jwks()).unwrap();
+        assert_eq!(fetcher.fetch_count(), 3);
+
+        let policy = RetryPolicy::new(10).backoff(Duration::from_millis(100), Duration::from_secs(1)).jitter(false);
+        assert_eq!(policy.backoff_for(0), Duration::from_millis(100));
+        assert_eq!(policy.backoff_for(2), Duration::from_millis(400));
+        assert_eq!(policy.backoff_for(8), Duration::from_secs(1));
+        let policy = policy.jitter(true);
+        for retry in 0..4 {
+            let backoff = policy.backoff_for(retry);
+            assert!(backoff >= Duration::from_millis(50 << retry) && backoff <= Duration::from_millis(100 << retry));
+        }
+    }
+}
@@ -0,0 +1,164 @@
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::sync::RwLock;
+
+use serde_json::value::Value;
+
+/// Checks whether a verified token has been revoked
+///
+/// Cognito's revocation endpoint only revokes refresh tokens, and access and ID
+/// tokens remain valid until they expire, even after a global sign out. A
+/// `RevocationCheck` set via `KeySet::set_revocation_check()` is consulted after
+/// a token's signature and claims have been verified, so that tokens on a
+/// deny-list can be rejected with an `Error::TokenRevoked`.
+///
+/// This is called for every verification (including `try_verify()`) so it
+/// shouldn't block, e.g. it should check a local copy of a shared deny-list.
+/// [RevocationList] is an in-memory implementation.
+pub trait RevocationCheck: Debug + Send + Sync {
+    /// Returns true if the token with the given (verified) claims was revoked
+    fn is_revoked(&self, claims: &Value) -> bool;
+}
+
+/// An in-memory [RevocationCheck] for revoking tokens by their `jti` or
+/// `origin_jti` claims, or all of a user's tokens issued before a given time
+///
+/// ```
+/// # use jsonwebtokens_cognito::{KeySet, RevocationList};
+/// # use std::sync::Arc;
+/// # fn main() -> Result<(), jsonwebtokens_cognito::Error> {
+/// let revoked = Arc::new(RevocationList::new());
+/// let mut keyset = KeySet::new("eu-west-1", "eu-west-1_AbCd1234")?;
+/// keyset.set_revocation_check(revoked.clone());
+///
+/// // After a user signs out globally
+/// # let (sub, now, exp) = ("user", 0, 0);
+/// revoked.revoke_user(sub, now);
+///
+/// // After revoking a refresh token, revoke the tokens that were issued with it
+/// # let origin_jti = "";
+/// revoked.revoke_origin_jti(origin_jti, exp);
+/// # Ok(())
+/// # }
+/// ```
+///
+/// Revoked `jti`s are remembered until the given expiry time (the `exp` of the
+/// token, or the longest lifetime of the tokens issued with an `origin_jti`),
+/// after which the tokens would be rejected anyway.
+#[derive(Debug, Default)]
+pub struct RevocationList {
+    inner: RwLock<Revoked>,
+}
+
+#[derive(Debug, Default)]
+struct Revoked {
+    // Values are the unix time after which the tokens will have expired
+    jtis: HashMap<String, u64>,
+    origin_jtis: HashMap<String, u64>,
+
+    // Tokens for the user (`sub`) issued at or before this unix time are revoked
+    users: HashMap<String, u64>,
+}
+
+impl RevocationList {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Revokes the token with the given `jti` claim, until `exp`
+    pub fn revoke_jti(&self, jti: impl Into<String>, exp: u64) {
+        let mut revoked = self.inner.write().unwrap();
+        revoked.jtis.insert(jti.into(), exp);
+    }
+
+    /// Revokes all tokens with the given `origin_jti` claim (i.e. the tokens that
+    /// were issued with, or refreshed by, the same refresh token), until `exp`
+    pub fn revoke_origin_jti(&self, origin_jti: impl Into<String>, exp: u64) {
+        let mut revoked = self.inner.write().unwrap();
+        revoked.origin_jtis.insert(origin_jti.into(), exp);
+    }
+
+    /// Revokes all tokens for the given user (`sub`) that were issued (`iat`) at
+    /// or before the given unix time, such as after a global sign out
+    ///
+    /// Tokens without an `iat` claim are revoked too.
+    pub fn revoke_user(&self, sub: impl Into<String>, issued_before: u64) {
+        let mut revoked = self.inner.write().unwrap();
+        revoked.users.insert(sub.into(), issued_before);
+    }
+
+    /// Forgets revoked `jti`s and `origin_jti`s that have expired by `time_now`
+    pub fn remove_expired(&self, time_now: u64) {
+        let mut revoked = self.inner.write().unwrap();
+        revoked.jtis.retain(|_, exp| *exp > time_now);
+        revoked.origin_jtis.retain(|_, exp| *exp > time_now);
+    }
+}
+
+impl RevocationCheck for RevocationList {
+    fn is_revoked(&self, claims: &Value) -> bool {
+        let revoked = self.inner.read().unwrap();
+        let claim = |name: &str| claims.get(name).and_then(Value::as_str);
+
+        if matches!(claim("jti"), Some(jti) if revoked.jtis.contains_key(jti)) ||
+           matches!(claim("origin_jti"), Some(origin_jti) if revoked.origin_jtis.contains_key(origin_jti)) {
+            return true;
+        }
+
+        match claim("sub").and_then(|sub| revoked.users.get(sub)) {
+            Some(issued_before) => match claims.get("iat").and_then(Value::as_u64) {
+                Some(iat) => iat <= *issued_before,
+                None => true,
+            },
+            None => false,
+        }
+    }
+}
+
+impl<T: RevocationCheck + ?Sized> RevocationCheck for std::sync::Arc<T> {
+    fn is_revoked(&self, claims: &Value) -> bool {
+        (**self).is_revoked(claims)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use serde_json::json;
+
+    use super::*;
+    use crate::Error;
+    use crate::test_util::*;
+
+    #[test]
+    fn revocation() {
+        let mut keyset = test_keyset();
+        let revoked = Arc::new(RevocationList::new());
+        keyset.set_revocation_check(revoked.clone());
+        keyset.set_token_cache(10);
+        let claims = json!({ "iss": TEST_ISS,
+                             "sub": "user-0", "jti": "jti-0", "origin_jti": "origin-0", "iat": 100 });
+        let token = hs256_token(&claims);
+        let verifier = keyset.new_issuer_verifier().build().unwrap();
+
+        assert!(keyset.try_verify(&token, &verifier).is_ok());
+
+        // Also rejected when the claims have already been cached
+        revoked.revoke_jti("jti-0", 200);
+        assert!(matches!(keyset.try_verify(&token, &verifier), Err(Error::TokenRevoked())));
+        assert!(matches!(async_std::task::block_on(keyset.verify(&token, &verifier)),
+                         Err(Error::TokenRevoked())));
+        revoked.remove_expired(200);
+        assert!(keyset.try_verify(&token, &verifier).is_ok());
+
+        revoked.revoke_origin_jti("origin-0", 200);
+        assert!(matches!(keyset.try_verify(&token, &verifier), Err(Error::TokenRevoked())));
+        revoked.remove_expired(200);
+
+        revoked.revoke_user("user-0", 99);
+        assert!(keyset.try_verify(&token, &verifier).is_ok());
+        revoked.revoke_user("user-0", 100);
+        assert!(matches!(keyset.try_verify(&token, &verifier), Err(Error::TokenRevoked())));
+    }
+}
@@ -0,0 +1,175 @@
+//! Integration with the [Rocket](https://crates.io/crates/rocket) web framework
+//!
+//! The [CognitoUser] request guard verifies the bearer token of a request with
+//! the KeySet and Verifier of a [CognitoAuth] in Rocket's managed state,
+//! failing the request if it doesn't have a valid token. The verified claims
+//! are deserialized into the guard's type parameter.
+//!
+//! ```no_run
+//! # use jsonwebtokens_cognito::{AccessTokenClaims, KeySet};
+//! # use jsonwebtokens_cognito::rocket::{CognitoAuth, CognitoUser};
+//! # use rocket::{get, routes};
+//! #[get("/")]
+//! fn hello(user: CognitoUser<AccessTokenClaims>) -> String {
+//!     format!("Hello {}", user.0.sub)
+//! }
+//!
+//! # fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! let keyset = KeySet::new("eu-west-1", "eu-west-1_AbCd1234")?;
+//! let verifier = keyset.new_access_token_verifier(&["client-id-0"]).build()?;
+//!
+//! let rocket = rocket::build()
+//!     .manage(CognitoAuth::new(keyset, verifier))
+//!     .mount("/", routes![hello]);
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! By default a request without a token fails with a 401 status and a request
+//! with an invalid token fails with the error's `http_status()`. This can be
+//! changed with `CognitoAuth::status_mapping()`, and the [AuthError] can be
+//! handled by a catcher or with a `Result<CognitoUser<T>, AuthError>` guard.
+//!
+//! _Requires the `rocket` feature_
+
+use std::sync::Arc;
+
+use ::rocket::http::hyper::header::AUTHORIZATION;
+use ::rocket::http::Status;
+use ::rocket::request::{FromRequest, Outcome, Request};
+use serde::de::DeserializeOwned;
+use serde_json::value::Value;
+
+use jsonwebtokens::Verifier;
+
+use crate::Error;
+use crate::KeySet;
+use crate::http_util::parse_bearer;
+
+type StatusMapping = dyn Fn(&AuthError) -> Status + Send + Sync;
+
+/// The KeySet and Verifier used by the [CognitoUser] request guard, which
+/// must be added to Rocket's managed state (see the [module documentation](self))
+#[derive(Clone)]
+pub struct CognitoAuth {
+    keyset: KeySet,
+    verifier: Arc<Verifier>,
+    status_mapping: Arc<StatusMapping>,
+}
+
+impl CognitoAuth {
+    /// Constructs the state for verifying tokens with the given KeySet and Verifier
+    pub fn new(keyset: KeySet, verifier: impl Into<Arc<Verifier>>) -> Self {
+        CognitoAuth {
+            keyset,
+            verifier: verifier.into(),
+            status_mapping: Arc::new(AuthError::status),
+        }
+    }
+
+    /// Sets how a failure to verify a request's token maps to the status the
+    /// request fails with
+    ///
+    /// ```
+    /// # use jsonwebtokens_cognito::{Error, KeySet};
+    /// # use jsonwebtokens_cognito::rocket::{AuthError, CognitoAuth};
+    /// # use rocket::http::Status;
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// # let keyset = KeySet::new("eu-west-1", "eu-west-1_AbCd1234")?;
+    /// # let verifier = keyset.new_access_token_verifier(&["client-id-0"]).build()?;
+    /// // Only distinguish tokens that couldn't be verified due to JWKS network errors
+    /// let auth = CognitoAuth::new(keyset, verifier)
+    ///     .status_mapping(|e| match e {
+    ///         AuthError::InvalidToken(Error::NetworkError(_)) => Status::ServiceUnavailable,
+    ///         _ => Status::Unauthorized,
+    ///     });
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn status_mapping<F>(mut self, mapping: F) -> Self
+    where
+        F: Fn(&AuthError) -> Status + Send + Sync + 'static,
+    {
+        self.status_mapping = Arc::new(mapping);
+        self
+    }
+}
+
+impl std::fmt::Debug for CognitoAuth {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("CognitoAuth")
+            .field("keyset", &self.keyset)
+            .finish_non_exhaustive()
+    }
+}
+
+/// A request guard for the claims of a request's verified bearer token
+///
+/// The claims are deserialized into `T`, which can be `serde_json::Value`,
+/// `IdTokenClaims`, `AccessTokenClaims` or any other type that implements
+/// `Deserialize`.
+#[derive(Debug, Clone)]
+pub struct CognitoUser<T = Value>(pub T);
+
+#[::rocket::async_trait]
+impl<'r, T: DeserializeOwned + Send> FromRequest<'r> for CognitoUser<T> {
+    type Error = AuthError;
+
+    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let auth = match req.rocket().state::<CognitoAuth>() {
+            Some(auth) => auth,
+            None => return Outcome::Error((Status::InternalServerError, AuthError::MissingState)),
+        };
+
+        let token = req.headers().get_one(AUTHORIZATION.as_str()).and_then(parse_bearer);
+        let result = match token {
+            Some(token) => auth.keyset.verify(token, &auth.verifier).await
+                .and_then(crate::deserialize_claims)
+                .map_err(AuthError::InvalidToken),
+            None => Err(AuthError::MissingToken),
+        };
+
+        match result {
+            Ok(claims) => Outcome::Success(CognitoUser(claims)),
+            Err(e) => Outcome::Error(((auth.status_mapping)(&e), e)),
+        }
+    }
+}
+
+/// Why the [CognitoUser] request guard failed
+#[derive(Debug)]
+pub enum AuthError {
+    /// The request had no `Authorization: Bearer` token
+    MissingToken,
+
+    /// The token couldn't be verified
+    InvalidToken(Error),
+
+    /// There's no [CognitoAuth] in Rocket's managed state
+    MissingState,
+}
+
+impl AuthError {
+    /// The default status for the error: 401 for a missing token or the
+    /// `http_status()` of a verification error
+    pub fn status(&self) -> Status {
+        match self {
+            AuthError::MissingToken => Status::Unauthorized,
+            AuthError::InvalidToken(e) => Status::from_code(e.http_status().as_u16())
+                .unwrap_or(Status::Unauthorized),
+            AuthError::MissingState => Status::InternalServerError,
+        }
+    }
+}
+
+impl std::fmt::Display for AuthError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            AuthError::MissingToken => write!(f, "Missing bearer token"),
+            AuthError::InvalidToken(e) => e.fmt(f),
+            AuthError::MissingState => write!(f, "No CognitoAuth in Rocket's managed state"),
+        }
+    }
+}
+
+impl std::error::Error for AuthError {}
@@ -0,0 +1,143 @@
+//! Timer and spawn primitives for the async runtime selected by the
+//! `rt-tokio` or `rt-async-std` features
+//!
+//! Without either feature, timers use `futures-timer` so that the core of the
+//! crate works with any runtime, and tasks can only be spawned with the
+//! `tokio` feature.
+
+use std::time::Duration;
+
+#[cfg(any(feature = "tokio", feature = "rt-async-std", feature = "redis"))]
+use std::future::Future;
+
+// Waits for a duration, such as a retry backoff or refresh interval
+pub(crate) async fn sleep(duration: Duration) {
+    #[cfg(feature = "rt-tokio")]
+    if tokio::runtime::Handle::try_current().is_ok() {
+        return tokio::time::sleep(duration).await;
+    }
+
+    #[cfg(feature = "rt-async-std")]
+    async_std::task::sleep(duration).await;
+
+    #[cfg(not(feature = "rt-async-std"))]
+    futures_timer::Delay::new(duration).await;
+}
+
+// Waits for a future for up to a duration, returning None if it took longer
+#[cfg(all(feature = "redis", not(target_arch = "wasm32")))]
+pub(crate) async fn timeout<F: Future>(duration: Duration, future: F) -> Option<F::Output> {
+    use futures_util::future::{self, Either};
+
+    futures_util::pin_mut!(future);
+    match future::select(future, Box::pin(sleep(duration))).await {
+        Either::Left((output, _)) => Some(output),
+        Either::Right(_) => None,
+    }
+}
+
+/// A spawned task, which keeps running if it's dropped
+#[cfg(any(feature = "tokio", feature = "rt-async-std"))]
+#[derive(Debug)]
+pub(crate) enum Task {
+    #[cfg(feature = "tokio")]
+    Tokio(tokio::task::JoinHandle<()>),
+
+    #[cfg(feature = "rt-async-std")]
+    AsyncStd {
+        handle: async_std::task::JoinHandle<()>,
+        finished: std::sync::Arc<std::sync::atomic::AtomicBool>,
+    },
+}
+
+#[cfg(any(feature = "tokio", feature = "rt-async-std"))]
+impl Task {
+    // Stops the task at its next await point
+    pub(crate) fn abort(self) {
+        match self {
+            #[cfg(feature = "tokio")]
+            Task::Tokio(handle) => handle.abort(),
+            #[cfg(feature = "rt-async-std")]
+            Task::AsyncStd { handle, .. } => {
+                // Cancelling is asynchronous, so it's left to the executor
+                async_std::task::spawn(handle.cancel());
+            }
+        }
+    }
+
+    pub(crate) fn is_finished(&self) -> bool {
+        match self {
+            #[cfg(feature = "tokio")]
+            Task::Tokio(handle) => handle.is_finished(),
+            #[cfg(feature = "rt-async-std")]
+            Task::AsyncStd { finished, .. } => finished.load(std::sync::atomic::Ordering::Acquire),
+        }
+    }
+}
+
+// Returns true if `spawn()` has a runtime to spawn tasks on, which for tokio
+// means being called within the context of a tokio runtime
+#[cfg(any(feature = "tokio", feature = "rt-async-std"))]
+pub(crate) fn can_spawn() -> bool {
+    cfg!(feature = "rt-async-std") || tokio_runtime_available()
+}
+
+#[cfg(feature = "tokio")]
+fn tokio_runtime_available() -> bool {
+    tokio::runtime::Handle::try_current().is_ok()
+}
+
+#[cfg(all(not(feature = "tokio"), feature = "rt-async-std"))]
+fn tokio_runtime_available() -> bool {
+    false
+}
+
+// Spawns a task on the current tokio runtime, if there is one, or else on
+// async-std's executor
+//
+// With only the `tokio` feature this panics if it's not called within the
+// context of a tokio runtime (see `can_spawn()`).
+#[cfg(any(feature = "tokio", feature = "rt-async-std"))]
+pub(crate) fn spawn<F>(future: F) -> Task
+where
+    F: Future<Output = ()> + Send + 'static,
+{
+    #[cfg(feature = "rt-async-std")]
+    if !tokio_runtime_available() {
+        return spawn_async_std(future);
+    }
+    spawn_tokio(future)
+}
+
+#[cfg(feature = "tokio")]
+fn spawn_tokio<F: Future<Output = ()> + Send + 'static>(future: F) -> Task {
+    Task::Tokio(tokio::spawn(future))
+}
+
+// Only reachable when there's no tokio runtime
+#[cfg(all(not(feature = "tokio"), feature = "rt-async-std"))]
+fn spawn_tokio<F: Future<Output = ()> + Send + 'static>(future: F) -> Task {
+    spawn_async_std(future)
+}
+
+#[cfg(feature = "rt-async-std")]
+fn spawn_async_std<F: Future<Output = ()> + Send + 'static>(future: F) -> Task {
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+
+    // Set when the future completes or is dropped, including when cancelled
+    struct Finished(Arc<AtomicBool>);
+    impl Drop for Finished {
+        fn drop(&mut self) {
+            self.0.store(true, Ordering::Release);
+        }
+    }
+
+    let finished = Arc::new(AtomicBool::new(false));
+    let guard = Finished(finished.clone());
+    let handle = async_std::task::spawn(async move {
+        let _guard = guard;
+        future.await
+    });
+    Task::AsyncStd { handle, finished }
+}
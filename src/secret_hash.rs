@@ -0,0 +1,15 @@
+use ring::hmac;
+
+/// Computes the `SECRET_HASH` for Cognito user pool API requests (like
+/// `InitiateAuth`) made with a confidential app client
+///
+/// This is the base64 encoded HMAC-SHA256 of the username and client ID, keyed
+/// with the client secret.
+pub fn secret_hash(username: &str, client_id: &str, client_secret: &str) -> String {
+    let key = hmac::Key::new(hmac::HMAC_SHA256, client_secret.as_bytes());
+    let mut ctx = hmac::Context::with_key(&key);
+    ctx.update(username.as_bytes());
+    ctx.update(client_id.as_bytes());
+    base64::Engine::encode(&base64::engine::general_purpose::STANDARD, ctx.sign())
+}
+
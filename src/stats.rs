@@ -0,0 +1,123 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+use web_time::SystemTime;
+
+use crate::{Error, JwksResponse};
+
+/// A snapshot of a KeySet's cache and JWKS fetch statistics (see
+/// `KeySet::stats()`)
+///
+/// The counters are shared by all clones of a KeySet and only ever increase, so
+/// they can be exported as monotonic counters, e.g. to Prometheus or CloudWatch.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct KeySetStats {
+    /// The number of key lookups that found the key cached
+    pub cache_hits: u64,
+
+    /// The number of key lookups that didn't find the key cached
+    pub cache_misses: u64,
+
+    /// The number of requests made for the remote JWKS key set, including
+    /// retries
+    pub jwks_fetches: u64,
+
+    /// The number of JWKS requests that failed, due to a transport error, an
+    /// unsuccessful HTTP status or an invalid key set
+    pub jwks_fetch_failures: u64,
+
+    /// When the key set was last successfully fetched (or revalidated)
+    pub last_refresh: Option<SystemTime>,
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct Stats {
+    cache_hits: AtomicU64,
+    cache_misses: AtomicU64,
+    jwks_fetches: AtomicU64,
+    jwks_fetch_failures: AtomicU64,
+    // Milliseconds since the unix epoch, or zero if never refreshed
+    last_refresh_ms: AtomicU64,
+}
+
+impl Stats {
+    pub(crate) fn record_cache_hit(&self) {
+        self.cache_hits.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn record_cache_miss(&self) {
+        self.cache_misses.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn record_fetch(&self, result: &Result<JwksResponse, Error>) {
+        self.jwks_fetches.fetch_add(1, Ordering::Relaxed);
+        match result {
+            Ok(resp) if resp.status.is_success() || resp.status == reqwest::StatusCode::NOT_MODIFIED => {}
+            _ => self.record_fetch_failure(),
+        }
+    }
+
+    pub(crate) fn record_fetch_failure(&self) {
+        self.jwks_fetch_failures.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn record_refresh(&self) {
+        let now_ms = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map(|since_epoch| since_epoch.as_millis() as u64)
+            .unwrap_or(0);
+        self.last_refresh_ms.store(now_ms, Ordering::Relaxed);
+    }
+
+    pub(crate) fn last_refresh(&self) -> Option<SystemTime> {
+        match self.last_refresh_ms.load(Ordering::Relaxed) {
+            0 => None,
+            ms => Some(SystemTime::UNIX_EPOCH + Duration::from_millis(ms)),
+        }
+    }
+
+    pub(crate) fn snapshot(&self) -> KeySetStats {
+        let last_refresh = self.last_refresh();
+
+        KeySetStats {
+            cache_hits: self.cache_hits.load(Ordering::Relaxed),
+            cache_misses: self.cache_misses.load(Ordering::Relaxed),
+            jwks_fetches: self.jwks_fetches.load(Ordering::Relaxed),
+            jwks_fetch_failures: self.jwks_fetch_failures.load(Ordering::Relaxed),
+            last_refresh,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use reqwest::StatusCode;
+
+    use super::*;
+    use crate::{KeySet, RetryPolicy};
+    use crate::test_util::*;
+
+    #[test]
+    fn stats() {
+        let keyset = KeySet::builder("eu-west-1", "eu-west-1_AbCd1234")
+            .jwks_fetcher(TestFetcher::new(vec![
+                Ok(JwksResponse::new(StatusCode::INTERNAL_SERVER_ERROR, "")),
+                Ok(JwksResponse::new(StatusCode::OK, TEST_JWKS)),
+            ]))
+            .retry_policy(RetryPolicy::new(1).backoff(Duration::from_millis(1), Duration::from_millis(1)))
+            .build()
+            .unwrap();
+        assert_eq!(keyset.stats(), KeySetStats::default());
+
+        async_std::task::block_on(keyset.prefetch_jwks()).unwrap();
+        assert!(keyset.try_cache_lookup_algorithm("test-key-0").is_ok());
+        assert!(keyset.try_cache_lookup_algorithm("unknown-key").is_err());
+
+        let stats = keyset.clone().stats();
+        assert_eq!(stats.cache_hits, 1);
+        assert_eq!(stats.cache_misses, 1);
+        assert_eq!(stats.jwks_fetches, 2);
+        assert_eq!(stats.jwks_fetch_failures, 1);
+        assert!(stats.last_refresh.is_some());
+    }
+}
@@ -0,0 +1,92 @@
+// Fixtures shared by the unit tests of each module
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+use async_trait::async_trait;
+use reqwest::StatusCode;
+use serde_json::json;
+use serde_json::value::Value;
+
+use jsonwebtokens as jwt;
+use jwt::{Algorithm, AlgorithmID};
+
+use crate::{Error, JwksFetcher, JwksRequest, JwksResponse, KeySet};
+
+pub(crate) const TEST_REGION: &str = "eu-west-1";
+pub(crate) const TEST_POOL_ID: &str = "eu-west-1_AbCd1234";
+pub(crate) const TEST_ISS: &str = "https://cognito-idp.eu-west-1.amazonaws.com/eu-west-1_AbCd1234";
+pub(crate) const TEST_JWKS: &str = include_str!("../testdata/jwks.json");
+
+// A key set for the test user pool, with an HS256 "test-key" (see `hs256_token()`)
+pub(crate) fn test_keyset() -> KeySet {
+    let keyset = KeySet::new(TEST_REGION, TEST_POOL_ID).unwrap();
+    insert_test_key(&keyset, "test-key", "secret");
+    keyset
+}
+
+// Inserts an HS256 key, as if the key set had just been fetched
+pub(crate) fn insert_test_key(keyset: &KeySet, kid: &str, secret: &str) {
+    let algorithm = Algorithm::new_hmac(AlgorithmID::HS256, secret).unwrap();
+    keyset.insert_key(kid, algorithm);
+    keyset.cache.write().unwrap().last_jwks_get_time = Some(keyset.clock.now());
+}
+
+// An HS256 token with the given header, issued by the test user pool
+pub(crate) fn test_token(header: Value, secret: &str) -> String {
+    let algorithm = Algorithm::new_hmac(AlgorithmID::HS256, secret).unwrap();
+    jwt::encode(&header, &json!({ "iss": TEST_ISS }), &algorithm).unwrap()
+}
+
+// A token with the given claims, signed by the "test-key" of `test_keyset()`
+pub(crate) fn hs256_token(claims: &Value) -> String {
+    let algorithm = Algorithm::new_hmac(AlgorithmID::HS256, "secret").unwrap();
+    jwt::encode(&json!({ "alg": "HS256", "kid": "test-key" }), claims, &algorithm).unwrap()
+}
+
+// A token with the given claims, signed by the "test-key-0" key of `TEST_JWKS`
+pub(crate) fn rs256_token(claims: &Value) -> String {
+    let signer = Algorithm::new_rsa_pem_signer(AlgorithmID::RS256,
+                                               include_bytes!("../testdata/rsa-private.pem")).unwrap();
+    jwt::encode(&json!({ "alg": "RS256", "kid": "test-key-0" }), claims, &signer).unwrap()
+}
+
+// A fetcher that counts its requests and replies with each of its responses in
+// turn, repeating the last one. Clones share the count.
+#[derive(Debug, Clone)]
+pub(crate) struct TestFetcher {
+    responses: Arc<Mutex<Vec<Result<JwksResponse, Error>>>>,
+    fetches: Arc<AtomicUsize>,
+}
+
+impl TestFetcher {
+    pub(crate) fn new(responses: Vec<Result<JwksResponse, Error>>) -> Self {
+        assert!(!responses.is_empty());
+        TestFetcher { responses: Arc::new(Mutex::new(responses)), fetches: Default::default() }
+    }
+
+    // Always responds with `TEST_JWKS`
+    pub(crate) fn jwks() -> Self {
+        Self::status(StatusCode::OK, TEST_JWKS)
+    }
+
+    pub(crate) fn status(status: StatusCode, body: impl Into<Vec<u8>>) -> Self {
+        Self::new(vec![Ok(JwksResponse::new(status, body))])
+    }
+
+    pub(crate) fn fetch_count(&self) -> usize {
+        self.fetches.load(Ordering::SeqCst)
+    }
+}
+
+#[async_trait]
+impl JwksFetcher for TestFetcher {
+    async fn fetch_jwks(&self, _request: &JwksRequest<'_>) -> Result<JwksResponse, Error> {
+        let n = self.fetches.fetch_add(1, Ordering::SeqCst);
+        let responses = self.responses.lock().unwrap();
+        match &responses[n.min(responses.len() - 1)] {
+            Ok(response) => Ok(response.clone()),
+            Err(e) => Err(e.duplicate()),
+        }
+    }
+}
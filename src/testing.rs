@@ -0,0 +1,299 @@
+//! Utilities for testing token verification without AWS
+//!
+//! A [TestUserPool] generates an RSA key pair, publishes its public key as an
+//! in-memory JWKS key set and mints signed ID and access tokens, like a Cognito
+//! user pool. The KeySets it constructs fetch the in-memory key set instead of
+//! making requests:
+//!
+//! ```
+//! # use jsonwebtokens_cognito::testing::TestUserPool;
+//! # use serde_json::json;
+//! # #[async_std::main]
+//! # async fn main() -> Result<(), jsonwebtokens_cognito::Error> {
+//! let pool = TestUserPool::new("eu-west-1", "eu-west-1_AbCd1234")?;
+//! let keyset = pool.keyset()?;
+//! let verifier = keyset.new_id_token_verifier(&["client-id-0"]).build()?;
+//!
+//! let token = pool.id_token("client-id-0", json!({ "email": "alice@example.com" }))?;
+//! let claims = keyset.verify(&token, &verifier).await?;
+//! assert_eq!(claims["email"], "alice@example.com");
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! RSA key generation is slow in unoptimized builds, which can be avoided by
+//! optimizing the bignum dependency of tests, in the workspace's `Cargo.toml`:
+//!
+//! ```toml
+//! [profile.dev.package.num-bigint-dig]
+//! opt-level = 3
+//! ```
+//!
+//! _Requires the `testing` feature_
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, RwLock};
+
+use async_trait::async_trait;
+use reqwest::StatusCode;
+use rsa::pkcs1::{EncodeRsaPrivateKey, LineEnding};
+use rsa::traits::PublicKeyParts;
+use rsa::RsaPrivateKey;
+use serde_json::json;
+use serde_json::value::Value;
+
+use jsonwebtokens as jwt;
+use jwt::{Algorithm, AlgorithmID};
+
+use crate::{unix_time_now, Error, ErrorDetails, JwksFetcher, JwksRequest, JwksResponse, KeySet};
+
+// How long minted tokens are valid for, like Cognito's default
+const TOKEN_LIFETIME_SECS: u64 = 3600;
+
+/// An RSA key pair for signing test tokens with `RS256`
+#[derive(Debug)]
+pub struct TestKey {
+    kid: String,
+    private_key_pem: String,
+    jwk: Value,
+    signer: Algorithm,
+}
+
+impl TestKey {
+    /// Generates a 2048 bit RSA key pair with the given key ID (`kid`)
+    pub fn generate(kid: impl Into<String>) -> Result<Self, Error> {
+        let kid = kid.into();
+        let private_key = RsaPrivateKey::new(&mut rand_core::OsRng, 2048).map_err(|e| {
+            Error::InvalidConfiguration(ErrorDetails::map("Failed to generate RSA key", e))
+        })?;
+        let private_key_pem = private_key.to_pkcs1_pem(LineEnding::LF).map_err(|e| {
+            Error::InvalidConfiguration(ErrorDetails::map("Failed to encode RSA key", e))
+        })?;
+        let signer = Algorithm::new_rsa_pem_signer(AlgorithmID::RS256, private_key_pem.as_bytes())?;
+
+        let base64url = |bytes: Vec<u8>| base64::Engine::encode(&base64::engine::general_purpose::URL_SAFE_NO_PAD, bytes);
+        let jwk = json!({
+            "kty": "RSA",
+            "alg": "RS256",
+            "use": "sig",
+            "kid": kid,
+            "n": base64url(private_key.n().to_bytes_be()),
+            "e": base64url(private_key.e().to_bytes_be()),
+        });
+
+        Ok(TestKey { kid, private_key_pem: private_key_pem.to_string(), jwk, signer })
+    }
+
+    /// The key ID (`kid`)
+    pub fn kid(&self) -> &str {
+        &self.kid
+    }
+
+    /// The public key, as a JWK
+    pub fn jwk(&self) -> &Value {
+        &self.jwk
+    }
+
+    /// The private key, as a PKCS#1 PEM document
+    pub fn private_key_pem(&self) -> &str {
+        &self.private_key_pem
+    }
+
+    /// Signs a token with the given claims, with the key's `kid` in its header
+    pub fn sign(&self, claims: &Value) -> Result<String, Error> {
+        let header = json!({ "alg": "RS256", "kid": self.kid, "typ": "JWT" });
+        Ok(jwt::encode(&header, claims, &self.signer)?)
+    }
+}
+
+/// An in-memory JWKS key set, which can be used as a KeySet's [JwksFetcher]
+///
+/// Clones share the same key set, so it can be updated (e.g. to test key
+/// rotation) after it's given to a KeySet.
+#[derive(Debug, Clone, Default)]
+pub struct InMemoryJwks {
+    body: Arc<RwLock<String>>,
+    fetches: Arc<AtomicUsize>,
+}
+
+impl InMemoryJwks {
+    /// Constructs a key set with the public keys of the given keys
+    pub fn from_keys(keys: &[&TestKey]) -> Self {
+        let jwks = InMemoryJwks::default();
+        jwks.set_keys(keys);
+        jwks
+    }
+
+    /// Replaces the key set with the public keys of the given keys
+    pub fn set_keys(&self, keys: &[&TestKey]) {
+        let keys: Vec<&Value> = keys.iter().map(|key| key.jwk()).collect();
+        self.set_json(json!({ "keys": keys }).to_string());
+    }
+
+    /// Replaces the key set with the given JSON document, which doesn't have to
+    /// be valid (e.g. to test handling of malformed key sets)
+    pub fn set_json(&self, json: impl Into<String>) {
+        *self.body.write().unwrap() = json.into();
+    }
+
+    /// The number of times the key set has been fetched
+    pub fn fetch_count(&self) -> usize {
+        self.fetches.load(Ordering::SeqCst)
+    }
+}
+
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+impl JwksFetcher for InMemoryJwks {
+    async fn fetch_jwks(&self, _request: &JwksRequest<'_>) -> Result<JwksResponse, Error> {
+        self.fetches.fetch_add(1, Ordering::SeqCst);
+        let body = self.body.read().unwrap().clone();
+        Ok(JwksResponse::new(StatusCode::OK, body))
+    }
+}
+
+/// A fake Cognito user pool that mints tokens signed by its own keys (see the
+/// [module documentation](self))
+#[derive(Debug)]
+pub struct TestUserPool {
+    region: String,
+    pool_id: String,
+    iss: String,
+    keys: Vec<TestKey>,
+    jwks: InMemoryJwks,
+}
+
+impl TestUserPool {
+    /// Constructs a pool with a newly generated signing key, `test-key-0`
+    ///
+    /// The region and pool ID must be valid, as for `KeySet::new()`.
+    pub fn new(region: impl Into<String>, pool_id: impl Into<String>) -> Result<Self, Error> {
+        let (region, pool_id) = (region.into(), pool_id.into());
+        let iss = KeySet::new(&region, &pool_id)?.iss;
+        let key = TestKey::generate("test-key-0")?;
+        let jwks = InMemoryJwks::from_keys(&[&key]);
+        Ok(TestUserPool {
+            region,
+            pool_id,
+            iss,
+            keys: vec![key],
+            jwks,
+        })
+    }
+
+    /// The issuer (`iss`) of the pool's tokens, which is the same as a real
+    /// pool's in the same region
+    pub fn issuer(&self) -> &str {
+        &self.iss
+    }
+
+    /// The pool's in-memory JWKS key set
+    pub fn jwks(&self) -> &InMemoryJwks {
+        &self.jwks
+    }
+
+    /// The key that new tokens are signed with
+    pub fn signing_key(&self) -> &TestKey {
+        self.keys.last().expect("Pool has no keys")
+    }
+
+    /// Constructs a KeySet that fetches the pool's in-memory key set
+    pub fn keyset(&self) -> Result<KeySet, Error> {
+        KeySet::builder(&self.region, &self.pool_id)
+            .jwks_fetcher(self.jwks.clone())
+            .build()
+    }
+
+    /// Generates a new signing key, publishing it along with the previous keys
+    pub fn rotate_key(&mut self) -> Result<&TestKey, Error> {
+        let key = TestKey::generate(format!("test-key-{}", self.keys.len()))?;
+        self.keys.push(key);
+        let keys: Vec<&TestKey> = self.keys.iter().collect();
+        self.jwks.set_keys(&keys);
+        Ok(self.signing_key())
+    }
+
+    /// Signs a token with exactly the given claims
+    pub fn sign(&self, claims: &Value) -> Result<String, Error> {
+        self.signing_key().sign(claims)
+    }
+
+    /// Mints an ID token for the app client, for the user `test-user`
+    ///
+    /// The standard claims of a Cognito ID token are filled in (valid for an
+    /// hour) and may be overridden, or added to, by the given claims object.
+    pub fn id_token(&self, client_id: &str, claims: Value) -> Result<String, Error> {
+        let now = unix_time_now()?;
+        let mut token_claims = json!({
+            "sub": "test-user",
+            "iss": self.issuer(),
+            "aud": client_id,
+            "token_use": "id",
+            "cognito:username": "test-user",
+            "auth_time": now,
+            "iat": now,
+            "exp": now + TOKEN_LIFETIME_SECS,
+        });
+        merge_claims(&mut token_claims, claims);
+        self.sign(&token_claims)
+    }
+
+    /// Mints an access token for the app client, for the user `test-user`
+    /// (see `id_token()`)
+    pub fn access_token(&self, client_id: &str, claims: Value) -> Result<String, Error> {
+        let now = unix_time_now()?;
+        let mut token_claims = json!({
+            "sub": "test-user",
+            "iss": self.issuer(),
+            "client_id": client_id,
+            "token_use": "access",
+            "username": "test-user",
+            "scope": "openid",
+            "auth_time": now,
+            "iat": now,
+            "exp": now + TOKEN_LIFETIME_SECS,
+        });
+        merge_claims(&mut token_claims, claims);
+        self.sign(&token_claims)
+    }
+}
+
+fn merge_claims(claims: &mut Value, overrides: Value) {
+    if let (Value::Object(claims), Value::Object(overrides)) = (claims, overrides) {
+        claims.extend(overrides);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn testing_user_pool() {
+        let mut pool = TestUserPool::new("eu-west-1", "eu-west-1_AbCd1234").unwrap();
+        let keyset = pool.keyset().unwrap();
+        let id_verifier = keyset.new_id_token_verifier(&["client-id-0"]).build().unwrap();
+        let access_verifier = keyset.new_access_token_verifier(&["client-id-0"]).build().unwrap();
+
+        let token = pool.id_token("client-id-0", json!({ "email": "alice@example.com" })).unwrap();
+        let claims = async_std::task::block_on(keyset.verify(&token, &id_verifier)).unwrap();
+        assert_eq!(claims["email"], "alice@example.com");
+        assert_eq!(claims["cognito:username"], "test-user");
+
+        let token = pool.access_token("client-id-0", json!({ "exp": 1 })).unwrap();
+        assert!(matches!(async_std::task::block_on(keyset.verify(&token, &access_verifier)),
+                         Err(Error::TokenExpiredAt(1))));
+        assert_eq!(pool.jwks().fetch_count(), 1);
+
+        // Tokens signed by a new key are verified after refetching the key set
+        keyset.set_min_jwks_fetch_interval(Duration::from_secs(0));
+        assert_eq!(pool.rotate_key().unwrap().kid(), "test-key-1");
+        let token = pool.access_token("client-id-0", json!({})).unwrap();
+        assert!(async_std::task::block_on(keyset.verify(&token, &access_verifier)).is_ok());
+        assert_eq!(pool.jwks().fetch_count(), 2);
+    }
+}
@@ -0,0 +1,156 @@
+use std::collections::{BTreeMap, HashMap};
+
+use ring::digest;
+
+use crate::VerifiedTokenData;
+
+// The SHA-256 digest of a token
+type TokenDigest = [u8; 32];
+
+struct Entry {
+    data: VerifiedTokenData,
+    exp: u64,
+    last_used: u64,
+}
+
+// A bounded, least recently used cache of verified tokens, keyed by the
+// SHA-256 digest of the token (see `KeySet::set_token_cache()`)
+//
+// Since a cached token skips signature verification, the key must be
+// collision resistant, so that no other token can be crafted to match it.
+pub(crate) struct TokenCache {
+    capacity: usize,
+    entries: HashMap<TokenDigest, Entry>,
+    // Token digests, by when they were last used
+    lru: BTreeMap<u64, TokenDigest>,
+    tick: u64,
+}
+
+impl TokenCache {
+    pub(crate) fn new(capacity: usize) -> Self {
+        TokenCache {
+            capacity,
+            entries: HashMap::new(),
+            lru: BTreeMap::new(),
+            tick: 0,
+        }
+    }
+
+    fn token_digest(token: &str) -> TokenDigest {
+        let mut token_digest = TokenDigest::default();
+        token_digest.copy_from_slice(digest::digest(&digest::SHA256, token.as_bytes()).as_ref());
+        token_digest
+    }
+
+    fn next_tick(&mut self) -> u64 {
+        self.tick += 1;
+        self.tick
+    }
+
+    // Returns the data for a token that was verified before and hasn't expired
+    pub(crate) fn get(&mut self, token: &str, time_now: u64) -> Option<VerifiedTokenData> {
+        let token_digest = Self::token_digest(token);
+        let tick = self.next_tick();
+        let entry = self.entries.get_mut(&token_digest)?;
+
+        self.lru.remove(&entry.last_used);
+        if entry.exp <= time_now {
+            self.entries.remove(&token_digest);
+            return None;
+        }
+        entry.last_used = tick;
+        self.lru.insert(tick, token_digest);
+        Some(entry.data.clone())
+    }
+
+    // Remembers a verified token until it expires, evicting the least recently
+    // used token if the cache is full
+    pub(crate) fn insert(&mut self, token: &str, data: &VerifiedTokenData, exp: u64) {
+        if self.capacity == 0 {
+            return;
+        }
+
+        let token_digest = Self::token_digest(token);
+        let tick = self.next_tick();
+        if let Some(old) = self.entries.remove(&token_digest) {
+            self.lru.remove(&old.last_used);
+        }
+        while self.entries.len() >= self.capacity {
+            match self.lru.pop_first() {
+                Some((_, oldest)) => { self.entries.remove(&oldest); }
+                None => break,
+            }
+        }
+
+        self.entries.insert(token_digest, Entry { data: data.clone(), exp, last_used: tick });
+        self.lru.insert(tick, token_digest);
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.entries.clear();
+        self.lru.clear();
+    }
+}
+
+impl std::fmt::Debug for TokenCache {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("TokenCache")
+            .field("capacity", &self.capacity)
+            .field("len", &self.entries.len())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::{unix_time_now, KeySet};
+    use crate::test_util::*;
+
+    #[test]
+    fn token_cache() {
+        let mut keyset = KeySet::new("eu-west-1", "eu-west-1_AbCd1234").unwrap();
+        keyset.set_token_cache(1);
+        insert_test_key(&keyset, "test-key", "secret");
+        let verifier = keyset.new_issuer_verifier().build().unwrap();
+        let exp = unix_time_now().unwrap() + 3600;
+        let claims = json!({ "iss": TEST_ISS, "exp": exp });
+        let token = hs256_token(&claims);
+        let other_token = test_token(json!({ "alg": "HS256", "kid": "test-key", "typ": "JWT" }), "secret");
+
+        assert_eq!(keyset.try_verify(&token, &verifier).unwrap()["exp"], exp);
+
+        // A cached token is accepted without checking its signature again...
+        insert_test_key(&keyset, "test-key", "rotated-secret");
+        assert!(keyset.try_verify(&token, &verifier).is_ok());
+        assert!(async_std::task::block_on(keyset.verify(&token, &verifier)).is_ok());
+
+        // ...but its claims are still checked
+        let audience_verifier = keyset.new_issuer_verifier().audience("client-id-0").build().unwrap();
+        assert!(keyset.try_verify(&token, &audience_verifier).is_err());
+
+        // Tokens without an exp aren't cached
+        insert_test_key(&keyset, "test-key", "secret");
+        assert!(keyset.try_verify(&other_token, &verifier).is_ok());
+        insert_test_key(&keyset, "test-key", "rotated-secret");
+        assert!(keyset.try_verify(&token, &verifier).is_ok());
+        assert!(keyset.try_verify(&other_token, &verifier).is_err());
+
+        keyset.invalidate_cache();
+        insert_test_key(&keyset, "test-key", "rotated-secret");
+        assert!(keyset.try_verify(&token, &verifier).is_err());
+
+        // The least recently used token is evicted
+        let mut token_cache = TokenCache::new(2);
+        let data = VerifiedTokenData { header: json!({}), claims: json!({}), kid: "test-key".to_string() };
+        token_cache.insert("a", &data, exp);
+        token_cache.insert("b", &data, exp);
+        assert!(token_cache.get("a", 0).is_some());
+        token_cache.insert("c", &data, exp);
+        assert!(token_cache.get("a", 0).is_some());
+        assert!(token_cache.get("b", 0).is_none());
+        assert!(token_cache.get("c", exp).is_none());
+    }
+}
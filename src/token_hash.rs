@@ -0,0 +1,91 @@
+use serde_json::value::Value;
+
+use jsonwebtokens::Verifier;
+use ring::digest;
+
+use crate::{Error, ErrorDetails, KeySet, VerifiedTokenData};
+
+impl KeySet {
+    /// Verify an ID token, and that its `at_hash` claim matches the access token
+    /// that was issued with it
+    ///
+    /// OpenID Connect clients that receive an access token along with an ID
+    /// token (e.g. from the hosted UI's token endpoint) can use this to check
+    /// that the access token belongs with the ID token, as described by
+    /// [OpenID Connect Core](https://openid.net/specs/openid-connect-core-1_0.html#CodeIDToken).
+    ///
+    /// ```no_run
+    /// # use jsonwebtokens_cognito::KeySet;
+    /// # #[async_std::main]
+    /// # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// # let (id_token, access_token) = ("header.payload.signature", "header.payload.signature");
+    /// let keyset = KeySet::new("eu-west-1", "eu-west-1_AbCd1234")?;
+    /// let verifier = keyset.new_id_token_verifier(&["client-id-0"]).build()?;
+    /// let claims = keyset.verify_id_token_with_access_token(id_token, access_token, &verifier).await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// Returns a `ClaimMismatch` error if the `at_hash` claim is missing or
+    /// doesn't match.
+    pub async fn verify_id_token_with_access_token(
+        &self,
+        id_token: &str,
+        access_token: &str,
+        verifier: &Verifier
+    ) -> Result<Value, Error> {
+        let data = self.verify_full(id_token, verifier).await?;
+        check_hash_claim(&data, "at_hash", access_token)?;
+        Ok(data.claims)
+    }
+
+    /// Verify an ID token, and that its `c_hash` claim matches the authorization
+    /// code that was issued with it (see `verify_id_token_with_access_token()`)
+    ///
+    /// Returns a `ClaimMismatch` error if the `c_hash` claim is missing or
+    /// doesn't match.
+    pub async fn verify_id_token_with_code(
+        &self,
+        id_token: &str,
+        code: &str,
+        verifier: &Verifier
+    ) -> Result<Value, Error> {
+        let data = self.verify_full(id_token, verifier).await?;
+        check_hash_claim(&data, "c_hash", code)?;
+        Ok(data.claims)
+    }
+}
+
+// Checks a verified ID token's `at_hash` or `c_hash` claim, which is the
+// base64url encoded left half of the hash of the value, using the hash function
+// of the token's signing algorithm
+fn check_hash_claim(data: &VerifiedTokenData, claim: &str, value: &str) -> Result<(), Error> {
+    let alg = data.header.get("alg").and_then(Value::as_str).unwrap_or_default();
+    let expected = token_hash(alg, value)?;
+
+    match data.claims.get(claim).and_then(Value::as_str) {
+        Some(actual) if actual == expected => Ok(()),
+        actual => Err(Error::ClaimMismatch {
+            claim: claim.to_string(),
+            expected: Some(expected),
+            actual: actual.map(String::from),
+        }),
+    }
+}
+
+pub(crate) fn token_hash(alg: &str, value: &str) -> Result<String, Error> {
+    let algorithm = if alg.ends_with("256") {
+        &digest::SHA256
+    } else if alg.ends_with("384") {
+        &digest::SHA384
+    } else if alg.ends_with("512") {
+        &digest::SHA512
+    } else {
+        return Err(Error::MalformedToken(ErrorDetails::new(
+            format!("Can't determine the hash function for algorithm \"{}\"", alg))));
+    };
+
+    let hash = digest::digest(algorithm, value.as_bytes());
+    let left_half = &hash.as_ref()[..hash.as_ref().len() / 2];
+    Ok(base64::Engine::encode(&base64::engine::general_purpose::URL_SAFE_NO_PAD, left_half))
+}
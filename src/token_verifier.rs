@@ -0,0 +1,144 @@
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::sync::{Arc, Mutex};
+
+use async_trait::async_trait;
+use serde_json::value::Value;
+
+use jsonwebtokens::Verifier;
+
+use crate::{Error, KeySet};
+
+/// Verifies tokens, returning their claims
+///
+/// Application code can depend on a `dyn TokenVerifier` (or a generic
+/// `impl TokenVerifier`) instead of a [KeySet], so that verification can be
+/// replaced with a [MockVerifier] in unit tests. [KeySetVerifier] implements it
+/// with a KeySet and a built `Verifier`.
+///
+/// ```
+/// # use jsonwebtokens_cognito::{Error, MockVerifier, TokenVerifier};
+/// # use serde_json::json;
+/// async fn handle_request(verifier: &dyn TokenVerifier, token: &str) -> Result<String, Error> {
+///     let claims = verifier.verify(token).await?;
+///     Ok(format!("Hello {}", claims["sub"]))
+/// }
+///
+/// # #[async_std::main]
+/// # async fn main() {
+/// let verifier = MockVerifier::new().accept("token-0", json!({ "sub": "user-0" }));
+/// assert!(handle_request(&verifier, "token-0").await.is_ok());
+/// assert!(handle_request(&verifier, "token-1").await.is_err());
+/// # }
+/// ```
+///
+/// _The [async_trait](crate::async_trait) attribute is re-exported for
+/// implementing this trait_
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+pub trait TokenVerifier: Debug + Send + Sync {
+    /// Verifies the token's signature and claims, returning its claims
+    async fn verify(&self, token: &str) -> Result<Value, Error>;
+}
+
+/// A [TokenVerifier] that verifies tokens with a [KeySet] and `Verifier`
+#[derive(Clone)]
+pub struct KeySetVerifier {
+    keyset: KeySet,
+    verifier: Arc<Verifier>,
+}
+
+impl KeySetVerifier {
+    pub fn new(keyset: KeySet, verifier: impl Into<Arc<Verifier>>) -> Self {
+        KeySetVerifier {
+            keyset,
+            verifier: verifier.into(),
+        }
+    }
+
+    /// The key set that tokens are verified with
+    pub fn keyset(&self) -> &KeySet {
+        &self.keyset
+    }
+}
+
+impl Debug for KeySetVerifier {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("KeySetVerifier")
+            .field("keyset", &self.keyset)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+impl TokenVerifier for KeySetVerifier {
+    async fn verify(&self, token: &str) -> Result<Value, Error> {
+        self.keyset.verify(token, &self.verifier).await
+    }
+}
+
+/// A [TokenVerifier] for unit tests, which accepts or rejects specific tokens
+///
+/// Tokens that haven't been given to `accept()` or `reject()` are rejected with
+/// an `InvalidSignature` error.
+#[derive(Debug, Default)]
+pub struct MockVerifier {
+    // Errors aren't Sync, so are only shared behind a lock
+    results: Mutex<HashMap<String, Result<Value, Error>>>,
+}
+
+impl MockVerifier {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Accepts the given token, returning the given claims
+    pub fn accept(mut self, token: impl Into<String>, claims: Value) -> Self {
+        self.results.get_mut().unwrap().insert(token.into(), Ok(claims));
+        self
+    }
+
+    /// Rejects the given token with the given error
+    pub fn reject(mut self, token: impl Into<String>, error: Error) -> Self {
+        self.results.get_mut().unwrap().insert(token.into(), Err(error));
+        self
+    }
+}
+
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+impl TokenVerifier for MockVerifier {
+    async fn verify(&self, token: &str) -> Result<Value, Error> {
+        match self.results.lock().unwrap().get(token) {
+            Some(Ok(claims)) => Ok(claims.clone()),
+            Some(Err(e)) => Err(e.duplicate()),
+            None => Err(Error::InvalidSignature()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::test_util::*;
+
+    #[test]
+    fn token_verifier() {
+        let keyset = test_keyset();
+        let token = test_token(json!({ "alg": "HS256", "kid": "test-key" }), "secret");
+        let verifier: Box<dyn TokenVerifier> =
+            Box::new(KeySetVerifier::new(keyset.clone(), keyset.new_issuer_verifier().build().unwrap()));
+        assert!(async_std::task::block_on(verifier.verify(&token)).is_ok());
+        assert!(async_std::task::block_on(verifier.verify("header.payload.signature")).is_err());
+
+        let verifier: Box<dyn TokenVerifier> = Box::new(MockVerifier::new()
+            .accept("token-0", json!({ "sub": "user-0" }))
+            .reject("token-1", Error::TokenExpiredAt(1)));
+        assert_eq!(async_std::task::block_on(verifier.verify("token-0")).unwrap()["sub"], "user-0");
+        assert!(matches!(async_std::task::block_on(verifier.verify("token-1")), Err(Error::TokenExpiredAt(1))));
+        assert!(matches!(async_std::task::block_on(verifier.verify("token-2")), Err(Error::InvalidSignature())));
+    }
+}
@@ -0,0 +1,243 @@
+//! A framework-agnostic [tower](https://crates.io/crates/tower) layer for
+//! verifying bearer tokens
+//!
+//! A [CognitoAuthLayer] can wrap any `Service` that handles `http::Request`s
+//! (such as with hyper, warp, tonic or axum). It verifies the bearer token of
+//! each request with a KeySet and Verifier and rejects requests that don't have
+//! a valid token. The verified claims are added to the request extensions as
+//! [VerifiedClaims].
+//!
+//! A [Policy] can be added with `CognitoAuthLayer::policy()`, to reject
+//! requests whose claims it denies with a `403 Forbidden` status.
+//!
+//! Rejected requests get an empty response body with the status given by
+//! `Error::http_status()`, or `401 Unauthorized` if there's no bearer token.
+//! `401` responses include a `WWW-Authenticate: Bearer` header. The body is
+//! empty because the layer can wrap services with any type of response body
+//! (only requiring `Default`); the [axum](crate::axum) module has a layer whose
+//! rejections describe why the request was rejected.
+//!
+//! ```no_run
+//! # use jsonwebtokens_cognito::KeySet;
+//! # use jsonwebtokens_cognito::tower::{CognitoAuthLayer, VerifiedClaims};
+//! # use tower_layer::Layer;
+//! # use tower_service::Service;
+//! # #[derive(Clone)]
+//! # struct MyService;
+//! # impl Service<http::Request<()>> for MyService {
+//! #     type Response = http::Response<String>;
+//! #     type Error = std::convert::Infallible;
+//! #     type Future = std::future::Ready<Result<Self::Response, Self::Error>>;
+//! #     fn poll_ready(&mut self, _cx: &mut std::task::Context<'_>) -> std::task::Poll<Result<(), Self::Error>> {
+//! #         std::task::Poll::Ready(Ok(()))
+//! #     }
+//! #     fn call(&mut self, req: http::Request<()>) -> Self::Future {
+//! #         let claims = req.extensions().get::<VerifiedClaims>().unwrap();
+//! #         std::future::ready(Ok(http::Response::new(claims.0["sub"].to_string())))
+//! #     }
+//! # }
+//! # fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! let keyset = KeySet::new("eu-west-1", "eu-west-1_AbCd1234")?;
+//! let verifier = keyset.new_access_token_verifier(&["client-id-0"]).build()?;
+//!
+//! let service = CognitoAuthLayer::new(keyset, verifier).layer(MyService);
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! _Requires the `tower` feature_
+
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+use http::header::{HeaderValue, AUTHORIZATION, WWW_AUTHENTICATE};
+use http::{HeaderMap, Request, Response, StatusCode};
+use serde_json::value::Value;
+use tower_layer::Layer;
+use tower_service::Service;
+
+use jsonwebtokens::Verifier;
+
+use crate::{Error, KeySet, Policy};
+use crate::http_util::parse_bearer;
+
+/// The verified claims of a request's token, as added to the request
+/// extensions by a [CognitoAuthLayer]
+#[derive(Debug, Clone)]
+pub struct VerifiedClaims(pub Value);
+
+// Returns the token from an `Authorization: Bearer <token>` header
+pub(crate) fn bearer_token(headers: &HeaderMap) -> Option<&str> {
+    parse_bearer(headers.get(AUTHORIZATION)?.to_str().ok()?)
+}
+
+// Verifies a request's token and checks its claims against the policy
+pub(crate) async fn verify_request(keyset: &KeySet, verifier: &Verifier, policy: &Policy, token: &str)
+    -> Result<Value, Error>
+{
+    let claims = keyset.verify(token, verifier).await?;
+    policy.check(&claims).map_err(Error::PolicyDenied)?;
+    Ok(claims)
+}
+
+fn rejection<B: Default>(status: StatusCode) -> Response<B> {
+    let mut response = Response::new(B::default());
+    *response.status_mut() = status;
+    if status == StatusCode::UNAUTHORIZED {
+        response.headers_mut().insert(WWW_AUTHENTICATE, HeaderValue::from_static("Bearer"));
+    }
+    response
+}
+
+/// A tower layer that verifies bearer tokens with a KeySet and Verifier (see the
+/// [module documentation](self))
+#[derive(Clone)]
+pub struct CognitoAuthLayer {
+    keyset: KeySet,
+    verifier: Arc<Verifier>,
+    policy: Arc<Policy>,
+}
+
+impl CognitoAuthLayer {
+    /// Constructs a layer that verifies tokens with the given KeySet and Verifier
+    pub fn new(keyset: KeySet, verifier: impl Into<Arc<Verifier>>) -> Self {
+        CognitoAuthLayer {
+            keyset,
+            verifier: verifier.into(),
+            policy: Arc::new(Policy::new()),
+        }
+    }
+
+    /// Rejects requests whose verified claims are denied by the given policy,
+    /// with a `403 Forbidden` status
+    pub fn policy(mut self, policy: Policy) -> Self {
+        self.policy = Arc::new(policy);
+        self
+    }
+}
+
+impl<S> Layer<S> for CognitoAuthLayer {
+    type Service = CognitoAuthService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        CognitoAuthService {
+            inner,
+            keyset: self.keyset.clone(),
+            verifier: self.verifier.clone(),
+            policy: self.policy.clone(),
+        }
+    }
+}
+
+/// The service created by a [CognitoAuthLayer]
+#[derive(Clone)]
+pub struct CognitoAuthService<S> {
+    inner: S,
+    keyset: KeySet,
+    verifier: Arc<Verifier>,
+    policy: Arc<Policy>,
+}
+
+impl<S, ReqBody, ResBody> Service<Request<ReqBody>> for CognitoAuthService<S>
+where
+    S: Service<Request<ReqBody>, Response = Response<ResBody>> + Clone + Send + 'static,
+    S::Future: Send + 'static,
+    ReqBody: Send + 'static,
+    ResBody: Default,
+{
+    type Response = Response<ResBody>;
+    type Error = S::Error;
+    #[allow(clippy::type_complexity)]
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, S::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut req: Request<ReqBody>) -> Self::Future {
+        // Take the service that was driven to readiness, leaving a clone in its place
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+        let keyset = self.keyset.clone();
+        let verifier = self.verifier.clone();
+        let policy = self.policy.clone();
+        let token = bearer_token(req.headers()).map(String::from);
+
+        Box::pin(async move {
+            let token = match token {
+                Some(token) => token,
+                None => return Ok(rejection(StatusCode::UNAUTHORIZED)),
+            };
+
+            match verify_request(&keyset, &verifier, &policy, &token).await {
+                Ok(claims) => {
+                    req.extensions_mut().insert(VerifiedClaims(claims));
+                    inner.call(req).await
+                }
+                Err(e) => Ok(rejection(e.http_status())),
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::Infallible;
+    use std::future::Ready;
+
+    use serde_json::json;
+
+    use super::*;
+    use crate::test_util::*;
+
+    #[derive(Clone)]
+    struct ClaimsService;
+
+    impl Service<Request<()>> for ClaimsService {
+        type Response = Response<String>;
+        type Error = Infallible;
+        type Future = Ready<Result<Self::Response, Self::Error>>;
+
+        fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn call(&mut self, req: Request<()>) -> Self::Future {
+            let claims = req.extensions().get::<VerifiedClaims>().unwrap();
+            std::future::ready(Ok(Response::new(claims.0["iss"].as_str().unwrap().to_string())))
+        }
+    }
+
+    #[test]
+    fn auth_layer_rejections() {
+        let keyset = test_keyset();
+        let verifier = keyset.new_issuer_verifier().build().unwrap();
+        let mut service = CognitoAuthLayer::new(keyset, verifier).layer(ClaimsService);
+        let mut call = |authorization: Option<&str>| {
+            let mut req = Request::new(());
+            if let Some(authorization) = authorization {
+                req.headers_mut().insert(AUTHORIZATION, HeaderValue::from_str(authorization).unwrap());
+            }
+            async_std::task::block_on(service.call(req)).unwrap()
+        };
+
+        let token = hs256_token(&json!({ "iss": TEST_ISS }));
+        let response = call(Some(&format!("Bearer {}", token)));
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.body(), TEST_ISS);
+
+        // Rejections have an empty body, since the body type is only known to
+        // implement Default
+        let response = call(None);
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(response.headers()[WWW_AUTHENTICATE], "Bearer");
+        assert_eq!(response.body(), "");
+
+        let token = hs256_token(&json!({ "iss": "https://example.com" }));
+        let response = call(Some(&format!("Bearer {}", token)));
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(response.body(), "");
+    }
+}
@@ -0,0 +1,40 @@
+// Helpers for emitting `tracing` events (with the `tracing` feature)
+//
+// Tokens are never recorded, only their key IDs.
+
+use serde_json::value::Value;
+use web_time::Instant;
+
+use jsonwebtokens as jwt;
+
+use crate::{Error, JwksResponse, SkippedKey};
+
+pub(crate) fn fetch_result(url: &str, result: &Result<JwksResponse, Error>, started: Instant, attempt: u32) {
+    let duration_ms = started.elapsed().as_millis() as u64;
+    match result {
+        Ok(resp) if resp.status.is_success() || resp.status == reqwest::StatusCode::NOT_MODIFIED => {
+            tracing::debug!(url, status = resp.status.as_u16(), duration_ms, attempt, "Fetched JWKS key set");
+        }
+        Ok(resp) => {
+            tracing::warn!(url, status = resp.status.as_u16(), duration_ms, attempt,
+                           "JWKS key set request failed");
+        }
+        Err(e) => {
+            tracing::warn!(url, error = %e, duration_ms, attempt, "Failed to fetch JWKS key set");
+        }
+    }
+}
+
+pub(crate) fn skipped_key(key: &SkippedKey) {
+    tracing::warn!(kid = key.kid.as_deref(), reason = %key.reason, "Skipped JWKS key");
+}
+
+pub(crate) fn verify_failure(token: &str, error: &Error) {
+    // An oversized token isn't decoded, as for verification
+    let header = match error {
+        Error::TokenTooLarge { .. } => None,
+        _ => jwt::raw::decode_header_only(token).ok(),
+    };
+    let kid = header.as_ref().and_then(|header| header.get("kid")).and_then(Value::as_str);
+    tracing::debug!(kid, error = %error, "Token verification failed");
+}
@@ -0,0 +1,185 @@
+//! Format checks for AWS region names and Cognito user pool IDs
+//!
+//! These are `const fn`s so that the `keyset!` macro can check literal
+//! arguments at compile time.
+
+use crate::{Error, ErrorDetails};
+
+/// Returns true if `region` looks like an AWS region name, such as "eu-west-1"
+/// or "us-gov-west-1"
+///
+/// A region is made of at least three '-' separated parts: a two letter prefix,
+/// one or more lowercase words and a trailing number.
+pub const fn is_valid_region(region: &str) -> bool {
+    let bytes = region.as_bytes();
+
+    let mut n_parts = 0;
+    let mut part_start = 0;
+    let mut i = 0;
+    while i <= bytes.len() {
+        if i == bytes.len() || bytes[i] == b'-' {
+            let is_last = i == bytes.len();
+            if i == part_start || (n_parts == 0 && i - part_start != 2) {
+                return false;
+            }
+
+            let mut j = part_start;
+            while j < i {
+                let valid = if is_last {
+                    bytes[j].is_ascii_digit()
+                } else {
+                    bytes[j].is_ascii_lowercase()
+                };
+                if !valid {
+                    return false;
+                }
+                j += 1;
+            }
+
+            n_parts += 1;
+            part_start = i + 1;
+        }
+        i += 1;
+    }
+
+    n_parts >= 3
+}
+
+/// Returns true if `pool_id` looks like a Cognito user pool ID for the given
+/// region, such as "eu-west-1_AbCd1234"
+pub const fn is_valid_pool_id(region: &str, pool_id: &str) -> bool {
+    let region = region.as_bytes();
+    let pool_id = pool_id.as_bytes();
+
+    if pool_id.len() < region.len() + 2 {
+        return false;
+    }
+
+    let mut i = 0;
+    while i < region.len() {
+        if pool_id[i] != region[i] {
+            return false;
+        }
+        i += 1;
+    }
+
+    if pool_id[i] != b'_' {
+        return false;
+    }
+    i += 1;
+
+    while i < pool_id.len() {
+        if !pool_id[i].is_ascii_alphanumeric() {
+            return false;
+        }
+        i += 1;
+    }
+
+    true
+}
+
+// Checks the region and user pool ID given to `KeySet::new()`, so that typos
+// fail early instead of as failed JWKS requests
+pub(crate) fn check_region_and_pool_id(region: &str, pool_id: &str) -> Result<(), Error> {
+    let message = if !is_valid_region(region) {
+        if is_valid_region(pool_id) && is_valid_pool_id(pool_id, region) {
+            format!("The region \"{}\" and user pool ID \"{}\" appear to be swapped", region, pool_id)
+        } else {
+            format!("Invalid AWS region \"{}\"", region)
+        }
+    } else if is_valid_pool_id(region, pool_id) {
+        return Ok(());
+    } else {
+        match pool_id.split_once('_') {
+            Some((pool_region, _)) if pool_region != region && is_valid_region(pool_region) => {
+                format!("User pool ID \"{}\" doesn't belong to the region \"{}\"", pool_id, region)
+            }
+            _ => format!("Invalid user pool ID \"{}\", expected \"{}_<id>\"", pool_id, region),
+        }
+    };
+    Err(Error::InvalidConfiguration(ErrorDetails::new(message)))
+}
+
+#[doc(hidden)]
+pub const fn check_keyset_macro_args(region: &str, pool_id: &str) {
+    if !is_valid_region(region) {
+        panic!("keyset!: invalid AWS region name");
+    }
+    if !is_valid_pool_id(region, pool_id) {
+        panic!("keyset!: user pool ID doesn't match the format \"<region>_<alphanumeric id>\"");
+    }
+}
+
+/// Constructs a [KeySet](crate::KeySet) after checking the region and user pool
+/// ID at compile time
+///
+/// Both arguments must be string literals. This expands to a call to
+/// `KeySet::new()` (so it evaluates to a `Result`) but a malformed region, such
+/// as "us-east-l", or a pool ID that doesn't belong to the given region will fail
+/// to compile instead of only surfacing as a failed JWKS request at runtime.
+///
+/// ```
+/// # use jsonwebtokens_cognito::keyset;
+/// # fn main() -> Result<(), jsonwebtokens_cognito::Error> {
+/// let keyset = keyset!("eu-west-1", "eu-west-1_AbCd1234")?;
+/// # Ok(())
+/// # }
+/// ```
+///
+/// ```compile_fail
+/// # use jsonwebtokens_cognito::keyset;
+/// let keyset = keyset!("us-east-l", "us-east-1_AbCd1234");
+/// ```
+#[macro_export]
+macro_rules! keyset {
+    ($region:literal, $pool_id:literal $(,)?) => {{
+        const _: () = $crate::__check_keyset_macro_args($region, $pool_id);
+        $crate::KeySet::new($region, $pool_id)
+    }};
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::KeySet;
+
+    #[test]
+    fn region_format() {
+        assert!(is_valid_region("eu-west-1"));
+        assert!(is_valid_region("us-gov-west-1"));
+        assert!(is_valid_region("ap-southeast-2"));
+        assert!(!is_valid_region("us-east-l"));
+        assert!(!is_valid_region("us-east"));
+        assert!(!is_valid_region("useast-1"));
+        assert!(!is_valid_region("us--1"));
+        assert!(!is_valid_region("us-east-1-"));
+        assert!(!is_valid_region("US-EAST-1"));
+        assert!(!is_valid_region(""));
+    }
+
+    #[test]
+    fn pool_id_format() {
+        assert!(is_valid_pool_id("eu-west-1", "eu-west-1_AbCd1234"));
+        assert!(!is_valid_pool_id("eu-west-1", "eu-west-2_AbCd1234"));
+        assert!(!is_valid_pool_id("eu-west-1", "eu-west-1_"));
+        assert!(!is_valid_pool_id("eu-west-1", "eu-west-1-AbCd1234"));
+        assert!(!is_valid_pool_id("eu-west-1", "eu-west-1_AbCd-1234"));
+        assert!(!is_valid_pool_id("eu-west-1", "my-user-pool-id"));
+    }
+
+    #[test]
+    fn keyset_args() {
+        assert!(KeySet::new("eu-west-1", "eu-west-1_AbCd1234").is_ok());
+        let err = KeySet::new("eu-west-1_AbCd1234", "eu-west-1").unwrap_err();
+        assert!(matches!(err, Error::InvalidConfiguration(_)) && err.to_string().contains("swapped"));
+        assert!(matches!(KeySet::new("eu-west-1", "eu-west-2_AbCd1234"), Err(Error::InvalidConfiguration(_))));
+        assert!(matches!(KeySet::new("us-east-l", "us-east-l_AbCd1234"), Err(Error::InvalidConfiguration(_))));
+        assert!(matches!(KeySet::new("eu-west-1", "my-user-pool-id"), Err(Error::InvalidConfiguration(_))));
+
+        // E.g. for a local emulator
+        assert!(KeySet::builder("local", "local_AbCd1234")
+            .jwks_url("http://localhost:9229/local_AbCd1234/.well-known/jwks.json")
+            .issuer("http://localhost:9229/local_AbCd1234")
+            .build().is_ok());
+    }
+}
@@ -0,0 +1,478 @@
+use std::time::Duration;
+
+use jsonwebtokens::VerifierBuilder;
+use serde_json::value::Value;
+
+use crate::{unix_time_now, Scopes};
+
+/// Cognito specific helpers for configuring a `VerifierBuilder`
+///
+/// ```no_run
+/// # use jsonwebtokens_cognito::{KeySet, VerifierBuilderExt};
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// let keyset = KeySet::new("eu-west-1", "eu-west-1_AbCd1234")?;
+/// let verifier = keyset.new_access_token_verifier(&["client-id-0"])
+///     .require_scope("resource-server/read")
+///     .build()?;
+/// # Ok(())
+/// # }
+/// ```
+///
+/// Tokens that don't meet a requirement fail verification with a
+/// `ClaimMismatch` error for the claim, such as `scope` or `cognito:groups`.
+pub trait VerifierBuilderExt {
+    /// Requires the access token's `scope` claim to include the given scope
+    ///
+    /// Each of the scope requirements replaces any previous scope requirement,
+    /// so use `require_scopes()` to require multiple scopes.
+    fn require_scope(&mut self, scope: impl Into<String>) -> &mut Self;
+
+    /// Requires the access token's `scope` claim to include all of the given scopes
+    fn require_scopes(&mut self, scopes: &[&str]) -> &mut Self;
+
+    /// Requires the access token's `scope` claim to include at least one of the
+    /// given scopes
+    fn require_any_scope(&mut self, scopes: &[&str]) -> &mut Self;
+
+    /// Requires the token's `cognito:groups` claim to include the given group
+    ///
+    /// Each of the group requirements replaces any previous group requirement,
+    /// so use `require_all_groups()` to require multiple groups.
+    fn require_group(&mut self, group: impl Into<String>) -> &mut Self;
+
+    /// Requires the token's `cognito:groups` claim to include all of the given groups
+    fn require_all_groups(&mut self, groups: &[&str]) -> &mut Self;
+
+    /// Requires the token's `cognito:groups` claim to include at least one of
+    /// the given groups
+    fn require_any_group(&mut self, groups: &[&str]) -> &mut Self;
+
+    /// Requires the ID token's `email` claim to be an address in the given
+    /// domain, such as for restricting sign in to a company's users
+    ///
+    /// The domain is compared case-insensitively and must match exactly, so
+    /// subdomains aren't included. Combine this with `require_email_verified()`,
+    /// unless the user pool doesn't let users change their email address
+    /// without verifying it.
+    ///
+    /// ```no_run
+    /// # use jsonwebtokens_cognito::{KeySet, VerifierBuilderExt};
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// let keyset = KeySet::new("eu-west-1", "eu-west-1_AbCd1234")?;
+    /// let verifier = keyset.new_id_token_verifier(&["client-id-0"])
+    ///     .require_email_domain("example.com")
+    ///     .require_email_verified()
+    ///     .build()?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// Each of the email domain requirements replaces any previous one, so use
+    /// `require_any_email_domain()` to allow multiple domains.
+    fn require_email_domain(&mut self, domain: impl Into<String>) -> &mut Self;
+
+    /// Requires the ID token's `email` claim to be an address in one of the
+    /// given domains (see `require_email_domain()`)
+    fn require_any_email_domain(&mut self, domains: &[&str]) -> &mut Self;
+
+    /// Requires the ID token's `email_verified` claim to be true
+    fn require_email_verified(&mut self) -> &mut Self;
+
+    /// Requires the token to have the given claim, such as a `custom:tenant_id`
+    /// attribute, so that its value can be read from the verified claims
+    ///
+    /// Tokens where the claim is missing, `null` or an empty string fail
+    /// verification. The value can then be read from the claims, such as with
+    /// `VerifiedToken::extras()`, e.g. to route multi-tenant requests by tenant:
+    ///
+    /// ```no_run
+    /// # use jsonwebtokens_cognito::{KeySet, VerifiedToken, VerifierBuilderExt};
+    /// # async fn example(token: &str) -> Result<(), Box<dyn std::error::Error>> {
+    /// let keyset = KeySet::new("eu-west-1", "eu-west-1_AbCd1234")?;
+    /// let verifier = keyset.new_id_token_verifier(&["client-id-0"])
+    ///     .claim_extract("custom:tenant_id")
+    ///     .build()?;
+    /// let verified = VerifiedToken::Id(keyset.verify_into(token, &verifier).await?);
+    /// let tenant_id = &verified.extras()["custom:tenant_id"];
+    /// # Ok(())
+    /// # }
+    /// ```
+    fn claim_extract(&mut self, claim: impl Into<String>) -> &mut Self;
+
+    /// Requires the token to have a `jti` (token ID) claim that's a UUID, as
+    /// Cognito's are
+    ///
+    /// This is for rejecting tokens that can't be individually revoked or
+    /// audited by their `jti` (see [RevocationList](crate::RevocationList)).
+    fn require_jti(&mut self) -> &mut Self;
+
+    /// Requires the token to have an `origin_jti` claim that's a UUID
+    ///
+    /// Cognito includes this in tokens issued since revocation was enabled for
+    /// the app client, for revoking all the tokens from a refresh token.
+    fn require_origin_jti(&mut self) -> &mut Self;
+
+    /// Requires the ID token's `nonce` claim to match the nonce that was sent
+    /// in the authorization request
+    ///
+    /// OpenID Connect clients (such as those using Cognito's hosted UI) must
+    /// check this to prevent replayed ID tokens from being accepted. Tokens
+    /// without a `nonce` fail verification.
+    ///
+    /// ```no_run
+    /// # use jsonwebtokens_cognito::{KeySet, VerifierBuilderExt};
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// # let nonce_from_session = "n-0S6_WzA2Mj";
+    /// let keyset = KeySet::new("eu-west-1", "eu-west-1_AbCd1234")?;
+    /// let verifier = keyset.new_id_token_verifier(&["client-id-0"])
+    ///     .with_nonce(nonce_from_session)
+    ///     .build()?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    fn with_nonce(&mut self, nonce: impl Into<String>) -> &mut Self;
+
+    /// Requires the user to have authenticated within the given duration, such
+    /// as for sensitive operations, according to the token's `auth_time` claim
+    ///
+    /// Tokens that have been refreshed since the user authenticated are
+    /// rejected too, even if they haven't expired. Cognito includes `auth_time`
+    /// in ID and access tokens for users, but not in client credentials
+    /// (machine to machine) access tokens, so for tokens without it the age is
+    /// checked from their `iat` (issued at) time instead. Tokens with neither
+    /// fail verification.
+    ///
+    /// The `auth_time` fallback only applies when verifying with a KeySet (or
+    /// an `AlbKeySet`), not when using the verifier directly.
+    ///
+    /// The age is checked against the current time, even when verifying for a
+    /// given time.
+    fn max_age(&mut self, max_age: Duration) -> &mut Self;
+}
+
+fn scope_callback(
+    builder: &mut VerifierBuilder,
+    check: impl Fn(&Scopes) -> bool + Send + Sync + 'static
+) -> &mut VerifierBuilder {
+    builder.claim_callback("scope", move |value| match value {
+        Value::String(scope) => check(&Scopes::new(scope)),
+        _ => false,
+    })
+}
+
+fn group_callback(
+    builder: &mut VerifierBuilder,
+    check: impl Fn(&[&str]) -> bool + Send + Sync + 'static
+) -> &mut VerifierBuilder {
+    builder.claim_callback("cognito:groups", move |value| match value {
+        Value::Array(groups) => {
+            let groups: Vec<&str> = groups.iter().filter_map(Value::as_str).collect();
+            check(&groups)
+        }
+        _ => false,
+    })
+}
+
+// Whether the value is a UUID like 2b3e2c6b-7d4a-4b8e-9c1f-0a1b2c3d4e5f
+fn is_uuid(value: &str) -> bool {
+    let groups: Vec<&str> = value.split('-').collect();
+    groups.len() == 5
+        && groups.iter().zip([8, 4, 4, 4, 12]).all(|(group, len)| {
+            group.len() == len && group.bytes().all(|b| b.is_ascii_hexdigit())
+        })
+}
+
+fn email_domain_callback(builder: &mut VerifierBuilder, domains: Vec<String>) -> &mut VerifierBuilder {
+    builder.claim_callback("email", move |value| {
+        let domain = match value.as_str().and_then(|email| email.rsplit_once('@')) {
+            Some((local, domain)) if !local.is_empty() => domain,
+            _ => return false,
+        };
+        domains.iter().any(|d| d.eq_ignore_ascii_case(domain))
+    })
+}
+
+impl VerifierBuilderExt for VerifierBuilder {
+    fn require_scope(&mut self, scope: impl Into<String>) -> &mut Self {
+        let scope = scope.into();
+        scope_callback(self, move |scopes| scopes.contains(&scope))
+    }
+
+    fn require_scopes(&mut self, scopes: &[&str]) -> &mut Self {
+        let required: Vec<String> = scopes.iter().map(|s| s.to_string()).collect();
+        scope_callback(self, move |scopes| required.iter().all(|s| scopes.contains(s)))
+    }
+
+    fn require_any_scope(&mut self, scopes: &[&str]) -> &mut Self {
+        let required: Vec<String> = scopes.iter().map(|s| s.to_string()).collect();
+        scope_callback(self, move |scopes| required.iter().any(|s| scopes.contains(s)))
+    }
+
+    fn require_group(&mut self, group: impl Into<String>) -> &mut Self {
+        let group = group.into();
+        group_callback(self, move |groups| groups.contains(&group.as_str()))
+    }
+
+    fn require_all_groups(&mut self, groups: &[&str]) -> &mut Self {
+        let required: Vec<String> = groups.iter().map(|g| g.to_string()).collect();
+        group_callback(self, move |groups| required.iter().all(|g| groups.contains(&g.as_str())))
+    }
+
+    fn require_any_group(&mut self, groups: &[&str]) -> &mut Self {
+        let required: Vec<String> = groups.iter().map(|g| g.to_string()).collect();
+        group_callback(self, move |groups| required.iter().any(|g| groups.contains(&g.as_str())))
+    }
+
+    fn require_email_domain(&mut self, domain: impl Into<String>) -> &mut Self {
+        email_domain_callback(self, vec![domain.into()])
+    }
+
+    fn require_any_email_domain(&mut self, domains: &[&str]) -> &mut Self {
+        email_domain_callback(self, domains.iter().map(|d| d.to_string()).collect())
+    }
+
+    fn require_email_verified(&mut self) -> &mut Self {
+        // Cognito gives the claim as a string for some federated users
+        self.claim_callback("email_verified", |value| match value {
+            Value::Bool(verified) => *verified,
+            Value::String(verified) => verified == "true",
+            _ => false,
+        })
+    }
+
+    fn claim_extract(&mut self, claim: impl Into<String>) -> &mut Self {
+        self.claim_callback(claim, |value| match value {
+            Value::Null => false,
+            Value::String(value) => !value.is_empty(),
+            _ => true,
+        })
+    }
+
+    fn require_jti(&mut self) -> &mut Self {
+        self.claim_callback("jti", |value| value.as_str().is_some_and(is_uuid))
+    }
+
+    fn require_origin_jti(&mut self) -> &mut Self {
+        self.claim_callback("origin_jti", |value| value.as_str().is_some_and(is_uuid))
+    }
+
+    fn with_nonce(&mut self, nonce: impl Into<String>) -> &mut Self {
+        self.string_equals("nonce", nonce)
+    }
+
+    fn max_age(&mut self, max_age: Duration) -> &mut Self {
+        let max_age = max_age.as_secs();
+        self.claim_callback("auth_time", move |value| match (value.as_u64(), unix_time_now()) {
+            (Some(auth_time), Ok(now)) => now.saturating_sub(auth_time) <= max_age,
+            _ => false,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::{token_hash, Claims, Error, VerifiedToken};
+    use crate::test_util::*;
+
+    #[test]
+    fn required_scopes() {
+        let keyset = test_keyset();
+        let claims = json!({
+            "iss": TEST_ISS,
+            "scope": "api/read api/write",
+        });
+        let token = hs256_token(&claims);
+
+        let verifier = keyset.new_issuer_verifier().require_scope("api/read").build().unwrap();
+        assert!(keyset.try_verify(&token, &verifier).is_ok());
+        let verifier = keyset.new_issuer_verifier().require_scopes(&["api/read", "api/admin"]).build().unwrap();
+        assert!(keyset.try_verify(&token, &verifier).is_err());
+        let verifier = keyset.new_issuer_verifier().require_any_scope(&["api/write", "api/admin"]).build().unwrap();
+        assert!(keyset.try_verify(&token, &verifier).is_ok());
+    }
+
+    #[test]
+    fn required_groups() {
+        let keyset = test_keyset();
+        let claims = json!({
+            "iss": TEST_ISS,
+            "cognito:groups": ["admins", "editors"],
+        });
+        let token = hs256_token(&claims);
+
+        let verifier = keyset.new_issuer_verifier().require_group("admins").build().unwrap();
+        assert!(keyset.try_verify(&token, &verifier).is_ok());
+        let verifier = keyset.new_issuer_verifier().require_all_groups(&["admins", "owners"]).build().unwrap();
+        assert!(keyset.try_verify(&token, &verifier).is_err());
+        let verifier = keyset.new_issuer_verifier().require_any_group(&["editors", "owners"]).build().unwrap();
+        assert!(keyset.try_verify(&token, &verifier).is_ok());
+
+        // Tokens for users without any groups don't have a cognito:groups claim
+        let token = test_token(json!({ "alg": "HS256", "kid": "test-key" }), "secret");
+        let verifier = keyset.new_issuer_verifier().require_group("admins").build().unwrap();
+        assert!(keyset.try_verify(&token, &verifier).is_err());
+    }
+
+    #[test]
+    fn max_age() {
+        let keyset = test_keyset();
+        let now = unix_time_now().unwrap();
+        let token = |auth_time: u64| {
+            let claims = json!({
+                "iss": TEST_ISS,
+                "auth_time": auth_time,
+                "iat": now,
+            });
+            hs256_token(&claims)
+        };
+
+        let verifier = keyset.new_issuer_verifier().max_age(Duration::from_secs(300)).build().unwrap();
+        assert!(keyset.try_verify(&token(now - 60), &verifier).is_ok());
+        assert!(matches!(keyset.try_verify(&token(now - 600), &verifier),
+                         Err(Error::ClaimMismatch { claim, .. }) if claim == "auth_time"));
+
+        // Without an auth_time, the iat time is checked
+        let token = |iat: u64| hs256_token(&json!({ "iss": TEST_ISS, "iat": iat }));
+        assert!(keyset.try_verify(&token(now - 60), &verifier).is_ok());
+        assert!(matches!(keyset.try_verify(&token(now - 600), &verifier),
+                         Err(Error::ClaimMismatch { claim, .. }) if claim == "auth_time"));
+        let token = hs256_token(&json!({ "iss": TEST_ISS }));
+        assert!(matches!(keyset.try_verify(&token, &verifier),
+                         Err(Error::ClaimMismatch { claim, actual: None, .. }) if claim == "auth_time"));
+    }
+
+    #[test]
+    fn email_requirements() {
+        let keyset = test_keyset();
+        let token = |email: &str, email_verified: Value| {
+            let claims = json!({
+                "iss": TEST_ISS,
+                "email": email,
+                "email_verified": email_verified,
+            });
+            hs256_token(&claims)
+        };
+
+        let verifier = keyset.new_issuer_verifier()
+            .require_email_domain("example.com")
+            .require_email_verified()
+            .build()
+            .unwrap();
+        assert!(keyset.try_verify(&token("jo@example.com", json!(true)), &verifier).is_ok());
+        assert!(keyset.try_verify(&token("jo@EXAMPLE.com", json!("true")), &verifier).is_ok());
+        assert!(matches!(keyset.try_verify(&token("jo@example.com.evil.com", json!(true)), &verifier),
+                         Err(Error::ClaimMismatch { claim, .. }) if claim == "email"));
+        assert!(keyset.try_verify(&token("jo@sub.example.com", json!(true)), &verifier).is_err());
+        assert!(keyset.try_verify(&token("@example.com", json!(true)), &verifier).is_err());
+        assert!(matches!(keyset.try_verify(&token("jo@example.com", json!(false)), &verifier),
+                         Err(Error::ClaimMismatch { claim, .. }) if claim == "email_verified"));
+        assert!(keyset.try_verify(&hs256_token(&json!({ "iss": TEST_ISS })), &verifier).is_err());
+
+        let verifier = keyset.new_issuer_verifier()
+            .require_any_email_domain(&["example.com", "example.org"])
+            .build()
+            .unwrap();
+        assert!(keyset.try_verify(&token("jo@example.org", json!(false)), &verifier).is_ok());
+        assert!(keyset.try_verify(&token("jo@example.net", json!(false)), &verifier).is_err());
+    }
+
+    #[test]
+    fn require_jti() {
+        let keyset = test_keyset();
+        let token = |jti: Value| {
+            let claims = json!({
+                "iss": TEST_ISS,
+                "jti": jti,
+                "origin_jti": "0F6A9D2C-3B4E-4F5A-8B6C-7D8E9FA0B1C2",
+            });
+            hs256_token(&claims)
+        };
+
+        let verifier = keyset.new_issuer_verifier().require_jti().require_origin_jti().build().unwrap();
+        let claims = keyset.try_verify(&token(json!("2b3e2c6b-7d4a-4b8e-9c1f-0a1b2c3d4e5f")), &verifier).unwrap();
+        let claims = Claims::from(claims);
+        assert_eq!(claims.jti(), Some("2b3e2c6b-7d4a-4b8e-9c1f-0a1b2c3d4e5f"));
+        assert_eq!(claims.origin_jti(), Some("0F6A9D2C-3B4E-4F5A-8B6C-7D8E9FA0B1C2"));
+
+        for jti in [json!("not-a-uuid"), json!("2b3e2c6b7d4a4b8e9c1f0a1b2c3d4e5f"), json!(1), Value::Null] {
+            assert!(matches!(keyset.try_verify(&token(jti), &verifier),
+                             Err(Error::ClaimMismatch { claim, .. }) if claim == "jti"));
+        }
+        let verifier = keyset.new_issuer_verifier().require_origin_jti().build().unwrap();
+        assert!(keyset.try_verify(&hs256_token(&json!({ "iss": TEST_ISS })), &verifier).is_err());
+    }
+
+    #[test]
+    fn claim_extract() {
+        let keyset = test_keyset();
+        let now = unix_time_now().unwrap();
+        let token = |tenant_id: Value| {
+            let claims = json!({
+                "sub": "user-0",
+                "iss": TEST_ISS,
+                "aud": "client-id-0",
+                "token_use": "id",
+                "exp": now + 3600,
+                "iat": now,
+                "auth_time": now,
+                "cognito:username": "jo",
+                "custom:tenant_id": tenant_id,
+            });
+            hs256_token(&claims)
+        };
+
+        let verifier = keyset.new_id_token_verifier(&["client-id-0"])
+            .claim_extract("custom:tenant_id")
+            .build()
+            .unwrap();
+        let claims = async_std::task::block_on(keyset.verify_into(&token(json!("tenant-0")), &verifier)).unwrap();
+        let verified = VerifiedToken::Id(claims);
+        assert_eq!(verified.extras()["custom:tenant_id"], "tenant-0");
+
+        for tenant_id in [Value::Null, json!("")] {
+            assert!(matches!(keyset.try_verify(&token(tenant_id), &verifier),
+                             Err(Error::ClaimMismatch { claim, .. }) if claim == "custom:tenant_id"));
+        }
+    }
+
+    #[test]
+    fn nonce() {
+        let keyset = test_keyset();
+        let claims = json!({ "iss": TEST_ISS,
+                             "nonce": "nonce-0" });
+        let token = hs256_token(&claims);
+
+        let verifier = keyset.new_issuer_verifier().with_nonce("nonce-0").build().unwrap();
+        assert!(keyset.try_verify(&token, &verifier).is_ok());
+        assert!(keyset.try_verify(&hs256_token(&json!({ "iss": TEST_ISS })), &verifier).is_err());
+
+        let verifier = keyset.new_issuer_verifier().with_nonce("nonce-1").build().unwrap();
+        assert!(matches!(keyset.try_verify(&token, &verifier),
+                         Err(Error::ClaimMismatch { ref claim, .. }) if claim == "nonce"));
+    }
+
+    #[test]
+    fn at_hash() {
+        // The example from OpenID Connect Core's test vectors
+        let access_token = "jHkWEdUXMU1BwAsC4vtUsZwnNvTIxEl0z9K3vx5KF0Y";
+        assert_eq!(token_hash::token_hash("RS256", access_token).unwrap(), "77QmUPtjPfzWtF2AnpK9RQ");
+
+        let keyset = test_keyset();
+        let claims = json!({ "iss": TEST_ISS,
+                             "at_hash": "77QmUPtjPfzWtF2AnpK9RQ" });
+        let id_token = hs256_token(&claims);
+        let verifier = keyset.new_issuer_verifier().build().unwrap();
+
+        assert!(async_std::task::block_on(
+            keyset.verify_id_token_with_access_token(&id_token, access_token, &verifier)).is_ok());
+        assert!(matches!(async_std::task::block_on(
+                             keyset.verify_id_token_with_access_token(&id_token, "other-token", &verifier)),
+                         Err(Error::ClaimMismatch { ref claim, .. }) if claim == "at_hash"));
+        assert!(matches!(async_std::task::block_on(
+                             keyset.verify_id_token_with_code(&id_token, access_token, &verifier)),
+                         Err(Error::ClaimMismatch { claim, actual: None, .. }) if claim == "c_hash"));
+    }
+}